@@ -0,0 +1,70 @@
+//! Merge throughput on large generated manifests.
+//!
+//! Run with `cargo bench --bench merge`. Uses a plain timing loop so the
+//! benchmark needs no extra dependencies.
+
+use std::fmt::Write as _;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use cargo_merge_assist::merge::merge_manifest_texts;
+
+/// Builds a manifest with `deps` dependencies and `targets` target tables,
+/// tagging every `stride`-th dependency version with `variant`.
+fn manifest(deps: usize, targets: usize, stride: usize, variant: &str) -> String {
+    let mut out =
+        String::from("[package]\nname = \"bench\"\nversion = \"0.1.0\"\n\n[dependencies]\n");
+    for i in 0..deps {
+        let version = if stride > 0 && i % stride == 0 {
+            format!("1.{i}.0{variant}")
+        } else {
+            format!("1.{i}.0")
+        };
+        writeln!(
+            out,
+            "dep{i} = {{ version = \"{version}\", features = [\"a\", \"b\"], default-features = false }}"
+        )
+        .unwrap();
+    }
+    for t in 0..targets {
+        writeln!(out, "\n[target.'cfg(target_os = \"os{t}\")'.dependencies]").unwrap();
+        for i in 0..20 {
+            writeln!(out, "tdep{t}_{i} = \"0.{i}\"").unwrap();
+        }
+    }
+    out
+}
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!("{name:<40} {:>10.3} ms/iter", per_iter.as_secs_f64() * 1e3);
+    black_box(Duration::ZERO);
+}
+
+fn main() {
+    for &(deps, targets) in &[(200, 10), (2_000, 100)] {
+        let base = manifest(deps, targets, 0, "");
+        let ours = manifest(deps, targets, 7, "-ours");
+        let theirs = manifest(deps, targets, 0, "");
+        let theirs_disjoint = {
+            let mut text = base.clone();
+            text.push_str("\n[features]\nextra = []\n");
+            text
+        };
+
+        bench(&format!("one-sided ({deps} deps)"), 20, || {
+            black_box(merge_manifest_texts(&base, &ours, &theirs).unwrap());
+        });
+        bench(&format!("both sides ({deps} deps)"), 20, || {
+            black_box(merge_manifest_texts(&base, &ours, &theirs_disjoint).unwrap());
+        });
+        bench(&format!("identical ({deps} deps)"), 20, || {
+            black_box(merge_manifest_texts(&base, &base, &base).unwrap());
+        });
+    }
+}
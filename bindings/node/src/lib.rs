@@ -0,0 +1,72 @@
+//! Node.js bindings for the cargo-merge-assist merge engine.
+
+use cargo_merge_assist::lock;
+use cargo_merge_assist::merge::{self, MergeConflict, MergeError};
+use napi_derive::napi;
+use toml::Value;
+
+/// A semantic conflict; values are rendered as TOML, `null` when deleted.
+#[napi(object)]
+pub struct Conflict {
+    pub path: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+    /// Stable ID for `.cargo-merge-assist-ignore`.
+    pub fingerprint: String,
+}
+
+/// Result of `mergeManifest`: exactly one of `merged` / `conflict` is set.
+#[napi(object)]
+pub struct MergeOutcome {
+    pub merged: Option<String>,
+    pub conflict: Option<Conflict>,
+}
+
+/// 3-way semantic merge of `Cargo.toml` contents.
+///
+/// Conflicts are returned in the outcome; inputs that cannot be merged at all
+/// throw.
+#[napi]
+pub fn merge_manifest(base: String, ours: String, theirs: String) -> napi::Result<MergeOutcome> {
+    match merge::merge_manifest_texts(&base, &ours, &theirs) {
+        Ok(merged) => Ok(MergeOutcome {
+            merged: Some(merged),
+            conflict: None,
+        }),
+        Err(MergeError::Conflict(conflict)) => Ok(MergeOutcome {
+            merged: None,
+            conflict: Some(conflict.into()),
+        }),
+        Err(err) => Err(napi::Error::from_reason(err.to_string())),
+    }
+}
+
+/// Merges the `Cargo.lock` contents of `manifest`, the merged manifest.
+///
+/// Lockfiles do not conflict: when both sides changed them, ours is
+/// re-resolved with cargo (kept off the network when `offline`). Lockfiles
+/// that cannot be resolved throw.
+#[napi]
+pub fn merge_lock(
+    manifest: String,
+    base: String,
+    ours: String,
+    theirs: String,
+    offline: Option<bool>,
+) -> napi::Result<String> {
+    lock::merge_lock_texts(&manifest, &base, &ours, &theirs, offline.unwrap_or(false))
+        .map_err(|err| napi::Error::from_reason(err.to_string()))
+}
+
+impl From<MergeConflict> for Conflict {
+    fn from(conflict: MergeConflict) -> Self {
+        Self {
+            path: conflict.path,
+            base: conflict.base.as_ref().map(Value::to_string),
+            ours: conflict.ours.as_ref().map(Value::to_string),
+            theirs: conflict.theirs.as_ref().map(Value::to_string),
+            fingerprint: conflict.fingerprint,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+class MergeConflict:
+    """A semantic conflict; values are rendered as TOML, `None` when deleted."""
+
+    path: str
+    base: str | None
+    ours: str | None
+    theirs: str | None
+    fingerprint: str
+
+class MergeConflictError(Exception):
+    conflict: MergeConflict
+
+def merge_manifest(base: str, ours: str, theirs: str) -> str:
+    """3-way semantic merge of Cargo.toml contents.
+
+    Raises MergeConflictError on conflicts and ValueError for inputs that
+    cannot be merged at all.
+    """
+
+def merge_lock(
+    manifest: str, base: str, ours: str, theirs: str, offline: bool = False
+) -> str:
+    """Merges the Cargo.lock contents of `manifest`, the merged manifest.
+
+    When both sides changed the lockfile, ours is re-resolved with cargo
+    (kept off the network when `offline`). Raises ValueError for lockfiles
+    that cannot be resolved.
+    """
@@ -0,0 +1,111 @@
+//! Python bindings for the cargo-merge-assist merge engine.
+
+use engine::{lock, merge};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use toml::Value;
+
+create_exception!(
+    cargo_merge_assist,
+    MergeConflictError,
+    PyException,
+    "Raised when both sides changed the same key differently; `.conflict` holds the details."
+);
+
+/// A semantic conflict; values are rendered as TOML, `None` when deleted.
+#[pyclass(frozen, get_all, module = "cargo_merge_assist")]
+#[derive(Clone)]
+pub struct MergeConflict {
+    path: String,
+    base: Option<String>,
+    ours: Option<String>,
+    theirs: Option<String>,
+    fingerprint: String,
+}
+
+#[pymethods]
+impl MergeConflict {
+    fn __repr__(&self) -> String {
+        format!("MergeConflict(path={:?})", self.path)
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl std::fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let render = |v: &Option<String>| v.clone().unwrap_or_else(|| "<deleted>".to_string());
+        write!(
+            f,
+            "semantic conflict at `{}`\n  base  : {}\n  ours  : {}\n  theirs: {}\n  id    : {}",
+            self.path,
+            render(&self.base),
+            render(&self.ours),
+            render(&self.theirs),
+            self.fingerprint
+        )
+    }
+}
+
+impl From<merge::MergeConflict> for MergeConflict {
+    fn from(conflict: merge::MergeConflict) -> Self {
+        Self {
+            path: conflict.path,
+            base: conflict.base.as_ref().map(Value::to_string),
+            ours: conflict.ours.as_ref().map(Value::to_string),
+            theirs: conflict.theirs.as_ref().map(Value::to_string),
+            fingerprint: conflict.fingerprint,
+        }
+    }
+}
+
+/// 3-way semantic merge of `Cargo.toml` contents.
+///
+/// Returns the merged manifest or raises `MergeConflictError`; inputs that
+/// cannot be merged at all raise `ValueError`.
+#[pyfunction]
+fn merge_manifest(py: Python<'_>, base: &str, ours: &str, theirs: &str) -> PyResult<String> {
+    match merge::merge_manifest_texts(base, ours, theirs) {
+        Ok(merged) => Ok(merged),
+        Err(merge::MergeError::Conflict(conflict)) => {
+            let conflict = MergeConflict::from(conflict);
+            let err = MergeConflictError::new_err(conflict.to_string());
+            err.value(py).setattr("conflict", conflict)?;
+            Err(err)
+        }
+        Err(err) => Err(PyValueError::new_err(err.to_string())),
+    }
+}
+
+/// Merges the `Cargo.lock` contents of `manifest`, the merged manifest.
+///
+/// Lockfiles do not conflict: when both sides changed them, ours is
+/// re-resolved with cargo (kept off the network when `offline`). Lockfiles
+/// that cannot be resolved raise `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (manifest, base, ours, theirs, offline = false))]
+fn merge_lock(
+    manifest: &str,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    offline: bool,
+) -> PyResult<String> {
+    lock::merge_lock_texts(manifest, base, ours, theirs, offline)
+        .map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn cargo_merge_assist(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(merge_manifest, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_lock, m)?)?;
+    m.add_class::<MergeConflict>()?;
+    m.add(
+        "MergeConflictError",
+        m.py().get_type::<MergeConflictError>(),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+"""Tests for the Python bindings; run after `maturin develop` with
+`python -m unittest discover tests`."""
+
+import unittest
+
+import cargo_merge_assist as cma
+
+BASE = '[package]\nname = "app"\nversion = "0.1.0"\n'
+
+
+def lock(*packages):
+    text = "version = 3\n"
+    for name, version in packages:
+        text += f'\n[[package]]\nname = "{name}"\nversion = "{version}"\n'
+    return text
+
+
+class MergeManifestTest(unittest.TestCase):
+    def test_merges_disjoint_changes(self):
+        ours = BASE + '\n[dependencies]\nlog = "0.4"\n'
+        theirs = BASE.replace('"app"', '"app2"')
+        merged = cma.merge_manifest(BASE, ours, theirs)
+        self.assertIn('name = "app2"', merged)
+        self.assertIn('log = "0.4"', merged)
+
+    def test_raises_typed_conflicts(self):
+        base = BASE + '\n[dependencies]\nlog = "0.4.19"\n'
+        ours = base.replace("0.4.19", "0.4.20")
+        theirs = base.replace("0.4.19", "0.4.21")
+        with self.assertRaises(cma.MergeConflictError) as raised:
+            cma.merge_manifest(base, ours, theirs)
+        conflict = raised.exception.conflict
+        self.assertEqual(conflict.path, "dependencies.log")
+        self.assertEqual((conflict.ours, conflict.theirs), ('"0.4.20"', '"0.4.21"'))
+
+    def test_rejects_invalid_toml(self):
+        with self.assertRaises(ValueError):
+            cma.merge_manifest(BASE, "[package", "[dependencies")
+
+
+class MergeLockTest(unittest.TestCase):
+    def test_takes_the_only_changed_side(self):
+        base = lock(("app", "0.1.0"), ("log", "0.4.20"))
+        ours = lock(("app", "0.1.0"), ("log", "0.4.21"))
+        self.assertEqual(cma.merge_lock(BASE, base, ours, base), ours)
+        self.assertEqual(cma.merge_lock(BASE, base, base, ours, offline=True), ours)
+
+    def test_rejects_invalid_lockfiles(self):
+        base = lock(("app", "0.1.0"))
+        ours = lock(("app", "0.2.0"))
+        with self.assertRaises(ValueError):
+            cma.merge_lock(BASE, base, ours, "[[package]", True)
+
+
+if __name__ == "__main__":
+    unittest.main()
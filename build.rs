@@ -0,0 +1,5 @@
+fn main() {
+    // `self-update` needs the target triple to pick the matching release asset.
+    let target = std::env::var("TARGET").expect("cargo sets TARGET for build scripts");
+    println!("cargo:rustc-env=CARGO_MERGE_ASSIST_TARGET={target}");
+}
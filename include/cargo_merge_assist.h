@@ -0,0 +1,35 @@
+/*
+ * C interface to the cargo-merge-assist semantic merge engine.
+ *
+ * Build with
+ *   cargo rustc --release --lib --features bindings --crate-type cdylib
+ * the shared library is written to target/release/libcargo_merge_assist.{so,dylib}
+ * (or cargo_merge_assist.dll on Windows).
+ */
+#ifndef CARGO_MERGE_ASSIST_H
+#define CARGO_MERGE_ASSIST_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * 3-way merge of Cargo.toml buffers (NUL-terminated UTF-8).
+ *
+ * Returns a JSON object whose "status" is one of:
+ *   "merged"   - "merged" holds the merged manifest text
+ *   "conflict" - "conflict" holds { path, base, ours, theirs, fingerprint }
+ *   "error"    - "message" describes invalid input or an engine panic
+ *
+ * The result is never NULL and must be released with cma_string_free().
+ */
+char *cma_merge_manifest(const char *base, const char *ours, const char *theirs);
+
+/* Releases a string returned by cma_merge_manifest(). NULL is ignored. */
+void cma_string_free(char *ptr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CARGO_MERGE_ASSIST_H */
@@ -0,0 +1,271 @@
+//! Git dependency `rev` conflicts settled by ancestry (`--git-ancestry`).
+//!
+//! Branches that each move a git dependency forward pin different `rev`s of
+//! the same repository, and usually one commit is the other plus more work.
+//! With [`GitAncestry`] enabled, such pairs are settled before merging: when
+//! one side's commit descends from the other's (`git merge-base
+//! --is-ancestor`), the descendant is taken on both sides, reported under the
+//! rule `git-ancestry`. Revs that diverged still conflict.
+//!
+//! The commits are looked up in cargo's clones under `$CARGO_HOME/git/db`.
+//! When cargo has not fetched both, [`GitAncestry::Fetch`] fetches their
+//! history (commits only, no file contents) into a clone cached under
+//! `<git common dir>/cargo-merge-assist/git/` (the system temporary directory
+//! outside a repository). Only `https`, `ssh`, `git` and `file` URLs are
+//! fetched.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use sha2::{Digest, Sha256};
+use toml::Value;
+
+use crate::cache::git_common_dir;
+use crate::estimate::cargo_home;
+use crate::merge::{GitAncestry, Resolution, Side};
+use crate::pins::{dependency_tables, lookup, lookup_mut};
+
+/// Where the commits of git dependencies are looked for.
+pub(crate) struct Repositories {
+    /// Cargo's bare clones, `$CARGO_HOME/git/db`.
+    cargo_db: Option<PathBuf>,
+    /// Clones fetched for ancestry checks, when fetching is allowed.
+    fetched: Option<PathBuf>,
+}
+
+impl Repositories {
+    fn new(mode: GitAncestry) -> Self {
+        let fetched = (mode == GitAncestry::Fetch).then(|| match git_common_dir(Path::new(".")) {
+            Some(dir) => dir.join("cargo-merge-assist").join("git"),
+            None => std::env::temp_dir().join("cargo-merge-assist-git"),
+        });
+        Self {
+            cargo_db: cargo_home().map(|home| home.join("git").join("db")),
+            fetched,
+        }
+    }
+
+    /// A clone of `url` holding both commits, if one can be found or
+    /// fetched.
+    fn clone_with(&self, url: &str, revs: [&str; 2]) -> Option<PathBuf> {
+        if !fetchable(url) {
+            return None;
+        }
+        let has_both = |dir: &Path| revs.iter().all(|rev| has_commit(dir, rev));
+        // Cargo names its clones `<last URL segment>-<hash>`.
+        let name = url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()?
+            .trim_end_matches(".git");
+        let cached = self
+            .cargo_db
+            .iter()
+            .flat_map(|db| fs::read_dir(db).into_iter().flatten())
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|dir| {
+                dir.file_name()
+                    .and_then(|file| file.to_str())
+                    .and_then(|file| file.strip_prefix(name))
+                    .is_some_and(|rest| rest.starts_with('-'))
+            })
+            .find(|dir| has_both(dir));
+        if cached.is_some() {
+            return cached;
+        }
+
+        let hash = Sha256::digest(url.as_bytes());
+        let dir = self.fetched.as_ref()?.join(
+            hash[..8]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>(),
+        );
+        if !dir.join("HEAD").exists() {
+            fs::create_dir_all(&dir).ok()?;
+            git(&dir, &["init", "--quiet", "--bare"]).then_some(())?;
+        }
+        if !has_both(&dir) {
+            // Servers only hand out commits named by their full hash; a
+            // short rev takes fetching every branch.
+            let full = |rev: &str| rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit());
+            let mut args = vec!["fetch", "--quiet", "--filter=blob:none", "--", url];
+            if revs.iter().all(|rev| full(rev)) {
+                args.extend(revs);
+            } else {
+                args.push("+refs/heads/*:refs/heads/*");
+            }
+            git(&dir, &args);
+        }
+        has_both(&dir).then_some(dir)
+    }
+}
+
+/// Makes both sides pin the descendant `rev` of each git dependency whose
+/// revs both sides changed, where one descends from the other. Returns the
+/// resolutions.
+pub(crate) fn resolve(
+    mode: GitAncestry,
+    base: &Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+) -> Vec<Resolution> {
+    if mode == GitAncestry::Off {
+        return Vec::new();
+    }
+    resolve_in(&Repositories::new(mode), base, ours, theirs)
+}
+
+fn resolve_in(
+    repositories: &Repositories,
+    base: &Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    for table in dependency_tables([base, ours, theirs]) {
+        let keys: Vec<String> = lookup(ours, &table)
+            .and_then(Value::as_table)
+            .into_iter()
+            .flat_map(|entries| entries.keys().cloned())
+            .collect();
+        for key in keys {
+            let mut path = table.clone();
+            path.push(key);
+            let rev = |doc: &Value, field: &str| {
+                lookup(doc, &path)?.get(field)?.as_str().map(str::to_string)
+            };
+            let (Some(url), Some(ours_rev), Some(theirs_rev)) =
+                (rev(ours, "git"), rev(ours, "rev"), rev(theirs, "rev"))
+            else {
+                continue;
+            };
+            let base_rev = rev(base, "rev");
+            if rev(theirs, "git").as_ref() != Some(&url)
+                || ours_rev == theirs_rev
+                || base_rev.as_ref() == Some(&ours_rev)
+                || base_rev.as_ref() == Some(&theirs_rev)
+            {
+                continue;
+            }
+            let Some(clone) = repositories.clone_with(&url, [&ours_rev, &theirs_rev]) else {
+                continue;
+            };
+            let (side, descendant, doc) = if is_ancestor(&clone, &theirs_rev, &ours_rev) {
+                (Side::Ours, ours_rev, &mut *theirs)
+            } else if is_ancestor(&clone, &ours_rev, &theirs_rev) {
+                (Side::Theirs, theirs_rev, &mut *ours)
+            } else {
+                continue;
+            };
+            if let Some(Value::Table(entry)) = lookup_mut(doc, &path) {
+                entry.insert("rev".to_string(), Value::String(descendant));
+            }
+            path.push("rev".to_string());
+            resolutions.push(Resolution {
+                path: path.join("."),
+                side: Some(side),
+                rule: "git-ancestry",
+            });
+        }
+    }
+    resolutions
+}
+
+/// Whether `url` may be handed to `git fetch`: a URL with a scheme git
+/// fetches over by itself, never something git would read as an option or
+/// a transport helper (`ext::`).
+fn fetchable(url: &str) -> bool {
+    !url.starts_with('-')
+        && ["https://", "ssh://", "git://", "file://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+}
+
+fn has_commit(dir: &Path, rev: &str) -> bool {
+    git(dir, &["cat-file", "-e", &format!("{rev}^{{commit}}")])
+}
+
+/// Whether `ancestor` is an ancestor of (or the same commit as) `rev`.
+fn is_ancestor(dir: &Path, ancestor: &str, rev: &str) -> bool {
+    git(dir, &["merge-base", "--is-ancestor", ancestor, rev])
+}
+
+/// Runs git in `dir`; whether it succeeded.
+fn git(dir: &Path, args: &[&str]) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_descendant_rev() {
+        let scratch = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-ancestry-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&scratch);
+        let upstream = scratch.join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+        let run = |args: &[&str]| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(&upstream)
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{output:?}");
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+        run(&["init", "--quiet", "--initial-branch=main"]);
+        let mut commits = Vec::new();
+        for message in ["one", "two", "three"] {
+            run(&["commit", "--quiet", "--allow-empty", "-m", message]);
+            commits.push(run(&["rev-parse", "HEAD"]));
+        }
+        run(&["checkout", "--quiet", "-b", "side", &commits[0]]);
+        run(&["commit", "--quiet", "--allow-empty", "-m", "diverged"]);
+        let diverged = run(&["rev-parse", "HEAD"]);
+
+        let url = format!("file://{}", upstream.display());
+        let manifest = |rev: &str| -> Value {
+            format!("[dependencies]\ndep = {{ git = {url:?}, rev = \"{rev}\" }}\n")
+                .parse()
+                .unwrap()
+        };
+        let repositories = Repositories {
+            cargo_db: None,
+            fetched: Some(scratch.join("fetched")),
+        };
+        let base = manifest(&commits[0]);
+        let mut ours = manifest(&commits[2]);
+        let mut theirs = manifest(&commits[1][..12]);
+        let resolutions = resolve_in(&repositories, &base, &mut ours, &mut theirs);
+        assert_eq!(theirs, ours);
+        assert_eq!(resolutions[0].path, "dependencies.dep.rev");
+        assert_eq!(resolutions[0].side, Some(Side::Ours));
+
+        let mut theirs = manifest(&diverged);
+        assert!(resolve_in(&repositories, &base, &mut ours, &mut theirs).is_empty());
+        assert_eq!(theirs, manifest(&diverged));
+
+        assert!(!fetchable("--upload-pack=touch pwned"));
+        assert!(!fetchable("ext::sh -c touch% pwned"));
+        assert!(!fetchable(upstream.to_str().unwrap()));
+        assert!(fetchable("https://github.com/serde-rs/serde"));
+        fs::remove_dir_all(&scratch).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! In-file record of automatic resolutions (`--audit-trail`).
+//!
+//! With [`MergeOptions::audit`](crate::merge::MergeOptions::audit) the merged
+//! manifest ends with one comment per value that both sides changed and the
+//! merge resolved on its own, so a reviewer sees what automation decided in
+//! the diff itself:
+//!
+//! ```toml
+//! # cargo-merge-assist: took dependencies.serde from theirs (online-hints)
+//! ```
+//!
+//! Footers describe one merge only; [`strip`] removes them from the inputs
+//! of the next one.
+
+use crate::merge::Resolution;
+
+/// Prefix of every audit line.
+pub const MARKER: &str = "# cargo-merge-assist:";
+
+/// The footer for `resolutions`, starting with a blank line; empty when
+/// nothing was resolved automatically.
+pub fn footer(resolutions: &[Resolution], newline: &str) -> String {
+    let mut footer = String::new();
+    if resolutions.is_empty() {
+        return footer;
+    }
+    footer.push_str(newline);
+    for resolution in resolutions {
+        let line = match resolution.side {
+            Some(side) => format!(
+                "{MARKER} took {} from {side} ({})",
+                resolution.path, resolution.rule
+            ),
+            None => format!(
+                "{MARKER} set {} to a new value ({})",
+                resolution.path, resolution.rule
+            ),
+        };
+        footer.push_str(&line);
+        footer.push_str(newline);
+    }
+    footer
+}
+
+/// `text` without audit lines, and without the blank lines that separated
+/// a trailing footer from the manifest.
+pub fn strip(text: &str) -> String {
+    if !text.contains(MARKER) {
+        return text.to_string();
+    }
+    let mut stripped: String = text
+        .split_inclusive('\n')
+        .filter(|line| !line.starts_with(MARKER))
+        .collect();
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    stripped.truncate(stripped.trim_end_matches(['\n', '\r']).len());
+    if !stripped.is_empty() {
+        stripped.push_str(newline);
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeOptions, Proposal, Side, merge_manifest_texts_with};
+    use toml::Value;
+
+    fn prefer_theirs(
+        _path: &[&str],
+        _base: Option<&Value>,
+        _ours: Option<&Value>,
+        theirs: Option<&Value>,
+    ) -> Option<Proposal> {
+        Some(Proposal {
+            value: theirs.cloned(),
+            rule: "prefer-theirs",
+            confidence: 1.0,
+        })
+    }
+
+    #[test]
+    fn records_automatic_resolutions_and_replaces_old_records() {
+        let base = "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.0.100\"\n";
+        let ours = base.replace("1.0.100", "1.0.200");
+        let theirs = base.replace("1.0.100", "1.0.199");
+        let options = MergeOptions {
+            heuristics: vec![prefer_theirs],
+            audit: true,
+            ..MergeOptions::default()
+        };
+
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(
+            merged.ends_with(
+                "\n\n# cargo-merge-assist: took dependencies.serde from theirs (prefer-theirs)\n"
+            ),
+            "{merged}"
+        );
+
+        // The next merge starts from a file carrying the old record.
+        let theirs = merged.replace("name = \"demo\"", "name = \"demo2\"");
+        let merged = merge_manifest_texts_with(&merged, &merged, &theirs, &options).unwrap();
+        assert!(!merged.contains(MARKER), "{merged}");
+        assert!(merged.contains("name = \"demo2\""), "{merged}");
+    }
+
+    #[test]
+    fn strips_footers_and_formats_new_values() {
+        let resolutions = [Resolution {
+            path: "package.version".to_string(),
+            side: None,
+            rule: "semver-max",
+        }];
+        let text = format!("a = 1\r\n{}", footer(&resolutions, "\r\n"));
+        assert_eq!(
+            text,
+            "a = 1\r\n\r\n# cargo-merge-assist: set package.version to a new value (semver-max)\r\n"
+        );
+        assert_eq!(strip(&text), "a = 1\r\n");
+        assert_eq!(strip("a = 1\n"), "a = 1\n");
+
+        let resolutions = [Resolution {
+            path: "dependencies.log".to_string(),
+            side: Some(Side::Ours),
+            rule: "cluster",
+        }];
+        assert!(footer(&resolutions, "\n").contains("took dependencies.log from ours"));
+    }
+}
@@ -0,0 +1,437 @@
+//! Merging many manifests in one invocation.
+//!
+//! A batch spec lists merge jobs as TOML:
+//!
+//! ```toml
+//! [[merge]]
+//! base = "base/Cargo.toml"
+//! ours = "ours/Cargo.toml"
+//! theirs = "theirs/Cargo.toml"
+//! out = "ours/Cargo.toml"
+//! ```
+//!
+//! Instead of (or besides) listing jobs, a spec can have them discovered:
+//!
+//! ```toml
+//! [discover]
+//! base = "base"
+//! theirs = "theirs"
+//! ```
+//!
+//! creates a job for every in-scope manifest under the spec's directory (see
+//! [`discover`](crate::discover)) that also exists under both the `base` and
+//! `theirs` directories, merging into the manifest itself.
+//!
+//! Relative paths are resolved against the spec's directory. With the
+//! `parallel` feature jobs (reading, parsing, merging, writing) run on a rayon
+//! pool; results are always returned in spec order so reports are
+//! deterministic regardless of scheduling.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use toml::Value;
+
+use crate::cleanup;
+use crate::discover::Excludes;
+use crate::input;
+use crate::merge::{
+    MergeConflict, MergeError, MergeOptions, Resolution, merge_manifest_texts_detailed,
+};
+use crate::timings::Timings;
+
+/// One 3-way merge to perform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchJob {
+    pub base: PathBuf,
+    pub ours: PathBuf,
+    pub theirs: PathBuf,
+    pub out: PathBuf,
+}
+
+/// Result of a single job.
+#[derive(Debug)]
+pub enum BatchOutcome {
+    /// The merge succeeded and was written to the job's `out`, resolving
+    /// these values automatically.
+    Merged(Vec<Resolution>),
+    /// The merge conflicted; nothing was written.
+    Conflict(MergeConflict),
+    /// Reading, merging or writing failed; nothing was written.
+    Failed(String),
+}
+
+/// Parses a batch spec, resolving relative paths against `spec_dir`.
+pub fn parse_spec(text: &str, spec_dir: &Path) -> Result<Vec<BatchJob>, String> {
+    let spec: Value = toml::from_str(text).map_err(|err| format!("invalid batch spec: {err}"))?;
+    let Some(entries) = spec.get("merge").and_then(Value::as_array) else {
+        if spec.get("discover").is_some() {
+            return Ok(Vec::new());
+        }
+        return Err("batch spec has no [[merge]] entries or [discover] table".to_string());
+    };
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .map(|path| spec_dir.join(path))
+                    .ok_or_else(|| format!("merge entry #{} is missing `{name}`", idx + 1))
+            };
+            Ok(BatchJob {
+                base: field("base")?,
+                ours: field("ours")?,
+                theirs: field("theirs")?,
+                out: field("out")?,
+            })
+        })
+        .collect()
+}
+
+/// Jobs for the spec's `[discover]` table, if any, scanning the root of
+/// `excludes` (the spec's directory).
+pub fn discover_jobs(
+    text: &str,
+    spec_dir: &Path,
+    excludes: &Excludes,
+) -> Result<Vec<BatchJob>, String> {
+    let spec: Value = toml::from_str(text).map_err(|err| format!("invalid batch spec: {err}"))?;
+    let Some(discover) = spec.get("discover") else {
+        return Ok(Vec::new());
+    };
+    let dir = |name: &str| {
+        discover
+            .get(name)
+            .and_then(Value::as_str)
+            .map(Path::new)
+            .ok_or_else(|| format!("[discover] is missing `{name}`"))
+    };
+    let (base_dir, theirs_dir) = (dir("base")?, dir("theirs")?);
+
+    let mut jobs = Vec::new();
+    for manifest in excludes.manifests() {
+        let Ok(relative) = manifest.strip_prefix(excludes.root()) else {
+            continue;
+        };
+        if relative.starts_with(base_dir) || relative.starts_with(theirs_dir) {
+            continue;
+        }
+        let base = spec_dir.join(base_dir).join(relative);
+        let theirs = spec_dir.join(theirs_dir).join(relative);
+        if base.is_file() && theirs.is_file() {
+            jobs.push(BatchJob {
+                base,
+                ours: spec_dir.join(relative),
+                theirs,
+                out: spec_dir.join(relative),
+            });
+        }
+    }
+    Ok(jobs)
+}
+
+/// What merging two directory trees does with one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectoryEntry {
+    /// A manifest in base, ours and theirs, merged.
+    Merge(BatchJob),
+    /// A file only one side added, one both added identically, or a
+    /// lockfile beside a manifest, copied to `out` (a lockfile is ours',
+    /// else theirs', to be regenerated).
+    Copy { from: PathBuf, out: PathBuf },
+    /// A file one side deleted and the other left unchanged; `out` is not
+    /// written, and removed if present.
+    Deleted { out: PathBuf },
+    /// A file the sides cannot agree on: deleted on one side and changed on
+    /// the other, or added differently on both.
+    Conflict { out: PathBuf, reason: String },
+}
+
+/// What to do with every in-scope manifest under the roots of `ours` and
+/// `theirs` (two directory trees, scoped alike) and with the `Cargo.lock`
+/// beside each, given the tree at `base`: manifests in all three are merged,
+/// one-sided additions and deletions are taken, and lockfiles are carried
+/// over for regeneration. Entries are sorted by relative path.
+pub fn directory_entries(
+    base: &Path,
+    ours: &Excludes,
+    theirs: &Excludes,
+    out: &Path,
+) -> Vec<DirectoryEntry> {
+    let relative = |excludes: &Excludes| -> Vec<PathBuf> {
+        excludes
+            .manifests()
+            .into_iter()
+            .filter_map(|manifest| {
+                let relative = manifest.strip_prefix(excludes.root()).ok()?;
+                Some(relative.to_path_buf())
+            })
+            .collect()
+    };
+    let mut manifests = relative(ours);
+    manifests.extend(relative(theirs));
+    manifests.sort();
+    manifests.dedup();
+
+    let mut entries = Vec::new();
+    for manifest in manifests {
+        let lockfile = manifest.with_file_name("Cargo.lock");
+        for (relative, is_lock) in [(manifest, false), (lockfile, true)] {
+            let [base_file, ours_file, theirs_file] =
+                [base, ours.root(), theirs.root()].map(|root| root.join(&relative));
+            let out = out.join(&relative);
+            let read = |path: &Path| fs::read(path).ok();
+            let entry = match [&base_file, &ours_file, &theirs_file].map(|path| read(path)) {
+                [_, None, None] => continue,
+                [_, Some(_), Some(_)] if is_lock => DirectoryEntry::Copy {
+                    from: ours_file,
+                    out,
+                },
+                [Some(_), Some(_), Some(_)] => DirectoryEntry::Merge(BatchJob {
+                    base: base_file,
+                    ours: ours_file,
+                    theirs: theirs_file,
+                    out,
+                }),
+                [None, Some(ours_bytes), Some(theirs_bytes)] => {
+                    if ours_bytes == theirs_bytes {
+                        DirectoryEntry::Copy {
+                            from: ours_file,
+                            out,
+                        }
+                    } else {
+                        DirectoryEntry::Conflict {
+                            out,
+                            reason: "added differently on both sides".to_string(),
+                        }
+                    }
+                }
+                [None, Some(_), None] => DirectoryEntry::Copy {
+                    from: ours_file,
+                    out,
+                },
+                [None, None, Some(_)] => DirectoryEntry::Copy {
+                    from: theirs_file,
+                    out,
+                },
+                [Some(base_bytes), Some(kept), None] | [Some(base_bytes), None, Some(kept)] => {
+                    let (deleted_by, kept_file) = if theirs_file.exists() {
+                        ("ours", theirs_file)
+                    } else {
+                        ("theirs", ours_file)
+                    };
+                    if kept == base_bytes {
+                        DirectoryEntry::Deleted { out }
+                    } else if is_lock {
+                        // Regenerated anyway, if its manifest is kept.
+                        DirectoryEntry::Copy {
+                            from: kept_file,
+                            out,
+                        }
+                    } else {
+                        DirectoryEntry::Conflict {
+                            out,
+                            reason: format!(
+                                "deleted by {deleted_by} but changed by the other side"
+                            ),
+                        }
+                    }
+                }
+            };
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// Runs every job and returns the outcomes in job order.
+///
+/// `jobs_limit` caps the number of worker threads (default: one per CPU); it
+/// is ignored without the `parallel` feature.
+pub fn run_batch(
+    jobs: &[BatchJob],
+    options: &MergeOptions,
+    lossy: bool,
+    jobs_limit: Option<usize>,
+) -> Vec<BatchOutcome> {
+    #[cfg(feature = "parallel")]
+    {
+        let run = || {
+            jobs.par_iter()
+                .map(|job| run_job(job, options, lossy))
+                .collect()
+        };
+        match jobs_limit {
+            Some(threads) => match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+                Ok(pool) => pool.install(run),
+                Err(_) => run(),
+            },
+            None => run(),
+        }
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        let _ = jobs_limit;
+        jobs.iter()
+            .map(|job| run_job(job, options, lossy))
+            .collect()
+    }
+}
+
+fn run_job(job: &BatchJob, options: &MergeOptions, lossy: bool) -> BatchOutcome {
+    let read = |path: &Path| -> Result<String, String> {
+        let bytes =
+            fs::read(path).map_err(|err| format!("failed reading {}: {err}", path.display()))?;
+        input::decode(&bytes, lossy)
+            .map_err(|err| format!("failed decoding {}: {err}", path.display()))
+    };
+    let texts = (|| Ok::<_, String>((read(&job.base)?, read(&job.ours)?, read(&job.theirs)?)))();
+    let (base, ours, theirs) = match texts {
+        Ok(texts) => texts,
+        Err(message) => return BatchOutcome::Failed(message),
+    };
+
+    match merge_manifest_texts_detailed(&base, &ours, &theirs, options, &mut Timings::default()) {
+        Ok(merged) => match cleanup::write_atomic(&job.out, input::encode(&merged.text, lossy)) {
+            Ok(()) => BatchOutcome::Merged(merged.resolutions),
+            Err(err) => {
+                BatchOutcome::Failed(format!("failed writing {}: {err}", job.out.display()))
+            }
+        },
+        Err(MergeError::Conflict(conflict)) => BatchOutcome::Conflict(conflict),
+        Err(err) => BatchOutcome::Failed(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-batch-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_spec_relative_to_its_directory() {
+        let jobs = parse_spec(
+            "[[merge]]\nbase = \"b.toml\"\nours = \"o.toml\"\ntheirs = \"t.toml\"\nout = \"/abs/out.toml\"\n",
+            Path::new("/repo"),
+        )
+        .unwrap();
+        assert_eq!(
+            jobs,
+            vec![BatchJob {
+                base: PathBuf::from("/repo/b.toml"),
+                ours: PathBuf::from("/repo/o.toml"),
+                theirs: PathBuf::from("/repo/t.toml"),
+                out: PathBuf::from("/abs/out.toml"),
+            }]
+        );
+
+        let err = parse_spec("[[merge]]\nbase = \"b\"\n", Path::new(".")).unwrap_err();
+        assert!(err.contains("missing `ours`"), "{err}");
+    }
+
+    #[test]
+    fn discovers_jobs_with_both_counterparts() {
+        let dir = scratch_dir("discover");
+        for manifest in [
+            "Cargo.toml",
+            "a/Cargo.toml",
+            "b/Cargo.toml",
+            "vendor/c/Cargo.toml",
+            "base/Cargo.toml",
+            "base/a/Cargo.toml",
+            "base/vendor/c/Cargo.toml",
+            "theirs/Cargo.toml",
+            "theirs/a/Cargo.toml",
+            "theirs/vendor/c/Cargo.toml",
+        ] {
+            let path = dir.join(manifest);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "[package]\n").unwrap();
+        }
+        let spec = "[discover]\nbase = \"base\"\ntheirs = \"theirs\"\n";
+        assert_eq!(parse_spec(spec, &dir).unwrap(), []);
+
+        let excludes = Excludes::new(&dir, &[], &[]).unwrap();
+        let jobs = discover_jobs(spec, &dir, &excludes).unwrap();
+        let outs: Vec<_> = jobs.iter().map(|job| job.out.clone()).collect();
+        // `b` has no counterparts, `vendor/` is excluded.
+        assert_eq!(outs, [dir.join("Cargo.toml"), dir.join("a/Cargo.toml")]);
+        assert_eq!(jobs[1].base, dir.join("base/a/Cargo.toml"));
+        assert_eq!(jobs[1].theirs, dir.join("theirs/a/Cargo.toml"));
+
+        // The same trees given as directories.
+        let ours = Excludes::new(&dir, &["base/".into(), "theirs/".into()], &[]).unwrap();
+        let theirs = Excludes::new(&dir.join("theirs"), &[], &[]).unwrap();
+        let entries = directory_entries(&dir.join("base"), &ours, &theirs, &dir.join("out"));
+        assert_eq!(entries.len(), 3);
+        let DirectoryEntry::Merge(job) = &entries[1] else {
+            panic!("expected a merge, got {:?}", entries[1]);
+        };
+        assert_eq!(job.ours, dir.join("a/Cargo.toml"));
+        assert_eq!(job.out, dir.join("out/a/Cargo.toml"));
+        // Only ours added `b`.
+        assert_eq!(
+            entries[2],
+            DirectoryEntry::Copy {
+                from: dir.join("b/Cargo.toml"),
+                out: dir.join("out/b/Cargo.toml"),
+            }
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_outcomes_in_job_order() {
+        let dir = scratch_dir("order");
+        fs::write(dir.join("base.toml"), "a = 1\n").unwrap();
+        fs::write(dir.join("two.toml"), "a = 2\n").unwrap();
+        fs::write(dir.join("three.toml"), "a = 3\n").unwrap();
+
+        let job = |ours: &str, theirs: &str, out: &str| BatchJob {
+            base: dir.join("base.toml"),
+            ours: dir.join(ours),
+            theirs: dir.join(theirs),
+            out: dir.join(out),
+        };
+        let mut jobs = Vec::new();
+        for i in 0..16 {
+            jobs.push(job("two.toml", "base.toml", &format!("out{i}.toml")));
+            jobs.push(job("two.toml", "three.toml", "never.toml"));
+            jobs.push(job("missing.toml", "base.toml", "never.toml"));
+        }
+
+        let outcomes = run_batch(&jobs, &MergeOptions::default(), false, Some(4));
+        for (idx, outcome) in outcomes.iter().enumerate() {
+            match idx % 3 {
+                0 => assert!(
+                    matches!(outcome, BatchOutcome::Merged(_)),
+                    "#{idx}: {outcome:?}"
+                ),
+                1 => assert!(matches!(outcome, BatchOutcome::Conflict(c) if c.path == "a")),
+                _ => assert!(
+                    matches!(outcome, BatchOutcome::Failed(m) if m.contains("missing.toml"))
+                ),
+            }
+        }
+        assert_eq!(
+            fs::read_to_string(dir.join("out15.toml")).unwrap(),
+            "a = 2\n"
+        );
+        assert!(!dir.join("never.toml").exists());
+    }
+}
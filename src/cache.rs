@@ -0,0 +1,111 @@
+//! Content-addressed cache of merge results.
+//!
+//! CI reruns and rebases merge the same (base, ours, theirs) triples over and
+//! over. Successful results are stored under
+//! `<git common dir>/cargo-merge-assist/cache/`, keyed by a SHA-256 of the
+//! three inputs, the tool version and the merge configuration, so any change
+//! to inputs or settings misses the cache. Conflicts are never cached.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+use crate::cleanup;
+
+/// A directory of cached merge results.
+#[derive(Debug, Clone)]
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache of the git repository containing `start`, if any.
+    pub fn for_repo(start: &Path) -> Option<Self> {
+        git_common_dir(start).map(|dir| Self::new(dir.join("cargo-merge-assist").join("cache")))
+    }
+
+    /// Cache key for a merge. `config` must describe every setting that can
+    /// change the merged output (strategies, policies, output style).
+    pub fn key(base: &str, ours: &str, theirs: &str, config: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [env!("CARGO_PKG_VERSION"), config, base, ours, theirs] {
+            // Length prefixes keep ("ab", "c") and ("a", "bc") distinct.
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.entry(key)).ok()
+    }
+
+    pub fn put(&self, key: &str, merged: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Concurrent readers never see a partial entry.
+        cleanup::write_atomic(&self.entry(key), merged)
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+}
+
+pub(crate) fn git_common_dir(start: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(start)
+        .args(["rev-parse", "--git-common-dir"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let dir = PathBuf::from(String::from_utf8(output.stdout).ok()?.trim());
+    Some(if dir.is_absolute() {
+        dir
+    } else {
+        start.join(dir)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_depends_on_every_input() {
+        let key = ResultCache::key("b", "o", "t", "");
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, ResultCache::key("b", "o", "t", ""));
+        assert_ne!(key, ResultCache::key("b", "o", "t", "strategy=ours"));
+        assert_ne!(key, ResultCache::key("b", "t", "o", ""));
+        assert_ne!(
+            ResultCache::key("ab", "c", "", ""),
+            ResultCache::key("a", "bc", "", "")
+        );
+    }
+
+    #[test]
+    fn stores_and_returns_results() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-merge-assist-cache-{}", std::process::id()));
+        let cache = ResultCache::new(&dir);
+        let key = ResultCache::key("b", "o", "t", "");
+
+        assert_eq!(cache.get(&key), None);
+        cache.put(&key, "merged = true\n").unwrap();
+        assert_eq!(cache.get(&key).as_deref(), Some("merged = true\n"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Running cargo for `resolve-lock` and `merge-all`.
+//!
+//! cargo's output is captured rather than passed through: inside `git merge`
+//! it would be interleaved with git's own output, and `-q` runs would hide
+//! it entirely. On failure the last [`TAIL_LINES`] lines of stderr are kept
+//! on the [`Error::Cargo`] and the complete output is written to a log file
+//! under `<git common dir>/cargo-merge-assist/logs/` (the system temporary
+//! directory outside a repository) that the error message points to.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache::git_common_dir;
+use crate::error::Error;
+
+/// Lines of cargo's stderr kept on the error.
+pub const TAIL_LINES: usize = 20;
+
+/// Numbers the logs of one process; checks can fail concurrently.
+static LOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether cargo can be run at all.
+pub fn available() -> bool {
+    Command::new("cargo")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Runs `cargo <args>` in `repo`, adding `--offline` when asked.
+pub fn run(repo: &Path, args: &[&str], offline: bool) -> Result<(), Error> {
+    stdout(repo, args, offline).map(drop)
+}
+
+/// Like [`run`], returning what cargo printed on stdout.
+pub fn stdout(repo: &Path, args: &[&str], offline: bool) -> Result<String, Error> {
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(repo).args(args);
+    if offline {
+        cmd.arg("--offline");
+    }
+    let output = cmd
+        .output()
+        .map_err(|err| Error::io("executing cargo in", repo, &err))?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    Err(Error::Cargo {
+        dir: repo.to_path_buf(),
+        args: args.iter().map(ToString::to_string).collect(),
+        exit: output.status.code(),
+        stderr: lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n"),
+        log: write_log(repo, args, &output),
+    })
+}
+
+/// Moves every locked version of a pinned crate (crate name to exact version)
+/// to its pin with `cargo update -p <crate>@<locked> --precise <pin>`.
+/// Returns the `<crate> <locked> -> <pin>` moves made.
+pub fn apply_pins(
+    repo: &Path,
+    pins: &BTreeMap<String, String>,
+    offline: bool,
+) -> Result<Vec<String>, Error> {
+    if pins.is_empty() {
+        return Ok(Vec::new());
+    }
+    let lockfile = repo.join("Cargo.lock");
+    let text =
+        fs::read_to_string(&lockfile).map_err(|err| Error::io("reading", &lockfile, &err))?;
+    let lock: toml::Value = toml::from_str(&text).map_err(|err| Error::Schema {
+        file: Some(lockfile.clone()),
+        problems: vec![err.message().to_string()],
+    })?;
+    let mut moves = Vec::new();
+    for package in lock
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let field = |key: &str| package.get(key).and_then(toml::Value::as_str);
+        let (Some(name), Some(locked)) = (field("name"), field("version")) else {
+            continue;
+        };
+        let Some(pin) = pins.get(name).filter(|pin| *pin != locked) else {
+            continue;
+        };
+        let spec = format!("{name}@{locked}");
+        run(repo, &["update", "-p", &spec, "--precise", pin], offline)?;
+        moves.push(format!("{name} {locked} -> {pin}"));
+    }
+    Ok(moves)
+}
+
+/// Writes cargo's complete output; `None` if the log cannot be written.
+fn write_log(repo: &Path, args: &[&str], output: &Output) -> Option<PathBuf> {
+    let dir = match git_common_dir(repo) {
+        Some(dir) => dir.join("cargo-merge-assist").join("logs"),
+        None => std::env::temp_dir().join("cargo-merge-assist-logs"),
+    };
+    fs::create_dir_all(&dir).ok()?;
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let command = args.first().copied().unwrap_or("cargo");
+    let path = dir.join(format!(
+        "cargo-{command}-{secs}-{}-{}.log",
+        std::process::id(),
+        LOGS.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut log = format!("$ cargo {}\n# in {}\n", args.join(" "), repo.display());
+    log.push_str("\n## stdout\n");
+    log.push_str(&String::from_utf8_lossy(&output.stdout));
+    log.push_str("\n## stderr\n");
+    log.push_str(&String::from_utf8_lossy(&output.stderr));
+    fs::write(&path, log).ok()?;
+    Some(fs::canonicalize(&path).unwrap_or(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_carry_the_stderr_tail_and_a_full_log() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-cargo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(repo.join("Cargo.toml"), "[package\n").unwrap();
+
+        let err = run(&repo, &["generate-lockfile"], true).unwrap_err();
+        let Error::Cargo {
+            exit, stderr, log, ..
+        } = &err
+        else {
+            panic!("expected a cargo failure, got {err:?}");
+        };
+        assert_ne!(*exit, Some(0));
+        assert!(stderr.contains("Cargo.toml"), "{stderr}");
+        let log = log.as_ref().expect("log written");
+        let text = fs::read_to_string(log).unwrap();
+        assert!(text.starts_with("$ cargo generate-lockfile\n"), "{text}");
+        assert!(text.contains(stderr.lines().last().unwrap()), "{text}");
+        assert!(
+            err.to_string().contains(&log.display().to_string()),
+            "{err}"
+        );
+
+        fs::remove_file(log).unwrap();
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+//! Target sections keyed by equivalent `cfg()` expressions.
+//!
+//! `[target.'cfg(any(unix, windows))'.dependencies]` and
+//! `[target."cfg(any(windows,unix))".dependencies]` select the same targets,
+//! but as table keys they differ, so a branch respelling the predicate (by
+//! hand or with a formatter) looked like one deleted section and one added.
+//! Before merging, target keys are parsed as cfg expressions and compared
+//! by meaning: whitespace, the order and repetition of `all()`/`any()`
+//! arguments, nested `all()`/`any()`, double negation and aliases such as
+//! `unix` for `target_family = "unix"` make no difference. Base's and
+//! theirs' key is then renamed to ours' spelling, or base's to theirs' when
+//! ours has no such section. Target triples, keys that do not parse and
+//! inputs with two equivalent keys are left as written.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use cfg_expr::Expression;
+use cfg_expr::expr::Logic;
+use toml::Value;
+
+use crate::merge::{Resolution, Side};
+
+/// Renames equivalent `cfg()` keys of `[target]` in `base` and `theirs` to
+/// the spelling ours (else theirs) uses. Returns the resolutions: one per
+/// section ours and theirs spelled differently.
+pub(crate) fn unify(base: &mut Value, ours: &mut Value, theirs: &mut Value) -> Vec<Resolution> {
+    let [base_keys, ours_keys, theirs_keys] = [&*base, &*ours, &*theirs].map(cfg_keys);
+    let mut meanings: Vec<&String> = [&base_keys, &ours_keys, &theirs_keys]
+        .into_iter()
+        .flatten()
+        .map(|(meaning, _)| meaning)
+        .collect();
+    meanings.sort_unstable();
+    meanings.dedup();
+
+    let mut resolutions = Vec::new();
+    for meaning in meanings {
+        let spellings = [&base_keys, &ours_keys, &theirs_keys].map(|keys| {
+            keys.iter()
+                .filter(|(other, _)| other == meaning)
+                .map(|(_, key)| key.as_str())
+                .collect::<Vec<_>>()
+        });
+        if spellings.iter().any(|keys| keys.len() > 1) {
+            continue;
+        }
+        let [base_key, ours_key, theirs_key] = spellings.map(|keys| keys.first().copied());
+        let Some(chosen) = ours_key.or(theirs_key).or(base_key) else {
+            continue;
+        };
+        for (doc, key) in [(&mut *base, base_key), (&mut *theirs, theirs_key)] {
+            if let Some(key) = key
+                && key != chosen
+                && let Some(targets) = doc.get_mut("target").and_then(Value::as_table_mut)
+                && let Some(section) = targets.remove(key)
+            {
+                targets.insert(chosen.to_string(), section);
+            }
+        }
+        if ours_key.is_some() && theirs_key.is_some_and(|key| key != chosen) {
+            resolutions.push(Resolution {
+                path: format!("target.{chosen}"),
+                side: Some(Side::Ours),
+                rule: "cfg",
+            });
+        }
+    }
+    resolutions
+}
+
+/// The `cfg()` keys of `doc`'s `[target]` table with their meaning.
+fn cfg_keys(doc: &Value) -> Vec<(String, String)> {
+    doc.get("target")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, _)| Some((meaning(key)?, key.clone())))
+        .collect()
+}
+
+/// A normal form of the `cfg()` expression `key`, equal for equivalent
+/// expressions; `None` for anything else.
+fn meaning(key: &str) -> Option<String> {
+    let key = key.trim();
+    if !key.starts_with("cfg(") {
+        return None;
+    }
+    let expression = Expression::parse(key).ok()?;
+    let normal = expression.eval(|predicate| Normal::Predicate(format!("{predicate:?}")));
+    Some(normal.collapse().to_string())
+}
+
+/// A cfg expression with `all()`/`any()` arguments as sets.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Normal {
+    Predicate(String),
+    All(BTreeSet<Normal>),
+    Any(BTreeSet<Normal>),
+    Not(Box<Normal>),
+}
+
+impl Normal {
+    /// `all(x)` and `any(x)` as `x`.
+    fn collapse(self) -> Normal {
+        match self {
+            Normal::All(items) | Normal::Any(items) if items.len() == 1 => {
+                items.into_iter().next().expect("one item")
+            }
+            other => other,
+        }
+    }
+}
+
+impl Logic for Normal {
+    fn top() -> Self {
+        Normal::All(BTreeSet::new())
+    }
+
+    fn bottom() -> Self {
+        Normal::Any(BTreeSet::new())
+    }
+
+    fn and(self, other: Self) -> Self {
+        let mut items = match self {
+            Normal::All(items) => items,
+            other => BTreeSet::from([other.collapse()]),
+        };
+        match other.collapse() {
+            Normal::All(nested) => items.extend(nested),
+            other => {
+                items.insert(other);
+            }
+        }
+        Normal::All(items)
+    }
+
+    fn or(self, other: Self) -> Self {
+        let mut items = match self {
+            Normal::Any(items) => items,
+            other => BTreeSet::from([other.collapse()]),
+        };
+        match other.collapse() {
+            Normal::Any(nested) => items.extend(nested),
+            other => {
+                items.insert(other);
+            }
+        }
+        Normal::Any(items)
+    }
+
+    fn not(self) -> Self {
+        match self.collapse() {
+            Normal::Not(inner) => *inner,
+            other => Normal::Not(Box::new(other)),
+        }
+    }
+}
+
+impl fmt::Display for Normal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, items: &BTreeSet<Normal>| {
+            let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+            write!(f, "{name}({})", items.join(","))
+        };
+        match self {
+            Normal::Predicate(predicate) => f.write_str(predicate),
+            Normal::All(items) => list(f, "all", items),
+            Normal::Any(items) => list(f, "any", items),
+            Normal::Not(inner) => write!(f, "not({inner})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge_manifest_texts_detailed;
+    use crate::timings::Timings;
+
+    #[test]
+    fn compares_cfg_keys_by_meaning() {
+        let same = [
+            ("cfg(unix)", "cfg( unix )"),
+            ("cfg(unix)", "cfg(target_family = \"unix\")"),
+            ("cfg(any(unix, windows))", "cfg(any(windows,unix))"),
+            (
+                "cfg(all(unix, any(a, b)))",
+                "cfg(all(any(b, a), all(unix)))",
+            ),
+            ("cfg(not(not(test)))", "cfg(test)"),
+        ];
+        for (one, other) in same {
+            assert_eq!(meaning(one), meaning(other), "{one} vs {other}");
+        }
+        assert_ne!(
+            meaning("cfg(any(unix, windows))"),
+            meaning("cfg(all(unix, windows))")
+        );
+        assert_eq!(meaning("x86_64-pc-windows-msvc"), None);
+    }
+
+    #[test]
+    fn merges_sections_spelled_differently() {
+        let base = "[package]\nname = \"a\"\n\n[target.'cfg(any(unix, windows))'.dependencies]\nlibc = \"0.2\"\n";
+        let ours = base.replace("0.2\"", "0.2.150\"");
+        let theirs = base.replace("'cfg(any(unix, windows))'", "\"cfg(any(windows,unix))\"")
+            + "rand = \"0.8\"\n";
+
+        let merged = merge_manifest_texts_detailed(
+            base,
+            &ours,
+            &theirs,
+            &Default::default(),
+            &mut Timings::default(),
+        )
+        .unwrap();
+        assert_eq!(merged.text, ours + "rand = \"0.8\"\n");
+        assert_eq!(merged.resolutions.len(), 1);
+        assert_eq!(merged.resolutions[0].rule, "cfg");
+    }
+}
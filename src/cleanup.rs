@@ -0,0 +1,235 @@
+//! Rollback of in-progress writes when the process is interrupted.
+//!
+//! Work that leaves files in an intermediate state registers how to undo it:
+//! temp files to remove, or originals to restore. The binary runs
+//! [`run_pending`] from its SIGINT/SIGTERM handler, so a Ctrl-C (or git killing
+//! the driver) does not leave partial outputs or stray temp files behind.
+//! Registrations are dropped as soon as the work completes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+enum Action {
+    Remove(PathBuf),
+    RemoveDir(PathBuf),
+    Restore {
+        path: PathBuf,
+        original: Option<Vec<u8>>,
+    },
+}
+
+struct Registry {
+    next_id: u64,
+    actions: Vec<(u64, Action)>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            next_id: 0,
+            actions: Vec::new(),
+        }
+    }
+
+    fn register(&mut self, action: Action) -> u64 {
+        self.next_id += 1;
+        self.actions.push((self.next_id, action));
+        self.next_id
+    }
+
+    fn unregister(&mut self, id: u64) {
+        self.actions.retain(|(entry, _)| *entry != id);
+    }
+
+    /// Undoes every registered action, newest first.
+    fn run(&mut self) {
+        for (_, action) in self.actions.drain(..).rev() {
+            match action {
+                Action::Remove(path) => {
+                    let _ = fs::remove_file(path);
+                }
+                Action::RemoveDir(path) => {
+                    let _ = fs::remove_dir_all(path);
+                }
+                Action::Restore { path, original } => {
+                    let _ = match original {
+                        Some(bytes) => fs::write(&path, bytes),
+                        None => fs::remove_file(&path),
+                    };
+                }
+            }
+        }
+    }
+}
+
+static PENDING: Mutex<Registry> = Mutex::new(Registry::new());
+
+fn with_registry<T>(f: impl FnOnce(&mut Registry) -> T) -> T {
+    let mut registry = PENDING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut registry)
+}
+
+/// Keeps an undo action registered until dropped.
+#[must_use = "the action is unregistered when the guard is dropped"]
+pub struct Pending {
+    id: u64,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        with_registry(|registry| registry.unregister(self.id));
+    }
+}
+
+/// Removes `path` if the process is interrupted while the guard is alive.
+pub fn remove_on_interrupt(path: impl Into<PathBuf>) -> Pending {
+    let id = with_registry(|registry| registry.register(Action::Remove(path.into())));
+    Pending { id }
+}
+
+/// Removes the directory `path` and its contents if the process is
+/// interrupted while the guard is alive.
+pub fn remove_dir_on_interrupt(path: impl Into<PathBuf>) -> Pending {
+    let id = with_registry(|registry| registry.register(Action::RemoveDir(path.into())));
+    Pending { id }
+}
+
+/// Snapshots `path` now and restores the snapshot (or removes the file, if it
+/// did not exist) if the process is interrupted while the guard is alive.
+pub fn restore_on_interrupt(path: impl Into<PathBuf>) -> io::Result<Pending> {
+    let path = path.into();
+    let original = match fs::read(&path) {
+        Ok(bytes) => Some(bytes),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let id = with_registry(|registry| registry.register(Action::Restore { path, original }));
+    Ok(Pending { id })
+}
+
+/// Undoes all registered work. Called from the interrupt handler.
+pub fn run_pending() {
+    with_registry(Registry::run);
+}
+
+/// Writes `contents` to `path` via a sibling temp file and a rename, so readers
+/// (and interruptions) only ever see the old or the new file.
+///
+/// Symlinks are written through to their target, and the existing file's
+/// permissions are kept.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink());
+    let target = if is_symlink {
+        fs::canonicalize(path)?
+    } else {
+        path.to_path_buf()
+    };
+
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".cargo-merge-assist-{}.tmp", std::process::id()));
+    let staged = target.with_file_name(name);
+
+    let _guard = remove_on_interrupt(&staged);
+    let result = fs::write(&staged, contents).and_then(|()| {
+        if let Ok(meta) = fs::metadata(&target) {
+            fs::set_permissions(&staged, meta.permissions())?;
+        }
+        fs::rename(&staged, &target)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-cleanup-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn registry_removes_temps_and_restores_originals() {
+        let dir = scratch_dir("registry");
+        let temp = dir.join("partial.tmp");
+        let edited = dir.join("Cargo.lock");
+        let created = dir.join("new.lock");
+        fs::write(&temp, "partial").unwrap();
+        fs::write(&edited, "half-written").unwrap();
+        fs::write(&created, "half-written").unwrap();
+
+        let mut registry = Registry::new();
+        registry.register(Action::Remove(temp.clone()));
+        registry.register(Action::Restore {
+            path: edited.clone(),
+            original: Some(b"original".to_vec()),
+        });
+        registry.register(Action::Restore {
+            path: created.clone(),
+            original: None,
+        });
+        registry.run();
+
+        assert!(!temp.exists());
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "original");
+        assert!(!created.exists());
+        assert!(registry.actions.is_empty());
+    }
+
+    #[test]
+    fn finished_work_is_unregistered() {
+        let mut registry = Registry::new();
+        let id = registry.register(Action::Remove(PathBuf::from("/nonexistent")));
+        registry.unregister(id);
+        assert!(registry.actions.is_empty());
+    }
+
+    #[test]
+    fn atomic_write_replaces_file_without_leftovers() {
+        let dir = scratch_dir("atomic");
+        let path = dir.join("Cargo.toml");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let leftovers: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_write_follows_symlinks() {
+        let dir = scratch_dir("symlink");
+        let real = dir.join("real.toml");
+        let link = dir.join("link.toml");
+        fs::write(&real, "old").unwrap();
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        write_atomic(&link, "new").unwrap();
+
+        assert!(
+            fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&real).unwrap(), "new");
+    }
+}
@@ -0,0 +1,453 @@
+//! Grouping conflicts that concern the same dependency.
+//!
+//! A dependency is often declared in several places: `[dependencies]`,
+//! `[dev-dependencies]`, a `[target.*]` table and `[workspace.dependencies]`.
+//! A version bump race then conflicts in all of them at once. The merge
+//! reports the first conflict it meets with the others attached as
+//! [`MergeConflict::related`], so they read, and can be decided, as one; with
+//! [`MergeOptions::all_conflicts`] each cluster is one entry of the report.
+//!
+//! Automatic resolutions are also made per cluster: when heuristics resolve
+//! any member toward one side, every conflicting member takes that side, and
+//! when they pick different sides for different members the whole cluster
+//! stays a conflict. With [`MergeOptions::build_min_confidence`] set, a
+//! crate's build dependency entries form a cluster of their own.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+use crate::merge::{
+    MergeConflict, MergeError, MergeOptions, Merged, Proposal, Resolution, Side, merge_value,
+};
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The side chosen for each dependency cluster; `None` when heuristics chose
+/// different sides for different members.
+#[derive(Debug, Default)]
+pub(crate) struct Decisions {
+    sides: RefCell<BTreeMap<String, Option<Side>>>,
+    recording: bool,
+    /// Cluster build dependencies apart from the crate's other entries.
+    isolate_build: bool,
+    resolutions: RefCell<Vec<Resolution>>,
+}
+
+impl Decisions {
+    /// Resolves a conflicting value given the heuristic `proposal` accepted
+    /// for it, if any. `None` leaves it a conflict.
+    pub(crate) fn resolve<'a>(
+        &self,
+        path: &[&str],
+        ours: Option<&'a Value>,
+        theirs: Option<&'a Value>,
+        proposal: Option<Proposal>,
+    ) -> Option<Option<Merged<'a>>> {
+        let Some(name) = cluster_of(path, self.isolate_build) else {
+            return proposal.map(|proposal| self.accept(path, ours, theirs, proposal));
+        };
+
+        if self.recording {
+            let side = proposal
+                .as_ref()
+                .and_then(|proposal| side_of(proposal, ours, theirs));
+            if let Some(side) = side {
+                let mut sides = self.sides.borrow_mut();
+                let decided = sides.entry(name).or_insert(Some(side));
+                if *decided != Some(side) {
+                    *decided = None;
+                }
+            }
+            // Keep walking past unresolved conflicts so every member is seen;
+            // the recording pass's output is discarded.
+            return Some(None);
+        }
+
+        let decided = self.sides.borrow().get(&name).copied();
+        match decided {
+            Some(Some(side)) => {
+                let rule = proposal
+                    .filter(|proposal| side_of(proposal, ours, theirs) == Some(side))
+                    .map_or("cluster", |proposal| proposal.rule);
+                self.record(path, Some(side), rule);
+                Some(match side {
+                    Side::Theirs => theirs.map(Merged::Borrowed),
+                    _ => ours.map(Merged::Borrowed),
+                })
+            }
+            Some(None) => None,
+            None => proposal.map(|proposal| self.accept(path, ours, theirs, proposal)),
+        }
+    }
+
+    /// Automatic resolutions made so far, in merge order.
+    pub(crate) fn resolutions(&self) -> Vec<Resolution> {
+        self.resolutions.borrow().clone()
+    }
+
+    fn accept<'a>(
+        &self,
+        path: &[&str],
+        ours: Option<&'a Value>,
+        theirs: Option<&'a Value>,
+        proposal: Proposal,
+    ) -> Option<Merged<'a>> {
+        self.record(path, side_of(&proposal, ours, theirs), proposal.rule);
+        proposal.value.map(Merged::Owned)
+    }
+
+    fn record(&self, path: &[&str], side: Option<Side>, rule: &'static str) {
+        if !self.recording {
+            self.resolutions.borrow_mut().push(Resolution {
+                path: path.join("."),
+                side,
+                rule,
+            });
+        }
+    }
+}
+
+/// The side whose value `proposal` takes, if either.
+fn side_of(proposal: &Proposal, ours: Option<&Value>, theirs: Option<&Value>) -> Option<Side> {
+    if proposal.value.as_ref() == ours {
+        Some(Side::Ours)
+    } else if proposal.value.as_ref() == theirs {
+        Some(Side::Theirs)
+    } else {
+        None
+    }
+}
+
+/// Runs the heuristics over every dependency entry and settles one side per
+/// cluster before the real merge.
+pub(crate) fn decide(
+    options: &MergeOptions,
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+) -> Decisions {
+    let mut decisions = Decisions {
+        isolate_build: options.build_min_confidence.is_some(),
+        ..Decisions::default()
+    };
+    if options.heuristics.is_empty() && options.strategies.is_empty() {
+        return decisions;
+    }
+    decisions.recording = true;
+    for_each_entry(base, ours, theirs, |path, b, o, t| {
+        let _ = merge_value(options, &decisions, path, b, o, t, None);
+    });
+    decisions.recording = false;
+    decisions
+}
+
+/// Conflicts on the same dependency as `primary` elsewhere in the documents.
+pub(crate) fn related_conflicts<'a>(
+    options: &MergeOptions,
+    decisions: &Decisions,
+    base: &'a Value,
+    ours: &'a Value,
+    theirs: &'a Value,
+    primary: &MergeConflict,
+) -> Vec<MergeConflict> {
+    let mut conflicts = Vec::new();
+    for_each_entry(base, ours, theirs, |path, b, o, t| {
+        let name = path[path.len() - 1];
+        if let Err(MergeError::Conflict(conflict)) =
+            merge_value(options, decisions, path, b, o, t, None)
+        {
+            conflicts.push((name, conflict));
+        }
+    });
+
+    let Some(name) = conflicts
+        .iter()
+        .find(|(_, conflict)| conflict.path == primary.path)
+        .map(|(name, _)| *name)
+    else {
+        return Vec::new();
+    };
+    conflicts
+        .into_iter()
+        .filter(|(other, conflict)| *other == name && conflict.path != primary.path)
+        .map(|(_, conflict)| conflict)
+        .collect()
+}
+
+/// Folds the conflicts of an all-conflicts report into one per cluster: the
+/// first member met carries the others as [`MergeConflict::related`].
+pub(crate) fn group(options: &MergeOptions, conflicts: Vec<MergeConflict>) -> Vec<MergeConflict> {
+    let isolate_build = options.build_min_confidence.is_some();
+    let mut grouped: Vec<MergeConflict> = Vec::new();
+    let mut primaries: BTreeMap<String, usize> = BTreeMap::new();
+    for conflict in conflicts {
+        let segments = segments(&conflict.path);
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        match cluster_of(&segments, isolate_build) {
+            Some(name) if primaries.contains_key(&name) => {
+                grouped[primaries[&name]].related.push(conflict);
+            }
+            Some(name) => {
+                primaries.insert(name, grouped.len());
+                grouped.push(conflict);
+            }
+            None => grouped.push(conflict),
+        }
+    }
+    grouped
+}
+
+/// Splits a conflict's dotted path back into keys, keeping a `[target.*]`
+/// key whole even when it contains dots.
+fn segments(path: &str) -> Vec<String> {
+    let keys: Vec<&str> = path.split('.').collect();
+    if keys.first() != Some(&"target") {
+        return keys.into_iter().map(str::to_string).collect();
+    }
+    let Some(table) = keys
+        .iter()
+        .skip(2)
+        .position(|key| DEPENDENCY_TABLES.contains(key))
+        .map(|at| at + 2)
+    else {
+        return keys.into_iter().map(str::to_string).collect();
+    };
+    let mut segments = vec![keys[0].to_string(), keys[1..table].join(".")];
+    segments.extend(keys[table..].iter().map(|key| key.to_string()));
+    segments
+}
+
+/// The cluster a path belongs to: its dependency, kept apart for build
+/// dependencies when `isolate_build` is set.
+fn cluster_of(path: &[&str], isolate_build: bool) -> Option<String> {
+    let name = dependency_of(path)?;
+    Some(if isolate_build && is_build_dependency(path) {
+        format!("build-dependencies.{name}")
+    } else {
+        name.to_string()
+    })
+}
+
+/// The dependency a path points into, e.g. `libc` for
+/// `target.cfg(unix).dependencies.libc.version`.
+pub(crate) fn dependency_of<'a>(path: &[&'a str]) -> Option<&'a str> {
+    let path = match path {
+        ["target", _, rest @ ..] | ["workspace", rest @ ..] => rest,
+        _ => path,
+    };
+    match path {
+        [table, name, ..] if DEPENDENCY_TABLES.contains(table) => Some(name),
+        _ => None,
+    }
+}
+
+/// Whether `path` points into a `[build-dependencies]` table.
+pub(crate) fn is_build_dependency(path: &[&str]) -> bool {
+    let path = match path {
+        ["target", _, rest @ ..] | ["workspace", rest @ ..] => rest,
+        _ => path,
+    };
+    path.first() == Some(&"build-dependencies")
+}
+
+/// Calls `f` with the path and base/ours/theirs values of every dependency
+/// entry in any of the documents.
+fn for_each_entry<'a>(
+    base: &'a Value,
+    ours: &'a Value,
+    theirs: &'a Value,
+    mut f: impl FnMut(&mut Vec<&'a str>, Option<&'a Value>, Option<&'a Value>, Option<&'a Value>),
+) {
+    for table in dependency_tables([base, ours, theirs]) {
+        let tables = [base, ours, theirs].map(|doc| {
+            table
+                .iter()
+                .try_fold(doc, |value, key| value.get(key))
+                .and_then(Value::as_table)
+        });
+        let mut names: Vec<&str> = tables
+            .iter()
+            .flatten()
+            .flat_map(|table| table.keys().map(String::as_str))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        for name in names {
+            let [b, o, t] = tables.map(|table| table.and_then(|table| table.get(name)));
+            let mut path = table.clone();
+            path.push(name);
+            f(&mut path, b, o, t);
+        }
+    }
+}
+
+/// Key paths of every dependency table present in any of `docs`.
+fn dependency_tables(docs: [&Value; 3]) -> Vec<Vec<&str>> {
+    let mut tables: Vec<Vec<&str>> = DEPENDENCY_TABLES.iter().map(|t| vec![*t]).collect();
+    tables.push(vec!["workspace", "dependencies"]);
+    let mut targets: Vec<&str> = docs
+        .iter()
+        .filter_map(|doc| doc.get("target").and_then(Value::as_table))
+        .flat_map(|targets| targets.keys().map(String::as_str))
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    for target in targets {
+        tables.extend(DEPENDENCY_TABLES.iter().map(|t| vec!["target", target, *t]));
+    }
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use crate::merge::{
+        MergeError, MergeOptions, Proposal, merge_manifest_texts, merge_manifest_texts_with,
+    };
+
+    #[test]
+    fn attaches_conflicts_on_the_same_dependency_elsewhere() {
+        let base = r#"
+[dependencies]
+serde = "1.0.100"
+log = "0.4"
+
+[dev-dependencies]
+serde = { version = "1.0.100", features = ["derive"] }
+
+[target.'cfg(unix)'.dependencies]
+serde = "1.0.100"
+
+[workspace.dependencies]
+serde = "1.0.100"
+"#;
+        let ours = base.replace("1.0.100", "1.0.200").replace("0.4", "0.4.20");
+        let theirs = base.replace("1.0.100", "1.0.199").replace("0.4", "0.4.21");
+
+        let err = merge_manifest_texts(base, &ours, &theirs).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.log");
+        assert!(conflict.related.is_empty(), "{conflict}");
+
+        let ours = base.replace("1.0.100", "1.0.200");
+        let theirs = base.replace("1.0.100", "1.0.199");
+        let err = merge_manifest_texts(base, &ours, &theirs).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.serde");
+        let related: Vec<_> = conflict.related.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            related,
+            [
+                "dev-dependencies.serde.version",
+                "workspace.dependencies.serde",
+                "target.cfg(unix).dependencies.serde",
+            ]
+        );
+        assert!(conflict.to_string().contains("also conflicts at"));
+    }
+
+    #[test]
+    fn groups_all_conflicts_reports_by_cluster() {
+        let base = r#"
+[package]
+version = "0.1.0"
+
+[dependencies]
+serde = "1.0.100"
+
+[dev-dependencies]
+serde = { version = "1.0.100", features = ["derive"] }
+
+[target.'cfg(target_os = "linux")'.dependencies]
+serde = "1.0.100"
+"#;
+        let ours = base.replace("1.0.100", "1.0.200").replace("0.1.0", "0.2.0");
+        let theirs = base.replace("1.0.100", "1.0.199").replace("0.1.0", "0.3.0");
+        let options = MergeOptions {
+            all_conflicts: true,
+            highest_version: false,
+            ..MergeOptions::default()
+        };
+
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Report(report) = err else {
+            panic!("expected a report, got {err:?}");
+        };
+        let paths: Vec<&str> = report.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["dependencies.serde", "package.version"]);
+        let related: Vec<_> = report.conflicts[0]
+            .related
+            .iter()
+            .map(|c| c.path.as_str())
+            .collect();
+        assert_eq!(
+            related,
+            [
+                "dev-dependencies.serde.version",
+                "target.cfg(target_os = \"linux\").dependencies.serde",
+            ]
+        );
+        assert!(report.conflicts[1].related.is_empty());
+        assert!(report.to_string().starts_with("2 semantic conflicts\n"));
+    }
+
+    #[test]
+    fn applies_one_heuristic_decision_to_the_whole_cluster() {
+        // Only resolves plain-string requirements, preferring the higher one.
+        fn higher_string(
+            _path: &[&str],
+            _base: Option<&Value>,
+            ours: Option<&Value>,
+            theirs: Option<&Value>,
+        ) -> Option<Proposal> {
+            let (o, t) = (ours?.as_str()?, theirs?.as_str()?);
+            Some(Proposal {
+                value: Some(Value::String(o.max(t).to_string())),
+                rule: "higher",
+                confidence: 1.0,
+            })
+        }
+
+        let base = r#"
+[dependencies]
+serde = "1.0.100"
+
+[dev-dependencies]
+serde = { version = "1.0.100", features = ["derive"] }
+"#;
+        let ours = base
+            .replace("1.0.100", "1.0.200")
+            .replace("[\"derive\"]", "[\"derive\", \"std\"]");
+        let theirs = base
+            .replace("1.0.100", "1.0.199")
+            .replace("[\"derive\"]", "[\"rc\"]");
+        let options = MergeOptions {
+            heuristics: vec![higher_string],
+            ..MergeOptions::default()
+        };
+
+        // No heuristic handles the conflicting feature lists; they follow the
+        // decision made for the version requirements and take ours.
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("serde = \"1.0.200\""), "{merged}");
+        assert!(!merged.contains("1.0.199"), "{merged}");
+        assert!(merged.contains("\"std\""), "{merged}");
+        assert!(!merged.contains("\"rc\""), "{merged}");
+
+        // Members resolved toward different sides leave the cluster unresolved.
+        let theirs = theirs.replace(
+            "serde = { version = \"1.0.199\"",
+            "serde = { version = \"1.0.300\"",
+        );
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,277 @@
+//! 3-way merge for `.cargo/config.toml`.
+//!
+//! The file is merged table by table like a manifest, with extra rules for
+//! the sections that conflict in practice:
+//!
+//! - `[env]`: variables merge independently. A variable written as a plain
+//!   string on one side and as `{ value, force, relative }` on the other is
+//!   merged field by field, so one branch can set `force = true` while the
+//!   other changes the value.
+//! - `[build]`, `[target.<triple>]`, `[host]`: `rustflags` and `rustdocflags`
+//!   changed on both sides become the union of both sides' additions, minus
+//!   what either side removed, without duplicates. A flag and its argument
+//!   (`-C opt-level=3`) count as one flag.
+//! - `[net]`: `retry` changed on both sides takes the larger count, and
+//!   `ssh.known-hosts` entries are united like flags.
+//!
+//! Everything else conflicts as usual when both sides changed it.
+
+use toml::{Table, Value};
+
+use crate::merge::{Document, MergeError, MergeOptions, MergeResult, Proposal, merge_texts};
+use crate::timings::Timings;
+
+/// Merges three versions of a `.cargo/config.toml`. `options.heuristics`
+/// are consulted after the config rules.
+pub fn merge_config_texts(
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+    timings: &mut Timings,
+) -> Result<MergeResult, MergeError> {
+    let mut options = options.clone();
+    options
+        .heuristics
+        .splice(0..0, [env_fields, flag_union, net_retry]);
+    merge_texts(
+        Document::Other,
+        base_text,
+        ours_text,
+        theirs_text,
+        &options,
+        timings,
+    )
+}
+
+/// `[env]` variables in mixed string/table form, merged per field.
+fn env_fields(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let ["env", _] = path else {
+        return None;
+    };
+    let (base, ours, theirs) = (
+        env_table(base)?,
+        env_table(Some(ours?))?,
+        env_table(Some(theirs?))?,
+    );
+    let mut merged = Table::new();
+    for field in ["value", "force", "relative"] {
+        let [b, o, t] = [&base, &ours, &theirs].map(|table| table.get(field));
+        let value = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            return None;
+        };
+        if let Some(value) = value {
+            merged.insert(field.to_string(), value.clone());
+        }
+    }
+    let value = match merged.len() {
+        1 => merged.remove("value")?,
+        _ => Value::Table(merged),
+    };
+    Some(Proposal {
+        value: Some(value),
+        rule: "env-fields",
+        confidence: 1.0,
+    })
+}
+
+/// An `[env]` entry as a table; a missing base entry is an empty table.
+fn env_table(value: Option<&Value>) -> Option<Table> {
+    match value {
+        None => Some(Table::new()),
+        Some(Value::String(value)) => {
+            let mut table = Table::new();
+            table.insert("value".to_string(), Value::String(value.clone()));
+            Some(table)
+        }
+        Some(Value::Table(table)) => Some(table.clone()),
+        Some(_) => None,
+    }
+}
+
+/// Flag lists and `net.ssh.known-hosts`, united.
+fn flag_union(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let flags = match path {
+        ["build" | "host", "rustflags" | "rustdocflags"]
+        | ["target", _, "rustflags" | "rustdocflags"] => true,
+        ["net", "ssh", "known-hosts"] => false,
+        _ => return None,
+    };
+    let (base_items, ours_items, theirs_items) = (
+        items(base, flags)?,
+        items(ours, flags)?,
+        items(theirs, flags)?,
+    );
+
+    let removed = |side: &[Vec<&str>]| {
+        base_items
+            .iter()
+            .filter(|item| !side.contains(item))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let removed = [removed(&ours_items), removed(&theirs_items)].concat();
+    let mut merged: Vec<Vec<&str>> = Vec::new();
+    for item in ours_items.iter().chain(&theirs_items) {
+        if !removed.contains(item) && !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+
+    // Keep the form ours uses (theirs' when ours deleted the key).
+    let value = match ours.or(theirs) {
+        Some(Value::String(_)) => Value::String(merged.concat().join(" ")),
+        _ => Value::Array(merged.concat().into_iter().map(Value::from).collect()),
+    };
+    Some(Proposal {
+        value: Some(value),
+        rule: "flag-union",
+        confidence: 1.0,
+    })
+}
+
+/// The entries of a list, as flags when `flags` is set; flag strings are
+/// split on whitespace like cargo does.
+fn items(value: Option<&Value>, flags: bool) -> Option<Vec<Vec<&str>>> {
+    let tokens: Vec<&str> = match value {
+        None => Vec::new(),
+        Some(Value::String(text)) if flags => text.split_whitespace().collect(),
+        Some(Value::Array(items)) => items.iter().map(Value::as_str).collect::<Option<_>>()?,
+        Some(_) => return None,
+    };
+    Some(if flags {
+        group_flags(tokens)
+    } else {
+        tokens.into_iter().map(|token| vec![token]).collect()
+    })
+}
+
+/// Groups command-line tokens into flags, keeping an option that takes a
+/// separate argument (`-C opt-level=3`, `--cfg foo`) together.
+fn group_flags(tokens: Vec<&str>) -> Vec<Vec<&str>> {
+    let mut flags: Vec<Vec<&str>> = Vec::new();
+    let mut takes_argument = false;
+    for token in tokens {
+        match flags.last_mut() {
+            Some(flag) if takes_argument && !token.starts_with('-') => {
+                flag.push(token);
+                takes_argument = false;
+            }
+            _ => {
+                takes_argument = token.starts_with('-') && !token.contains('=');
+                flags.push(vec![token]);
+            }
+        }
+    }
+    flags
+}
+
+/// `net.retry` set differently on both sides: the larger count.
+fn net_retry(
+    path: &[&str],
+    _base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    if path != ["net", "retry"] {
+        return None;
+    }
+    let retry = ours?.as_integer()?.max(theirs?.as_integer()?);
+    Some(Proposal {
+        value: Some(Value::Integer(retry)),
+        rule: "net-retry-max",
+        confidence: 1.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> Value {
+        let options = MergeOptions::default();
+        let merged =
+            merge_config_texts(base, ours, theirs, &options, &mut Timings::default()).unwrap();
+        merged.text.parse().unwrap()
+    }
+
+    #[test]
+    fn unites_flags_and_known_hosts() {
+        let base = "[build]\nrustflags = [\"-C\", \"target-cpu=native\", \"-Dwarnings\"]\n\n\
+                    [net.ssh]\nknown-hosts = [\"a\"]\n";
+        let ours = "[build]\nrustflags = [\"-C\", \"target-cpu=native\", \"--cfg\", \"tokio_unstable\"]\n\n\
+                    [net.ssh]\nknown-hosts = [\"a\", \"b\"]\n";
+        let theirs = "[build]\nrustflags = [\"-C\", \"target-cpu=native\", \"-Dwarnings\", \"-C\", \"opt-level=3\", \"--cfg\", \"tokio_unstable\"]\n\n\
+                      [net.ssh]\nknown-hosts = [\"c\"]\n";
+        let merged = merge(base, ours, theirs);
+        let flags: Vec<_> = merged["build"]["rustflags"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|flag| flag.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                "-C",
+                "target-cpu=native",
+                "--cfg",
+                "tokio_unstable",
+                "-C",
+                "opt-level=3"
+            ]
+        );
+        assert_eq!(
+            merged["net"]["ssh"]["known-hosts"].as_array().unwrap(),
+            &[Value::from("b"), Value::from("c")]
+        );
+
+        let base = "[target.x86_64-unknown-linux-gnu]\nrustflags = \"-C opt-level=2\"\n";
+        let ours = base.replace("opt-level=2", "opt-level=2 -Dwarnings");
+        let theirs = base.replace("-C opt-level=2", "-C opt-level=2 -C debuginfo=1");
+        let merged = merge(base, &ours, &theirs);
+        assert_eq!(
+            merged["target"]["x86_64-unknown-linux-gnu"]["rustflags"].as_str(),
+            Some("-C opt-level=2 -Dwarnings -C debuginfo=1")
+        );
+    }
+
+    #[test]
+    fn merges_env_fields_and_net_retry() {
+        let base = "[env]\nOPENSSL_DIR = \"/opt/ssl\"\nCC = \"cc\"\n\n[net]\nretry = 2\n";
+        let ours = "[env]\nOPENSSL_DIR = { value = \"/opt/ssl\", force = true }\nCC = \"cc\"\n\n\
+                    [net]\nretry = 5\n";
+        let theirs =
+            "[env]\nOPENSSL_DIR = \"/usr/local/ssl\"\nCC = \"clang\"\n\n[net]\nretry = 3\n";
+        let merged = merge(base, ours, theirs);
+        let openssl = &merged["env"]["OPENSSL_DIR"];
+        assert_eq!(openssl["value"].as_str(), Some("/usr/local/ssl"));
+        assert_eq!(openssl["force"].as_bool(), Some(true));
+        assert_eq!(merged["env"]["CC"].as_str(), Some("clang"));
+        assert_eq!(merged["net"]["retry"].as_integer(), Some(5));
+
+        // Both sides changing the value itself is still a conflict.
+        let ours = base.replace("\"cc\"", "\"gcc\"");
+        let options = MergeOptions::default();
+        let err =
+            merge_config_texts(base, &ours, theirs, &options, &mut Timings::default()).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "env.CC");
+    }
+}
@@ -0,0 +1,232 @@
+//! Deprecated manifest sections.
+//!
+//! Old manifests use `[project]` for `[package]` and
+//! `[dev_dependencies]`/`[build_dependencies]` for the dashed forms, and may
+//! still carry `[badges]`, which crates.io no longer shows. Merged key by
+//! key, a branch that migrated `[project]` to `[package]` against one that
+//! bumped `project.version` is a delete/modify conflict on the version and
+//! leaves both tables behind. Before merging, a section one side migrated to
+//! its current name is renamed in the inputs still using the old name, so
+//! their edits land in the current section. With `migrate` every input is
+//! renamed, migrating the result even when no side did.
+//!
+//! Retired sections are merged conservatively: a side removing `[badges]`
+//! wins over edits to it on the other side.
+
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::merge::{Resolution, Side};
+
+/// Deprecated table names and their current ones. The dependency tables
+/// also appear in `[target.*]`.
+const ALIASES: [(&str, &str); 3] = [
+    ("project", "package"),
+    ("dev_dependencies", "dev-dependencies"),
+    ("build_dependencies", "build-dependencies"),
+];
+
+/// Sections with no effect any more, removed rather than merged.
+const RETIRED: [&str; 1] = ["badges"];
+
+/// Renames deprecated sections of `base`, `ours` and `theirs` to their
+/// current names where a side migrated them (every one with `migrate`),
+/// and drops retired sections a side removed. Returns the resolutions.
+pub(crate) fn normalize(
+    migrate: bool,
+    base: &mut Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    let mut scopes = vec![Vec::new()];
+    let mut targets: Vec<String> = [&*base, &*ours, &*theirs]
+        .iter()
+        .filter_map(|doc| doc.get("target").and_then(Value::as_table))
+        .flat_map(|targets| targets.keys().cloned())
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    scopes.extend(
+        targets
+            .into_iter()
+            .map(|target| vec!["target".to_string(), target]),
+    );
+
+    for scope in &scopes {
+        for (old, new) in ALIASES {
+            let uses = |doc: &Value, key: &str, other: &str| {
+                table_at(doc, scope)
+                    .is_some_and(|table| table.contains_key(key) && !table.contains_key(other))
+            };
+            let [base_old, ours_old, theirs_old] =
+                [&*base, &*ours, &*theirs].map(|doc| uses(doc, old, new));
+            let migrated = [(Side::Ours, &*ours), (Side::Theirs, &*theirs)]
+                .into_iter()
+                .find(|(_, doc)| uses(doc, new, old))
+                .map(|(side, _)| side);
+            if !(migrate || migrated.is_some()) || !(base_old || ours_old || theirs_old) {
+                continue;
+            }
+            for (doc, uses_old) in [
+                (&mut *base, base_old),
+                (&mut *ours, ours_old),
+                (&mut *theirs, theirs_old),
+            ] {
+                if uses_old && let Some(table) = table_at_mut(doc, scope) {
+                    let section = table.remove(old).expect("checked above");
+                    table.insert(new.to_string(), section);
+                }
+            }
+            if ours_old || theirs_old {
+                resolutions.push(Resolution {
+                    path: path(scope, new),
+                    side: migrated,
+                    rule: "deprecated",
+                });
+            }
+        }
+    }
+
+    for key in RETIRED {
+        let [in_base, in_ours, in_theirs] =
+            [&*base, &*ours, &*theirs].map(|doc| doc.get(key).is_some());
+        let remover = match (in_base, in_ours, in_theirs) {
+            (true, false, true) => Side::Ours,
+            (true, true, false) => Side::Theirs,
+            _ => continue,
+        };
+        for doc in [&mut *base, &mut *ours, &mut *theirs] {
+            if let Some(root) = doc.as_table_mut() {
+                root.remove(key);
+            }
+        }
+        resolutions.push(Resolution {
+            path: key.to_string(),
+            side: Some(remover),
+            rule: "deprecated",
+        });
+    }
+    resolutions
+}
+
+/// `text` with the sections renamed in `doc` (ours after [`normalize`])
+/// renamed the same way, each keeping its place, and the retired sections
+/// dropped from `doc` removed; `None` when nothing changed.
+pub(crate) fn apply_to_text(text: &str, doc: &Value) -> Option<String> {
+    let mut edited: DocumentMut = text.parse().ok()?;
+    let mut changed = false;
+    for key in RETIRED {
+        if doc.get(key).is_none() && edited.remove(key).is_some() {
+            changed = true;
+        }
+    }
+    let mut scopes: Vec<Vec<String>> = vec![Vec::new()];
+    if let Some(targets) = doc.get("target").and_then(Value::as_table) {
+        scopes.extend(
+            targets
+                .keys()
+                .map(|target| vec!["target".to_string(), target.clone()]),
+        );
+    }
+    for scope in &scopes {
+        let Some(table) = table_at(doc, scope) else {
+            continue;
+        };
+        let Some(section) = scope
+            .iter()
+            .try_fold(edited.as_table_mut() as &mut dyn TableLike, |table, key| {
+                table.get_mut(key)?.as_table_like_mut()
+            })
+        else {
+            continue;
+        };
+        for (old, new) in ALIASES {
+            if table.contains_key(new)
+                && !table.contains_key(old)
+                && section.contains_key(old)
+                && !section.contains_key(new)
+                && let Some(item) = section.remove(old)
+            {
+                section.insert(new, item);
+                changed = true;
+            }
+        }
+    }
+    changed.then(|| edited.to_string())
+}
+
+fn path(scope: &[String], key: &str) -> String {
+    scope
+        .iter()
+        .map(String::as_str)
+        .chain([key])
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn table_at<'a>(doc: &'a Value, scope: &[String]) -> Option<&'a Table> {
+    scope
+        .iter()
+        .try_fold(doc, |value, key| value.get(key))?
+        .as_table()
+}
+
+fn table_at_mut<'a>(doc: &'a mut Value, scope: &[String]) -> Option<&'a mut Table> {
+    scope
+        .iter()
+        .try_fold(doc, |value, key| value.get_mut(key))?
+        .as_table_mut()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeOptions, merge_manifest_texts_detailed, merge_manifest_texts_with};
+    use crate::timings::Timings;
+
+    const BASE: &str = "[project]\nname = \"old\"\nversion = \"0.1.0\"\n\n\
+                        [dev_dependencies]\nlog = \"0.4\"\n\n\
+                        [badges]\nmaintenance = { status = \"actively-developed\" }\n";
+
+    #[test]
+    fn merges_edits_to_deprecated_sections_into_migrated_ones() {
+        let migrated = BASE
+            .replace("[project]", "[package]")
+            .replace("[dev_dependencies]", "[dev-dependencies]")
+            .replace(
+                "\n[badges]\nmaintenance = { status = \"actively-developed\" }\n",
+                "",
+            );
+        let edited = BASE
+            .replace("0.1.0", "0.2.0")
+            .replace("0.4", "0.4.21")
+            .replace("actively-developed", "passively-maintained");
+
+        let merged = merge_manifest_texts_detailed(
+            BASE,
+            &edited,
+            &migrated,
+            &MergeOptions::default(),
+            &mut Timings::default(),
+        )
+        .unwrap();
+        assert!(merged.text.starts_with("[package]\n"), "{}", merged.text);
+        let doc: toml::Value = merged.text.parse().unwrap();
+        assert_eq!(doc["package"]["version"].as_str(), Some("0.2.0"));
+        assert_eq!(doc["dev-dependencies"]["log"].as_str(), Some("0.4.21"));
+        for key in ["project", "dev_dependencies", "badges"] {
+            assert!(doc.get(key).is_none(), "{key} in {}", merged.text);
+        }
+        assert_eq!(merged.resolutions.len(), 3);
+
+        // Without a migrating side the deprecated names stay, unless asked.
+        let mut options = MergeOptions::default();
+        let theirs = BASE.replace("name = \"old\"", "name = \"new\"");
+        let merged = merge_manifest_texts_with(BASE, &edited, &theirs, &options).unwrap();
+        assert!(merged.starts_with("[project]\nname = \"new\""), "{merged}");
+        options.migrate_deprecated = true;
+        let merged = merge_manifest_texts_with(BASE, BASE, &theirs, &options).unwrap();
+        assert!(merged.contains("[package]\nname = \"new\""), "{merged}");
+        assert!(!merged.contains("[project]"), "{merged}");
+    }
+}
@@ -0,0 +1,171 @@
+//! Finding the manifests a repository-wide command should touch.
+//!
+//! Scans skip what is not part of the workspace being merged: anything
+//! `.gitignore`d, build output (`target/`), vendored sources (`vendor/`),
+//! git submodules and nested repositories (directories with their own
+//! `.git`), and the `exclude` patterns of the merge policy. Policy `include`
+//! patterns take precedence over the built-in and policy excludes, e.g. to
+//! merge a patched crate kept under `vendor/`. Patterns use `.gitignore`
+//! syntax and are relative to the scanned root.
+
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Directories skipped unless a policy `include` pattern selects them.
+pub const DEFAULT_EXCLUDES: [&str; 2] = ["target/", "vendor/"];
+
+/// Which paths under a root are out of scope.
+#[derive(Debug, Clone)]
+pub struct Excludes {
+    root: PathBuf,
+    matcher: Gitignore,
+    has_includes: bool,
+}
+
+impl Excludes {
+    /// The built-in excludes plus `exclude`, overridden by `include`.
+    pub fn new(root: &Path, exclude: &[String], include: &[String]) -> Result<Self, String> {
+        let mut builder = GitignoreBuilder::new(root);
+        let includes = include.iter().map(|pattern| format!("!{pattern}"));
+        let lines = DEFAULT_EXCLUDES
+            .into_iter()
+            .map(str::to_string)
+            .chain(exclude.iter().cloned())
+            .chain(includes);
+        for line in lines {
+            builder
+                .add_line(None, &line)
+                .map_err(|err| format!("invalid exclude pattern `{line}`: {err}"))?;
+        }
+        let matcher = builder
+            .build()
+            .map_err(|err| format!("invalid exclude patterns: {err}"))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            matcher,
+            has_includes: !include.is_empty(),
+        })
+    }
+
+    /// The directory scanned, and the base of relative paths and patterns.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether `path` (under the root, or relative to it) is out of scope.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let relative = path
+            .strip_prefix(&self.root)
+            .or_else(|_| path.strip_prefix("."))
+            .unwrap_or(path);
+        if relative.has_root() {
+            return false;
+        }
+        if self
+            .matcher
+            .matched_path_or_any_parents(relative, false)
+            .is_ignore()
+        {
+            return true;
+        }
+        relative
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| self.root.join(dir).join(".git").exists())
+    }
+
+    /// Every `Cargo.toml` under the root that is in scope, sorted.
+    pub fn manifests(&self) -> Vec<PathBuf> {
+        let root = self.root.clone();
+        let matcher = self.matcher.clone();
+        let prune = !self.has_includes;
+        let mut manifests: Vec<PathBuf> = WalkBuilder::new(&self.root)
+            .require_git(false)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+                if !is_dir || entry.depth() == 0 {
+                    return true;
+                }
+                // Nested repositories and submodules are merged on their own.
+                if entry.path().join(".git").exists() {
+                    return false;
+                }
+                // With includes a pruned directory could hide selected paths.
+                let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                !(prune && matcher.matched(relative, true).is_ignore())
+            })
+            .build()
+            .filter_map(Result::ok)
+            .map(ignore::DirEntry::into_path)
+            .filter(|path| path.file_name().is_some_and(|name| name == "Cargo.toml"))
+            .filter(|path| !self.is_excluded(path))
+            .collect();
+        manifests.sort();
+        manifests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn skips_build_output_vendored_sources_and_nested_repositories() {
+        let root = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-discover-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [
+            "",
+            "crates/a",
+            "target/package/a",
+            "vendor/serde",
+            "vendor/patched",
+            "third_party/sub",
+            "legacy/old",
+            "ignored",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("Cargo.toml"), "[package]\n").unwrap();
+        }
+        fs::write(
+            root.join("third_party/sub/.git"),
+            "gitdir: ../../.git/modules/sub\n",
+        )
+        .unwrap();
+        fs::write(root.join(".gitignore"), "/ignored/\n").unwrap();
+
+        let excludes = Excludes::new(
+            &root,
+            &["legacy/".to_string()],
+            &["vendor/patched/".to_string()],
+        )
+        .unwrap();
+        let found: Vec<_> = excludes
+            .manifests()
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            found,
+            [
+                PathBuf::from("Cargo.toml"),
+                PathBuf::from("crates/a/Cargo.toml"),
+                PathBuf::from("vendor/patched/Cargo.toml"),
+            ]
+        );
+
+        assert!(excludes.is_excluded(&root.join("target/package/a/Cargo.toml")));
+        assert!(excludes.is_excluded(&root.join("third_party/sub/Cargo.toml")));
+        assert!(excludes.is_excluded(Path::new("legacy/old/Cargo.toml")));
+        assert!(!excludes.is_excluded(&root.join("crates/a/Cargo.toml")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
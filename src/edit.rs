@@ -0,0 +1,161 @@
+//! Guided resolution of conflicts in an editor (`edit`).
+//!
+//! [`provisional_merge`] merges as far as possible, keeping ours wherever
+//! the sides conflict, and [`annotate`] puts one commented block per
+//! conflict at the top of the result:
+//!
+//! ```toml
+//! # TODO(cargo-merge-assist): resolve `dependencies.serde` (id c8fe904205d0c901)
+//! #   base  : "1.0.100"
+//! #   ours  : "1.0.200"  <- in the file below
+//! #   theirs: "1.0.199"
+//! ```
+//!
+//! The user edits the values and deletes each block once it is resolved;
+//! [`validate`] accepts the file when it is valid TOML without blocks left.
+
+use crate::merge::{MergeConflict, MergeError, MergeOptions, Side, merge_manifest_texts_with};
+use crate::suppress::Suppression;
+
+/// Starts the first line of every conflict block.
+pub const TODO_MARKER: &str = "# TODO(cargo-merge-assist):";
+
+/// Upper bound on conflicts collected, in case a resolution keeps producing
+/// new ones.
+const MAX_CONFLICTS: usize = 1000;
+
+/// Merges with every conflict provisionally resolved to ours. Returns the
+/// merged text and the conflicts, in the order they were found.
+pub fn provisional_merge(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+) -> Result<(String, Vec<MergeConflict>), MergeError> {
+    provisional_merge_to(Side::Ours, base, ours, theirs, options)
+}
+
+/// Like [`provisional_merge`], resolving conflicts to `side` instead.
+pub fn provisional_merge_to(
+    side: Side,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+) -> Result<(String, Vec<MergeConflict>), MergeError> {
+    let mut options = options.clone();
+    // Conflicts are taken one at a time below.
+    options.all_conflicts = false;
+    let mut conflicts = Vec::new();
+    loop {
+        match merge_manifest_texts_with(base, ours, theirs, &options) {
+            Ok(merged) => return Ok((merged, conflicts)),
+            Err(MergeError::Conflict(conflict))
+                if conflicts.len() < MAX_CONFLICTS
+                    && !options
+                        .suppressions
+                        .iter()
+                        .any(|known| known.fingerprint == conflict.fingerprint) =>
+            {
+                options.suppressions.push(Suppression {
+                    fingerprint: conflict.fingerprint.clone(),
+                    side,
+                });
+                conflicts.push(conflict);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// `merged` with a TODO block per conflict in front of it.
+pub fn annotate(merged: &str, conflicts: &[MergeConflict]) -> String {
+    let newline = if merged.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let value =
+        |value: Option<&toml::Value>| value.map_or("<deleted>".to_string(), ToString::to_string);
+    let mut out = String::new();
+    for conflict in conflicts {
+        let lines = [
+            format!(
+                "{TODO_MARKER} resolve `{}` (id {})",
+                conflict.path, conflict.fingerprint
+            ),
+            format!("#   base  : {}", value(conflict.base.as_ref())),
+            format!(
+                "#   ours  : {}  <- in the file below",
+                value(conflict.ours.as_ref())
+            ),
+            format!("#   theirs: {}", value(conflict.theirs.as_ref())),
+            "#".to_string(),
+        ];
+        for line in lines {
+            out.push_str(&line);
+            out.push_str(newline);
+        }
+    }
+    if !conflicts.is_empty() {
+        out.push_str("# Delete each block above once its value below is resolved.");
+        out.push_str(newline);
+        out.push_str(newline);
+    }
+    out.push_str(merged);
+    out
+}
+
+/// Checks an edited file: valid TOML and no conflict blocks left.
+pub fn validate(text: &str) -> Result<(), String> {
+    let open = text
+        .lines()
+        .filter(|line| line.starts_with(TODO_MARKER))
+        .count();
+    if open > 0 {
+        return Err(format!("{open} conflicts are still marked TODO"));
+    }
+    text.parse::<toml::Value>()
+        .map(drop)
+        .map_err(|err| format!("not valid TOML: {}", err.message().trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_every_conflict_and_keeps_ours_in_place() {
+        let base =
+            "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("serde = \"1\"", "serde = \"1.0.200\"");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("serde = \"1\"", "serde = \"1.0.199\"")
+            .replace("0.4", "0.4.21");
+
+        // Keep the version bumps a conflict.
+        let options = MergeOptions {
+            highest_version: false,
+            ..MergeOptions::default()
+        };
+        let (merged, conflicts) = provisional_merge(base, &ours, &theirs, &options).unwrap();
+        let paths: Vec<_> = conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["dependencies.serde", "package.version"]);
+        assert!(merged.contains("log = \"0.4.21\""), "{merged}");
+
+        let annotated = annotate(&merged, &conflicts);
+        assert!(annotated.starts_with(&format!(
+            "{TODO_MARKER} resolve `dependencies.serde` (id {})\n#   base  : \"1\"\n",
+            conflicts[0].fingerprint
+        )));
+        assert_eq!(
+            validate(&annotated).unwrap_err(),
+            "2 conflicts are still marked TODO"
+        );
+        assert_eq!(validate(&merged), Ok(()));
+        assert!(validate("[package\n").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Failures of the command-line driver, by kind.
+//!
+//! Each kind has its own exit status ([`Error::exit_code`]) and, with the
+//! `serde` feature, a JSON form tagged by `kind`, so scripts and editor
+//! integrations can tell a conflict from a broken input or a failed cargo run
+//! without parsing messages:
+//!
+//! | Status | Kind |
+//! | --- | --- |
+//! | 1 | `conflict_set`: values both sides changed differently |
+//! | 2 | anything else (invalid arguments, unexpected failures) |
+//! | 3 | `parse`: an input is not valid TOML |
+//! | 4 | `schema`: valid TOML that is not an acceptable manifest |
+//! | 5 | `cargo`: a cargo command failed; `no_cargo`: cargo is not installed |
+//! | 6 | `git`: a git command failed |
+//! | 7 | `io`: a file could not be read or written |
+//! | 8 | `license`: a new dependency's license is not allowed |
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::merge::{MergeConflict, MergeError, ParseError, Side};
+
+/// Exit status for failures without a kind of their own.
+pub const EXIT_OTHER: i32 = 2;
+
+/// A failure of one of the driver's commands.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum Error {
+    /// An input file is not valid TOML.
+    Parse {
+        file: Option<PathBuf>,
+        error: ParseError,
+    },
+    /// A document is valid TOML but not acceptable: unknown keys under
+    /// `--strict`, nesting beyond the depth limit, or a merge result that
+    /// cannot be written back.
+    Schema {
+        file: Option<PathBuf>,
+        problems: Vec<String>,
+    },
+    /// Values both sides changed differently, across one or more files.
+    ConflictSet { conflicts: Vec<FileConflict> },
+    /// A cargo command exited unsuccessfully (`exit` is `None` when it was
+    /// killed by a signal). `stderr` holds the end of its error output and
+    /// `log` the file with all of it; see [`crate::cargo`].
+    Cargo {
+        dir: PathBuf,
+        args: Vec<String>,
+        exit: Option<i32>,
+        stderr: String,
+        log: Option<PathBuf>,
+    },
+    /// Several cargo commands failed, e.g. the parallel checks of
+    /// [`crate::verify`]; each failure is an [`Error::Cargo`].
+    CargoSet { failures: Vec<Error> },
+    /// cargo is needed but not on `PATH`.
+    NoCargo,
+    /// A git command exited unsuccessfully.
+    Git {
+        args: Vec<String>,
+        exit: Option<i32>,
+        stderr: String,
+    },
+    /// Reading or writing a file failed.
+    Io {
+        path: PathBuf,
+        operation: &'static str,
+        message: String,
+    },
+    /// Dependencies the merge of `file` brings in have licenses the policy
+    /// does not allow; one message per package.
+    License {
+        file: PathBuf,
+        violations: Vec<String>,
+    },
+}
+
+/// A conflict and the manifest it is in.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileConflict {
+    pub file: PathBuf,
+    pub conflict: MergeConflict,
+}
+
+impl Error {
+    /// The process exit status for this failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ConflictSet { .. } => 1,
+            Error::Parse { .. } => 3,
+            Error::Schema { .. } => 4,
+            Error::Cargo { .. } | Error::CargoSet { .. } | Error::NoCargo => 5,
+            Error::Git { .. } => 6,
+            Error::Io { .. } => 7,
+            Error::License { .. } => 8,
+        }
+    }
+
+    /// Classifies a failed merge of `inputs` (base, ours, theirs) into `out`.
+    pub fn merge(err: MergeError, inputs: [&Path; 3], out: &Path) -> Self {
+        let [base, ours, theirs] = inputs;
+        match err {
+            MergeError::Parse(error) => {
+                let file = match error.side {
+                    Side::Base => base,
+                    Side::Ours => ours,
+                    Side::Theirs => theirs,
+                };
+                Error::Parse {
+                    file: Some(file.to_path_buf()),
+                    error,
+                }
+            }
+            MergeError::Conflict(conflict) => Error::ConflictSet {
+                conflicts: vec![FileConflict {
+                    file: out.to_path_buf(),
+                    conflict,
+                }],
+            },
+            MergeError::Report(report) => Error::ConflictSet {
+                conflicts: report
+                    .conflicts
+                    .into_iter()
+                    .map(|conflict| FileConflict {
+                        file: out.to_path_buf(),
+                        conflict,
+                    })
+                    .collect(),
+            },
+            err @ (MergeError::DepthLimit { .. } | MergeError::Serialize { .. }) => Error::Schema {
+                file: Some(out.to_path_buf()),
+                problems: vec![err.to_string()],
+            },
+        }
+    }
+
+    /// `operation` (e.g. "reading") on `path` failed with `err`.
+    pub fn io(operation: &'static str, path: &Path, err: &io::Error) -> Self {
+        Error::Io {
+            path: path.to_path_buf(),
+            operation,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { file, error } => match file {
+                Some(file) => write!(f, "failed parsing {}: {error}", file.display()),
+                None => error.fmt(f),
+            },
+            Error::Schema { file, problems } => {
+                match file {
+                    Some(file) => write!(f, "{} does not fit the Cargo schema", file.display())?,
+                    None => f.write_str("manifest does not fit the Cargo schema")?,
+                }
+                for problem in problems {
+                    write!(f, "\n  {problem}")?;
+                }
+                Ok(())
+            }
+            Error::ConflictSet { conflicts } => {
+                for (idx, FileConflict { file, conflict }) in conflicts.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str("\n")?;
+                    }
+                    if conflicts.len() > 1 {
+                        write!(f, "{}: ", file.display())?;
+                    }
+                    conflict.fmt(f)?;
+                }
+                Ok(())
+            }
+            Error::Cargo {
+                dir,
+                args,
+                exit,
+                stderr,
+                log,
+            } => {
+                write!(
+                    f,
+                    "cargo {} failed in {}{}",
+                    args.join(" "),
+                    dir.display(),
+                    status(*exit)
+                )?;
+                tail(f, stderr)?;
+                match log {
+                    Some(log) => write!(f, "\nfull output: {}", log.display()),
+                    None => Ok(()),
+                }
+            }
+            Error::CargoSet { failures } => {
+                write!(f, "{} cargo commands failed", failures.len())?;
+                for failure in failures {
+                    write!(f, "\n\n{failure}")?;
+                }
+                Ok(())
+            }
+            Error::NoCargo => f.write_str(
+                "cargo is not installed (not found on PATH); pass --allow-no-cargo to merge \
+                 without the steps that need it",
+            ),
+            Error::Git { args, exit, stderr } => {
+                write!(f, "git {} failed{}", args.join(" "), status(*exit))?;
+                tail(f, stderr)
+            }
+            Error::Io {
+                path,
+                operation,
+                message,
+            } => write!(f, "failed {operation} {}: {message}", path.display()),
+            Error::License { file, violations } => {
+                write!(
+                    f,
+                    "{} brings in dependencies with licenses that are not allowed",
+                    file.display()
+                )?;
+                for violation in violations {
+                    write!(f, "\n  {violation}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn status(exit: Option<i32>) -> String {
+    match exit {
+        Some(code) => format!(" (exit status {code})"),
+        None => " (terminated by a signal)".to_string(),
+    }
+}
+
+fn tail(f: &mut fmt::Formatter<'_>, stderr: &str) -> fmt::Result {
+    let stderr = stderr.trim_end();
+    if stderr.is_empty() {
+        return Ok(());
+    }
+    write!(f, "\n{stderr}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeOptions, merge_manifest_texts, merge_manifest_texts_with};
+
+    #[test]
+    fn classifies_merge_failures() {
+        let inputs = [Path::new("b"), Path::new("o"), Path::new("t")];
+        let base = "[package]\nversion = \"0.1.0\"\n";
+        let ours = base.replace("0.1.0", "0.2.0");
+        let theirs = base.replace("0.1.0", "0.3.0");
+
+        let options = MergeOptions {
+            highest_version: false,
+            ..MergeOptions::default()
+        };
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let err = Error::merge(err, inputs, Path::new("out"));
+        assert_eq!(err.exit_code(), 1);
+        assert!(
+            err.to_string()
+                .starts_with("semantic conflict at `package.version`")
+        );
+
+        let err = merge_manifest_texts(base, &ours, "[package\n").unwrap_err();
+        let err = Error::merge(err, inputs, Path::new("out"));
+        assert_eq!(err.exit_code(), 3);
+        assert!(
+            err.to_string().starts_with("failed parsing t: theirs"),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_tagged_by_kind() {
+        let err = Error::Cargo {
+            dir: PathBuf::from("repo"),
+            args: vec!["generate-lockfile".to_string()],
+            exit: Some(101),
+            stderr: "error: failed to select a version\n".to_string(),
+            log: None,
+        };
+        assert_eq!(
+            err.to_string(),
+            "cargo generate-lockfile failed in repo (exit status 101)\n\
+             error: failed to select a version"
+        );
+        let json = toml::Value::try_from(&err).unwrap();
+        assert_eq!(json["kind"].as_str(), Some("cargo"));
+        assert_eq!(json["exit"].as_integer(), Some(101));
+    }
+}
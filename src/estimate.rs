@@ -0,0 +1,685 @@
+//! Offline estimate of how a manifest change will move `Cargo.lock`.
+//!
+//! Compares the dependency requirements in a manifest with the versions
+//! already locked, and predicts replacement versions from cargo's local copy
+//! of the registry index (`$CARGO_HOME/registry/index/*/.cache`). Nothing is
+//! resolved: transitive effects are not modelled, so this is a fast signal
+//! for CI rather than a substitute for `resolve-lock`.
+//!
+//! [`dangling_patches`] catches a lockfile still resolving a crate from a git
+//! fork after the merge dropped the `[patch]` (or dependency) pointing at it:
+//! typically one branch removed the patch while the other's lockfile still
+//! relies on it.
+//!
+//! [`RegistryIndex::online`] additionally queries the crates.io sparse index;
+//! it backs `--online-hints` and is never used by `estimate-lock`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use semver::{Version, VersionReq};
+use toml::Value;
+
+/// Base URL of the crates.io sparse index.
+pub const CRATES_IO_SPARSE_INDEX: &str = "https://index.crates.io";
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// A lockfile change the manifest is expected to force.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum LockChange {
+    /// A new dependency; `version` is the predicted pick, if the index knows.
+    Add {
+        name: String,
+        version: Option<String>,
+    },
+    /// No locked version satisfies `req` any more.
+    Update {
+        name: String,
+        from: String,
+        to: Option<String>,
+        req: String,
+    },
+    /// A direct dependency of the root package that the manifest dropped.
+    Remove { name: String, version: String },
+    /// A package locked from a git `source` that neither a `[patch]` nor a
+    /// dependency of the manifest points at any more.
+    Unpatch {
+        name: String,
+        version: String,
+        source: String,
+    },
+}
+
+impl fmt::Display for LockChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add { name, version } => {
+                write!(
+                    f,
+                    "add     {name} {}",
+                    version.as_deref().unwrap_or("(unknown)")
+                )
+            }
+            Self::Update {
+                name,
+                from,
+                to,
+                req,
+            } => write!(
+                f,
+                "update  {name} {from} -> {} (requires {req})",
+                to.as_deref().unwrap_or("(unknown)")
+            ),
+            Self::Remove { name, version } => write!(f, "remove  {name} {version}"),
+            Self::Unpatch {
+                name,
+                version,
+                source,
+            } => write!(f, "unpatch {name} {version} (locked from {source})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EstimateError {
+    Manifest(String),
+    Lockfile(String),
+}
+
+impl fmt::Display for EstimateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manifest(detail) => write!(f, "invalid manifest: {detail}"),
+            Self::Lockfile(detail) => write!(f, "invalid lockfile: {detail}"),
+        }
+    }
+}
+
+impl std::error::Error for EstimateError {}
+
+/// Cargo's on-disk cache of registry index entries, optionally backed by a
+/// live sparse index.
+#[derive(Debug, Clone)]
+pub struct RegistryIndex {
+    caches: Vec<PathBuf>,
+    sparse_url: Option<String>,
+}
+
+impl RegistryIndex {
+    /// Reads entries from the given `.cache` directories, first match wins.
+    pub fn new(caches: Vec<PathBuf>) -> Self {
+        Self {
+            caches,
+            sparse_url: None,
+        }
+    }
+
+    /// Queries the crates.io sparse index, falling back to the local caches
+    /// when it cannot be reached.
+    pub fn online() -> Self {
+        Self {
+            sparse_url: Some(CRATES_IO_SPARSE_INDEX.to_string()),
+            ..Self::from_cargo_home()
+        }
+    }
+
+    /// Every registry index cache under `$CARGO_HOME` (default `~/.cargo`).
+    pub fn from_cargo_home() -> Self {
+        let Some(home) = cargo_home() else {
+            return Self::new(Vec::new());
+        };
+        let mut caches: Vec<PathBuf> = fs::read_dir(home.join("registry").join("index"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join(".cache"))
+            .filter(|cache| cache.is_dir())
+            .collect();
+        caches.sort();
+        Self::new(caches)
+    }
+
+    /// Known versions of `name` with their yanked flag; empty if unknown.
+    pub fn versions(&self, name: &str) -> Vec<(Version, bool)> {
+        let relative = index_path(&name.to_lowercase());
+        if let Some(versions) = self
+            .sparse_url
+            .as_deref()
+            .and_then(|url| fetch_sparse_entry(url, &relative))
+        {
+            return versions;
+        }
+        self.caches
+            .iter()
+            .find_map(|cache| fs::read(cache.join(&relative)).ok())
+            .map(|bytes| parse_cache_entry(&bytes))
+            .unwrap_or_default()
+    }
+
+    /// The version cargo would most likely pick for `req`: the highest
+    /// non-yanked cached release matching it.
+    pub fn best_match(&self, name: &str, req: &VersionReq) -> Option<Version> {
+        self.versions(name)
+            .into_iter()
+            .filter(|(version, yanked)| !yanked && req.matches(version))
+            .map(|(version, _)| version)
+            .max()
+    }
+}
+
+/// Predicts the lockfile changes `manifest_text` forces on `lock_text`.
+pub fn estimate(
+    manifest_text: &str,
+    lock_text: &str,
+    index: &RegistryIndex,
+) -> Result<Vec<LockChange>, EstimateError> {
+    let manifest: Value = manifest_text
+        .parse()
+        .map_err(|err: toml::de::Error| EstimateError::Manifest(err.message().to_string()))?;
+    let lock: Value = lock_text
+        .parse()
+        .map_err(|err: toml::de::Error| EstimateError::Lockfile(err.message().to_string()))?;
+
+    let requirements = direct_requirements(&manifest)?;
+    let locked = locked_versions(&lock);
+    let mut changes = Vec::new();
+
+    for (name, reqs) in &requirements {
+        let versions = locked.get(name.as_str());
+        for req in reqs.iter().flatten() {
+            let Some(versions) = versions else {
+                break;
+            };
+            if versions.iter().any(|version| req.matches(version)) {
+                continue;
+            }
+            let from = versions.iter().max().expect("locked names have versions");
+            changes.push(LockChange::Update {
+                name: name.clone(),
+                from: from.to_string(),
+                to: index.best_match(name, req).map(|v| v.to_string()),
+                req: req.to_string(),
+            });
+        }
+        if versions.is_none() {
+            let req = reqs
+                .iter()
+                .flatten()
+                .next()
+                .cloned()
+                .unwrap_or(VersionReq::STAR);
+            changes.push(LockChange::Add {
+                name: name.clone(),
+                version: index.best_match(name, &req).map(|v| v.to_string()),
+            });
+        }
+    }
+
+    let root = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(Value::as_str);
+    if let Some(root) = root {
+        for (name, version) in root_dependencies(&lock, root) {
+            if !requirements.contains_key(&name) {
+                changes.push(LockChange::Remove { name, version });
+            }
+        }
+    }
+    changes.extend(dangling_patches(&manifest, &lock));
+
+    Ok(changes)
+}
+
+/// [`LockChange::Unpatch`] for every git-sourced package (or unused patch)
+/// in `lock` whose repository `manifest` no longer references. Packages that
+/// another git package depends on are transitive and left alone.
+pub fn dangling_patches(manifest: &Value, lock: &Value) -> Vec<LockChange> {
+    let mut tables: Vec<&Value> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|key| manifest.get(*key))
+        .collect();
+    if let Some(targets) = manifest.get("target").and_then(Value::as_table) {
+        for target in targets.values() {
+            tables.extend(DEPENDENCY_TABLES.iter().filter_map(|key| target.get(*key)));
+        }
+    }
+    tables.extend(
+        manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies")),
+    );
+    if let Some(registries) = manifest.get("patch").and_then(Value::as_table) {
+        tables.extend(registries.values());
+    }
+    let referenced: Vec<&str> = tables
+        .iter()
+        .filter_map(|table| table.as_table())
+        .flat_map(|table| table.values())
+        .filter_map(|spec| spec.get("git").and_then(Value::as_str))
+        .map(repository)
+        .collect();
+
+    let transitive: Vec<&str> = packages(lock)
+        .filter(|package| git_source(package).is_some())
+        .flat_map(|package| {
+            package
+                .get("dependencies")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+        })
+        .filter_map(|entry| entry.as_str()?.split(' ').next())
+        .collect();
+    let unused = lock
+        .get("patch")
+        .and_then(|patch| patch.get("unused"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+
+    let mut dangling = Vec::new();
+    for package in packages(lock).chain(unused) {
+        let field = |key: &str| package.get(key).and_then(Value::as_str);
+        let (Some(name), Some(version), Some(source)) =
+            (field("name"), field("version"), git_source(package))
+        else {
+            continue;
+        };
+        if referenced.contains(&repository(source)) || transitive.contains(&name) {
+            continue;
+        }
+        dangling.push(LockChange::Unpatch {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: repository(source).to_string(),
+        });
+    }
+    dangling
+}
+
+/// The git URL a lock package was resolved from, without the `git+` prefix.
+fn git_source(package: &Value) -> Option<&str> {
+    package
+        .get("source")
+        .and_then(Value::as_str)?
+        .strip_prefix("git+")
+}
+
+/// `url` without query, fragment, trailing slash or `.git`, so manifest and
+/// lockfile spellings of a repository compare equal.
+fn repository(url: &str) -> &str {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+/// Registry requirements per package name. Path and git dependencies have a
+/// `None` requirement: they are tracked by name only.
+fn direct_requirements(
+    manifest: &Value,
+) -> Result<BTreeMap<String, Vec<Option<VersionReq>>>, EstimateError> {
+    let mut tables: Vec<&Value> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|key| manifest.get(*key))
+        .collect();
+    if let Some(targets) = manifest.get("target").and_then(Value::as_table) {
+        for target in targets.values() {
+            tables.extend(DEPENDENCY_TABLES.iter().filter_map(|key| target.get(*key)));
+        }
+    }
+    let workspace = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"));
+    tables.extend(workspace);
+
+    let mut requirements: BTreeMap<String, Vec<Option<VersionReq>>> = BTreeMap::new();
+    for (key, spec) in tables.iter().filter_map(|t| t.as_table()).flatten() {
+        let (name, req) = match spec {
+            Value::String(req) => (key.as_str(), Some(req.as_str())),
+            Value::Table(detail) => {
+                if detail.get("workspace").and_then(Value::as_bool) == Some(true) {
+                    // Resolved through `[workspace.dependencies]`.
+                    continue;
+                }
+                let name = detail.get("package").and_then(Value::as_str).unwrap_or(key);
+                let registry = !detail.contains_key("path") && !detail.contains_key("git");
+                let req = detail.get("version").and_then(Value::as_str);
+                (
+                    name,
+                    if registry {
+                        Some(req.unwrap_or("*"))
+                    } else {
+                        None
+                    },
+                )
+            }
+            _ => continue,
+        };
+        let req = req
+            .map(|req| {
+                VersionReq::parse(req).map_err(|err| {
+                    EstimateError::Manifest(format!("dependency `{key}` version `{req}`: {err}"))
+                })
+            })
+            .transpose()?;
+        let reqs = requirements.entry(name.to_string()).or_default();
+        if !reqs.contains(&req) {
+            reqs.push(req);
+        }
+    }
+    Ok(requirements)
+}
+
+fn locked_versions(lock: &Value) -> BTreeMap<&str, Vec<Version>> {
+    let mut locked: BTreeMap<&str, Vec<Version>> = BTreeMap::new();
+    for package in packages(lock) {
+        let name = package.get("name").and_then(Value::as_str);
+        let version = package
+            .get("version")
+            .and_then(Value::as_str)
+            .and_then(|version| Version::parse(version).ok());
+        if let (Some(name), Some(version)) = (name, version) {
+            locked.entry(name).or_default().push(version);
+        }
+    }
+    locked
+}
+
+/// `(name, version)` of each direct dependency of the unsourced `root` package.
+fn root_dependencies(lock: &Value, root: &str) -> Vec<(String, String)> {
+    let Some(package) = packages(lock).find(|package| {
+        package.get("name").and_then(Value::as_str) == Some(root) && package.get("source").is_none()
+    }) else {
+        return Vec::new();
+    };
+    let locked = locked_versions(lock);
+    package
+        .get("dependencies")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter_map(|entry| {
+            // "name", "name version" or "name version (source)".
+            let mut parts = entry.split(' ');
+            let name = parts.next()?;
+            let version = match parts.next() {
+                Some(version) => version.to_string(),
+                None => locked.get(name)?.first()?.to_string(),
+            };
+            Some((name.to_string(), version))
+        })
+        .collect()
+}
+
+fn packages(lock: &Value) -> impl Iterator<Item = &Value> {
+    lock.get("package")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    if let Some(home) = std::env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(home));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".cargo"))
+}
+
+/// Location of a crate inside an index, e.g. `se/rd/serde`, `3/s/syn`.
+fn index_path(name: &str) -> PathBuf {
+    match name.len() {
+        1 => Path::new("1").join(name),
+        2 => Path::new("2").join(name),
+        3 => Path::new("3").join(&name[..1]).join(name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(name),
+    }
+}
+
+/// Parses a cargo index cache file: a version byte (3), a u32 index format
+/// version and a NUL-terminated revision, then NUL-separated pairs of
+/// version and JSON line. Unknown layouts yield no versions.
+fn parse_cache_entry(bytes: &[u8]) -> Vec<(Version, bool)> {
+    if bytes.first() != Some(&3) || bytes.len() < 5 {
+        return Vec::new();
+    }
+    let mut fields = bytes[5..].split(|&b| b == 0).skip(1);
+    let mut versions = Vec::new();
+    while let (Some(version), Some(line)) = (fields.next(), fields.next()) {
+        let Some(version) = std::str::from_utf8(version)
+            .ok()
+            .and_then(|version| Version::parse(version).ok())
+        else {
+            continue;
+        };
+        // Avoids a JSON dependency for a single boolean field.
+        let line = String::from_utf8_lossy(line);
+        let yanked = line.contains("\"yanked\":true") || line.contains("\"yanked\": true");
+        versions.push((version, yanked));
+    }
+    versions
+}
+
+fn fetch_sparse_entry(base_url: &str, relative: &Path) -> Option<Vec<(Version, bool)>> {
+    let path: Vec<_> = relative.iter().filter_map(|part| part.to_str()).collect();
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), path.join("/"));
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--location", "--max-time", "10"])
+        .arg(&url)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.lines().filter_map(parse_index_line).collect())
+}
+
+/// Extracts `vers` and `yanked` from one line of index JSON.
+fn parse_index_line(line: &str) -> Option<(Version, bool)> {
+    // Avoids a JSON dependency for two fields of a flat, fixed format.
+    let field = |name: &str| {
+        let start = line.find(&format!("\"{name}\""))? + name.len() + 2;
+        line[start..]
+            .trim_start()
+            .strip_prefix(':')
+            .map(str::trim_start)
+    };
+    let vers = field("vers")?.strip_prefix('"')?;
+    let version = Version::parse(&vers[..vers.find('"')?]).ok()?;
+    Some((
+        version,
+        field("yanked").is_some_and(|v| v.starts_with("true")),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "log",
+ "serde",
+]
+
+[[package]]
+name = "log"
+version = "0.4.20"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.150"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    fn write_cache(dir: &Path, name: &str, entries: &[(&str, bool)]) {
+        let path = dir.join(index_path(name));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut bytes = vec![3, 2, 0, 0, 0];
+        bytes.extend_from_slice(b"etag\0");
+        for (version, yanked) in entries {
+            bytes.extend_from_slice(version.as_bytes());
+            bytes.push(0);
+            let line = format!(r#"{{"name":"{name}","vers":"{version}","yanked":{yanked}}}"#);
+            bytes.extend_from_slice(line.as_bytes());
+            bytes.push(0);
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn predicts_updates_additions_and_removals_from_cached_index() {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-estimate-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        write_cache(
+            &dir,
+            "serde",
+            &[("1.0.150", false), ("1.0.200", false), ("1.0.210", true)],
+        );
+        write_cache(&dir, "rand", &[("0.8.5", false), ("0.9.0", false)]);
+        let index = RegistryIndex::new(vec![dir.clone()]);
+
+        let manifest = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+serde = "1.0.180"
+rand = { version = "0.8" }
+
+[target.'cfg(unix)'.dependencies]
+local = { path = "../local" }
+"#;
+        let changes = estimate(manifest, LOCK, &index).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                LockChange::Add {
+                    name: "local".into(),
+                    version: None
+                },
+                LockChange::Add {
+                    name: "rand".into(),
+                    version: Some("0.8.5".into())
+                },
+                LockChange::Update {
+                    name: "serde".into(),
+                    from: "1.0.150".into(),
+                    to: Some("1.0.200".into()),
+                    req: "^1.0.180".into()
+                },
+                LockChange::Remove {
+                    name: "log".into(),
+                    version: "0.4.20".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_forks_the_merge_no_longer_patches() {
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["fork-helper", "serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "git+https://github.com/acme/serde.git?branch=fix#0123abcd"
+dependencies = ["serde_derive"]
+
+[[package]]
+name = "serde_derive"
+version = "1.0.200"
+source = "git+https://github.com/acme/serde.git?branch=fix#0123abcd"
+
+[[package]]
+name = "fork-helper"
+version = "0.2.0"
+source = "git+https://github.com/acme/helper#89ab"
+
+[[patch.unused]]
+name = "log"
+version = "0.4.99"
+source = "git+https://github.com/acme/log#cdef"
+"#
+        .parse::<Value>()
+        .unwrap();
+        let manifest = r#"
+[package]
+name = "app"
+
+[dependencies]
+serde = "1"
+fork-helper = { git = "https://github.com/acme/helper/" }
+
+[patch.crates-io]
+serde = { git = "https://github.com/acme/serde", branch = "fix" }
+"#;
+        let unpatched = manifest.replace("serde = { git", "# serde = { git");
+
+        assert_eq!(
+            dangling_patches(&manifest.parse().unwrap(), &lock),
+            [LockChange::Unpatch {
+                name: "log".into(),
+                version: "0.4.99".into(),
+                source: "https://github.com/acme/log".into(),
+            }]
+        );
+        let changes = dangling_patches(&unpatched.parse().unwrap(), &lock);
+        assert_eq!(changes.len(), 2, "{changes:?}");
+        assert_eq!(
+            changes[0].to_string(),
+            "unpatch serde 1.0.200 (locked from https://github.com/acme/serde)"
+        );
+    }
+
+    #[test]
+    fn parses_sparse_index_lines() {
+        let line = r#"{"name":"serde","vers":"1.0.200","deps":[],"cksum":"00","features":{},"yanked":false}"#;
+        assert_eq!(
+            parse_index_line(line),
+            Some((Version::new(1, 0, 200), false))
+        );
+        assert!(parse_index_line(&line.replace("false", "true")).unwrap().1);
+        assert_eq!(
+            parse_index_line(r#"{"name": "a", "vers": "0.1.0", "yanked": true}"#),
+            Some((Version::new(0, 1, 0), true))
+        );
+        assert_eq!(parse_index_line("{}"), None);
+    }
+
+    #[test]
+    fn satisfied_requirements_change_nothing() {
+        let manifest =
+            "[package]\nname = \"app\"\n\n[dependencies]\nlog = \"0.4\"\nserde = \"1\"\n";
+        let changes = estimate(manifest, LOCK, &RegistryIndex::new(Vec::new())).unwrap();
+        assert!(changes.is_empty(), "{changes:?}");
+    }
+}
@@ -0,0 +1,440 @@
+//! Merging of `[features]`.
+//!
+//! A feature's enable list is a set: `full = ["a", "dep:b", "c?/x"]` means the
+//! same in any order. Lists both sides changed are therefore merged against
+//! base like the member lists: ours' entries minus those theirs removed, then
+//! the entries theirs added, at their sorted position when ours keeps the list
+//! sorted. Entries are compared by what they enable, so `c?/x` (enable `x` of
+//! `c` if something else enables `c`) and `c/x` (enable both) name the same
+//! entry; when one side wrote the weak form and the other the strong one, the
+//! strong one is kept, as it enables what the weak one does.
+//!
+//! Merging feature by feature cannot see a feature one side deleted while the
+//! other started to enable it from another feature: the deletion would win and
+//! leave the manifest enabling a feature that does not exist. [`dangling`]
+//! finds those, and they are reported as conflicts
+//! ([`ConflictKind::Referenced`](crate::merge::ConflictKind::Referenced)).
+//!
+//! Neither catches a feature one side added that enables a dependency the
+//! other side removed. [`check`] validates the feature graph of a merged
+//! manifest: every entry must name a feature or a dependency that exists, and
+//! every optional dependency must be enabled by some feature.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use toml::Value;
+
+use crate::merge::Side;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// What an entry of an enable list enables.
+#[derive(Debug, PartialEq, Eq)]
+enum Entry<'a> {
+    /// `name`: another feature, or an optional dependency's implicit one.
+    Feature(&'a str),
+    /// `dep:name`: the optional dependency itself.
+    Dependency(&'a str),
+    /// `dep/feature` or, `weak`, `dep?/feature`.
+    DependencyFeature {
+        dependency: &'a str,
+        feature: &'a str,
+        weak: bool,
+    },
+}
+
+impl<'a> Entry<'a> {
+    fn parse(entry: &'a str) -> Self {
+        if let Some(dependency) = entry.strip_prefix("dep:") {
+            return Entry::Dependency(dependency);
+        }
+        match entry.split_once('/') {
+            Some((dependency, feature)) => match dependency.strip_suffix('?') {
+                Some(dependency) => Entry::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: true,
+                },
+                None => Entry::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: false,
+                },
+            },
+            None => Entry::Feature(entry),
+        }
+    }
+
+    /// Whether both name the same entry, in weak or strong form.
+    fn same(&self, other: &Entry) -> bool {
+        match (self, other) {
+            (
+                Entry::DependencyFeature {
+                    dependency,
+                    feature,
+                    ..
+                },
+                Entry::DependencyFeature {
+                    dependency: other_dependency,
+                    feature: other_feature,
+                    ..
+                },
+            ) => dependency == other_dependency && feature == other_feature,
+            _ => self == other,
+        }
+    }
+
+    fn is_weak(&self) -> bool {
+        matches!(self, Entry::DependencyFeature { weak: true, .. })
+    }
+}
+
+impl fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Feature(name) => f.write_str(name),
+            Entry::Dependency(name) => write!(f, "dep:{name}"),
+            Entry::DependencyFeature {
+                dependency,
+                feature,
+                weak,
+            } => write!(f, "{dependency}{}/{feature}", if *weak { "?" } else { "" }),
+        }
+    }
+}
+
+/// The merged enable list at `path` when it is a feature of `[features]`
+/// and all three sides are lists of strings (base may be missing); `None`
+/// leaves it to the usual merge.
+pub(crate) fn union(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Value> {
+    let ["features", _] = path else {
+        return None;
+    };
+    let base = match base {
+        None => Vec::new(),
+        Some(base) => strings(base)?,
+    };
+    let ours = strings(ours?)?;
+    let theirs = strings(theirs?)?;
+    let listed = |list: &[&str], entry: &str| {
+        let entry = Entry::parse(entry);
+        list.iter().any(|other| Entry::parse(other).same(&entry))
+    };
+
+    let sorted = ours.is_sorted();
+    let mut merged: Vec<&str> = Vec::new();
+    for entry in ours.iter().copied() {
+        // Dropped unless theirs kept it, in either form.
+        if (!base.contains(&entry) || listed(&theirs, entry)) && !listed(&merged, entry) {
+            merged.push(entry);
+        }
+    }
+    for entry in theirs {
+        if base.contains(&entry) {
+            continue;
+        }
+        let parsed = Entry::parse(entry);
+        match merged
+            .iter_mut()
+            .find(|other| Entry::parse(other).same(&parsed))
+        {
+            Some(other) => {
+                if Entry::parse(other).is_weak() && !parsed.is_weak() {
+                    *other = entry;
+                }
+            }
+            None if sorted => {
+                let at = merged.partition_point(|other| *other < entry);
+                merged.insert(at, entry);
+            }
+            None => merged.push(entry),
+        }
+    }
+    Some(Value::Array(
+        merged.into_iter().map(|entry| entry.into()).collect(),
+    ))
+}
+
+/// Features of base that one side deleted while the other side started to
+/// enable them from another feature: the deleted feature, the side enabling
+/// it and the feature enabling it.
+pub(crate) fn dangling<'a>(
+    base: Option<&'a Value>,
+    ours: Option<&'a Value>,
+    theirs: Option<&'a Value>,
+) -> Vec<(&'a str, Side, &'a str)> {
+    let [Some(base), Some(ours), Some(theirs)] = [base, ours, theirs].map(|side| side?.as_table())
+    else {
+        return Vec::new();
+    };
+    let mut dangling = Vec::new();
+    for (deleting, keeping, side) in [(ours, theirs, Side::Theirs), (theirs, ours, Side::Ours)] {
+        for name in base.keys() {
+            if deleting.contains_key(name) {
+                continue;
+            }
+            let enabling = keeping.iter().find(|(feature, list)| {
+                let enables = |list: Option<&Value>| {
+                    list.and_then(strings)
+                        .is_some_and(|list| list.contains(&name.as_str()))
+                };
+                enables(Some(list)) && !enables(base.get(*feature))
+            });
+            if let Some((feature, _)) = enabling {
+                dangling.push((name.as_str(), side, feature.as_str()));
+            }
+        }
+    }
+    dangling
+}
+
+/// An inconsistency in the `[features]` of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureProblem {
+    /// `feature` enables `entry`, which is neither a feature nor an optional
+    /// dependency.
+    UnknownFeature { feature: String, entry: String },
+    /// `feature` enables `entry`, a `dep:` entry naming no optional
+    /// dependency.
+    NotOptional { feature: String, entry: String },
+    /// `feature` enables `entry`, a `dep/feature` entry naming no dependency.
+    UnknownDependency { feature: String, entry: String },
+    /// No feature enables the optional `dependency`, and the manifest hides
+    /// the implicit features of optional dependencies behind `dep:` entries.
+    Unreachable { dependency: String },
+}
+
+impl fmt::Display for FeatureProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureProblem::UnknownFeature { feature, entry } => write!(
+                f,
+                "feature `{feature}` enables `{entry}`, which is neither a feature nor an optional dependency"
+            ),
+            FeatureProblem::NotOptional { feature, entry } => write!(
+                f,
+                "feature `{feature}` enables `{entry}`, which is not an optional dependency"
+            ),
+            FeatureProblem::UnknownDependency { feature, entry } => write!(
+                f,
+                "feature `{feature}` enables `{entry}`, but there is no such dependency"
+            ),
+            FeatureProblem::Unreachable { dependency } => write!(
+                f,
+                "optional dependency `{dependency}` is enabled by no feature"
+            ),
+        }
+    }
+}
+
+/// Problems in the feature graph of the manifest `doc`, in the order of
+/// `[features]`, then the unreachable optional dependencies.
+pub fn check(doc: &Value) -> Vec<FeatureProblem> {
+    let features = doc.get("features").and_then(Value::as_table);
+    let lists: Vec<(&str, Vec<Entry>)> = features
+        .into_iter()
+        .flatten()
+        .map(|(feature, list)| {
+            let entries = list
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(Entry::parse)
+                .collect();
+            (feature.as_str(), entries)
+        })
+        .collect();
+    let (dependencies, optional) = dependencies(doc);
+    let namespaced: BTreeSet<&str> = lists
+        .iter()
+        .flat_map(|(_, entries)| entries)
+        .filter_map(|entry| match entry {
+            Entry::Dependency(dependency) => Some(*dependency),
+            _ => None,
+        })
+        .collect();
+
+    let mut problems = Vec::new();
+    for (feature, entries) in &lists {
+        for entry in entries {
+            let problem = |make: fn(String, String) -> FeatureProblem| {
+                make(feature.to_string(), entry.to_string())
+            };
+            match entry {
+                Entry::Feature(name) => {
+                    let implicit = optional.contains(name) && !namespaced.contains(name);
+                    if !implicit && !features.is_some_and(|features| features.contains_key(*name)) {
+                        problems.push(problem(|feature, entry| FeatureProblem::UnknownFeature {
+                            feature,
+                            entry,
+                        }));
+                    }
+                }
+                Entry::Dependency(name) if !optional.contains(name) => {
+                    problems.push(problem(|feature, entry| FeatureProblem::NotOptional {
+                        feature,
+                        entry,
+                    }));
+                }
+                Entry::DependencyFeature { dependency, .. }
+                    if !dependencies.contains(dependency) =>
+                {
+                    problems.push(problem(|feature, entry| {
+                        FeatureProblem::UnknownDependency { feature, entry }
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if !namespaced.is_empty() {
+        for dependency in &optional {
+            let enabled = lists
+                .iter()
+                .flat_map(|(_, entries)| entries)
+                .any(|entry| match entry {
+                    Entry::Feature(name) | Entry::Dependency(name) => name == dependency,
+                    Entry::DependencyFeature {
+                        dependency: name,
+                        weak,
+                        ..
+                    } => !weak && name == dependency,
+                });
+            if !enabled {
+                problems.push(FeatureProblem::Unreachable {
+                    dependency: dependency.to_string(),
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// The names of the dependencies of `doc` that features can refer to, and
+/// of the optional ones among them.
+fn dependencies(doc: &Value) -> (BTreeSet<&str>, BTreeSet<&str>) {
+    let mut tables: Vec<&Value> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|kind| doc.get(*kind))
+        .collect();
+    for target in doc
+        .get("target")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values())
+    {
+        tables.extend(
+            DEPENDENCY_TABLES
+                .iter()
+                .filter_map(|kind| target.get(*kind)),
+        );
+    }
+
+    let mut dependencies = BTreeSet::new();
+    let mut optional = BTreeSet::new();
+    for (name, spec) in tables.into_iter().filter_map(Value::as_table).flatten() {
+        dependencies.insert(name.as_str());
+        if spec.get("optional").and_then(Value::as_bool) == Some(true) {
+            optional.insert(name.as_str());
+        }
+    }
+    (dependencies, optional)
+}
+
+/// The items of `value` when it is a list of strings.
+fn strings(value: &Value) -> Option<Vec<&str>> {
+    value.as_array()?.iter().map(Value::as_str).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, MergeOptions, merge_manifest_texts, merge_manifest_texts_with};
+
+    #[test]
+    fn merges_enable_lists_as_sets() {
+        let base = "[features]\nfull = [\"a\", \"dep:b\", \"c?/x\"]\nserde = [\"dep:serde\"]\n";
+        let ours =
+            "[features]\nfull = [\"dep:b\", \"a\", \"c/x\", \"d\"]\nserde = [\"dep:serde\"]\n";
+        let theirs = "[features]\nfull = [\"a\", \"c?/x\", \"e\"]\nserde = [\"dep:serde\", \"serde?/std\"]\n";
+
+        let merged: toml::Table = merge_manifest_texts(base, ours, theirs)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected: toml::Table = "[features]\n\
+             full = [\"a\", \"c/x\", \"d\", \"e\"]\n\
+             serde = [\"dep:serde\", \"serde?/std\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+
+        let weak = Value::from(vec!["a", "c?/x"]);
+        let strong = Value::from(vec!["a", "c/x"]);
+        assert_eq!(
+            union(&["features", "full"], None, Some(&weak), Some(&strong)),
+            Some(strong)
+        );
+    }
+
+    #[test]
+    fn reports_deleted_features_the_other_side_enables() {
+        let base = "[features]\ndefault = [\"std\"]\nstd = []\nlegacy = []\n";
+        let ours = "[features]\ndefault = [\"std\"]\nstd = []\n";
+        let theirs = "[features]\ndefault = [\"std\", \"legacy\"]\nstd = []\nlegacy = []\n";
+
+        let Err(MergeError::Conflict(conflict)) =
+            merge_manifest_texts_with(base, ours, theirs, &MergeOptions::default())
+        else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflict.path, "features.legacy");
+        assert_eq!(
+            conflict.kind,
+            crate::merge::ConflictKind::Referenced {
+                side: Side::Theirs,
+                by: "default".to_string()
+            }
+        );
+        assert!(conflict.to_string().contains(
+            "\n  used  : theirs started enabling it from `features.default`, ours deleted it"
+        ));
+    }
+
+    #[test]
+    fn finds_broken_references_and_unreachable_dependencies() {
+        let doc: Value = "[dependencies]\nserde = { version = \"1\", optional = true }\n\
+             log = \"0.4\"\nrand = { version = \"0.8\", optional = true }\n\n\
+             [target.'cfg(unix)'.dependencies]\nlibc = { version = \"0.2\", optional = true }\n\n\
+             [features]\ndefault = [\"std\", \"legacy\"]\nstd = [\"serde?/std\", \"log/std\", \"dep:libc\"]\n\
+             derive = [\"dep:serde_derive\", \"dep:log\", \"tokio/rt\"]\n"
+            .parse()
+            .unwrap();
+
+        let problems: Vec<String> = check(&doc).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            problems,
+            [
+                "feature `default` enables `legacy`, which is neither a feature nor an optional dependency",
+                "feature `derive` enables `dep:serde_derive`, which is not an optional dependency",
+                "feature `derive` enables `dep:log`, which is not an optional dependency",
+                "feature `derive` enables `tokio/rt`, but there is no such dependency",
+                "optional dependency `rand` is enabled by no feature",
+                "optional dependency `serde` is enabled by no feature",
+            ]
+        );
+        let implicit: Value = "[dependencies]\nserde = { version = \"1\", optional = true }\n\n\
+             [features]\nfull = [\"serde\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(check(&implicit), []);
+    }
+}
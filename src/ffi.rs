@@ -0,0 +1,186 @@
+//! C ABI for editor and IDE plugins.
+//!
+//! Buffers go in as NUL-terminated UTF-8 strings and the result comes back as
+//! a JSON document allocated by this library. Callers must release it with
+//! [`cma_string_free`]. The matching declarations live in
+//! `include/cargo_merge_assist.h`.
+
+use std::any::Any;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+
+use serde::Serialize;
+
+use crate::merge::{MergeConflict, MergeError, merge_manifest_texts};
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum FfiResult {
+    Merged { merged: String },
+    Conflict { conflict: MergeConflict },
+    Error { message: String },
+}
+
+/// Runs a 3-way `Cargo.toml` merge and returns the outcome as JSON.
+///
+/// The returned object has a `status` of `merged` (with `merged`), `conflict`
+/// (with `conflict`) or `error` (with `message`, for invalid input or inputs
+/// the engine refuses to merge, or when the engine panics). Never returns
+/// null and never unwinds into the caller.
+///
+/// # Safety
+///
+/// Each argument must be null or point to a valid NUL-terminated string that
+/// stays alive for the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cma_merge_manifest(
+    base: *const c_char,
+    ours: *const c_char,
+    theirs: *const c_char,
+) -> *mut c_char {
+    // SAFETY: the caller upholds the pointer contract documented above.
+    let result = guarded(|| unsafe { merge_from_ptrs(base, ours, theirs) });
+    into_c_json(&result)
+}
+
+/// Releases a string returned by this library.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer previously returned by this library that
+/// has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cma_string_free(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        // SAFETY: `ptr` was produced by `CString::into_raw` in `into_c_json`.
+        drop(unsafe { CString::from_raw(ptr) });
+    }
+}
+
+unsafe fn merge_from_ptrs(
+    base: *const c_char,
+    ours: *const c_char,
+    theirs: *const c_char,
+) -> FfiResult {
+    let inputs = unsafe {
+        (
+            read_arg("base", base),
+            read_arg("ours", ours),
+            read_arg("theirs", theirs),
+        )
+    };
+    let (base, ours, theirs) = match inputs {
+        (Ok(base), Ok(ours), Ok(theirs)) => (base, ours, theirs),
+        (Err(message), _, _) | (_, Err(message), _) | (_, _, Err(message)) => {
+            return FfiResult::Error { message };
+        }
+    };
+
+    match merge_manifest_texts(base, ours, theirs) {
+        Ok(merged) => FfiResult::Merged { merged },
+        Err(MergeError::Conflict(conflict)) => FfiResult::Conflict { conflict },
+        Err(err) => FfiResult::Error {
+            message: err.to_string(),
+        },
+    }
+}
+
+/// Runs `merge`, turning a panic into an error result: unwinding out of an
+/// `extern "C"` function aborts the host process.
+fn guarded(merge: impl FnOnce() -> FfiResult) -> FfiResult {
+    panic::catch_unwind(AssertUnwindSafe(merge)).unwrap_or_else(|payload| FfiResult::Error {
+        message: format!("merge engine panicked: {}", panic_message(&*payload)),
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+unsafe fn read_arg<'a>(name: &str, ptr: *const c_char) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(format!("`{name}` must not be null"));
+    }
+    // SAFETY: non-null and NUL-terminated per the caller contract.
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|err| format!("`{name}` is not valid UTF-8: {err}"))
+}
+
+fn into_c_json(result: &FfiResult) -> *mut c_char {
+    let json = serde_json::to_string(result).unwrap_or_else(|err| {
+        serde_json::json!({
+            "status": "error",
+            "message": format!("failed to encode result: {err}"),
+        })
+        .to_string()
+    });
+    // JSON escapes control characters, so the encoded text never contains NUL.
+    CString::new(json)
+        .expect("JSON output contains no interior NUL")
+        .into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(base: &str, ours: &str, theirs: &str) -> serde_json::Value {
+        let base = CString::new(base).unwrap();
+        let ours = CString::new(ours).unwrap();
+        let theirs = CString::new(theirs).unwrap();
+
+        unsafe {
+            let raw = cma_merge_manifest(base.as_ptr(), ours.as_ptr(), theirs.as_ptr());
+            let json = CStr::from_ptr(raw).to_str().unwrap().to_string();
+            cma_string_free(raw);
+            serde_json::from_str(&json).unwrap()
+        }
+    }
+
+    #[test]
+    fn returns_merged_document_as_json() {
+        let out = call(
+            "[dependencies]\nserde = \"1\"\n",
+            "[dependencies]\nserde = \"1\"\nclap = \"4\"\n",
+            "[dependencies]\nserde = \"1\"\nanyhow = \"1\"\n",
+        );
+        assert_eq!(out["status"], "merged");
+        assert!(out["merged"].as_str().unwrap().contains("clap = \"4\""));
+    }
+
+    #[test]
+    fn returns_conflict_as_json() {
+        let out = call(
+            "[dependencies]\nserde = \"1\"\n",
+            "[dependencies]\nserde = \"1.0.200\"\n",
+            "[dependencies]\nserde = \"1.0.199\"\n",
+        );
+        assert_eq!(out["status"], "conflict");
+        assert_eq!(out["conflict"]["path"], "dependencies.serde");
+    }
+
+    #[test]
+    fn rejects_null_input() {
+        let ours = CString::new("").unwrap();
+        let raw = unsafe { cma_merge_manifest(std::ptr::null(), ours.as_ptr(), ours.as_ptr()) };
+        let json = unsafe { CStr::from_ptr(raw) }.to_str().unwrap().to_string();
+        unsafe { cma_string_free(raw) };
+        assert!(json.contains("`base` must not be null"));
+    }
+
+    #[test]
+    fn reports_panics_as_errors() {
+        let result = guarded(|| panic!("bad \"state\""));
+        let raw = into_c_json(&result);
+        let json = unsafe { CStr::from_ptr(raw) }.to_str().unwrap().to_string();
+        unsafe { cma_string_free(raw) };
+        let out: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(out["status"], "error");
+        assert_eq!(out["message"], "merge engine panicked: bad \"state\"");
+    }
+}
@@ -0,0 +1,657 @@
+//! Built-in rules for individual package fields.
+//!
+//! Some fields have an obvious answer when both sides changed them.
+//! Editions only move forward, so two different `edition` bumps resolve to
+//! the newer edition (rule `edition`) unless
+//! [`MergeOptions::newest_edition`] is off. Release branches race on
+//! `version`; two different bumps resolve to the higher semver version (rule
+//! `version`) unless [`MergeOptions::highest_version`] is off. Two different `rust-version`
+//! changes resolve to the higher or lower version (rule `rust-version`) as
+//! [`MergeOptions::rust_version`] says; by default they conflict.
+//!
+//! Publishing metadata has safe defaults too. Two different `publish`
+//! changes resolve to the more restrictive one (rule `publish`): `false`
+//! beats a registry list, which beats `true`, and two lists keep the
+//! registries both allow. Two different `description`s resolve to the longer
+//! one (rule `description`). Conflicts on `license` and `license-file` are
+//! never settled automatically, only by explicit decisions; see
+//! [`MergeOptions::publish`], [`MergeOptions::description`] and
+//! [`MergeOptions::license`] to turn these off.
+//!
+//! In `[profile.*]` tables (and their `build-override` and `package`
+//! overrides) two different `opt-level`s resolve to the higher one and two
+//! different `codegen-units` to the lower one; `debug`, `lto` and `strip`
+//! conflict unless [`MergeOptions::profile`] says to take the higher or
+//! lower level. The rule is named after the setting.
+//!
+//! Two different levels for the same lint in `[lints]` or
+//! `[workspace.lints]`, in either `"deny"` or `{ level = "deny", ... }` form,
+//! resolve to the stricter one (rule `lint-level`), so tightening lint policy
+//! on both branches does not block the merge; see
+//! [`MergeOptions::lint_levels`]. Only the levels are compared: a lint one
+//! side wrote as a table keeps its `priority` and other keys.
+//!
+//! The package lists `include`, `exclude`, `authors`, `keywords` and
+//! `categories` merge as sets: ours' entries minus those theirs removed,
+//! then those theirs added. crates.io accepts at most five `keywords` and
+//! five `categories`, so a union longer than that is a conflict
+//! ([`ConflictKind::Limit`]) for someone to pick five.
+
+use semver::Version;
+use toml::Value;
+
+use crate::merge::{
+    ConflictKind, DescriptionMode, LintLevels, MergeOptions, ProfileMode, Proposal, PublishMode,
+    RustVersionMode,
+};
+use crate::metadata;
+
+/// Profile settings with a rule, each also the rule's name.
+const PROFILE_SETTINGS: [&str; 5] = ["opt-level", "codegen-units", "debug", "lto", "strip"];
+
+/// Package lists merged as sets, with the number of entries crates.io
+/// accepts.
+const PACKAGE_LISTS: [(&str, Option<usize>); 5] = [
+    ("include", None),
+    ("exclude", None),
+    ("authors", None),
+    ("keywords", Some(5)),
+    ("categories", Some(5)),
+];
+
+/// The set union of a package list at `path`, or the kind of conflict it
+/// is when the union exceeds crates.io's limit; `None` for other paths and
+/// for values that are not lists of strings.
+pub(crate) fn package_list(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Result<Value, ConflictKind>> {
+    let (["package", field] | ["workspace", "package", field]) = path else {
+        return None;
+    };
+    let (_, limit) = PACKAGE_LISTS.iter().find(|(list, _)| list == field)?;
+    if base.is_some_and(|base| strings(base).is_none()) {
+        return None;
+    }
+    let items = metadata::lists(base, strings(ours?)?, strings(theirs?)?);
+    match limit {
+        Some(limit) if items.len() > *limit => Some(Err(ConflictKind::Limit {
+            limit: *limit,
+            count: items.len(),
+        })),
+        _ => Some(Ok(Value::Array(items))),
+    }
+}
+
+/// The items of `value` when it is a list of strings.
+fn strings(value: &Value) -> Option<&Vec<Value>> {
+    let items = value.as_array()?;
+    items.iter().all(Value::is_str).then_some(items)
+}
+
+/// Whether `path` is a package's `license` or `license-file`.
+pub(crate) fn is_license(path: &[&str]) -> bool {
+    matches!(
+        path,
+        ["package", "license" | "license-file"]
+            | ["workspace", "package", "license" | "license-file"]
+    )
+}
+
+/// A proposal from the first built-in rule covering `path`, if any.
+pub(crate) fn propose(
+    options: &MergeOptions,
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let field = match path {
+        ["package", field] | ["workspace", "package", field] => *field,
+        ["profile", _, setting]
+        | ["profile", _, "build-override", setting]
+        | ["profile", _, "package", _, setting] => {
+            let setting = PROFILE_SETTINGS
+                .into_iter()
+                .find(|known| known == setting)?;
+            return profile_setting(options.profile.mode(setting)?, setting, ours?, theirs?);
+        }
+        ["lints", _, _]
+        | ["lints", _, _, "level"]
+        | ["workspace", "lints", _, _]
+        | ["workspace", "lints", _, _, "level"] => {
+            return lint_level(options.lint_levels, base, ours?, theirs?);
+        }
+        _ => return None,
+    };
+    match field {
+        "edition" if options.newest_edition => newest_edition(ours?, theirs?),
+        "version" if options.highest_version => highest_version(ours?, theirs?),
+        "rust-version" => rust_version(options.rust_version, ours?, theirs?),
+        "publish" if options.publish == PublishMode::Restrictive => {
+            restrictive_publish(ours, theirs)
+        }
+        "description" if options.description == DescriptionMode::Longer => {
+            longer_description(ours, theirs)
+        }
+        _ => None,
+    }
+}
+
+/// The newer of two editions (`"2015"` .. `"2024"`).
+fn newest_edition(ours: &Value, theirs: &Value) -> Option<Proposal> {
+    let year = |edition: &Value| edition.as_str()?.parse::<u32>().ok();
+    let newest = if year(ours)? >= year(theirs)? {
+        ours
+    } else {
+        theirs
+    };
+    Some(Proposal {
+        value: Some(newest.clone()),
+        rule: "edition",
+        confidence: 1.0,
+    })
+}
+
+/// The higher of two semver `version`s.
+fn highest_version(ours: &Value, theirs: &Value) -> Option<Proposal> {
+    let version = |value: &Value| Version::parse(value.as_str()?).ok();
+    let highest = if version(ours)? >= version(theirs)? {
+        ours
+    } else {
+        theirs
+    };
+    Some(Proposal {
+        value: Some(highest.clone()),
+        rule: "version",
+        confidence: 1.0,
+    })
+}
+
+/// The higher (`Max`) or lower (`Min`) of two `rust-version`s.
+fn rust_version(mode: RustVersionMode, ours: &Value, theirs: &Value) -> Option<Proposal> {
+    let (ours_version, theirs_version) = (msrv(ours)?, msrv(theirs)?);
+    let ours_wins = match mode {
+        RustVersionMode::Conflict => return None,
+        RustVersionMode::Max => ours_version >= theirs_version,
+        RustVersionMode::Min => ours_version <= theirs_version,
+    };
+    Some(Proposal {
+        value: Some(if ours_wins { ours } else { theirs }.clone()),
+        rule: "rust-version",
+        confidence: 1.0,
+    })
+}
+
+/// The more restrictive of two `publish` settings; a deleted one is `true`.
+fn restrictive_publish(ours: Option<&Value>, theirs: Option<&Value>) -> Option<Proposal> {
+    let (ours_allowed, theirs_allowed) = (registries(ours)?, registries(theirs)?);
+    let allowed = match (&ours_allowed, &theirs_allowed) {
+        (None, allowed) | (allowed, None) => allowed.clone(),
+        (Some(ours), Some(theirs)) => Some(
+            ours.iter()
+                .filter(|registry| theirs.contains(registry))
+                .copied()
+                .collect(),
+        ),
+    };
+    // Keep a side's own spelling where it already says the same.
+    let value = if allowed == ours_allowed {
+        ours.cloned()
+    } else if allowed == theirs_allowed {
+        theirs.cloned()
+    } else {
+        Some(Value::Array(
+            allowed
+                .unwrap_or_default()
+                .into_iter()
+                .map(|registry| Value::String(registry.to_string()))
+                .collect(),
+        ))
+    };
+    Some(Proposal {
+        value,
+        rule: "publish",
+        confidence: 1.0,
+    })
+}
+
+/// The registries a `publish` setting allows, `None` standing for any
+/// registry; `None` overall for a malformed setting.
+fn registries(value: Option<&Value>) -> Option<Option<Vec<&str>>> {
+    match value {
+        None | Some(Value::Boolean(true)) => Some(None),
+        Some(Value::Boolean(false)) => Some(Some(Vec::new())),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<_>>()
+            .map(Some),
+        Some(_) => None,
+    }
+}
+
+/// The longer of two `description`s, ours on a tie; a deleted one is empty.
+fn longer_description(ours: Option<&Value>, theirs: Option<&Value>) -> Option<Proposal> {
+    let length = |value: Option<&Value>| match value {
+        None => Some(0),
+        Some(value) => value.as_str().map(|text| text.trim().chars().count()),
+    };
+    let longer = if length(ours)? >= length(theirs)? {
+        ours
+    } else {
+        theirs
+    };
+    Some(Proposal {
+        value: longer.cloned(),
+        rule: "description",
+        confidence: 1.0,
+    })
+}
+
+/// The higher (`Max`) or lower (`Min`) of two values of a profile `setting`.
+fn profile_setting(
+    mode: ProfileMode,
+    setting: &'static str,
+    ours: &Value,
+    theirs: &Value,
+) -> Option<Proposal> {
+    let (ours_scale, ours_level) = profile_level(setting, ours)?;
+    let (theirs_scale, theirs_level) = profile_level(setting, theirs)?;
+    if ours_scale != theirs_scale {
+        return None;
+    }
+    let ours_wins = match mode {
+        ProfileMode::Conflict => return None,
+        ProfileMode::Max => ours_level >= theirs_level,
+        ProfileMode::Min => ours_level <= theirs_level,
+    };
+    Some(Proposal {
+        value: Some(if ours_wins { ours } else { theirs }.clone()),
+        rule: setting,
+        confidence: 1.0,
+    })
+}
+
+/// Lint levels from the loosest to the strictest.
+const LINT_LEVELS: [&str; 4] = ["allow", "warn", "deny", "forbid"];
+
+/// Two settings of a lint, each a level or a table with a `level`, merged:
+/// the level one side changed from `base`, or when both did the stricter
+/// (`Strictest`) or looser (`Loosest`) one, with the other keys of the side
+/// that wrote a table. Tables on both sides are merged key by key instead.
+fn lint_level(
+    mode: LintLevels,
+    base: Option<&Value>,
+    ours: &Value,
+    theirs: &Value,
+) -> Option<Proposal> {
+    fn level(value: &Value) -> Option<&str> {
+        match value {
+            Value::Table(table) => table.get("level")?.as_str(),
+            value => value.as_str(),
+        }
+    }
+    let rank = |level: &str| LINT_LEVELS.iter().position(|known| *known == level);
+    let (ours_level, theirs_level) = (level(ours)?, level(theirs)?);
+    let (ours_rank, theirs_rank) = (rank(ours_level)?, rank(theirs_level)?);
+    let base_level = base.and_then(level);
+    let merged = if ours_level == theirs_level || base_level == Some(theirs_level) {
+        ours_level
+    } else if base_level == Some(ours_level) {
+        theirs_level
+    } else {
+        match mode {
+            LintLevels::Conflict => return None,
+            LintLevels::Strictest if ours_rank > theirs_rank => ours_level,
+            LintLevels::Loosest if ours_rank < theirs_rank => ours_level,
+            _ => theirs_level,
+        }
+    };
+    let value = match (ours, theirs) {
+        (Value::Table(_), Value::Table(_)) => return None,
+        (Value::Table(table), _) | (_, Value::Table(table)) => {
+            let mut table = table.clone();
+            table.insert("level".to_string(), Value::String(merged.to_string()));
+            Value::Table(table)
+        }
+        _ => Value::String(merged.to_string()),
+    };
+    Some(Proposal {
+        value: Some(value),
+        rule: "lint-level",
+        confidence: 1.0,
+    })
+}
+
+/// Where `value` ranks among the values of a profile `setting`: levels are
+/// only comparable on the same scale (`opt-level`'s `"s"` and `"z"` are a
+/// scale of their own).
+fn profile_level(setting: &str, value: &Value) -> Option<(u8, i64)> {
+    let level = match (setting, value) {
+        ("opt-level" | "codegen-units", Value::Integer(level)) => *level,
+        ("opt-level", Value::String(level)) => {
+            return match level.as_str() {
+                "s" => Some((1, 0)),
+                "z" => Some((1, 1)),
+                _ => None,
+            };
+        }
+        ("debug", Value::Boolean(false) | Value::Integer(0)) => 0,
+        ("debug", Value::Integer(1)) => 3,
+        ("debug", Value::Boolean(true) | Value::Integer(2)) => 4,
+        ("debug", Value::String(level)) => match level.as_str() {
+            "none" => 0,
+            "line-directives-only" => 1,
+            "line-tables-only" => 2,
+            "limited" => 3,
+            "full" => 4,
+            _ => return None,
+        },
+        ("lto", Value::Boolean(false)) => 1,
+        ("lto", Value::Boolean(true)) => 3,
+        ("lto", Value::String(level)) => match level.as_str() {
+            "off" => 0,
+            "thin" => 2,
+            "fat" => 3,
+            _ => return None,
+        },
+        ("strip", Value::Boolean(false)) => 0,
+        ("strip", Value::Boolean(true)) => 2,
+        ("strip", Value::String(level)) => match level.as_str() {
+            "none" => 0,
+            "debuginfo" => 1,
+            "symbols" => 2,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((0, level))
+}
+
+/// The components of a `rust-version` (`"1.70"`, `"1.70.1"`), missing ones
+/// as zero.
+fn msrv(value: &Value) -> Option<[u64; 3]> {
+    let mut components = [0; 3];
+    let mut parts = value.as_str()?.split('.');
+    for (index, part) in parts.by_ref().take(3).enumerate() {
+        components[index] = part.parse().ok()?;
+    }
+    parts.next().is_none().then_some(components)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{
+        DescriptionMode, LicenseMode, LintLevels, MergeError, MergeOptions, ProfileMode,
+        PublishMode, Resolution, RustVersionMode, Side, merge_manifest_texts_detailed,
+        merge_manifest_texts_with,
+    };
+    use crate::timings::Timings;
+
+    #[test]
+    fn takes_the_newest_edition() {
+        let base = "[package]\nname = \"a\"\nedition = \"2018\"\n";
+        let ours = base.replace("2018", "2024");
+        let theirs = base.replace("2018", "2021");
+        let mut options = MergeOptions::default();
+
+        let merged = merge_manifest_texts_with(base, &theirs, &ours, &options).unwrap();
+        assert_eq!(merged, ours);
+        let workspace = |text: &str| text.replace("[package]", "[workspace.package]");
+        let merged = merge_manifest_texts_with(
+            &workspace(base),
+            &workspace(&ours),
+            &workspace(&theirs),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(merged, workspace(&ours));
+
+        options.newest_edition = false;
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+
+    #[test]
+    fn takes_the_higher_package_version() {
+        let base = "[package]\nname = \"a\"\nversion = \"0.3.0\"\n";
+        let ours = base.replace("0.3.0", "0.3.10");
+        let theirs = base.replace("0.3.0", "0.3.2");
+        let mut options = MergeOptions::default();
+
+        let result =
+            merge_manifest_texts_detailed(base, &ours, &theirs, &options, &mut Timings::default())
+                .unwrap();
+        assert_eq!(result.text, ours);
+        assert_eq!(
+            result.resolutions,
+            [Resolution {
+                path: "package.version".to_string(),
+                side: Some(Side::Ours),
+                rule: "version",
+            }]
+        );
+        // Not semver: left to the user.
+        let theirs = base.replace("0.3.0", "next");
+        assert!(merge_manifest_texts_with(base, &ours, &theirs, &options).is_err());
+
+        options.highest_version = false;
+        let theirs = base.replace("0.3.0", "0.3.2");
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+
+    #[test]
+    fn resolves_rust_version_changes_per_mode() {
+        let base = "[package]\nname = \"a\"\nrust-version = \"1.70\"\n";
+        let ours = base.replace("1.70", "1.74.1");
+        let theirs = base.replace("1.70", "1.80");
+        let mut options = MergeOptions::default();
+
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+        options.rust_version = RustVersionMode::Max;
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert_eq!(merged, theirs);
+        options.rust_version = RustVersionMode::Min;
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert_eq!(merged, ours);
+    }
+
+    #[test]
+    fn settles_lint_levels_key_by_key() {
+        let base = "[lints.rust]\nunsafe_code = \"warn\"\n\n\
+                    [lints.clippy]\npedantic = { level = \"warn\", priority = -1 }\n";
+        let ours = base
+            .replace(
+                "unsafe_code = \"warn\"",
+                "unsafe_code = \"deny\"\nmissing_docs = \"warn\"",
+            )
+            .replace("level = \"warn\"", "level = \"allow\"");
+        let theirs = base
+            .replace("unsafe_code = \"warn\"", "unsafe_code = \"forbid\"")
+            .replace("-1 }", "-1 }\nunwrap_used = \"deny\"")
+            .replace("level = \"warn\"", "level = \"deny\"");
+        let mut options = MergeOptions::default();
+
+        let result =
+            merge_manifest_texts_detailed(base, &ours, &theirs, &options, &mut Timings::default())
+                .unwrap();
+        let merged: toml::Table = result.text.parse().unwrap();
+        let expected: toml::Table = "[lints.rust]\nunsafe_code = \"forbid\"\nmissing_docs = \"warn\"\n\n\
+             [lints.clippy]\npedantic = { level = \"deny\", priority = -1 }\nunwrap_used = \"deny\"\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+        assert!(
+            result
+                .resolutions
+                .iter()
+                .all(|resolution| resolution.rule == "lint-level"
+                    && resolution.side == Some(Side::Theirs)),
+            "{:?}",
+            result.resolutions
+        );
+
+        let workspace = |text: &str| text.replace("[lints.", "[workspace.lints.");
+        options.lint_levels = LintLevels::Loosest;
+        let merged: toml::Table = merge_manifest_texts_with(
+            &workspace(base),
+            &workspace(&ours),
+            &workspace(&theirs),
+            &options,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+        let lints = &merged["workspace"]["lints"];
+        assert_eq!(lints["rust"]["unsafe_code"].as_str(), Some("deny"));
+        assert_eq!(lints["clippy"]["pedantic"]["level"].as_str(), Some("allow"));
+
+        options.lint_levels = LintLevels::Conflict;
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+
+        // Only levels are compared: a side's `priority` is kept.
+        let base = "[lints.clippy]\nall = \"warn\"\n";
+        let ours = base.replace("\"warn\"", "{ level = \"deny\", priority = -1 }");
+        let theirs = base.replace("warn", "forbid");
+        let lint = |options: &MergeOptions, theirs: &str| {
+            let merged: toml::Table = merge_manifest_texts_with(base, &ours, theirs, options)
+                .unwrap()
+                .parse()
+                .unwrap();
+            merged["lints"]["clippy"]["all"].clone()
+        };
+        let expected: toml::Value = "level = \"forbid\"\npriority = -1\n".parse().unwrap();
+        options.lint_levels = LintLevels::Strictest;
+        assert_eq!(lint(&options, &theirs), expected);
+        // Ours left the level alone: theirs' change stands, in any mode.
+        options.lint_levels = LintLevels::Conflict;
+        let ours = base.replace("\"warn\"", "{ level = \"warn\", priority = -1 }");
+        let merged: toml::Table =
+            merge_manifest_texts_with(base, &ours, &base.replace("warn", "deny"), &options)
+                .unwrap()
+                .parse()
+                .unwrap();
+        let expected: toml::Value = "level = \"deny\"\npriority = -1\n".parse().unwrap();
+        assert_eq!(merged["lints"]["clippy"]["all"], expected);
+    }
+
+    #[test]
+    fn settles_publishing_metadata() {
+        let base = "[package]\nname = \"a\"\npublish = [\"a\", \"b\", \"c\"]\n\
+                    description = \"A crate\"\nlicense = \"MIT\"\n";
+        let merge = |ours: &str, theirs: &str, options: &MergeOptions| {
+            merge_manifest_texts_with(base, ours, theirs, options)
+        };
+        let mut options = MergeOptions::default();
+
+        let ours = base.replace("[\"a\", \"b\", \"c\"]", "false");
+        let theirs = base.replace("\"a\", \"b\", ", "");
+        assert_eq!(merge(&ours, &theirs, &options).unwrap(), ours);
+        let ours = base.replace("\"c\"]", "\"c\", \"d\"]");
+        let theirs = base.replace("\"a\", ", "");
+        assert_eq!(
+            merge(&ours, &theirs, &options).unwrap(),
+            base.replace("[\"a\", \"b\", \"c\"]", "[\"b\", \"c\"]")
+        );
+        options.publish = PublishMode::Conflict;
+        assert!(merge(&ours, &theirs, &options).is_err());
+
+        let ours = base.replace("A crate", "A crate for merging");
+        let theirs = base.replace("A crate", "Merges things");
+        assert_eq!(merge(&ours, &theirs, &options).unwrap(), ours);
+        options.description = DescriptionMode::Conflict;
+        assert!(merge(&ours, &theirs, &options).is_err());
+
+        // Even a fallback side leaves license conflicts alone.
+        let ours = base.replace("MIT", "Apache-2.0");
+        let theirs = base.replace("MIT", "MIT OR Apache-2.0");
+        options.heuristics.push(|_, _, ours, _| {
+            Some(crate::merge::Proposal {
+                value: ours.cloned(),
+                rule: "prefer-ours",
+                confidence: 1.0,
+            })
+        });
+        let err = merge(&ours, &theirs, &options).unwrap_err();
+        assert!(
+            matches!(&err, MergeError::Conflict(conflict) if conflict.path == "package.license"),
+            "{err:?}"
+        );
+        options.license = LicenseMode::Conflict;
+        assert_eq!(merge(&ours, &theirs, &options).unwrap(), ours);
+    }
+
+    #[test]
+    fn unites_package_lists_within_crates_io_limits() {
+        let base = "[package]\nname = \"a\"\nkeywords = [\"toml\", \"git\"]\n\
+                    include = [\"src/\", \"README.md\"]\n";
+        let options = MergeOptions::default();
+        let ours = base
+            .replace("\"git\"]", "\"git\", \"merge\"]")
+            .replace("\"src/\", ", "");
+        let theirs = base
+            .replace("[\"toml\", ", "[\"toml\", \"cargo\", ")
+            .replace("\"README.md\"]", "\"README.md\", \"LICENSE\"]");
+        assert_eq!(
+            merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap(),
+            "[package]\nname = \"a\"\nkeywords = [\"toml\", \"git\", \"merge\", \"cargo\"]\n\
+             include = [\"README.md\", \"LICENSE\"]\n"
+        );
+
+        let theirs = base.replace("\"git\"]", "\"git\", \"vcs\", \"driver\", \"ci\"]");
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = &err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "package.keywords");
+        assert_eq!(
+            conflict.kind,
+            super::ConflictKind::Limit { limit: 5, count: 6 }
+        );
+        assert!(
+            err.to_string().contains("crates.io accepts at most 5"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn settles_profile_settings_per_rule() {
+        let base = "[profile.release]\nopt-level = 2\ncodegen-units = 16\nlto = \"thin\"\n\n\
+                    [profile.release.package.ring]\nopt-level = \"s\"\n";
+        let ours = base
+            .replace("opt-level = 2", "opt-level = 3")
+            .replace("16", "4")
+            .replace("\"thin\"", "true");
+        let theirs = base
+            .replace("opt-level = 2", "opt-level = 1")
+            .replace("16", "1")
+            .replace("\"thin\"", "false");
+        let mut options = MergeOptions::default();
+
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        assert!(
+            matches!(&err, MergeError::Conflict(conflict) if conflict.path == "profile.release.lto"),
+            "{err:?}"
+        );
+        options.profile.lto = ProfileMode::Max;
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert_eq!(
+            merged,
+            ours.replace("codegen-units = 4", "codegen-units = 1")
+        );
+        options.profile.lto = ProfileMode::Min;
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("lto = false"), "{merged}");
+
+        // `"s"` and `"z"` rank among themselves, not against levels.
+        let ours = base.replace("\"s\"", "\"z\"");
+        let theirs = base.replace("\"s\"", "3");
+        assert!(merge_manifest_texts_with(base, &ours, &theirs, &options).is_err());
+    }
+}
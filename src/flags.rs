@@ -0,0 +1,125 @@
+//! The `optional` and `default-features` flags of dependency entries.
+//!
+//! A flag left out means its default: `serde = "1"`, `serde = { version = "1" }`
+//! and `serde = { version = "1", optional = false, default-features = true }`
+//! are the same dependency. Compared as written, a branch that spells out a
+//! default while editing the entry (`optional = true, default-features =
+//! true`) conflicts with a branch that changes that flag (`default-features =
+//! false`), although only the latter changed it. [`merge`] compares the flags
+//! by their meaning instead, so each side's change to each flag carries over.
+//!
+//! `default_features` is the deprecated spelling of `default-features`. When
+//! the sides spell one entry's flag differently, [`normalize`] renames it in
+//! base and theirs to ours' spelling, so the merge sees one key rather than
+//! leaving both behind.
+
+use toml::Value;
+
+use crate::pins::{dependency_tables, lookup, lookup_mut};
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The spellings of the `default-features` flag, current one first.
+const DEFAULT_FEATURES: [&str; 2] = ["default-features", "default_features"];
+
+/// Renames `default-features` in the dependency entries of `base` and
+/// `theirs` to the spelling ours uses (theirs' in base when ours has
+/// neither).
+pub(crate) fn normalize(base: &mut Value, ours: &Value, theirs: &mut Value) {
+    for table in dependency_tables([base, ours, theirs]) {
+        let mut keys: Vec<String> = [&*base, ours, &*theirs]
+            .iter()
+            .filter_map(|doc| lookup(doc, &table).and_then(Value::as_table))
+            .flat_map(|entries| entries.keys().cloned())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let mut path = table.clone();
+            path.push(key);
+            let spelling = |doc: &Value| {
+                let entry = lookup(doc, &path)?.as_table()?;
+                DEFAULT_FEATURES
+                    .into_iter()
+                    .find(|spelling| entry.contains_key(*spelling))
+            };
+            let Some(wanted) = spelling(ours).or_else(|| spelling(theirs)) else {
+                continue;
+            };
+            for doc in [&mut *base, &mut *theirs] {
+                if let Some(Value::Table(entry)) = lookup_mut(doc, &path)
+                    && !entry.contains_key(wanted)
+                    && let Some(other) = DEFAULT_FEATURES.iter().find(|other| **other != wanted)
+                    && let Some(value) = entry.remove(*other)
+                {
+                    entry.insert(wanted.to_string(), value);
+                }
+            }
+        }
+    }
+}
+
+/// The side whose value the flag at `path` takes, when it is a dependency
+/// flag and all three sides are booleans or left it out; `None` leaves it
+/// to the usual merge.
+pub(crate) fn merge<'a>(
+    path: &[&str],
+    base: Option<&'a Value>,
+    ours: Option<&'a Value>,
+    theirs: Option<&'a Value>,
+) -> Option<Option<&'a Value>> {
+    let default = default(path)?;
+    let flag = |value: Option<&Value>| match value {
+        None => Some(default),
+        Some(value) => value.as_bool(),
+    };
+    let [base_flag, ours_flag, theirs_flag] = [base, ours, theirs].map(flag);
+    let (base_flag, ours_flag, theirs_flag) = (base_flag?, ours_flag?, theirs_flag?);
+    // Booleans: a side that changed the flag changed it to what the other
+    // side did, if that changed it too.
+    Some(if ours_flag == base_flag && theirs_flag != base_flag {
+        theirs
+    } else {
+        ours
+    })
+}
+
+/// The default of the dependency flag at `path`, if it is one.
+fn default(path: &[&str]) -> Option<bool> {
+    let entry = match path {
+        ["target", _, rest @ ..] | ["workspace", rest @ ..] => rest,
+        _ => path,
+    };
+    match entry {
+        [table, _, "optional"] if DEPENDENCY_TABLES.contains(table) => Some(false),
+        [table, _, key] if DEPENDENCY_TABLES.contains(table) && DEFAULT_FEATURES.contains(key) => {
+            Some(true)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::merge_manifest_texts;
+
+    #[test]
+    fn merges_flags_by_their_meaning() {
+        let base = "[dependencies]\nserde = \"1\"\nlog = { version = \"0.4\", default_features = false }\n";
+        let ours = "[dependencies]\nserde = { version = \"1\", optional = true, default-features = true }\n\
+                    log = { version = \"0.4\", default_features = false, optional = true }\n";
+        let theirs = "[dependencies]\nserde = { version = \"1\", default-features = false }\n\
+                      log = { version = \"0.4.22\", default-features = false }\n";
+
+        let merged: toml::Table = merge_manifest_texts(base, ours, theirs)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected: toml::Table = "[dependencies]\n\
+             serde = { version = \"1\", optional = true, default-features = false }\n\
+             log = { version = \"0.4.22\", default_features = false, optional = true }\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,160 @@
+//! Symlinked and generated manifests.
+//!
+//! Some build setups render `Cargo.toml` from a template or link it from
+//! elsewhere in the tree. A symlinked output is written through to its
+//! target, which [`resolve_target`] only allows for a regular file inside the
+//! repository holding the link. A manifest whose leading comments carry the
+//! [`SENTINEL`] is generated: merging it would be overwritten by the next
+//! generation, so the driver refuses to, or runs the user's regeneration
+//! command ([`regenerate`]) instead.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The marker of a generated manifest, in a comment before its first key or
+/// table, as cargo writes at the top of `Cargo.lock`.
+pub const SENTINEL: &str = "@generated";
+
+/// Whether the comments heading `text` mark it as generated.
+pub fn is_generated(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .any(|line| line.contains(SENTINEL))
+}
+
+/// The file writing to `out` changes: `out` itself, or the target of the
+/// symlink it is. Fails for a dangling link, a link to something other than a
+/// regular file, and one leading out of the git repository containing it.
+pub fn resolve_target(out: &Path) -> io::Result<PathBuf> {
+    let is_symlink = out
+        .symlink_metadata()
+        .is_ok_and(|meta| meta.file_type().is_symlink());
+    if !is_symlink {
+        return Ok(out.to_path_buf());
+    }
+    let target = out
+        .canonicalize()
+        .map_err(|err| io::Error::new(err.kind(), format!("symlink cannot be resolved: {err}")))?;
+    if !target.is_file() {
+        return Err(io::Error::other(format!(
+            "symlink points to {}, which is not a file",
+            target.display()
+        )));
+    }
+    let dir = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Some(root) = toplevel(dir)
+        && !target.starts_with(&root)
+    {
+        return Err(io::Error::other(format!(
+            "symlink points to {}, outside the repository at {}",
+            target.display(),
+            root.display()
+        )));
+    }
+    Ok(target)
+}
+
+/// Runs the shell `command` to produce the manifest at `out` from the
+/// `inputs` (base, ours, theirs), which it finds in the environment as
+/// `CARGO_MERGE_ASSIST_BASE`, `_OURS`, `_THEIRS` and `_OUT`.
+pub fn regenerate(command: &str, inputs: [&Path; 3], out: &Path) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let [base, ours, theirs] = inputs;
+    let status = shell
+        .arg(command)
+        .env("CARGO_MERGE_ASSIST_BASE", base)
+        .env("CARGO_MERGE_ASSIST_OURS", ours)
+        .env("CARGO_MERGE_ASSIST_THEIRS", theirs)
+        .env("CARGO_MERGE_ASSIST_OUT", out)
+        .status()
+        .map_err(|err| format!("cannot run `{command}`: {err}"))?;
+    if !status.success() {
+        return Err(format!("`{command}` failed ({status})"));
+    }
+    Ok(())
+}
+
+/// The canonical top-level directory of the repository containing `dir`.
+fn toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    PathBuf::from(String::from_utf8(output.stdout).ok()?.trim())
+        .canonicalize()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_generated_manifests() {
+        assert!(is_generated(
+            "# This file is @generated from Cargo.toml.in; do not edit.\n\n[package]\n"
+        ));
+        assert!(!is_generated(
+            "[package]\n# @generated below\nname = \"a\"\n"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_symlinks_inside_the_repository() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let scratch = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-generated-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&scratch);
+        let repo = scratch.join("repo");
+        fs::create_dir_all(repo.join("templates")).unwrap();
+        assert!(
+            Command::new("git")
+                .args(["init", "--quiet"])
+                .arg(&repo)
+                .status()
+                .unwrap()
+                .success()
+        );
+        fs::write(repo.join("templates/Cargo.toml"), "").unwrap();
+        fs::write(scratch.join("Cargo.toml"), "").unwrap();
+
+        let inside = repo.join("Cargo.toml");
+        symlink("templates/Cargo.toml", &inside).unwrap();
+        assert_eq!(
+            resolve_target(&inside).unwrap(),
+            repo.join("templates/Cargo.toml").canonicalize().unwrap()
+        );
+        let outside = repo.join("templates/Outside.toml");
+        symlink(scratch.join("Cargo.toml"), &outside).unwrap();
+        assert!(resolve_target(&outside).is_err());
+        let dangling = repo.join("Dangling.toml");
+        symlink("missing/Cargo.toml", &dangling).unwrap();
+        assert!(resolve_target(&dangling).is_err());
+        let plain = repo.join("templates/Cargo.toml");
+        assert_eq!(resolve_target(&plain).unwrap(), plain);
+        fs::remove_dir_all(&scratch).unwrap();
+    }
+}
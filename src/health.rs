@@ -0,0 +1,223 @@
+//! Cheap runtime check that the git merge drivers are still wired up.
+//!
+//! Installation is a one-off (`install-git-driver`), and repositories drift
+//! afterwards: `.gitattributes` gets reorganized, `git maintenance` or a
+//! fresh clone drops local config, the binary gets renamed. Git then falls
+//! back to its text merge without a word. [`check`] costs two `git`
+//! invocations and is run by the drivers themselves, so the next merge
+//! notices when coverage was lost.
+//!
+//! A repository where nothing is installed is not reported: only partial
+//! installations are drift.
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// Driver named by `.gitattributes` for `Cargo.toml`.
+pub const MANIFEST_DRIVER: &str = "cargo-merge-assist-manifest";
+/// Driver named by `.gitattributes` for `Cargo.lock`.
+pub const LOCK_DRIVER: &str = "cargo-merge-assist-lock";
+/// Driver named by `.gitattributes` for `.cargo/config.toml`; not checked,
+/// since installations predating it are not broken.
+pub const CONFIG_DRIVER: &str = "cargo-merge-assist-config";
+
+const DRIVERS: [(&str, &str); 2] = [("Cargo.toml", MANIFEST_DRIVER), ("Cargo.lock", LOCK_DRIVER)];
+
+/// A way the installation no longer covers Cargo files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthIssue {
+    /// `.gitattributes` no longer maps `file` to `driver`.
+    Unmapped {
+        file: &'static str,
+        driver: &'static str,
+    },
+    /// `merge.<driver>.driver` is not configured.
+    Unconfigured { driver: &'static str },
+    /// The configured command's program cannot be found.
+    MissingProgram {
+        driver: &'static str,
+        program: String,
+    },
+}
+
+impl fmt::Display for HealthIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unmapped { file, driver } => {
+                write!(
+                    f,
+                    "{file} is no longer mapped to merge={driver} in .gitattributes"
+                )
+            }
+            Self::Unconfigured { driver } => {
+                write!(
+                    f,
+                    "merge driver {driver} is not configured (merge.{driver}.driver)"
+                )
+            }
+            Self::MissingProgram { driver, program } => {
+                write!(
+                    f,
+                    "merge driver {driver} runs `{program}`, which is not on PATH"
+                )
+            }
+        }
+    }
+}
+
+/// Checks the driver installation of the repository containing `repo`.
+/// Returns nothing outside a git repository or when nothing is installed.
+pub fn check(repo: &Path) -> Vec<HealthIssue> {
+    let Some(attributes) = git(
+        repo,
+        &["check-attr", "merge", "--", "Cargo.toml", "Cargo.lock"],
+    ) else {
+        return Vec::new();
+    };
+    let commands = git(
+        repo,
+        &[
+            "config",
+            "--get-regexp",
+            r"^merge\.cargo-merge-assist-.*\.driver$",
+        ],
+    )
+    .unwrap_or_default();
+
+    let mut issues = Vec::new();
+    let mut installed = false;
+    for (file, driver) in DRIVERS {
+        let mapped = attributes
+            .lines()
+            .any(|line| line == format!("{file}: merge: {driver}"));
+        installed |= mapped;
+        if !mapped {
+            issues.push(HealthIssue::Unmapped { file, driver });
+        }
+
+        let key = format!("merge.{driver}.driver ");
+        let command = commands.lines().find_map(|line| line.strip_prefix(&key));
+        installed |= command.is_some();
+        match command.and_then(|command| command.split_whitespace().next()) {
+            None => issues.push(HealthIssue::Unconfigured { driver }),
+            Some(program) if !program_exists(repo, program) => {
+                issues.push(HealthIssue::MissingProgram {
+                    driver,
+                    program: program.to_string(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    if installed { issues } else { Vec::new() }
+}
+
+/// The command that restores the installation for `repo`.
+pub fn fix_command(repo: &Path) -> String {
+    format!(
+        "cargo-merge-assist install-git-driver --repo {}",
+        repo.display()
+    )
+}
+
+fn git(repo: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Whether `program` resolves the way the shell running the driver would
+/// resolve it: a path (relative to the repository, where git runs drivers)
+/// or a name on `PATH`.
+fn program_exists(repo: &Path, program: &str) -> bool {
+    if program.contains('/') {
+        return repo.join(program).is_file();
+    }
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| {
+        dir.join(program).is_file()
+            || (cfg!(windows) && dir.join(format!("{program}.exe")).is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reports_lost_coverage_only_for_installed_repositories() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-health-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        assert_eq!(check(&repo), []);
+
+        fs::write(
+            repo.join(".gitattributes"),
+            format!("Cargo.toml merge={MANIFEST_DRIVER}\nCargo.lock merge={LOCK_DRIVER}\n"),
+        )
+        .unwrap();
+        // `git` stands in for the installed binary, which is on PATH.
+        git(&[
+            "config",
+            &format!("merge.{MANIFEST_DRIVER}.driver"),
+            "git merge-file %A %O %B",
+        ]);
+        git(&[
+            "config",
+            &format!("merge.{LOCK_DRIVER}.driver"),
+            "git status",
+        ]);
+        assert_eq!(check(&repo), []);
+
+        fs::write(
+            repo.join(".gitattributes"),
+            format!("Cargo.toml merge={MANIFEST_DRIVER}\n"),
+        )
+        .unwrap();
+        git(&[
+            "config",
+            &format!("merge.{LOCK_DRIVER}.driver"),
+            "renamed-binary resolve-lock",
+        ]);
+        let issues = check(&repo);
+        assert_eq!(
+            issues,
+            [
+                HealthIssue::Unmapped {
+                    file: "Cargo.lock",
+                    driver: LOCK_DRIVER
+                },
+                HealthIssue::MissingProgram {
+                    driver: LOCK_DRIVER,
+                    program: "renamed-binary".to_string()
+                },
+            ]
+        );
+
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+//! Registry-backed merge heuristics (`--online-hints`).
+//!
+//! When both sides changed a dependency requirement differently, the side
+//! whose requirement admits the newest published release is probably the
+//! deliberate upgrade. [`latest_published`] proposes that side; it queries
+//! the crates.io sparse index, so it is only enabled on request.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use semver::{Version, VersionReq};
+use toml::Value;
+
+use crate::estimate::RegistryIndex;
+use crate::merge::Proposal;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Confidence of [`latest_published`]: a good signal, not proof of intent.
+pub const LATEST_PUBLISHED_CONFIDENCE: f64 = 0.7;
+
+/// [`Heuristic`](crate::merge::Heuristic) preferring the side whose version
+/// requirement matches the latest published compatible release.
+pub fn latest_published(
+    path: &[&str],
+    _base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let name = dependency_name(path)?;
+    let ours_req = VersionReq::parse(ours?.as_str()?).ok()?;
+    let theirs_req = VersionReq::parse(theirs?.as_str()?).ok()?;
+
+    let versions = published_versions(name);
+    let latest = versions
+        .iter()
+        .filter(|version| ours_req.matches(version) || theirs_req.matches(version))
+        .max()?;
+    let value = match (ours_req.matches(latest), theirs_req.matches(latest)) {
+        (true, false) => ours,
+        (false, true) => theirs,
+        _ => return None,
+    };
+    Some(Proposal {
+        value: value.cloned(),
+        rule: "online-hints",
+        confidence: LATEST_PUBLISHED_CONFIDENCE,
+    })
+}
+
+/// The dependency a requirement path belongs to, for `<table>.<name>` and
+/// `<table>.<name>.version` under the package, a `target.<cfg>` or
+/// `workspace`. Renamed dependencies are looked up by their key.
+pub(crate) fn dependency_name<'a>(path: &[&'a str]) -> Option<&'a str> {
+    let path = match path {
+        ["target", _, rest @ ..] | ["workspace", rest @ ..] => rest,
+        _ => path,
+    };
+    match path {
+        [table, name] | [table, name, "version"] if DEPENDENCY_TABLES.contains(table) => Some(name),
+        _ => None,
+    }
+}
+
+/// Non-yanked versions of `name`, fetched once per process.
+fn published_versions(name: &str) -> Vec<Version> {
+    static INDEX: OnceLock<RegistryIndex> = OnceLock::new();
+    static SEEN: OnceLock<Mutex<HashMap<String, Vec<Version>>>> = OnceLock::new();
+
+    let seen = SEEN.get_or_init(Default::default);
+    if let Some(versions) = seen.lock().ok().and_then(|seen| seen.get(name).cloned()) {
+        return versions;
+    }
+    let versions: Vec<Version> = INDEX
+        .get_or_init(RegistryIndex::online)
+        .versions(name)
+        .into_iter()
+        .filter(|(_, yanked)| !yanked)
+        .map(|(version, _)| version)
+        .collect();
+    if let Ok(mut seen) = seen.lock() {
+        seen.insert(name.to_string(), versions.clone());
+    }
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_dependency_requirement_paths() {
+        assert_eq!(dependency_name(&["dependencies", "serde"]), Some("serde"));
+        assert_eq!(
+            dependency_name(&["target", "cfg(unix)", "dev-dependencies", "libc", "version"]),
+            Some("libc")
+        );
+        assert_eq!(
+            dependency_name(&["workspace", "dependencies", "toml"]),
+            Some("toml")
+        );
+        assert_eq!(dependency_name(&["package", "version"]), None);
+        assert_eq!(
+            dependency_name(&["dependencies", "serde", "features"]),
+            None
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! One-sided migration of dependencies to workspace inheritance.
+//!
+//! A branch moving a member to `serde = { workspace = true }` deletes the
+//! member's requirement, so a branch bumping `serde = "1"` to `"1.0.210"`
+//! conflicts with it. Both are kept when the bump moves to where the
+//! requirement now lives, the root's `[workspace.dependencies]`.
+//! [`migrate`] takes the migration in the member (with any other change the
+//! bumping side made to the entry) and, when the migrating side's manifest
+//! is the root itself, the bump in its `[workspace.dependencies]`. For a
+//! separate root, [`bumps`] lists what the root needs and [`apply`] edits it.
+//!
+//! After the merge, [`unresolved`] cross-checks a member's inherited
+//! dependencies against the merged root, which cargo rejects when an entry is
+//! missing.
+
+use std::fmt;
+
+use toml::Value;
+use toml_edit::DocumentMut;
+
+use crate::merge::{Resolution, Side};
+use crate::pins::{dependency_tables, lookup, lookup_mut};
+
+/// A requirement bump carried into `[workspace.dependencies]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bump {
+    /// The key of the entry in `[workspace.dependencies]`.
+    pub name: String,
+    /// The member's requirement before the merge.
+    pub from: String,
+    /// The requirement the bumping side set.
+    pub to: String,
+    /// The side that bumped it.
+    pub side: Side,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "workspace.dependencies.{} = \"{}\" (was \"{}\", bumped by {})",
+            self.name, self.to, self.from, self.side
+        )
+    }
+}
+
+/// A dependency inherited from the workspace that the root does not declare.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    /// Dotted path of the member's entry, e.g. `dev-dependencies.serde`.
+    pub entry: String,
+    /// The key it inherits.
+    pub name: String,
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` has `workspace = true`, but the root's [workspace.dependencies] has no `{}`",
+            self.entry, self.name
+        )
+    }
+}
+
+/// An entry one side moved to inheritance while the other bumped it.
+struct Migration {
+    entry: Vec<String>,
+    bump: Bump,
+}
+
+/// Rewrites each dependency entry one side moved to `workspace = true` while
+/// the other bumped its requirement: base's and the bumping side's entries
+/// inherit too, so the rest of the bumping side's changes still merge. The
+/// bump goes into the migrating side's own `[workspace.dependencies]` when it
+/// has the entry at the old requirement. Returns the resolutions.
+pub(crate) fn migrate(base: &mut Value, ours: &mut Value, theirs: &mut Value) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    for Migration { entry, bump } in migrations(base, ours, theirs) {
+        let (migrated, bumping) = match bump.side {
+            Side::Theirs => (&mut *ours, &mut *theirs),
+            _ => (&mut *theirs, &mut *ours),
+        };
+        for doc in [&mut *base, bumping] {
+            if let Some(value) = lookup_mut(doc, &entry) {
+                let mut table = value.as_table().cloned().unwrap_or_default();
+                table.remove("version");
+                table.insert("workspace".to_string(), Value::Boolean(true));
+                *value = Value::Table(table);
+            }
+        }
+        let inherited = ["workspace", "dependencies", &bump.name].map(str::to_string);
+        if let Some(value) = lookup_mut(migrated, &inherited)
+            && requirement(value) == Some(&bump.from)
+        {
+            set_requirement(value, &bump.to);
+        }
+        resolutions.push(Resolution {
+            path: format!("{}.version", entry.join(".")),
+            side: Some(bump.side),
+            rule: "workspace-inheritance",
+        });
+    }
+    resolutions
+}
+
+/// The bumps [`migrate`] leaves to a separate workspace root: those whose
+/// migrating side has no `[workspace.dependencies]` entry of its own.
+pub fn bumps(base: &Value, ours: &Value, theirs: &Value) -> Vec<Bump> {
+    let mut bumps: Vec<Bump> = Vec::new();
+    for Migration { bump, .. } in migrations(base, ours, theirs) {
+        let migrated = if bump.side == Side::Theirs {
+            ours
+        } else {
+            theirs
+        };
+        let local = lookup(
+            migrated,
+            &["workspace", "dependencies", &bump.name].map(str::to_string),
+        );
+        if local.is_none() && !bumps.iter().any(|known| known.name == bump.name) {
+            bumps.push(bump);
+        }
+    }
+    bumps
+}
+
+/// Applies `bumps` to the `[workspace.dependencies]` of the root manifest
+/// `root_text`, keeping its formatting. An entry is bumped only while it
+/// still has the member's old requirement, and added when missing. Returns
+/// the new text and the bumps skipped because the root's entry has moved on.
+pub fn apply<'a>(root_text: &str, bumps: &'a [Bump]) -> Result<(String, Vec<&'a Bump>), String> {
+    let mut doc: DocumentMut = root_text
+        .parse()
+        .map_err(|err: toml_edit::TomlError| err.message().to_string())?;
+    let Some(workspace) = doc
+        .get_mut("workspace")
+        .and_then(|item| item.as_table_like_mut())
+    else {
+        return Err("the root manifest has no [workspace] table".to_string());
+    };
+    let dependencies = workspace
+        .entry("dependencies")
+        .or_insert(toml_edit::table());
+    let Some(dependencies) = dependencies.as_table_like_mut() else {
+        return Err("`workspace.dependencies` is not a table".to_string());
+    };
+    let mut skipped = Vec::new();
+    for bump in bumps {
+        let slot = match dependencies.get_mut(&bump.name) {
+            None => {
+                dependencies.insert(&bump.name, toml_edit::value(bump.to.as_str()));
+                continue;
+            }
+            Some(item) if item.is_str() => item.as_value_mut(),
+            Some(item) => item
+                .as_table_like_mut()
+                .and_then(|entry| entry.get_mut("version"))
+                .and_then(toml_edit::Item::as_value_mut),
+        };
+        match slot {
+            Some(value) if value.as_str() == Some(&bump.to) => {}
+            Some(value) if value.as_str() == Some(&bump.from) => {
+                let decor = value.decor().clone();
+                *value = bump.to.as_str().into();
+                *value.decor_mut() = decor;
+            }
+            _ => skipped.push(bump),
+        }
+    }
+    Ok((doc.to_string(), skipped))
+}
+
+/// The dependencies of `member` inheriting from the workspace that the
+/// `[workspace.dependencies]` of `root` lacks. `member` may be `root`.
+pub fn unresolved(member: &Value, root: &Value) -> Vec<Unresolved> {
+    let declared = root
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(Value::as_table);
+    let mut unresolved = Vec::new();
+    for table in dependency_tables([member, member, member]) {
+        if table.first().is_some_and(|key| key == "workspace") {
+            continue;
+        }
+        let entries = lookup(member, &table).and_then(Value::as_table);
+        for (name, entry) in entries.into_iter().flatten() {
+            if inherits(entry) && !declared.is_some_and(|declared| declared.contains_key(name)) {
+                unresolved.push(Unresolved {
+                    entry: format!("{}.{name}", table.join(".")),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+    unresolved
+}
+
+fn migrations(base: &Value, ours: &Value, theirs: &Value) -> Vec<Migration> {
+    let mut migrations = Vec::new();
+    for table in dependency_tables([base, ours, theirs]) {
+        if table.first().is_some_and(|key| key == "workspace") {
+            continue;
+        }
+        let keys = lookup(base, &table)
+            .and_then(Value::as_table)
+            .into_iter()
+            .flat_map(|entries| entries.keys());
+        for key in keys {
+            let mut entry = table.clone();
+            entry.push(key.clone());
+            let [base_entry, ours_entry, theirs_entry] =
+                [base, ours, theirs].map(|doc| lookup(doc, &entry));
+            let Some(from) = base_entry.filter(|e| !inherits(e)).and_then(requirement) else {
+                continue;
+            };
+            for (migrated, bumping, side) in [
+                (ours_entry, theirs_entry, Side::Theirs),
+                (theirs_entry, ours_entry, Side::Ours),
+            ] {
+                let to = bumping.filter(|e| !inherits(e)).and_then(requirement);
+                if migrated.is_some_and(inherits)
+                    && let Some(to) = to.filter(|to| *to != from)
+                {
+                    migrations.push(Migration {
+                        entry: entry.clone(),
+                        bump: Bump {
+                            name: key.clone(),
+                            from: from.to_string(),
+                            to: to.to_string(),
+                            side,
+                        },
+                    });
+                }
+            }
+        }
+    }
+    migrations
+}
+
+/// Whether `entry` inherits from the workspace.
+fn inherits(entry: &Value) -> bool {
+    entry.get("workspace") == Some(&Value::Boolean(true))
+}
+
+fn requirement(entry: &Value) -> Option<&str> {
+    match entry {
+        Value::String(req) => Some(req),
+        Value::Table(table) => table.get("version").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+fn set_requirement(entry: &mut Value, req: &str) {
+    match entry {
+        Value::Table(table) => {
+            table.insert("version".to_string(), Value::String(req.to_string()));
+        }
+        entry => *entry = Value::String(req.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge_manifest_texts;
+
+    #[test]
+    fn carries_the_bump_into_workspace_dependencies() {
+        let base = "[package]\nname = \"a\"\n\n[dependencies]\nserde = \"1\"\n";
+        let ours = "[package]\nname = \"a\"\n\n[dependencies]\nserde = { workspace = true }\n";
+        let theirs = "[package]\nname = \"a\"\n\n[dependencies]\n\
+                      serde = { version = \"1.0.210\", features = [\"derive\"] }\n";
+        let merged: toml::Table = merge_manifest_texts(base, ours, theirs)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected: toml::Table = "[package]\nname = \"a\"\n\n[dependencies]\n\
+             serde = { workspace = true, features = [\"derive\"] }\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+        let [base, ours, theirs] = [base, ours, theirs].map(|text| text.parse().unwrap());
+        let bumps = bumps(&base, &ours, &theirs);
+        assert_eq!(
+            bumps,
+            [Bump {
+                name: "serde".to_string(),
+                from: "1".to_string(),
+                to: "1.0.210".to_string(),
+                side: Side::Theirs,
+            }]
+        );
+
+        let root = "[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies]\n\
+                    serde = { version = \"1\", default-features = false } # pinned\nlog = \"0.4\"\n";
+        let (text, skipped) = apply(root, &bumps).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(
+            text,
+            root.replace("version = \"1\"", "version = \"1.0.210\"")
+        );
+        let (_, skipped) = apply(&root.replace("\"1\"", "\"1.1\""), &bumps).unwrap();
+        assert_eq!(skipped, [&bumps[0]]);
+
+        let root: Value = root.parse().unwrap();
+        let member: Value =
+            "[dev-dependencies]\nserde.workspace = true\nrand = { workspace = true }\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            unresolved(&member, &root),
+            [Unresolved {
+                entry: "dev-dependencies.rand".to_string(),
+                name: "rand".to_string(),
+            }]
+        );
+
+        // A root package migrating its own member section bumps its own
+        // workspace entry.
+        let base = "[workspace.dependencies]\nserde = \"1\"\n\n[dependencies]\nserde = \"1\"\n";
+        let ours = base.replace("serde = \"1\"\n\n", "serde = \"1\"\nlog = \"0.4\"\n\n");
+        let ours = ours.replace(
+            "[dependencies]\nserde = \"1\"",
+            "[dependencies]\nserde.workspace = true",
+        );
+        let theirs = base.replace(
+            "[dependencies]\nserde = \"1\"",
+            "[dependencies]\nserde = \"1.0.210\"",
+        );
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[workspace.dependencies]\nserde = \"1.0.210\"\nlog = \"0.4\"\n\n\
+             [dependencies]\nserde.workspace = true\n"
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! First-run setup (`init`).
+//!
+//! Adopting the tool means writing a policy and wiring three merge drivers
+//! into `.gitattributes` and git config with the right flags. `init` does it
+//! from a [`Survey`] of the repository and a few [`Answers`]: [`policy`]
+//! renders the `.cargo-merge-assist.toml`, [`attributes`] and [`drivers`]
+//! the driver installation for the files the repository actually has. The
+//! command asks the questions; this module only decides what to write.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::discover::Excludes;
+use crate::health::{CONFIG_DRIVER, LOCK_DRIVER, MANIFEST_DRIVER};
+
+/// The dependency tables whose requirements `semver-max` resolves.
+const DEPENDENCY_PATHS: [&str; 5] = [
+    "dependencies.*",
+    "dev-dependencies.*",
+    "build-dependencies.*",
+    "target.*dependencies.*",
+    "workspace.dependencies.*",
+];
+
+/// A CI system the repository is set up for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ci {
+    GitHubActions,
+    GitLab,
+    CircleCi,
+    AzurePipelines,
+    Buildkite,
+    Jenkins,
+}
+
+impl Ci {
+    /// The files each system is configured by, relative to the root.
+    const FILES: [(&str, Ci); 6] = [
+        (".github/workflows", Ci::GitHubActions),
+        (".gitlab-ci.yml", Ci::GitLab),
+        (".circleci/config.yml", Ci::CircleCi),
+        ("azure-pipelines.yml", Ci::AzurePipelines),
+        (".buildkite", Ci::Buildkite),
+        ("Jenkinsfile", Ci::Jenkins),
+    ];
+}
+
+impl fmt::Display for Ci {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Ci::GitHubActions => "GitHub Actions",
+            Ci::GitLab => "GitLab CI",
+            Ci::CircleCi => "CircleCI",
+            Ci::AzurePipelines => "Azure Pipelines",
+            Ci::Buildkite => "Buildkite",
+            Ci::Jenkins => "Jenkins",
+        })
+    }
+}
+
+/// What `init` found in the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Survey {
+    /// Whether the root manifest declares a `[workspace]`.
+    pub workspace: bool,
+    /// The manifests merges cover, relative to the root.
+    pub manifests: Vec<PathBuf>,
+    /// Whether the root has a `Cargo.lock`.
+    pub lockfile: bool,
+    /// The `.cargo/config.toml` files, relative to the root.
+    pub cargo_configs: Vec<PathBuf>,
+    pub ci: Vec<Ci>,
+    /// Whether the repository already has a policy file.
+    pub policy: bool,
+}
+
+impl Survey {
+    /// Inspects the repository at `repo`.
+    pub fn inspect(repo: &Path) -> Result<Self, String> {
+        let root_manifest = fs::read_to_string(repo.join("Cargo.toml"))
+            .map_err(|err| format!("cannot read {}: {err}", repo.join("Cargo.toml").display()))?;
+        let workspace = root_manifest
+            .parse::<toml::Table>()
+            .is_ok_and(|doc| doc.contains_key("workspace"));
+        let manifests: Vec<PathBuf> = Excludes::new(repo, &[], &[])?
+            .manifests()
+            .into_iter()
+            .filter_map(|manifest| Some(manifest.strip_prefix(repo).ok()?.to_path_buf()))
+            .collect();
+        let cargo_configs = manifests
+            .iter()
+            .filter_map(|manifest| manifest.parent())
+            .map(|dir| dir.join(".cargo").join("config.toml"))
+            .filter(|config| repo.join(config).is_file())
+            .collect();
+        Ok(Self {
+            workspace,
+            lockfile: repo.join("Cargo.lock").is_file(),
+            cargo_configs,
+            ci: Ci::FILES
+                .into_iter()
+                .filter(|(file, _)| repo.join(file).exists())
+                .map(|(_, ci)| ci)
+                .collect(),
+            policy: repo.join(crate::policy::REPO_POLICY_FILE).exists(),
+            manifests,
+        })
+    }
+}
+
+/// The choices `init` asks about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answers {
+    /// Check the merged workspace with `cargo check` after regenerating the
+    /// lockfile.
+    pub verify: bool,
+    /// Never touch the network: no registry access, no remote policy.
+    pub offline: bool,
+    /// Resolve dependency requirements both sides changed to the higher one
+    /// (`semver-max`).
+    pub semver_max: bool,
+    /// Align a crate the members require at different versions to the
+    /// highest (`reconcile = "highest"`); workspaces only.
+    pub reconcile: bool,
+}
+
+impl Answers {
+    /// The suggested answers: verification where no CI checks merges,
+    /// `semver-max` and, in a workspace, reconciliation.
+    pub fn defaults(survey: &Survey) -> Self {
+        Self {
+            verify: survey.ci.is_empty(),
+            offline: false,
+            semver_max: true,
+            reconcile: survey.workspace,
+        }
+    }
+}
+
+/// The `.cargo-merge-assist.toml` for `answers`.
+pub fn policy(answers: &Answers) -> String {
+    let mut text = String::from(
+        "# Written by `cargo-merge-assist init`; see \"Merge policy\" in the README.\n",
+    );
+    if answers.reconcile {
+        text.push_str("reconcile = \"highest\"\n");
+    }
+    if answers.semver_max {
+        text.push_str("\n[strategies]\n");
+        for path in DEPENDENCY_PATHS {
+            text.push_str(&format!("\"{path}\" = \"semver-max\"\n"));
+        }
+    }
+    text
+}
+
+/// The `.gitattributes` lines assigning the drivers.
+pub fn attributes(survey: &Survey) -> Vec<String> {
+    let mut lines = vec![format!("Cargo.toml merge={MANIFEST_DRIVER}")];
+    if survey.lockfile {
+        lines.push(format!("Cargo.lock merge={LOCK_DRIVER}"));
+    }
+    if !survey.cargo_configs.is_empty() {
+        lines.push(format!("**/.cargo/config.toml merge={CONFIG_DRIVER}"));
+    }
+    lines
+}
+
+/// The git config settings defining the drivers [`attributes`] assigns.
+pub fn drivers(survey: &Survey, answers: &Answers) -> Vec<(String, String)> {
+    let offline = if answers.offline { " --offline" } else { "" };
+    let mut drivers = vec![(
+        MANIFEST_DRIVER,
+        "cargo-merge-assist semantic merge for Cargo.toml",
+        format!(
+            "cargo-merge-assist merge-manifest --base %O --ours %A --theirs %B --out %A --path %P{offline}"
+        ),
+    )];
+    if survey.lockfile {
+        let verify = if answers.verify { " --verify" } else { "" };
+        drivers.push((
+            LOCK_DRIVER,
+            "cargo-merge-assist lockfile regeneration driver",
+            format!(
+                "cargo-merge-assist resolve-lock --repo . --base %O --ours %A --theirs %B{verify}{offline}"
+            ),
+        ));
+    }
+    if !survey.cargo_configs.is_empty() {
+        drivers.push((
+            CONFIG_DRIVER,
+            "cargo-merge-assist semantic merge for .cargo/config.toml",
+            format!(
+                "cargo-merge-assist merge-cargo-config --base %O --ours %A --theirs %B --out %A{offline}"
+            ),
+        ));
+    }
+    drivers
+        .into_iter()
+        .flat_map(|(driver, name, command)| {
+            [
+                (format!("merge.{driver}.name"), name.to_string()),
+                (format!("merge.{driver}.driver"), command),
+            ]
+        })
+        .collect()
+}
+
+/// What the repository looks like, as `init` reports it.
+pub fn describe(survey: &Survey) -> String {
+    let layout = if survey.workspace {
+        "workspace"
+    } else {
+        "single package"
+    };
+    let plural = if survey.manifests.len() == 1 { "" } else { "s" };
+    let mut text = format!(
+        "{layout}, {} manifest{plural}, {}\n",
+        survey.manifests.len(),
+        if survey.lockfile {
+            "Cargo.lock tracked"
+        } else {
+            "no Cargo.lock"
+        }
+    );
+    if !survey.cargo_configs.is_empty() {
+        let configs: Vec<String> = survey
+            .cargo_configs
+            .iter()
+            .map(|config| config.display().to_string())
+            .collect();
+        text.push_str(&format!("cargo config: {}\n", configs.join(", ")));
+    }
+    let ci: Vec<String> = survey.ci.iter().map(ToString::to_string).collect();
+    if ci.is_empty() {
+        text.push_str("CI: none found\n");
+    } else {
+        text.push_str(&format!("CI: {}\n", ci.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Policy;
+
+    #[test]
+    fn sets_up_what_the_repository_has() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-init-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        for dir in ["crates/a/.cargo", ".github/workflows"] {
+            fs::create_dir_all(repo.join(dir)).unwrap();
+        }
+        fs::write(
+            repo.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/a\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join("crates/a/Cargo.toml"),
+            "[package]\nname = \"a\"\n",
+        )
+        .unwrap();
+        fs::write(repo.join("crates/a/.cargo/config.toml"), "").unwrap();
+        fs::write(repo.join("Cargo.lock"), "version = 4\n").unwrap();
+
+        let survey = Survey::inspect(&repo).unwrap();
+        assert_eq!(
+            describe(&survey),
+            "workspace, 2 manifests, Cargo.lock tracked\n\
+             cargo config: crates/a/.cargo/config.toml\nCI: GitHub Actions\n"
+        );
+        let mut answers = Answers::defaults(&survey);
+        assert!(!answers.verify && answers.reconcile);
+        answers.offline = true;
+
+        let policy = Policy::parse(&policy(&answers), "init").unwrap();
+        assert_eq!(policy.strategies.unwrap().len(), DEPENDENCY_PATHS.len());
+        assert_eq!(attributes(&survey).len(), 3);
+        let drivers = drivers(&survey, &answers);
+        assert_eq!(
+            drivers[3],
+            (
+                format!("merge.{LOCK_DRIVER}.driver"),
+                "cargo-merge-assist resolve-lock --repo . --base %O --ours %A --theirs %B --offline"
+                    .to_string()
+            )
+        );
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+//! Decoding of manifest bytes read from disk, and encoding merge results
+//! back.
+//!
+//! Lossy decoding keeps undecodable bytes rather than dropping them: each
+//! byte `b` becomes the private-use code point `U+10FF00 + b`, which TOML
+//! carries through comments and strings like any other character, and
+//! [`encode`] writes it back as the byte. Such a file cannot contain those
+//! code points itself; lossy decoding refuses it.
+
+use std::borrow::Cow;
+use std::fmt;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Code point standing for byte 0 in lossily decoded text; undecodable bytes
+/// are 0x80 and above.
+const ESCAPES: u32 = 0x10FF00;
+
+/// The input cannot be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input is not valid UTF-8 and lossy decoding was not requested.
+    Invalid {
+        /// 1-based line of the first undecodable byte.
+        line: usize,
+        /// 1-based byte column of the first undecodable byte.
+        column: usize,
+        /// The first undecodable byte.
+        byte: u8,
+    },
+    /// Lossy decoding was requested, but the input has a code point that
+    /// stands for an undecodable byte.
+    Reserved {
+        line: usize,
+        column: usize,
+        code_point: char,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Invalid { line, column, byte } => write!(
+                f,
+                "invalid UTF-8 at line {line}, column {column} (byte 0x{byte:02x})"
+            ),
+            DecodeError::Reserved {
+                line,
+                column,
+                code_point,
+            } => write!(
+                f,
+                "U+{:X} at line {line}, column {column} is reserved for undecodable bytes",
+                u32::from(*code_point)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes manifest bytes, dropping a leading UTF-8 byte order mark.
+///
+/// With `lossy`, undecodable bytes are escaped instead of failing; see the
+/// module documentation.
+pub fn decode(bytes: &[u8], lossy: bool) -> Result<String, DecodeError> {
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+
+    if lossy {
+        let mut text = String::with_capacity(bytes.len());
+        let mut start = 0;
+        for chunk in bytes.utf8_chunks() {
+            let valid = chunk.valid();
+            if let Some((offset, code_point)) = valid.char_indices().find(|(_, c)| is_escape(*c)) {
+                let (line, column) = position(bytes, start + offset);
+                return Err(DecodeError::Reserved {
+                    line,
+                    column,
+                    code_point,
+                });
+            }
+            text.push_str(valid);
+            text.extend(chunk.invalid().iter().map(|&byte| escape(byte)));
+            start += valid.len() + chunk.invalid().len();
+        }
+        return Ok(text);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.to_string()),
+        Err(err) => {
+            let offset = err.valid_up_to();
+            let (line, column) = position(bytes, offset);
+            Err(DecodeError::Invalid {
+                line,
+                column,
+                byte: bytes[offset],
+            })
+        }
+    }
+}
+
+/// The bytes to write for `text`, merged from inputs decoded with `lossy`:
+/// escaped bytes are restored.
+pub fn encode(text: &str, lossy: bool) -> Cow<'_, [u8]> {
+    if !lossy || !text.chars().any(is_escape) {
+        return Cow::Borrowed(text.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut buf = [0; 4];
+    for c in text.chars() {
+        match u32::from(c).checked_sub(ESCAPES) {
+            Some(byte) if is_escape(c) => bytes.push(byte as u8),
+            _ => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPES + u32::from(byte)).expect("escapes are valid code points")
+}
+
+fn is_escape(c: char) -> bool {
+    (ESCAPES + 0x80..=ESCAPES + 0xFF).contains(&u32::from(c))
+}
+
+/// 1-based line and byte column of `offset` in `bytes`.
+fn position(bytes: &[u8], offset: usize) -> (usize, usize) {
+    let line_start = bytes[..offset]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |idx| idx + 1);
+    (
+        bytes[..offset].iter().filter(|&&b| b == b'\n').count() + 1,
+        offset - line_start + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_byte_order_mark() {
+        assert_eq!(decode(b"\xEF\xBB\xBFa = 1\n", false).unwrap(), "a = 1\n");
+    }
+
+    #[test]
+    fn reports_position_of_invalid_bytes() {
+        let err = decode(b"a = 1\n# caf\xE9\n", false).unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::Invalid {
+                line: 2,
+                column: 6,
+                byte: 0xE9
+            }
+        );
+    }
+
+    #[test]
+    fn lossy_mode_keeps_invalid_bytes() {
+        let text = decode(b"\xEF\xBB\xBFa = \"\xE9t\xE9\"\n# caf\xE9\n", true).unwrap();
+        assert_eq!(
+            text.parse::<toml::Table>().unwrap()["a"]
+                .as_str()
+                .unwrap()
+                .len(),
+            9
+        );
+        assert_eq!(
+            &*encode(&text, true),
+            b"a = \"\xE9t\xE9\"\n# caf\xE9\n".as_slice()
+        );
+        assert_eq!(
+            encode("a = 1\n", true),
+            Cow::Borrowed(b"a = 1\n".as_slice())
+        );
+
+        let reserved = "# \u{10FFE9}\n";
+        assert_eq!(
+            decode(&[b"\xE9\n", reserved.as_bytes()].concat(), true).unwrap_err(),
+            DecodeError::Reserved {
+                line: 2,
+                column: 3,
+                code_point: '\u{10FFE9}'
+            }
+        );
+        // Without lossy decoding there is nothing to restore.
+        assert_eq!(encode(reserved, false), reserved.as_bytes());
+    }
+}
@@ -0,0 +1,654 @@
+//! Writing a merge result in the formatting of the "ours" input.
+//!
+//! The merge works on parsed values. Serializing the merged value from
+//! scratch would lose comments, key order and the choice between inline
+//! tables and sections, so the result is instead written by editing ours'
+//! document: keys whose value did not change keep their text exactly,
+//! removed keys are dropped, and only changed or added keys are rewritten.
+//! A value the merge took from theirs is copied with theirs' formatting.
+//! Values neither side had (heuristic results, combined tables) are
+//! formatted fresh: new top-level tables become sections, nested tables
+//! become inline tables unless they hold tables themselves. Rows of arrays
+//! of tables are edited in place, paired by `name` when every row has a
+//! distinct one (build targets), else by index when the counts match.
+
+use toml::Value;
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, TableLike};
+
+/// Renders `merged` by editing `ours_text` (which parses to `ours`), copying
+/// from `theirs_text` where `merged` has theirs' value. Top-level keys in
+/// `verbatim` are replaced whole, with theirs' text, rather than edited.
+pub(crate) fn render(
+    ours_text: &str,
+    theirs_text: &str,
+    ours: &Value,
+    merged: &Value,
+    verbatim: &[String],
+) -> Result<String, toml_edit::TomlError> {
+    let mut doc: DocumentMut = ours_text.parse()?;
+    let theirs: DocumentMut = theirs_text.parse()?;
+    if let (Some(ours), Some(merged)) = (ours.as_table(), merged.as_table()) {
+        // Sections added at the top level go after everything ours has.
+        let end = last_position(doc.as_table()).map_or(0, |position| position + 1);
+        let mut editor = Editor { end, verbatim };
+        let theirs = theirs.as_table() as &dyn TableLike;
+        editor.table(doc.as_table_mut(), Some(theirs), ours, merged, Scope::Root);
+    }
+    Ok(doc.to_string())
+}
+
+/// `text` with the keys of every table, inline ones included, in
+/// alphabetical order; `text` itself when it does not parse. Sections stay
+/// where they are.
+pub(crate) fn sort(text: &str) -> String {
+    let Ok(mut doc) = text.parse::<DocumentMut>() else {
+        return text.to_string();
+    };
+    sort_table(doc.as_table_mut());
+    doc.to_string()
+}
+
+fn sort_table(table: &mut Table) {
+    table.sort_values();
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => sort_table(table),
+            Item::ArrayOfTables(rows) => rows.iter_mut().for_each(sort_table),
+            Item::Value(value) => sort_value(value),
+            Item::None => {}
+        }
+    }
+}
+
+fn sort_value(value: &mut toml_edit::Value) {
+    match value {
+        toml_edit::Value::InlineTable(table) => {
+            table.sort_values();
+            table.iter_mut().for_each(|(_, value)| sort_value(value));
+        }
+        toml_edit::Value::Array(array) => array.iter_mut().for_each(sort_value),
+        _ => {}
+    }
+}
+
+/// Where a table being edited sits, which decides how new entries look.
+#[derive(Debug, Clone, Copy)]
+enum Scope {
+    Root,
+    /// A section (or a dotted or implicit table) at this document position.
+    Section(Option<usize>),
+    Inline,
+}
+
+struct Editor<'a> {
+    /// Document position for the next section added at the top level.
+    end: usize,
+    verbatim: &'a [String],
+}
+
+impl Editor<'_> {
+    /// Edits `target`, which holds `ours`, to hold `merged`.
+    fn table(
+        &mut self,
+        target: &mut dyn TableLike,
+        theirs: Option<&dyn TableLike>,
+        ours: &toml::Table,
+        merged: &toml::Table,
+        scope: Scope,
+    ) {
+        for key in ours.keys() {
+            if !merged.contains_key(key) {
+                target.remove(key);
+            }
+        }
+        let theirs_table = theirs;
+        let mut added = Vec::new();
+        for (key, value) in merged {
+            let theirs = theirs_table.and_then(|theirs| theirs.get(key));
+            let whole = matches!(scope, Scope::Root) && self.verbatim.contains(key);
+            match (ours.get(key), target.get_mut(key)) {
+                (Some(old), Some(_)) if old == value => {}
+                (Some(old), Some(item)) if !whole => self.item(item, theirs, old, value, scope),
+                (_, Some(item)) => {
+                    // Verbatim: theirs' item with its own comments.
+                    let position = item.as_table().and_then(Table::position);
+                    let mut new = self.fresh(theirs, value, true, true);
+                    self.place(&mut new, position.or(Some(self.end)));
+                    *item = new;
+                }
+                (_, None) => {
+                    if matches!(scope, Scope::Inline) {
+                        // The old last entry's padding before `}` would end
+                        // up before the comma.
+                        if let Some(last) = target
+                            .iter_mut()
+                            .last()
+                            .and_then(|(_, item)| item.as_value_mut())
+                        {
+                            last.decor_mut().set_suffix("");
+                        }
+                    }
+                    let standard = !matches!(scope, Scope::Inline);
+                    let root = matches!(scope, Scope::Root);
+                    let mut new = self.fresh(theirs, value, standard, root);
+                    if let Scope::Section(position) = scope {
+                        self.place(&mut new, position);
+                    }
+                    target.insert(key, new);
+                    added.push(key.clone());
+                }
+            }
+        }
+        if !added.is_empty() {
+            self.anchor(target, theirs_table, &added, scope);
+        }
+    }
+
+    /// Moves each key in `added` next to its nearest anchor: after the
+    /// closest key before it in theirs that the table has (and after keys
+    /// only ours added there), or failing that before the closest after it.
+    /// Keys theirs does not have stay at the end. Sections added at the top
+    /// level are placed after the anchor's sections.
+    fn anchor(
+        &mut self,
+        target: &mut dyn TableLike,
+        theirs: Option<&dyn TableLike>,
+        added: &[String],
+        scope: Scope,
+    ) {
+        let theirs: Vec<&str> = theirs
+            .map(|theirs| theirs.iter().map(|(key, _)| key).collect())
+            .unwrap_or_default();
+        let index = |key: &String| theirs.iter().position(|theirs| theirs == key);
+        let mut order: Vec<String> = target
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .filter(|key| !added.contains(key))
+            .collect();
+        let mut stray = Vec::new();
+        let mut anchored: Vec<(usize, &String)> = Vec::new();
+        for key in added {
+            match index(key) {
+                Some(at) => anchored.push((at, key)),
+                None => stray.push(key.clone()),
+            }
+        }
+        anchored.sort_unstable();
+        for (at, key) in anchored {
+            let find = |key: &&str| order.iter().position(|order| order == key);
+            let before = theirs[..at].iter().rev().find_map(find).map(|mut slot| {
+                slot += 1;
+                while order
+                    .get(slot)
+                    .is_some_and(|key| !theirs.contains(&key.as_str()))
+                {
+                    slot += 1;
+                }
+                slot
+            });
+            let after = || theirs[at + 1..].iter().find_map(find);
+            let slot = before.or_else(after).unwrap_or(order.len());
+            order.insert(slot, key.clone());
+        }
+        order.extend(stray);
+
+        let current: Vec<String> = target.iter().map(|(key, _)| key.to_string()).collect();
+        if order != current {
+            for key in &order {
+                let (Some(saved), Some(item)) = (target.key(key).cloned(), target.remove(key))
+                else {
+                    continue;
+                };
+                target.insert(key, item);
+                if let Some(mut key) = target.key_mut(key) {
+                    *key.leaf_decor_mut() = saved.leaf_decor().clone();
+                    *key.dotted_decor_mut() = saved.dotted_decor().clone();
+                }
+            }
+        }
+
+        if !matches!(scope, Scope::Root) {
+            return;
+        }
+        for (at, key) in order.iter().enumerate() {
+            let section = target
+                .get(key)
+                .is_some_and(|item| item.is_table() || item.is_array_of_tables());
+            if !added.contains(key) || !section {
+                continue;
+            }
+            let positions = |key: &String| target.get(key).map(positions).unwrap_or_default();
+            let before = order[..at]
+                .iter()
+                .rev()
+                .find_map(|key| positions(key).into_iter().max());
+            let after = || {
+                order[at + 1..]
+                    .iter()
+                    .filter(|key| !added.contains(key))
+                    .find_map(|key| positions(key).into_iter().min())
+            };
+            let position = before.or_else(after).unwrap_or_else(|| {
+                self.end += 1;
+                self.end - 1
+            });
+            if let Some(item) = target.get_mut(key) {
+                self.place(item, Some(position));
+            }
+        }
+    }
+
+    /// Edits the item `target`, which holds `ours`, to hold `merged`.
+    fn item(
+        &mut self,
+        target: &mut Item,
+        theirs: Option<&Item>,
+        ours: &Value,
+        merged: &Value,
+        scope: Scope,
+    ) {
+        let position = match target {
+            Item::Table(table) => table.position(),
+            _ => None,
+        }
+        .or(match scope {
+            Scope::Section(position) => position,
+            _ => None,
+        });
+        // An inline table that ends up as theirs' is copied whole below.
+        let theirs_inline =
+            target.is_inline_table() && theirs.and_then(plain).as_ref() == Some(merged);
+        match (ours, merged) {
+            (Value::Table(ours), Value::Table(merged))
+                if target.is_table_like() && !theirs_inline =>
+            {
+                let scope = if target.is_inline_table() {
+                    Scope::Inline
+                } else {
+                    Scope::Section(position)
+                };
+                let theirs = theirs.and_then(Item::as_table_like);
+                if let Some(table) = target.as_table_like_mut() {
+                    self.table(table, theirs, ours, merged, scope);
+                }
+                // Ours may only have had `[a.b]` headers or `a.b = ..` keys;
+                // emptied, the table needs one of its own to be written.
+                if merged.is_empty()
+                    && let Item::Table(table) = target
+                {
+                    table.set_implicit(false);
+                    table.set_dotted(false);
+                }
+                return;
+            }
+            (Value::Array(ours_rows), Value::Array(merged_rows))
+                if merged_rows.iter().all(Value::is_table)
+                    && let Some(old) = target.as_array_of_tables()
+                    && let Some(pairs) = pair_rows(ours_rows, merged_rows) =>
+            {
+                let theirs_rows = theirs.and_then(Item::as_array_of_tables);
+                let theirs_row = |idx: usize, merged: &Value| {
+                    let rows = theirs_rows?;
+                    match row_name(merged) {
+                        Some(name) => rows
+                            .iter()
+                            .find(|row| row.get("name").and_then(Item::as_str) == Some(name)),
+                        None => rows.get(idx),
+                    }
+                };
+                let mut rows = ArrayOfTables::new();
+                let mut position = old.iter().find_map(Table::position);
+                for (idx, (merged, paired)) in merged_rows.iter().zip(pairs).enumerate() {
+                    let theirs = theirs_row(idx, merged);
+                    let row = match (paired, merged) {
+                        (Some(at), Value::Table(merged_table)) => {
+                            let mut row = old.get(at).cloned().unwrap_or_default();
+                            if let Some(Value::Table(ours)) = ours_rows.get(at) {
+                                let scope = Scope::Section(row.position());
+                                let theirs = theirs.map(|table| table as &dyn TableLike);
+                                self.table(&mut row, theirs, ours, merged_table, scope);
+                            }
+                            row
+                        }
+                        _ => {
+                            // A row ours lacks goes after the one before it.
+                            let theirs = theirs.cloned().map(Item::Table);
+                            let mut new = self.fresh(theirs.as_ref(), merged, true, true);
+                            self.place(&mut new, position);
+                            new.into_table().unwrap_or_default()
+                        }
+                    };
+                    position = last_position(&row).or(position);
+                    rows.push(row);
+                }
+                *target = Item::ArrayOfTables(rows);
+                return;
+            }
+            _ => {}
+        }
+        let standard = !target.is_value();
+        let sections = target.is_array_of_tables();
+        let mut new = self.fresh(theirs, merged, standard, sections);
+        self.place(&mut new, position);
+        keep_decor(target, &mut new);
+        *target = new;
+    }
+
+    /// A new item for `merged`: theirs' when it holds that value, else one
+    /// formatted from scratch. Only a `standard` item may be a section;
+    /// `sections` makes tables sections even when they hold only values.
+    fn fresh(&self, theirs: Option<&Item>, merged: &Value, standard: bool, sections: bool) -> Item {
+        if let Some(theirs) = theirs
+            && plain(theirs).as_ref() == Some(merged)
+        {
+            return if standard {
+                theirs.clone()
+            } else {
+                match theirs.clone().into_value() {
+                    Ok(value) => Item::Value(value),
+                    Err(_) => build(merged, false, false),
+                }
+            };
+        }
+        build(merged, standard, sections)
+    }
+
+    /// Moves every section in `item` to `position`, or to the end when the
+    /// surrounding table has none.
+    fn place(&mut self, item: &mut Item, position: Option<usize>) {
+        let position = position.unwrap_or(self.end);
+        match item {
+            Item::Table(table) => reposition(table, position),
+            Item::ArrayOfTables(rows) => {
+                for row in rows.iter_mut() {
+                    reposition(row, position);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn reposition(table: &mut Table, position: usize) {
+    table.set_position(position);
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => reposition(table, position),
+            Item::ArrayOfTables(rows) => {
+                for row in rows.iter_mut() {
+                    reposition(row, position);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Formats `value` from scratch.
+fn build(value: &Value, standard: bool, sections: bool) -> Item {
+    match value {
+        Value::Table(table) if standard && (sections || table.values().any(Value::is_table)) => {
+            let mut section = Table::new();
+            for (key, value) in table {
+                section.insert(key, build(value, true, false));
+            }
+            // `[target]` over `[target.x]` adds nothing.
+            section.set_implicit(!table.is_empty() && table.values().all(Value::is_table));
+            Item::Table(section)
+        }
+        Value::Array(rows)
+            if standard && sections && !rows.is_empty() && rows.iter().all(Value::is_table) =>
+        {
+            let mut array = ArrayOfTables::new();
+            for row in rows {
+                if let Item::Table(table) = build(row, true, true) {
+                    array.push(table);
+                }
+            }
+            Item::ArrayOfTables(array)
+        }
+        value => Item::Value(inline(value)),
+    }
+}
+
+fn inline(value: &Value) -> toml_edit::Value {
+    match value {
+        Value::String(text) => text.as_str().into(),
+        Value::Integer(number) => (*number).into(),
+        Value::Float(number) => (*number).into(),
+        Value::Boolean(flag) => (*flag).into(),
+        Value::Datetime(datetime) => (*datetime).into(),
+        Value::Array(values) => {
+            let mut array = Array::new();
+            for value in values {
+                array.push(inline(value));
+            }
+            array.into()
+        }
+        Value::Table(table) => {
+            let mut inline_table = InlineTable::new();
+            for (key, value) in table {
+                inline_table.insert(key, inline(value));
+            }
+            inline_table.into()
+        }
+    }
+}
+
+/// The value an item holds, without its formatting.
+fn plain(item: &Item) -> Option<Value> {
+    let mut doc = DocumentMut::new();
+    doc.insert("v", item.clone());
+    doc.to_string().parse::<toml::Table>().ok()?.remove("v")
+}
+
+/// Carries the comments around `old` over to its replacement.
+fn keep_decor(old: &Item, new: &mut Item) {
+    match (old, new) {
+        (Item::Value(old), Item::Value(new)) => *new.decor_mut() = old.decor().clone(),
+        (Item::Table(old), Item::Table(new)) => *new.decor_mut() = old.decor().clone(),
+        _ => {}
+    }
+}
+
+/// For each of `merged` rows, the index of the row of `ours` it edits:
+/// the row with the same `name` when every row has a distinct one (build
+/// targets), else the row at the same index. `None` when rows cannot be
+/// paired.
+fn pair_rows(ours: &[Value], merged: &[Value]) -> Option<Vec<Option<usize>>> {
+    if let (Some(ours), Some(merged)) = (row_names(ours), row_names(merged)) {
+        return Some(
+            merged
+                .iter()
+                .map(|name| ours.iter().position(|other| other == name))
+                .collect(),
+        );
+    }
+    (ours.len() == merged.len()).then(|| (0..merged.len()).map(Some).collect())
+}
+
+/// The distinct `name`s of `rows`, if each has one.
+fn row_names(rows: &[Value]) -> Option<Vec<&str>> {
+    let names: Vec<&str> = rows.iter().map(row_name).collect::<Option<_>>()?;
+    let unique = names
+        .iter()
+        .enumerate()
+        .all(|(idx, name)| !names[..idx].contains(name));
+    unique.then_some(names)
+}
+
+fn row_name(row: &Value) -> Option<&str> {
+    row.get("name")?.as_str()
+}
+
+/// Document positions of the sections in `item`.
+fn positions(item: &Item) -> Vec<usize> {
+    let table = |table: &Table| {
+        table
+            .position()
+            .into_iter()
+            .chain(table.iter().flat_map(|(_, item)| positions(item)))
+            .collect::<Vec<_>>()
+    };
+    match item {
+        Item::Table(section) => table(section),
+        Item::ArrayOfTables(rows) => rows.iter().flat_map(table).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn last_position(table: &Table) -> Option<usize> {
+    table
+        .iter()
+        .filter_map(|(_, item)| match item {
+            Item::Table(table) => last_position(table),
+            Item::ArrayOfTables(rows) => rows.iter().filter_map(last_position).max(),
+            _ => None,
+        })
+        .chain(table.position())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeOptions, merge_manifest_texts, merge_manifest_texts_with};
+
+    const BASE: &str = r#"# Demo crate.
+[package]
+name = "demo"     # aligned
+version = "0.1.0"
+
+[dependencies]
+# Serialization.
+serde = { version = "1", features = ["derive"] }
+log = "0.4"
+
+[dev-dependencies.proptest]
+version = "1"
+"#;
+
+    #[test]
+    fn keeps_ours_formatting_and_only_touches_changed_keys() {
+        let ours = BASE
+            .replace("0.1.0", "0.2.0")
+            .replace("log = \"0.4\"\n", "");
+        let theirs =
+            BASE.replace(
+                "version = \"1\", features",
+                "version = \"1.0.200\", features",
+            )
+            .replace(
+                "version = \"1\"\n",
+                "version = \"1.4\"\ndefault-features = false\n",
+            ) + "\n[features]\n# Opt-in tracing.\ntrace = []\n";
+
+        let merged = merge_manifest_texts(BASE, &ours, &theirs).unwrap();
+        let expected = r#"# Demo crate.
+[package]
+name = "demo"     # aligned
+version = "0.2.0"
+
+[dependencies]
+# Serialization.
+serde = { version = "1.0.200", features = ["derive"] }
+
+[dev-dependencies.proptest]
+version = "1.4"
+default-features = false
+
+[features]
+# Opt-in tracing.
+trace = []
+"#;
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn ignores_formatting_only_differences_between_inputs() {
+        let base =
+            "[dependencies]\ntokio = { version = \"1\", features = [\"rt\"] }\nserde = \"1\"\n";
+        // Ours moves tokio into a section; theirs bumps it and spells serde
+        // with dotted keys.
+        let ours = "[dependencies]\nserde = \"1\"\n\n[dependencies.tokio]\nversion = \"1\"\nfeatures = ['rt']\n";
+        let theirs = "[dependencies]\ntokio = { version = \"1.2\", features = [\"rt\"] }\n\
+                      serde.version = \"1.0.200\"\nserde.features = [\"derive\"]\n";
+
+        let merged = merge_manifest_texts(base, ours, theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[dependencies]\nserde = { version = \"1.0.200\", features = [\"derive\"] }\n\n\
+             [dependencies.tokio]\nversion = \"1.2\"\nfeatures = ['rt']\n"
+        );
+
+        // A table ours only wrote as `[a.b]` sections keeps a header when
+        // theirs empties it.
+        let base = "[package]\nname = \"demo\"\n\n[dependencies.tokio]\nversion = \"1\"\n";
+        let ours = base.replace("demo", "demo2");
+        let theirs = "[package]\nname = \"demo\"\n\n[dependencies]\n";
+        let merged = merge_manifest_texts(base, &ours, theirs).unwrap();
+        assert_eq!(merged, "[package]\nname = \"demo2\"\n\n[dependencies]\n");
+    }
+
+    #[test]
+    fn edits_inline_tables_without_stray_spaces() {
+        let base = "[dependencies]\na = { version = \"1\" }\nb = \"1\"\n";
+        let theirs = base.replace("\"1\" }", "\"1\", features = [\"x\"] }");
+
+        // Theirs' entry, taken whole.
+        let ours = base.replace("b = \"1\"", "b = \"2\"");
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[dependencies]\na = { version = \"1\", features = [\"x\"] }\nb = \"2\"\n"
+        );
+
+        // Both sides edited the entry.
+        let ours = base.replace("\"1\" }", "\"1.1\" }");
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[dependencies]\na = { version = \"1.1\", features = [\"x\"] }\nb = \"1\"\n"
+        );
+        let ours = base.replace("\"1\" }", "\"1\", optional = true }");
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[dependencies]\na = { version = \"1\", optional = true, features = [\"x\"] }\n\
+             b = \"1\"\n"
+        );
+    }
+
+    #[test]
+    fn places_theirs_additions_after_their_anchors() {
+        let base = "[package]\nname = \"demo\"\n\n[dependencies]\n# Async.\ntokio = \"1\"\n\
+                    futures = \"0.3\"\n# Data.\nserde = \"1\"\n";
+        // Ours appends to the async group; theirs adds to both groups and a
+        // section after `[package]`.
+        let ours = "[package]\nname = \"demo\"\n\n[dependencies]\n# Async.\ntokio = \"1\"\n\
+                    futures = \"0.3\"\nasync-trait = \"0.1\"\n# Data.\nserde = \"1\"\n";
+        let theirs = "[package]\nname = \"demo\"\n\n[lib]\npath = \"lib.rs\"\n\n\
+                      [dependencies]\n# Async.\ntokio = \"1\"\nfutures = \"0.3\"\nbytes = \"1\"\n\
+                      # Data.\nserde = \"1\"\nserde_json = \"1\"\n";
+
+        let merged = merge_manifest_texts(base, ours, theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[package]\nname = \"demo\"\n\n[lib]\npath = \"lib.rs\"\n\n\
+             [dependencies]\n# Async.\ntokio = \"1\"\nfutures = \"0.3\"\nasync-trait = \"0.1\"\n\
+             bytes = \"1\"\n# Data.\nserde = \"1\"\nserde_json = \"1\"\n"
+        );
+
+        let options = MergeOptions {
+            sort: true,
+            ..MergeOptions::default()
+        };
+        let merged = merge_manifest_texts_with(base, ours, theirs, &options).unwrap();
+        assert!(
+            merged.contains(
+                "async-trait = \"0.1\"\nbytes = \"1\"\nfutures = \"0.3\"\n# Data.\nserde = \"1\"\n\
+                 serde_json = \"1\"\n# Async.\ntokio = \"1\"\n"
+            ),
+            "{merged}"
+        );
+    }
+}
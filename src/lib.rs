@@ -0,0 +1,86 @@
+//! Semantic merge engine behind the `cargo-merge-assist` CLI.
+//!
+//! The binary is a thin wrapper around this library; downstream tools can
+//! depend on the crate directly to run the same merges in-process.
+
+mod ancestry;
+pub mod audit;
+pub mod batch;
+pub mod cache;
+pub mod cargo;
+mod cfgs;
+pub mod cleanup;
+mod cluster;
+pub mod config;
+mod deprecated;
+pub mod discover;
+pub mod edit;
+pub mod error;
+pub mod estimate;
+pub mod features;
+mod fields;
+mod flags;
+pub mod generated;
+pub mod health;
+pub mod hints;
+pub mod inheritance;
+pub mod init;
+pub mod input;
+mod layout;
+mod lints;
+mod locations;
+pub mod lock;
+pub mod markers;
+mod members;
+pub mod merge;
+mod metadata;
+mod moves;
+pub mod owners;
+mod passthrough;
+pub mod patches;
+pub mod paths;
+mod pins;
+pub mod policy;
+pub mod provenance;
+pub mod reconcile;
+pub mod release;
+mod renames;
+pub mod resolutions;
+pub mod sandbox;
+mod shorthand;
+pub mod strategy;
+pub mod strict;
+pub mod style;
+pub mod summary;
+pub mod suppress;
+mod targets;
+pub mod timings;
+pub mod triage;
+pub mod unstable;
+
+#[cfg(feature = "bindings")]
+pub mod ffi;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "metrics")]
+pub mod stats;
+
+#[cfg(feature = "notify")]
+pub mod notify;
+
+#[cfg(feature = "driver-core")]
+pub mod licenses;
+#[cfg(feature = "driver-core")]
+pub mod resume;
+#[cfg(feature = "driver-core")]
+pub mod verify;
+
+#[cfg(feature = "serve")]
+pub mod serve;
+
+#[cfg(feature = "self-update")]
+pub mod self_update;
+
+#[cfg(feature = "test-support")]
+pub mod test_support;
@@ -0,0 +1,211 @@
+//! License check of the dependencies a merge brings in (`allowed-licenses`
+//! in the merge policy).
+//!
+//! A merge can add direct dependencies that nobody reviewed together: each
+//! branch passed its own checks, but CI on the merged result runs later, if
+//! at all. With an allowed list, `merge-all` looks up the license of every
+//! dependency the merged manifest has and the merge base did not, once the
+//! lockfile is regenerated, and fails before verification when one is not
+//! allowed. Licenses come from `cargo metadata`, which reads the packages
+//! from the local registry cache (downloading the missing ones unless
+//! offline). Path dependencies are not checked.
+//!
+//! A license is an SPDX expression: `OR` needs one allowed side, `AND` both,
+//! and `X WITH exception` is allowed when `X` or the whole expression is
+//! listed. Cargo's legacy `MIT/Apache-2.0` reads as `OR`. Packages with only
+//! a `license-file`, or an expression that does not parse, are violations.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::release::{self, DependencyChangeKind};
+
+/// A dependency whose license is not allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub package: String,
+    pub version: String,
+    /// Its license expression; `None` when it only has a `license-file`.
+    pub license: Option<String>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.license {
+            Some(license) => write!(
+                f,
+                "{} {}: license `{license}` is not allowed",
+                self.package, self.version
+            ),
+            None => write!(
+                f,
+                "{} {}: no license expression (license-file only)",
+                self.package, self.version
+            ),
+        }
+    }
+}
+
+/// Names of the crates `after` depends on directly that `before` did not.
+pub fn added_dependencies(before: &str, after: &str) -> Result<Vec<String>, String> {
+    let changes = release::changes(before, after).map_err(|err| err.to_string())?;
+    let mut added: Vec<String> = changes
+        .dependencies
+        .into_iter()
+        .filter(|change| matches!(change.kind, DependencyChangeKind::Added { .. }))
+        .map(|change| change.package)
+        .collect();
+    added.sort_unstable();
+    added.dedup();
+    Ok(added)
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    license: Option<String>,
+    /// `None` for path dependencies and workspace members.
+    source: Option<String>,
+}
+
+/// The packages named in `names` in `cargo metadata --format-version 1`
+/// output `metadata` whose license `allowed` does not permit.
+pub fn check(
+    metadata: &str,
+    names: &[String],
+    allowed: &[String],
+) -> Result<Vec<Violation>, String> {
+    let metadata: Metadata = serde_json::from_str(metadata)
+        .map_err(|err| format!("unexpected cargo metadata output: {err}"))?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter(|package| package.source.is_some() && names.contains(&package.name))
+        .filter(|package| {
+            !package
+                .license
+                .as_deref()
+                .is_some_and(|license| permits(license, allowed))
+        })
+        .map(|package| Violation {
+            package: package.name,
+            version: package.version,
+            license: package.license,
+        })
+        .collect())
+}
+
+/// Whether the SPDX expression `license` is satisfied by `allowed`.
+pub fn permits(license: &str, allowed: &[String]) -> bool {
+    let spaced = license
+        .replace('/', " OR ")
+        .replace('(', " ( ")
+        .replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut parser = Parser {
+        tokens: &tokens,
+        allowed,
+    };
+    matches!(parser.or(), Some(permitted) if parser.tokens.is_empty() && permitted)
+}
+
+/// Evaluates an expression while parsing it; `None` when it is malformed.
+struct Parser<'a> {
+    tokens: &'a [&'a str],
+    allowed: &'a [String],
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Option<bool> {
+        let mut permitted = self.and()?;
+        while self.eat("OR") {
+            permitted |= self.and()?;
+        }
+        Some(permitted)
+    }
+
+    fn and(&mut self) -> Option<bool> {
+        let mut permitted = self.term()?;
+        while self.eat("AND") {
+            permitted &= self.term()?;
+        }
+        Some(permitted)
+    }
+
+    fn term(&mut self) -> Option<bool> {
+        if self.eat("(") {
+            let permitted = self.or()?;
+            return self.eat(")").then_some(permitted);
+        }
+        let (id, rest) = self.tokens.split_first()?;
+        if matches!(*id, ")" | "AND" | "OR" | "WITH") {
+            return None;
+        }
+        self.tokens = rest;
+        let listed = |license: &str| {
+            self.allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(license))
+        };
+        if self.eat("WITH") {
+            let (exception, rest) = self.tokens.split_first()?;
+            self.tokens = rest;
+            return Some(listed(id) || listed(&format!("{id} WITH {exception}")));
+        }
+        Some(listed(id))
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        match self.tokens.split_first() {
+            Some((first, rest)) if first.eq_ignore_ascii_case(token) => {
+                self.tokens = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_licenses_of_added_dependencies() {
+        let allowed = ["MIT".to_string(), "Apache-2.0".to_string()];
+        assert!(permits("MIT OR Apache-2.0", &allowed));
+        assert!(permits("MIT/GPL-3.0", &allowed));
+        assert!(permits("(MIT OR GPL-3.0) AND Apache-2.0", &allowed));
+        assert!(permits("Apache-2.0 WITH LLVM-exception", &allowed));
+        assert!(!permits("MIT AND GPL-3.0", &allowed));
+        assert!(!permits("MIT OR", &allowed));
+
+        let before = "[dependencies]\nserde = \"1\"\n";
+        let after = "[dependencies]\nserde = \"1\"\ncopyleft = \"0.1\"\n\
+                     rng = { package = \"rand\", version = \"0.8\" }\n";
+        let added = added_dependencies(before, after).unwrap();
+        assert_eq!(added, ["copyleft", "rand"]);
+
+        let metadata = r#"{"packages": [
+            {"name": "copyleft", "version": "0.1.2", "license": "GPL-3.0-only", "source": "registry+https://github.com/rust-lang/crates.io-index"},
+            {"name": "rand", "version": "0.8.5", "license": "MIT OR Apache-2.0", "source": "registry+https://github.com/rust-lang/crates.io-index"},
+            {"name": "serde", "version": "1.0.200", "license": null, "source": "registry+https://github.com/rust-lang/crates.io-index"},
+            {"name": "app", "version": "0.1.0", "license": null, "source": null}
+        ]}"#;
+        let violations = check(metadata, &added, &allowed).unwrap();
+        assert_eq!(
+            violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["copyleft 0.1.2: license `GPL-3.0-only` is not allowed"]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Merging a switch to `[workspace.lints]` with member-level lint edits.
+//!
+//! A common race: one branch moves a crate's `[lints]` into
+//! `[workspace.lints]` and sets `lints.workspace = true`, while the other
+//! branch edits the member-level lints. Merged key by key, that is a
+//! delete/modify conflict on every edited tool table. With
+//! [`LintInheritance::Inherit`] the merge instead keeps the inherited form and
+//! carries the other side's lints over into `[workspace.lints]`, where they
+//! are merged with what the adopting side put there.
+//!
+//! This needs the workspace table in the same manifest (a root package that
+//! is also the workspace root). For a member in its own directory the lints
+//! it would carry belong to another file, and the conflict is reported as
+//! before.
+
+use toml::{Table, Value};
+
+use crate::merge::LintInheritance;
+
+/// Rewrites `base` and the non-adopting side so that their member-level
+/// lints appear as `[workspace.lints]` and the member inherits them.
+pub(crate) fn inherit(
+    mode: LintInheritance,
+    base: &mut Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+) {
+    if mode != LintInheritance::Inherit || inherits(base) {
+        return;
+    }
+    let other = match (inherits(ours), inherits(theirs)) {
+        (true, false) if has_workspace(ours) => theirs,
+        (false, true) if has_workspace(theirs) => ours,
+        _ => return,
+    };
+    if other.get("lints") == base.get("lints") {
+        // Nothing to carry over; the plain merge takes the inherited form.
+        return;
+    }
+    project(base);
+    project(other);
+}
+
+/// Whether `doc` has `lints.workspace = true`.
+fn inherits(doc: &Value) -> bool {
+    doc.get("lints")
+        .and_then(|lints| lints.get("workspace"))
+        .and_then(Value::as_bool)
+        == Some(true)
+}
+
+fn has_workspace(doc: &Value) -> bool {
+    doc.get("workspace").is_some_and(Value::is_table)
+}
+
+/// Moves the member-level lints of `doc` into `workspace.lints` (keeping
+/// lints the workspace already configures) and makes the member inherit.
+fn project(doc: &mut Value) {
+    let Some(root) = doc.as_table_mut() else {
+        return;
+    };
+    let mut inherited = Table::new();
+    inherited.insert("workspace".to_string(), Value::Boolean(true));
+    let Some(Value::Table(member)) = root.insert("lints".to_string(), Value::Table(inherited))
+    else {
+        return;
+    };
+
+    let workspace = root
+        .entry("workspace")
+        .or_insert_with(|| Value::Table(Table::new()));
+    let Some(workspace) = workspace.as_table_mut() else {
+        return;
+    };
+    let Value::Table(lints) = workspace
+        .entry("lints")
+        .or_insert_with(|| Value::Table(Table::new()))
+    else {
+        return;
+    };
+    for (tool, member_lints) in member {
+        let Value::Table(member_lints) = member_lints else {
+            continue;
+        };
+        let Value::Table(tool_lints) = lints
+            .entry(tool)
+            .or_insert_with(|| Value::Table(Table::new()))
+        else {
+            continue;
+        };
+        for (lint, level) in member_lints {
+            tool_lints.entry(lint).or_insert(level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{
+        LintInheritance, MergeError, MergeOptions, merge_manifest_texts, merge_manifest_texts_with,
+    };
+
+    const BASE: &str = r#"
+[workspace]
+
+[package]
+name = "app"
+
+[lints.rust]
+unsafe_code = "forbid"
+"#;
+
+    const ADOPTED: &str = r#"
+[workspace]
+
+[workspace.lints.rust]
+unsafe_code = "forbid"
+missing_docs = "warn"
+
+[package]
+name = "app"
+
+[lints]
+workspace = true
+"#;
+
+    #[test]
+    fn carries_member_lint_edits_into_workspace_lints() {
+        let edited = BASE.replace("\"forbid\"", "\"deny\"").replace(
+            "[lints.rust]",
+            "[lints.clippy]\npedantic = \"warn\"\n\n[lints.rust]",
+        );
+
+        for (ours, theirs) in [(ADOPTED, edited.as_str()), (edited.as_str(), ADOPTED)] {
+            let merged = merge_manifest_texts(BASE, ours, theirs).unwrap();
+            let doc: toml::Value = merged.parse().unwrap();
+            assert_eq!(doc["lints"]["workspace"].as_bool(), Some(true), "{merged}");
+            assert!(doc["lints"].get("rust").is_none(), "{merged}");
+            let lints = &doc["workspace"]["lints"];
+            assert_eq!(lints["rust"]["unsafe_code"].as_str(), Some("deny"));
+            assert_eq!(lints["rust"]["missing_docs"].as_str(), Some("warn"));
+            assert_eq!(lints["clippy"]["pedantic"].as_str(), Some("warn"));
+        }
+    }
+
+    #[test]
+    fn conflict_mode_and_separate_members_keep_the_conflict() {
+        let edited = BASE.replace("\"forbid\"", "\"deny\"");
+        let options = MergeOptions {
+            lint_inheritance: LintInheritance::Conflict,
+            ..MergeOptions::default()
+        };
+        let err = merge_manifest_texts_with(BASE, ADOPTED, &edited, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+
+        // A member without the workspace table cannot take the lints along.
+        let strip = |text: &str| text.replace("[workspace]\n", "");
+        let adopted = strip(ADOPTED).replace(
+            "[workspace.lints.rust]\nunsafe_code = \"forbid\"\nmissing_docs = \"warn\"\n",
+            "",
+        );
+        let err = merge_manifest_texts(&strip(BASE), &adopted, &strip(&edited)).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,123 @@
+//! Equivalent spellings of path dependencies.
+//!
+//! `path = "../utils"`, `"../utils/"` and `"./../utils"` name the same
+//! directory relative to the manifest, yet compared as strings a branch that
+//! only respelled the path conflicts with one that moved it elsewhere or
+//! edited the entry. [`normalize`] compares the `path` of every dependency
+//! and `[patch]` entry lexically, as cargo resolves it against the manifest
+//! directory, and rewrites equivalent spellings in base and theirs to ours'
+//! one, so only real changes of directory take part in the merge.
+
+use toml::Value;
+
+use crate::pins::{dependency_tables, lookup, lookup_mut};
+
+/// Rewrites the `path` of dependency and patch entries in `base` and
+/// `theirs` to ours' spelling where it names the same directory (theirs' in
+/// base when ours has no such entry).
+pub(crate) fn normalize(base: &mut Value, ours: &Value, theirs: &mut Value) {
+    let mut tables = dependency_tables([base, ours, theirs]);
+    let mut sources: Vec<String> = [&*base, ours, &*theirs]
+        .iter()
+        .filter_map(|doc| doc.get("patch").and_then(Value::as_table))
+        .flat_map(|patch| patch.keys().cloned())
+        .collect();
+    sources.sort_unstable();
+    sources.dedup();
+    tables.extend(
+        sources
+            .into_iter()
+            .map(|source| vec!["patch".to_string(), source]),
+    );
+
+    for table in tables {
+        let mut keys: Vec<String> = [&*base, ours, &*theirs]
+            .iter()
+            .filter_map(|doc| lookup(doc, &table).and_then(Value::as_table))
+            .flat_map(|entries| entries.keys().cloned())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let mut path = table.clone();
+            path.extend([key, "path".to_string()]);
+            let spelling = |doc: &Value| lookup(doc, &path)?.as_str().map(str::to_string);
+            let Some(wanted) = spelling(ours).or_else(|| spelling(theirs)) else {
+                continue;
+            };
+            for doc in [&mut *base, &mut *theirs] {
+                if let Some(value) = lookup_mut(doc, &path)
+                    && let Some(current) = value.as_str()
+                    && current != wanted
+                    && lexical(current) == lexical(&wanted)
+                {
+                    *value = Value::String(wanted.clone());
+                }
+            }
+        }
+    }
+}
+
+/// `path` without `.` components, repeated or trailing separators, and with
+/// `dir/..` folded away, like cargo's own path normalization (which does not
+/// consult the filesystem either).
+fn lexical(path: &str) -> String {
+    let absolute = path.starts_with(['/', '\\']);
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|last| *last != "..") => {
+                parts.pop();
+            }
+            // The root's parent is the root.
+            ".." if absolute => {}
+            part => parts.push(part),
+        }
+    }
+    let joined = parts.join("/");
+    match (absolute, joined.is_empty()) {
+        (true, _) => format!("/{joined}"),
+        (false, true) => ".".to_string(),
+        (false, false) => joined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, merge_manifest_texts};
+
+    #[test]
+    fn equivalent_paths_do_not_conflict() {
+        assert_eq!(lexical("./../utils/"), "../utils");
+        assert_eq!(lexical("crates/a/../b//"), "crates/b");
+        assert_eq!(lexical("/srv/../../x"), "/x");
+        assert_eq!(lexical("./"), ".");
+
+        let base = "[dependencies]\nutils = { path = \"../utils\" }\n\n\
+                    [patch.crates-io]\nlog = { path = \"vendor/log\" }\n";
+        let ours = "[dependencies]\nutils = { path = \"../utils/\", version = \"0.2\" }\n\n\
+                    [patch.crates-io]\nlog = { path = \"vendor/log\" }\n";
+        let theirs = "[dependencies]\nutils = { path = \"./../utils\", features = [\"io\"] }\n\n\
+                      [patch.crates-io]\nlog = { path = \"./vendor/./log\" }\n";
+        let merged: toml::Table = merge_manifest_texts(base, ours, theirs)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected: toml::Table = "[dependencies]\n\
+             utils = { path = \"../utils/\", version = \"0.2\", features = [\"io\"] }\n\n\
+             [patch.crates-io]\nlog = { path = \"vendor/log\" }\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+
+        // A different directory still conflicts.
+        let theirs = theirs.replace("./../utils", "../../utils");
+        let ours = ours.replace("../utils/", "../utils-next");
+        let Err(MergeError::Conflict(conflict)) = merge_manifest_texts(base, &ours, &theirs) else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflict.path, "dependencies.utils.path");
+    }
+}
@@ -0,0 +1,154 @@
+//! Merging `Cargo.lock` contents that have no checkout, for [`crate::serve`]
+//! and the Node.js bindings.
+//!
+//! A lockfile is not merged line by line. When only one side changed it (see
+//! [`trivial_merge`]) that side is the result, without running cargo.
+//! Otherwise ours' lockfile is re-resolved against the merged manifest in a
+//! [`Sandbox::standalone`], and every package theirs moved to a version base
+//! did not lock is moved there too, unless the merged manifest no longer
+//! allows it. Only a standalone manifest resolves this way: path
+//! dependencies and workspace members have no files in the sandbox.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cargo;
+use crate::error::Error;
+use crate::merge::trivial_merge;
+use crate::sandbox::Sandbox;
+
+/// Merges the `base`, `ours` and `theirs` lockfiles of `manifest`, the
+/// merged manifest.
+pub fn merge_lock_texts(
+    manifest: &str,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    offline: bool,
+) -> Result<String, Error> {
+    if let Some(merged) = trivial_merge(base, ours, theirs) {
+        return Ok(merged.to_string());
+    }
+    let moved = moved(&versions(base)?, &versions(theirs)?);
+
+    let sandbox = Sandbox::standalone(manifest, ours)?;
+    let scratch = sandbox.root();
+    cargo::run(scratch, &["update", "--workspace"], offline)?;
+    let lockfile = scratch.join("Cargo.lock");
+    let read =
+        || fs::read_to_string(&lockfile).map_err(|err| Error::io("reading", &lockfile, &err));
+    for (name, locked) in versions(&read()?)? {
+        let Some(version) = moved.get(&name).filter(|version| **version != locked) else {
+            continue;
+        };
+        let spec = format!("{name}@{locked}");
+        match cargo::run(
+            scratch,
+            &["update", "-p", &spec, "--precise", version],
+            offline,
+        ) {
+            // The merged manifest requires another version; ours' stays.
+            Ok(()) | Err(Error::Cargo { .. }) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    read()
+}
+
+/// The `(name, version)` of every package `lock` locks.
+fn versions(lock: &str) -> Result<BTreeSet<(String, String)>, Error> {
+    let lock: toml::Value = toml::from_str(lock).map_err(|err| Error::Schema {
+        file: Some(PathBuf::from("Cargo.lock")),
+        problems: vec![err.message().to_string()],
+    })?;
+    let packages = lock.get("package").and_then(toml::Value::as_array);
+    Ok(packages
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let field = |key: &str| package.get(key)?.as_str().map(str::to_string);
+            Some((field("name")?, field("version")?))
+        })
+        .collect())
+}
+
+/// The version of each package `theirs` locks at a version `base` did not,
+/// when `theirs` locks only that one.
+fn moved(
+    base: &BTreeSet<(String, String)>,
+    theirs: &BTreeSet<(String, String)>,
+) -> BTreeMap<String, String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (name, _) in theirs {
+        *counts.entry(name).or_default() += 1;
+    }
+    theirs
+        .difference(base)
+        .filter(|(name, _)| counts[name.as_str()] == 1)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                            [[bin]]\nname = \"tool\"\n";
+
+    fn lock(packages: &[(&str, &str)]) -> String {
+        let mut text = String::from("version = 3\n");
+        for (name, version) in packages {
+            text.push_str(&format!(
+                "\n[[package]]\nname = \"{name}\"\nversion = \"{version}\"\n"
+            ));
+        }
+        text
+    }
+
+    #[test]
+    fn merges_trivially_or_in_a_sandbox() {
+        let base = lock(&[("app", "0.1.0"), ("log", "0.4.20")]);
+        let ours = lock(&[("app", "0.1.0"), ("log", "0.4.21")]);
+        // No cargo needed when one side kept base's lockfile.
+        assert_eq!(
+            merge_lock_texts("not a manifest", &base, &ours, &base, true).unwrap(),
+            ours
+        );
+        assert_eq!(
+            merge_lock_texts("not a manifest", &base, &base, &ours, true).unwrap(),
+            ours
+        );
+
+        // Both moved: re-resolved against the manifest, which dropped `log`.
+        let theirs = lock(&[("app", "0.1.0"), ("log", "0.4.22")]);
+        let merged = merge_lock_texts(MANIFEST, &base, &ours, &theirs, true).unwrap();
+        let merged = versions(&merged).unwrap();
+        assert!(merged.contains(&("app".to_string(), "0.1.0".to_string())));
+        assert!(!merged.iter().any(|(name, _)| name == "log"), "{merged:?}");
+
+        let err = merge_lock_texts(MANIFEST, &base, &ours, "[[package]", true).unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn moves_only_unambiguous_packages() {
+        let base = versions(&lock(&[("a", "1.0.0"), ("b", "1.0.0")])).unwrap();
+        let theirs = versions(&lock(&[
+            ("a", "1.1.0"),
+            ("b", "1.0.0"),
+            ("b", "2.0.0"),
+            ("c", "0.1.0"),
+        ]))
+        .unwrap();
+        let moved = moved(&base, &theirs);
+        assert_eq!(
+            moved.into_iter().collect::<Vec<_>>(),
+            [
+                ("a".to_string(), "1.1.0".to_string()),
+                ("c".to_string(), "0.1.0".to_string())
+            ]
+        );
+    }
+}
@@ -1,19 +1,73 @@
-mod merge;
-
 use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::{Context, Result, bail};
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-use crate::merge::merge_manifest_texts;
+use cargo_merge_assist::audit;
+use cargo_merge_assist::batch::{self, BatchOutcome, DirectoryEntry};
+use cargo_merge_assist::cache::ResultCache;
+use cargo_merge_assist::cargo;
+use cargo_merge_assist::cleanup;
+use cargo_merge_assist::config;
+use cargo_merge_assist::discover::Excludes;
+use cargo_merge_assist::edit;
+use cargo_merge_assist::error::{self, Error, FileConflict};
+use cargo_merge_assist::estimate::{self, RegistryIndex};
+use cargo_merge_assist::features::{self, FeatureProblem};
+use cargo_merge_assist::generated;
+use cargo_merge_assist::health;
+use cargo_merge_assist::hints;
+use cargo_merge_assist::inheritance;
+use cargo_merge_assist::init;
+use cargo_merge_assist::input;
+use cargo_merge_assist::licenses;
+use cargo_merge_assist::markers;
+use cargo_merge_assist::merge::{
+    GitAncestry, Heuristic, MergeError, MergeOptions, MergeReport, MergeResult, Resolution, Side,
+    merge_manifest_texts_detailed, merge_manifest_texts_timed, trivial_merge,
+};
+use cargo_merge_assist::owners::Owners;
+use cargo_merge_assist::patches;
+use cargo_merge_assist::paths;
+use cargo_merge_assist::policy::{self, Branches, Policy};
+use cargo_merge_assist::provenance;
+use cargo_merge_assist::reconcile::{self, Member, ReconcileMode};
+use cargo_merge_assist::resolutions::{self, Choice};
+use cargo_merge_assist::resume::{self, RunState, Step};
+use cargo_merge_assist::sandbox::Sandbox;
+use cargo_merge_assist::strategy::{self, Strategy};
+use cargo_merge_assist::strict;
+use cargo_merge_assist::summary::{self, Outcome};
+use cargo_merge_assist::suppress;
+use cargo_merge_assist::timings::Timings;
+use cargo_merge_assist::triage;
+use cargo_merge_assist::unstable;
+use cargo_merge_assist::verify::{self, VerifyCache};
 
 #[derive(Parser, Debug)]
 #[command(name = "cargo-merge-assist")]
 #[command(version)]
 #[command(about = "Semantic merge assistant for Cargo.toml and Cargo.lock")]
 struct Cli {
+    /// Forbid environment-dependent behavior (network access, locale, clock)
+    /// so identical inputs always produce byte-identical outputs
+    #[arg(long, global = true)]
+    deterministic: bool,
+    /// Print time spent per phase (read, parse, merge, cargo, ...) to stderr
+    #[arg(long, global = true)]
+    timings: bool,
+    /// How a failure is reported on stderr: text, or json (one object
+    /// tagged by `kind`, with the `exit_code`)
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
+    /// Append each merge's outcome as a JSON line to this file
+    /// (default: $CARGO_MERGE_ASSIST_METRICS_LOG; off when neither is set)
+    #[cfg(feature = "metrics")]
+    #[arg(long, global = true, value_name = "PATH")]
+    metrics_log: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -22,20 +76,49 @@ struct Cli {
 enum Commands {
     /// 3-way semantic merge for Cargo.toml
     MergeManifest(MergeManifestArgs),
+    /// 3-way semantic merge for .cargo/config.toml
+    MergeCargoConfig(MergeCargoConfigArgs),
+    /// Merge Cargo.toml, then resolve the remaining conflicts in $EDITOR
+    Edit(EditArgs),
     /// Regenerate Cargo.lock from Cargo.toml
     ResolveLock(ResolveLockArgs),
     /// Merge manifest + regenerate lockfile + optional cargo check
     MergeAll(MergeAllArgs),
+    /// Merge many Cargo.toml triples listed in a batch spec
+    MergeBatch(MergeBatchArgs),
+    /// Group the conflicts of a heavily diverged merge and resolve whole
+    /// groups at once
+    Triage(TriageArgs),
+    /// Predict Cargo.lock changes from the local index cache, without cargo
+    EstimateLock(EstimateLockArgs),
     /// Install local Git merge drivers and .gitattributes entries
     InstallGitDriver(InstallGitDriverArgs),
+    /// Set the repository up: write a policy and install the merge drivers
+    /// after a few questions
+    Init(InitArgs),
+    /// Run as a long-lived merge server
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+    /// Serve a page for reviewing one merge and choosing conflict resolutions
+    #[cfg(feature = "serve")]
+    Preview(PreviewArgs),
+    /// Replace this binary with the latest release
+    #[cfg(feature = "self-update")]
+    SelfUpdate(SelfUpdateArgs),
+    /// Summarize the metrics log: merges, auto-resolution rate,
+    /// conflict-prone dependencies and merge times
+    #[cfg(feature = "metrics")]
+    Stats(StatsArgs),
 }
 
-#[derive(Args, Debug)]
+#[derive(Args, Clone, Debug)]
 struct MergeManifestArgs {
     /// Base (ancestor) Cargo.toml path (%O in Git merge driver)
     #[arg(long)]
     base: PathBuf,
-    /// Ours/current Cargo.toml path (%A in Git merge driver)
+    /// Ours/current Cargo.toml path (%A in Git merge driver), or a
+    /// directory: every manifest in it is merged with its counterparts in
+    /// the --base and --theirs directories
     #[arg(long)]
     ours: PathBuf,
     /// Theirs/incoming Cargo.toml path (%B in Git merge driver)
@@ -44,6 +127,178 @@ struct MergeManifestArgs {
     /// Output path (usually same as --ours)
     #[arg(long)]
     out: PathBuf,
+    /// Path of the manifest in the repository (%P in Git merge driver);
+    /// looked up in CODEOWNERS (default: --ours)
+    #[arg(long)]
+    path: Option<PathBuf>,
+    /// Carry undecodable (non-UTF-8) bytes through the merge instead of failing
+    #[arg(long)]
+    lossy_input: bool,
+    /// Do not read or write the merge result cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Maximum table nesting to merge before failing
+    /// (default: merge policy, else 64)
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Report heuristic resolutions less confident than this (0.0-1.0) as
+    /// conflicts (default: merge policy, else 0)
+    #[arg(long, value_parser = parse_confidence)]
+    min_confidence: Option<f64>,
+    /// Like --min-confidence, for [build-dependencies] only; also decides
+    /// them apart from the crate's other entries (default: merge policy)
+    #[arg(long, value_parser = parse_confidence)]
+    build_min_confidence: Option<f64>,
+    /// Resolve conflicting [build-dependencies] to this side: base, ours or
+    /// theirs (default: merge policy)
+    #[arg(long, value_parser = parse_side)]
+    build_prefer: Option<Side>,
+    /// Resolve conflicts as listed in this resolutions file (TOML, or JSON
+    /// with the same structure), e.g. one written by `preview`
+    #[arg(long, value_name = "PATH")]
+    apply_resolutions: Option<PathBuf>,
+    /// When both sides changed a dependency requirement, prefer the one
+    /// matching the newest release on crates.io (queries the registry)
+    #[arg(long)]
+    online_hints: bool,
+    /// When both sides moved a git dependency to different revs, take the
+    /// one descending from the other (looks up cargo's git clones, fetching
+    /// the commits unless --offline)
+    #[arg(long)]
+    git_ancestry: bool,
+    /// Resolve conflicts with a built-in strategy: semver-max takes the
+    /// higher of two compatible requirements, semver-intersection the
+    /// versions both admit, union the items of both lists, ours and theirs
+    /// always that side (repeatable)
+    #[arg(long = "strategy", value_name = "STRATEGY")]
+    strategies: Vec<Strategy>,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
+    /// Name of the branch merged into (%X in Git merge driver); selects
+    /// `[[branch-rules]]` (default: the checked-out branch)
+    #[arg(long)]
+    label_ours: Option<String>,
+    /// Name of the branch being merged (%Y in Git merge driver); selects
+    /// `[[branch-rules]]` (default: detected from git's environment)
+    #[arg(long)]
+    label_theirs: Option<String>,
+    /// Fail if the merged manifest has keys outside the Cargo schema
+    /// (e.g. a misspelled `[dependecies]` table)
+    #[arg(long)]
+    strict: bool,
+    /// Append a comment footer recording each automatic resolution
+    #[arg(long)]
+    audit_trail: bool,
+    /// Remove audit footers left by earlier merges (implied by --audit-trail)
+    #[arg(long)]
+    strip_audit_trail: bool,
+    /// Also write a JSON map from every key path of the result to where its
+    /// value came from: ours, theirs, base or policy:<rule>
+    #[arg(long, value_name = "PATH")]
+    provenance: Option<PathBuf>,
+    /// Report every conflict in the manifest instead of stopping at the
+    /// first
+    #[arg(long)]
+    all_conflicts: bool,
+    /// Rename deprecated sections ([project], [dev_dependencies],
+    /// [build_dependencies]) to their current names in the result
+    #[arg(long)]
+    migrate_deprecated: bool,
+    /// Report two different `edition` bumps as a conflict instead of taking
+    /// the newer edition
+    #[arg(long)]
+    no_newest_edition: bool,
+    /// Report two different `package.version` bumps as a conflict instead
+    /// of taking the higher version
+    #[arg(long)]
+    no_highest_version: bool,
+    /// Write the keys of every table in alphabetical order instead of
+    /// following ours' order
+    #[arg(long)]
+    sort: bool,
+    /// When the manifest is generated (its leading comments contain
+    /// `@generated`), run this shell command to rebuild it instead of
+    /// merging; it finds the inputs and output in $CARGO_MERGE_ASSIST_BASE,
+    /// _OURS, _THEIRS and _OUT
+    #[arg(long, value_name = "COMMAND")]
+    regenerate_cmd: Option<String>,
+    /// On conflict, still write --out: the merged entries plus
+    /// `<<<<<<<`/`=======`/`>>>>>>>` blocks for the conflicting ones, as
+    /// git merge-file does (exit status stays 1)
+    #[arg(long)]
+    write_conflicts: bool,
+    /// Resolve conflicts nothing else settles to this side (ours, theirs)
+    /// instead of failing; each override is logged on stderr
+    #[arg(long, value_enum, default_value_t = OnConflict::Fail)]
+    on_conflict: OnConflict,
+    /// Report format on stdout: text, or summary-md (a short Markdown table
+    /// for PR comments and CI job summaries)
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+/// How failures are reported on stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+/// What `merge-manifest` does with conflicts left after every other rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OnConflict {
+    Fail,
+    Ours,
+    Theirs,
+}
+
+/// How merge results are reported on stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    SummaryMd,
+}
+
+#[derive(Args, Debug)]
+struct MergeCargoConfigArgs {
+    /// Base (ancestor) config.toml path (%O in Git merge driver)
+    #[arg(long)]
+    base: PathBuf,
+    /// Ours/current config.toml path (%A in Git merge driver)
+    #[arg(long)]
+    ours: PathBuf,
+    /// Theirs/incoming config.toml path (%B in Git merge driver)
+    #[arg(long)]
+    theirs: PathBuf,
+    /// Output path (usually same as --ours)
+    #[arg(long)]
+    out: PathBuf,
+    /// Carry undecodable (non-UTF-8) bytes through the merge instead of failing
+    #[arg(long)]
+    lossy_input: bool,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
+}
+
+#[derive(Args, Debug)]
+struct EditArgs {
+    /// Base (ancestor) Cargo.toml path
+    #[arg(long)]
+    base: PathBuf,
+    /// Ours/current Cargo.toml path
+    #[arg(long)]
+    ours: PathBuf,
+    /// Theirs/incoming Cargo.toml path
+    #[arg(long)]
+    theirs: PathBuf,
+    /// Output path, edited in place (usually same as --ours)
+    #[arg(long)]
+    out: PathBuf,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
 }
 
 #[derive(Args, Debug)]
@@ -51,12 +306,37 @@ struct ResolveLockArgs {
     /// Repository root containing Cargo.toml
     #[arg(long, default_value = ".")]
     repo: PathBuf,
+    /// Base lockfile of the merge (%O in Git merge driver). With --ours and
+    /// --theirs, a side that alone changed it is the result without running
+    /// cargo; the result is written to --ours
+    #[arg(long, requires_all = ["ours", "theirs"])]
+    base: Option<PathBuf>,
+    /// Our lockfile (%A in Git merge driver)
+    #[arg(long, requires_all = ["base", "theirs"])]
+    ours: Option<PathBuf>,
+    /// Their lockfile (%B in Git merge driver)
+    #[arg(long, requires_all = ["base", "ours"])]
+    theirs: Option<PathBuf>,
     /// Also run `cargo check -q` after lockfile regeneration
     #[arg(long)]
     verify: bool,
+    /// Verify only the affected workspace members, split across up to N
+    /// concurrent `cargo check -p` runs
+    #[arg(long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Manifests the merge changed; they select the affected members
+    #[arg(skip)]
+    changed: Vec<PathBuf>,
+    /// Check even if the same manifests, lockfile and toolchain passed
+    /// before
+    #[arg(long)]
+    no_verify_cache: bool,
     /// Run cargo commands with --offline
     #[arg(long)]
     offline: bool,
+    /// Without cargo on PATH, warn and do nothing instead of failing
+    #[arg(long)]
+    allow_no_cargo: bool,
 }
 
 #[derive(Args, Debug)]
@@ -72,12 +352,202 @@ struct MergeAllArgs {
     /// Repository root containing Cargo.toml
     #[arg(long, default_value = ".")]
     repo: PathBuf,
+    /// Only report: merge in memory and print the Cargo.lock changes the
+    /// merged manifest is expected to force, writing nothing
+    #[arg(long)]
+    check: bool,
     /// Skip cargo check verification
     #[arg(long)]
     skip_verify: bool,
+    /// Verify only the workspace members affected by the merged manifest,
+    /// split across up to N concurrent `cargo check -p` runs
+    #[arg(long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Check even if the same manifests, lockfile and toolchain passed
+    /// before
+    #[arg(long)]
+    no_verify_cache: bool,
+    /// Start at this step (merge, lock or verify) instead of where an
+    /// earlier run of the same merge stopped
+    #[arg(long, value_name = "STEP")]
+    from_step: Option<Step>,
     /// Run cargo commands with --offline
     #[arg(long)]
     offline: bool,
+    /// Without cargo on PATH, only merge the manifests: skip the lockfile,
+    /// license check and verification with a warning instead of failing
+    #[arg(long)]
+    allow_no_cargo: bool,
+    /// Name of the branch merged into (%X in Git merge driver); selects
+    /// `[[branch-rules]]` (default: the checked-out branch)
+    #[arg(long)]
+    label_ours: Option<String>,
+    /// Name of the branch being merged (%Y in Git merge driver); selects
+    /// `[[branch-rules]]` (default: detected from git's environment)
+    #[arg(long)]
+    label_theirs: Option<String>,
+    /// Carry undecodable (non-UTF-8) bytes through the merge instead of failing
+    #[arg(long)]
+    lossy_input: bool,
+    /// Do not read or write the merge result cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Maximum table nesting to merge before failing
+    /// (default: merge policy, else 64)
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Report heuristic resolutions less confident than this (0.0-1.0) as
+    /// conflicts (default: merge policy, else 0)
+    #[arg(long, value_parser = parse_confidence)]
+    min_confidence: Option<f64>,
+    /// Like --min-confidence, for [build-dependencies] only; also decides
+    /// them apart from the crate's other entries (default: merge policy)
+    #[arg(long, value_parser = parse_confidence)]
+    build_min_confidence: Option<f64>,
+    /// Resolve conflicting [build-dependencies] to this side: base, ours or
+    /// theirs (default: merge policy)
+    #[arg(long, value_parser = parse_side)]
+    build_prefer: Option<Side>,
+    /// Resolve conflicts as listed in this resolutions file (TOML, or JSON
+    /// with the same structure), e.g. one written by `preview`
+    #[arg(long, value_name = "PATH")]
+    apply_resolutions: Option<PathBuf>,
+    /// When both sides changed a dependency requirement, prefer the one
+    /// matching the newest release on crates.io (queries the registry)
+    #[arg(long)]
+    online_hints: bool,
+    /// When both sides moved a git dependency to different revs, take the
+    /// one descending from the other (looks up cargo's git clones, fetching
+    /// the commits unless --offline)
+    #[arg(long)]
+    git_ancestry: bool,
+    /// Resolve conflicts with a built-in strategy: semver-max takes the
+    /// higher of two compatible requirements, semver-intersection the
+    /// versions both admit, union the items of both lists, ours and theirs
+    /// always that side (repeatable)
+    #[arg(long = "strategy", value_name = "STRATEGY")]
+    strategies: Vec<Strategy>,
+    /// Fail if the merged manifest has keys outside the Cargo schema
+    /// (e.g. a misspelled `[dependecies]` table)
+    #[arg(long)]
+    strict: bool,
+    /// Append a comment footer recording each automatic resolution
+    #[arg(long)]
+    audit_trail: bool,
+    /// Remove audit footers left by earlier merges (implied by --audit-trail)
+    #[arg(long)]
+    strip_audit_trail: bool,
+    /// Also write a JSON map from every key path of the result to where its
+    /// value came from: ours, theirs, base or policy:<rule>
+    #[arg(long, value_name = "PATH")]
+    provenance: Option<PathBuf>,
+    /// Report every conflict in the manifest instead of stopping at the
+    /// first
+    #[arg(long)]
+    all_conflicts: bool,
+    /// Rename deprecated sections ([project], [dev_dependencies],
+    /// [build_dependencies]) to their current names in the result
+    #[arg(long)]
+    migrate_deprecated: bool,
+    /// Report two different `edition` bumps as a conflict instead of taking
+    /// the newer edition
+    #[arg(long)]
+    no_newest_edition: bool,
+    /// Report two different `package.version` bumps as a conflict instead
+    /// of taking the higher version
+    #[arg(long)]
+    no_highest_version: bool,
+    /// Write the keys of every table in alphabetical order instead of
+    /// following ours' order
+    #[arg(long)]
+    sort: bool,
+    /// When the manifest is generated (its leading comments contain
+    /// `@generated`), run this shell command to rebuild it instead of
+    /// merging; it finds the inputs and output in $CARGO_MERGE_ASSIST_BASE,
+    /// _OURS, _THEIRS and _OUT
+    #[arg(long, value_name = "COMMAND")]
+    regenerate_cmd: Option<String>,
+    /// Report format on stdout: text, or summary-md (a short Markdown table
+    /// for PR comments and CI job summaries)
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Args, Debug)]
+struct MergeBatchArgs {
+    /// TOML file with one [[merge]] entry (base/ours/theirs/out) per job
+    spec: PathBuf,
+    /// Maximum number of jobs merged concurrently (default: number of CPUs)
+    #[arg(long)]
+    jobs: Option<usize>,
+    /// Carry undecodable (non-UTF-8) bytes through the merge instead of failing
+    #[arg(long)]
+    lossy_input: bool,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
+    /// After merging, check requirements across members: off, report, or
+    /// highest (harmonize) (default: merge policy, else report)
+    #[arg(long, value_name = "MODE")]
+    reconcile: Option<ReconcileMode>,
+    /// Move dependencies declared identically in several members into the
+    /// root's [workspace.dependencies]
+    #[arg(long)]
+    hoist_duplicates: bool,
+    /// Report format on stdout: text, or summary-md (a short Markdown table
+    /// for PR comments and CI job summaries)
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Workspace root manifest, checked to declare every dependency the
+    /// merged members inherit and used by --hoist-duplicates
+    #[arg(long, default_value = "Cargo.toml")]
+    workspace_root: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct TriageArgs {
+    /// Base (ancestor) Cargo.toml path
+    #[arg(long)]
+    base: PathBuf,
+    /// Ours/current Cargo.toml path
+    #[arg(long)]
+    ours: PathBuf,
+    /// Theirs/incoming Cargo.toml path
+    #[arg(long)]
+    theirs: PathBuf,
+    /// Where the merged manifest is written once the decisions settle every
+    /// conflict (default: --ours)
+    #[arg(long)]
+    out: Option<PathBuf>,
+    /// Resolve a partition, as named in the report (`<table>:<kind>`), with
+    /// a strategy: semver-max, semver-intersection, union, ours or theirs
+    /// (repeatable)
+    #[arg(long = "apply", value_name = "PARTITION=STRATEGY", value_parser = parse_decision)]
+    decisions: Vec<(String, Strategy)>,
+    /// Apply the suggested strategy to every partition that has one and no
+    /// --apply decision
+    #[arg(long)]
+    accept_suggested: bool,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
+}
+
+#[derive(Args, Debug)]
+struct EstimateLockArgs {
+    /// Repository root containing Cargo.toml and Cargo.lock
+    #[arg(long, default_value = ".")]
+    repo: PathBuf,
+    /// Estimate for the merge of these manifests instead of <repo>/Cargo.toml
+    #[arg(long, requires_all = ["ours", "theirs"])]
+    base: Option<PathBuf>,
+    #[arg(long, requires_all = ["base", "theirs"])]
+    ours: Option<PathBuf>,
+    #[arg(long, requires_all = ["base", "ours"])]
+    theirs: Option<PathBuf>,
+    /// Exit with status 1 when any lockfile change is expected
+    #[arg(long)]
+    exit_code: bool,
 }
 
 #[derive(Args, Debug)]
@@ -87,179 +557,2421 @@ struct InstallGitDriverArgs {
     repo: PathBuf,
 }
 
+#[derive(Args, Debug)]
+struct InitArgs {
+    /// Repository root to set up
+    #[arg(long, default_value = ".")]
+    repo: PathBuf,
+    /// Take the suggested answers without asking
+    #[arg(long, short)]
+    yes: bool,
+    /// Replace an existing .cargo-merge-assist.toml
+    #[arg(long)]
+    force: bool,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Speak newline-delimited JSON-RPC 2.0 on stdin/stdout
+    #[arg(long, conflicts_with = "http")]
+    stdio: bool,
+    /// Serve HTTP on this address (e.g. 127.0.0.1:8080)
+    #[arg(long, value_name = "ADDR")]
+    http: Option<String>,
+    /// Bearer token required by HTTP clients (default: $CARGO_MERGE_ASSIST_TOKEN)
+    #[arg(long, requires = "http")]
+    token: Option<String>,
+    /// Directory of repositories that HTTP clients may address by refs
+    #[arg(long, requires = "http")]
+    repo_root: Option<PathBuf>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Args, Debug)]
+struct PreviewArgs {
+    /// Base (ancestor) Cargo.toml path
+    #[arg(long)]
+    base: PathBuf,
+    /// Ours/current Cargo.toml path
+    #[arg(long)]
+    ours: PathBuf,
+    /// Theirs/incoming Cargo.toml path
+    #[arg(long)]
+    theirs: PathBuf,
+    /// Serve the page on this address (e.g. 127.0.0.1:8080)
+    #[arg(long, value_name = "ADDR")]
+    http: String,
+    /// File the chosen resolutions are written to
+    #[arg(long, default_value = "cargo-merge-assist-resolutions.toml")]
+    resolutions: PathBuf,
+    /// Use only the cached copy of a remote merge policy
+    #[arg(long)]
+    offline: bool,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Only merges at or after this time: YYYY-MM-DD, seconds since the
+    /// epoch, or an age such as 30d, 12h or 2w
+    #[arg(long)]
+    since: Option<String>,
+    /// Only merges at or before this time (same forms as --since)
+    #[arg(long)]
+    until: Option<String>,
+    /// Also count merge commits in the history of --repo that changed Cargo
+    /// files on both sides
+    #[arg(long)]
+    git: bool,
+    /// Repository mined by --git
+    #[arg(long, default_value = ".")]
+    repo: PathBuf,
+    /// Number of conflict-prone dependencies listed
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    /// Output: table, or json for dashboards
+    #[arg(long, value_enum, default_value_t = StatsFormat::Table)]
+    format: StatsFormat,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum StatsFormat {
+    Table,
+    Json,
+}
+
+#[cfg(feature = "self-update")]
+#[derive(Args, Debug)]
+struct SelfUpdateArgs {
+    /// Only report whether a newer release exists
+    #[arg(long)]
+    check: bool,
+    /// Reinstall even when already on the latest release (never downgrades)
+    #[arg(long)]
+    force: bool,
+}
+
 fn main() {
-    if let Err(err) = run() {
-        eprintln!("error: {err:#}");
-        std::process::exit(1);
+    // Roll back partial writes if interrupted (Ctrl-C, git killing the driver).
+    if let Err(err) = ctrlc::set_handler(|| {
+        cleanup::run_pending();
+        std::process::exit(130);
+    }) {
+        eprintln!("warning: failed to install interrupt handler: {err}");
     }
-}
 
-fn run() -> Result<()> {
     let cli = Cli::parse();
-
-    match cli.command {
-        Commands::MergeManifest(args) => merge_manifest_cmd(args),
-        Commands::ResolveLock(args) => resolve_lock_cmd(args),
-        Commands::MergeAll(args) => merge_all_cmd(args),
-        Commands::InstallGitDriver(args) => install_git_driver_cmd(args),
+    let error_format = cli.error_format;
+    if let Err(err) = run(cli) {
+        let typed = err.downcast_ref::<Error>();
+        let exit_code = typed.map_or(error::EXIT_OTHER, Error::exit_code);
+        match error_format {
+            ErrorFormat::Text => eprintln!("error: {err:#}"),
+            ErrorFormat::Json => {
+                let mut json = typed
+                    .and_then(|typed| serde_json::to_value(typed).ok())
+                    .unwrap_or_else(|| serde_json::json!({ "kind": "other" }));
+                json["message"] = format!("{err:#}").into();
+                json["exit_code"] = exit_code.into();
+                eprintln!("{json}");
+            }
+        }
+        std::process::exit(exit_code);
     }
 }
 
-fn merge_manifest_cmd(args: MergeManifestArgs) -> Result<()> {
-    let base_text = read_utf8(&args.base)?;
-    let ours_text = read_utf8(&args.ours)?;
-    let theirs_text = read_utf8(&args.theirs)?;
+fn run(cli: Cli) -> Result<()> {
+    let mut timings = Timings::default();
+
+    #[cfg(feature = "metrics")]
+    let metrics_log = cli
+        .metrics_log
+        .or_else(|| std::env::var_os("CARGO_MERGE_ASSIST_METRICS_LOG").map(PathBuf::from));
+    #[cfg(not(feature = "metrics"))]
+    let metrics_log: Option<PathBuf> = None;
+    let logged_merge = match &cli.command {
+        Commands::MergeManifest(args) => Some(("merge-manifest", args.ours.clone())),
+        // `--check` is a dry run, not a merge.
+        Commands::MergeAll(args) if !args.check => Some(("merge-all", args.ours.clone())),
+        _ => None,
+    };
 
-    let merged = merge_manifest_texts(&base_text, &ours_text, &theirs_text)
-        .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+    let result = match cli.command {
+        Commands::MergeManifest(mut args) => {
+            if cli.deterministic && args.online_hints {
+                bail!("--online-hints depends on the registry and cannot be --deterministic");
+            }
+            if cli.deterministic && args.git_ancestry {
+                bail!("--git-ancestry depends on git remotes and cannot be --deterministic");
+            }
+            // Cache hits depend on local state outside the inputs.
+            args.no_cache |= cli.deterministic;
+            args.offline |= cli.deterministic;
+            warn_if_unwired(Path::new("."), &mut timings);
+            merge_manifest_cmd(args, &mut timings)
+        }
+        Commands::MergeCargoConfig(mut args) => {
+            args.offline |= cli.deterministic;
+            merge_cargo_config_cmd(args, &mut timings)
+        }
+        Commands::Edit(mut args) => {
+            args.offline |= cli.deterministic;
+            edit_cmd(args)
+        }
+        Commands::ResolveLock(mut args) => {
+            // Resolving against a live registry depends on when it runs.
+            args.offline |= cli.deterministic;
+            warn_if_unwired(&args.repo, &mut timings);
+            resolve_lock_cmd(args, &mut timings)
+        }
+        Commands::MergeAll(mut args) => {
+            if cli.deterministic && args.online_hints {
+                bail!("--online-hints depends on the registry and cannot be --deterministic");
+            }
+            if cli.deterministic && args.git_ancestry {
+                bail!("--git-ancestry depends on git remotes and cannot be --deterministic");
+            }
+            args.offline |= cli.deterministic;
+            args.no_cache |= cli.deterministic;
+            merge_all_cmd(args, &mut timings)
+        }
+        Commands::MergeBatch(mut args) => {
+            args.offline |= cli.deterministic;
+            merge_batch_cmd(args, metrics_log.as_deref(), &mut timings)
+        }
+        Commands::Triage(mut args) => {
+            args.offline |= cli.deterministic;
+            triage_cmd(args, &mut timings)
+        }
+        Commands::EstimateLock(args) => estimate_lock_cmd(args, &mut timings),
+        Commands::InstallGitDriver(args) => install_git_driver_cmd(args),
+        Commands::Init(args) => init_cmd(args),
+        #[cfg(feature = "serve")]
+        Commands::Serve(args) => serve_cmd(args),
+        #[cfg(feature = "serve")]
+        Commands::Preview(args) => preview_cmd(args),
+        #[cfg(feature = "self-update")]
+        Commands::SelfUpdate(args) => self_update_cmd(args),
+        #[cfg(feature = "metrics")]
+        Commands::Stats(args) => stats_cmd(args, metrics_log.as_deref()),
+    };
 
-    fs::write(&args.out, merged)
-        .with_context(|| format!("failed writing merged manifest: {}", args.out.display()))?;
+    if cli.timings {
+        eprint!("{timings}");
+    }
+    #[cfg(feature = "metrics")]
+    if let (Some(log), Some((command, manifest))) = (&metrics_log, logged_merge) {
+        log_merge(log, command, &manifest, &result, &timings);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = logged_merge;
+    result
+}
 
-    Ok(())
+/// Warns when the merge drivers of `repo` no longer cover the Cargo files.
+fn warn_if_unwired(repo: &Path, timings: &mut Timings) {
+    let issues = timings.time("health check", || health::check(repo));
+    for issue in issues {
+        eprintln!(
+            "warning: {issue}; run `{}` to fix",
+            health::fix_command(repo)
+        );
+    }
 }
 
-fn resolve_lock_cmd(args: ResolveLockArgs) -> Result<()> {
-    ensure_manifest_exists(&args.repo)?;
+#[cfg(feature = "metrics")]
+fn log_merge(log: &Path, command: &str, manifest: &Path, result: &Result<()>, timings: &Timings) {
+    use cargo_merge_assist::metrics::{self, MergeRecord, Outcome};
 
-    run_cargo(&args.repo, &["generate-lockfile"], args.offline)?;
-    if args.verify {
-        run_cargo(&args.repo, &["check", "-q"], args.offline)?;
+    // `merge-all` can fail after the manifest merged, while resolving the lock.
+    let merged = result.is_ok() || timings.get("cargo generate-lockfile").is_some();
+    let err = result.as_ref().err();
+    let record = match (
+        err.and_then(|err| err.downcast_ref::<Error>()),
+        err.and_then(|err| err.downcast_ref::<MergeError>()),
+    ) {
+        (Some(err), _) if !merged => MergeRecord::error(command, manifest, err),
+        (_, Some(err)) if !merged => MergeRecord::failed(command, manifest, err),
+        _ if merged => {
+            // Phases that ran tell which path produced the result.
+            let resolution = if timings.get("parse").is_some() {
+                "semantic"
+            } else if timings.get("cache lookup").is_some() {
+                "cache"
+            } else {
+                "trivial"
+            };
+            let mut record = MergeRecord::new(command, manifest, Outcome::Merged);
+            record.resolution = Some(resolution.to_string());
+            record
+        }
+        _ => {
+            let mut record = MergeRecord::new(command, manifest, Outcome::Error);
+            record.error = Some("io".to_string());
+            record
+        }
+    };
+    if let Err(err) = metrics::append(log, &record.with_timings(timings)) {
+        eprintln!(
+            "warning: failed to write metrics log {}: {err}",
+            log.display()
+        );
     }
+}
 
-    Ok(())
+fn merge_manifest_cmd(args: MergeManifestArgs, timings: &mut Timings) -> Result<()> {
+    if args.ours.is_dir() {
+        merge_directories(args, timings)?;
+        return Ok(());
+    }
+    merge_manifest(args, false, timings).map(drop)
 }
 
-fn merge_all_cmd(args: MergeAllArgs) -> Result<()> {
-    merge_manifest_cmd(MergeManifestArgs {
-        base: args.base,
-        ours: args.ours,
-        theirs: args.theirs,
-        out: args.out,
+/// Merges one manifest and returns the merged text (`None` when a
+/// regeneration command produced it instead). A `dry_run` only merges and
+/// checks: it writes nothing, notifies no one and leaves the workspace root
+/// alone.
+fn merge_manifest(
+    args: MergeManifestArgs,
+    dry_run: bool,
+    timings: &mut Timings,
+) -> Result<Option<String>> {
+    let out = generated::resolve_target(&paths::normalize(&args.out))
+        .map_err(|err| Error::io("resolving", &args.out, &err))?;
+    let (base_text, ours_text, theirs_text) = timings.time("read", || {
+        Ok::<_, anyhow::Error>((
+            read_manifest(&args.base, args.lossy_input)?,
+            read_manifest(&args.ours, args.lossy_input)?,
+            read_manifest(&args.theirs, args.lossy_input)?,
+        ))
     })?;
+    // A merged generated manifest would be overwritten by the next
+    // generation: rebuild it, or leave it to the user.
+    if generated::is_generated(&ours_text) || generated::is_generated(&theirs_text) {
+        let Some(command) = args.regenerate_cmd.as_ref().filter(|_| !dry_run) else {
+            return Err(Error::Schema {
+                file: Some(out),
+                problems: vec![format!(
+                    "the manifest is generated (marked `{}`); merge its source and \
+                     regenerate it, or pass --regenerate-cmd",
+                    generated::SENTINEL
+                )],
+            }
+            .into());
+        };
+        let inputs = [args.base.as_path(), &args.ours, &args.theirs];
+        return timings
+            .time("regenerate", || {
+                generated::regenerate(command, inputs, &out)
+            })
+            .map(|()| None)
+            .map_err(anyhow::Error::msg);
+    }
 
-    resolve_lock_cmd(ResolveLockArgs {
-        repo: args.repo,
-        verify: !args.skip_verify,
-        offline: args.offline,
-    })?;
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let branches = Branches::detect(
+        Path::new("."),
+        args.label_ours.as_deref(),
+        args.label_theirs.as_deref(),
+    );
+    let mut options = merge_options(Path::new("."), &policy, &branches)?;
+    if let Some(max_depth) = args.max_depth {
+        options.max_depth = max_depth;
+    }
+    if let Some(min_confidence) = args.min_confidence {
+        options.min_confidence = min_confidence;
+    }
+    if args.build_min_confidence.is_some() {
+        options.build_min_confidence = args.build_min_confidence;
+    }
+    if let Some(path) = &args.apply_resolutions {
+        options.resolutions = load_resolutions(path)?;
+    }
+    if let Some(side) = args.build_prefer {
+        // Ahead of the policy's preferences: flags take precedence.
+        options
+            .preferences
+            .splice(0..0, policy::build_preferences(side));
+    }
+    // Strategies the user named come before the registry hints.
+    options
+        .heuristics
+        .extend(args.strategies.iter().map(|strategy| strategy.heuristic()));
+    let mut heuristics: Vec<String> = args.strategies.iter().map(ToString::to_string).collect();
+    if args.online_hints {
+        options.heuristics.push(hints::latest_published);
+        heuristics.push("online-hints".to_string());
+    }
+    // The fallback side goes last, after every heuristic had its say.
+    let fallback: Option<(&str, Heuristic)> = match args.on_conflict {
+        OnConflict::Fail => None,
+        OnConflict::Ours => Some(("ours", strategy::on_conflict_ours)),
+        OnConflict::Theirs => Some(("theirs", strategy::on_conflict_theirs)),
+    };
+    if let Some((side, heuristic)) = fallback {
+        options.heuristics.push(heuristic);
+        heuristics.push(format!("on-conflict={side}"));
+    }
+    options.audit = args.audit_trail;
+    options.strip_audit = args.strip_audit_trail;
+    options.all_conflicts = args.all_conflicts;
+    options.migrate_deprecated = args.migrate_deprecated;
+    if args.git_ancestry {
+        options.git_ancestry = if args.offline {
+            GitAncestry::Local
+        } else {
+            GitAncestry::Fetch
+        };
+    }
+    options.newest_edition = !args.no_newest_edition;
+    options.highest_version = !args.no_highest_version;
+    options.sort = args.sort;
+    let (base_text, ours_text, theirs_text) = if options.audit || options.strip_audit {
+        (
+            audit::strip(&base_text),
+            audit::strip(&ours_text),
+            audit::strip(&theirs_text),
+        )
+    } else {
+        (base_text, ours_text, theirs_text)
+    };
+    let summary_md = args.format == Format::SummaryMd;
+    let provenance = args.provenance.as_deref().map(paths::normalize);
+    // With pins, migration, sorting or a style even a one-sided change has
+    // to go through the merge.
+    let trivial = (options.pins.is_empty()
+        && !options.migrate_deprecated
+        && !options.sort
+        && options.style.is_none())
+    .then(|| trivial_merge(&base_text, &ours_text, &theirs_text))
+    .flatten();
+    let mut result = match trivial {
+        Some(winner) => Ok(MergeResult {
+            text: winner.to_string(),
+            resolutions: Vec::new(),
+        }),
+        // Cached results do not record their resolutions.
+        None => merge_with_cache(
+            &base_text,
+            &ours_text,
+            &theirs_text,
+            &options,
+            &heuristics,
+            args.no_cache || summary_md || fallback.is_some() || provenance.is_some(),
+            timings,
+        ),
+    };
+    if trivial.is_none() {
+        for note in unstable_notes(&base_text, &ours_text, &theirs_text) {
+            eprintln!("note: {note}");
+        }
+    }
+    if fallback.is_some()
+        && let Ok(merged) = &result
+    {
+        for line in overrides(&merged.resolutions) {
+            eprintln!("{line}");
+        }
+    }
 
-    Ok(())
-}
+    let manifest = args.path.as_deref().unwrap_or(&args.ours);
+    let owners = Owners::new(&policy, Path::new("."), manifest);
+    if !owners.is_empty() {
+        match &mut result {
+            Err(MergeError::Conflict(conflict)) => owners.assign(conflict),
+            Err(MergeError::Report(report)) => {
+                report.conflicts.iter_mut().for_each(|c| owners.assign(c));
+            }
+            _ => {}
+        }
+    }
 
-fn install_git_driver_cmd(args: InstallGitDriverArgs) -> Result<()> {
-    ensure_manifest_exists(&args.repo)?;
+    let message = result.as_ref().err().map(ToString::to_string);
+    let outcome = match (&result, &message) {
+        (Ok(merged), _) => Outcome::Merged(&merged.resolutions),
+        (Err(MergeError::Conflict(conflict)), _) => Outcome::Conflict(conflict),
+        (Err(MergeError::Report(report)), _) => Outcome::Conflicts(&report.conflicts),
+        (Err(_), message) => Outcome::Failed(message.as_deref().unwrap_or_default()),
+    };
+    if summary_md {
+        print!(
+            "{}",
+            summary::markdown(&[(args.ours.display().to_string(), outcome)])
+        );
+    }
+    // Webhooks are network access, which --offline (and --deterministic) rule out.
+    #[cfg(feature = "notify")]
+    if !args.offline
+        && !dry_run
+        && let Some(hooks) = &policy.notify
+        && let Outcome::Conflict(_) | Outcome::Conflicts(_) = outcome
+    {
+        let manifest = args.ours.display().to_string();
+        let report = cargo_merge_assist::notify::Report {
+            manifest: &manifest,
+            branches: &branches,
+            outcome,
+        };
+        let failures = timings.time("notify", || {
+            cargo_merge_assist::notify::notify(hooks, &report)
+        });
+        for err in failures {
+            eprintln!("warning: {err}");
+        }
+    }
 
-    let gitattributes_path = args.repo.join(".gitattributes");
-    append_unique_line(
-        &gitattributes_path,
-        "Cargo.toml merge=cargo-merge-assist-manifest",
-    )?;
-    append_unique_line(
-        &gitattributes_path,
-        "Cargo.lock merge=cargo-merge-assist-lock",
-    )?;
+    if args.write_conflicts
+        && !dry_run
+        && let Err(MergeError::Conflict(_) | MergeError::Report(_)) = &result
+    {
+        let labels = [
+            args.label_ours.as_deref().unwrap_or("ours"),
+            args.label_theirs.as_deref().unwrap_or("theirs"),
+        ];
+        let (marked, _) =
+            markers::with_markers(&base_text, &ours_text, &theirs_text, &options, labels).map_err(
+                |err| Error::merge(err, [&args.base, &args.ours, &args.theirs], &args.ours),
+            )?;
+        cleanup::write_atomic(&out, input::encode(&marked, args.lossy_input))
+            .map_err(|err| Error::io("writing merged manifest", &out, &err))?;
+    }
 
-    git_config(
-        &args.repo,
-        "merge.cargo-merge-assist-manifest.name",
-        "cargo-merge-assist semantic merge for Cargo.toml",
-    )?;
-    git_config(
-        &args.repo,
-        "merge.cargo-merge-assist-manifest.driver",
-        "cargo-merge-assist merge-manifest --base %O --ours %A --theirs %B --out %A",
-    )?;
-    git_config(
-        &args.repo,
-        "merge.cargo-merge-assist-lock.name",
-        "cargo-merge-assist lockfile regeneration driver",
-    )?;
-    git_config(
-        &args.repo,
-        "merge.cargo-merge-assist-lock.driver",
-        "cargo-merge-assist resolve-lock --repo .",
-    )?;
+    let MergeResult {
+        text: merged,
+        resolutions,
+    } = result
+        .map_err(|err| Error::merge(err, [&args.base, &args.ours, &args.theirs], &args.ours))?;
 
-    println!("Installed merge driver into {}", args.repo.display());
-    println!("Added/updated {}", gitattributes_path.display());
+    if args.strict {
+        check_strict(&merged, &out)?;
+    }
+    // Where the manifest lives, to find its workspace root.
+    let location = args.path.as_deref().unwrap_or(&out);
+    // A one-sided change cannot leave patches the other side invalidated,
+    // nor a feature graph broken.
+    if trivial.is_none() {
+        check_features(&merged, [&ours_text, &theirs_text], &out)?;
+        check_inheritance(
+            &merged,
+            [&base_text, &ours_text, &theirs_text],
+            location,
+            &out,
+        )?;
+        for warning in patch_warnings(&merged, manifest) {
+            eprintln!("warning: {warning}");
+        }
+    }
+    if dry_run {
+        return Ok(Some(merged));
+    }
 
-    Ok(())
-}
+    if let Some(path) = &provenance {
+        let origins =
+            provenance::provenance(&base_text, &ours_text, &theirs_text, &merged, &resolutions)
+                .map_err(|err| anyhow::anyhow!("cannot trace the merged values: {err}"))?;
+        let json = serde_json::to_string_pretty(&origins)? + "\n";
+        cleanup::write_atomic(path, json)
+            .map_err(|err| Error::io("writing provenance", path, &err))?;
+    }
+
+    timings
+        .time("write", || {
+            cleanup::write_atomic(&out, input::encode(&merged, args.lossy_input))
+        })
+        .map_err(|err| Error::io("writing merged manifest", &out, &err))?;
+    if trivial.is_none() {
+        carry_bumps(&base_text, &ours_text, &theirs_text, location)?;
+    }
 
-fn read_utf8(path: &Path) -> Result<String> {
-    fs::read_to_string(path).with_context(|| format!("failed reading {}", path.display()))
+    Ok(Some(merged))
 }
 
-fn ensure_manifest_exists(repo: &Path) -> Result<()> {
-    let manifest = repo.join("Cargo.toml");
-    if !manifest.exists() {
-        bail!(
-            "{} does not contain Cargo.toml; pass --repo with a Rust project root",
-            repo.display()
-        );
-    }
-    Ok(())
+/// The nearest manifest above `manifest` declaring a `[workspace]`.
+fn workspace_root(manifest: &Path) -> Option<PathBuf> {
+    let is_root = |path: &PathBuf| {
+        path != manifest
+            && fs::read_to_string(path).is_ok_and(|text| {
+                text.parse::<toml::Table>()
+                    .is_ok_and(|doc| doc.contains_key("workspace"))
+            })
+    };
+    manifest
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(is_root)
 }
 
-fn run_cargo(repo: &Path, args: &[&str], offline: bool) -> Result<()> {
-    let mut cmd = Command::new("cargo");
-    cmd.current_dir(repo);
-    cmd.args(args);
-    if offline {
-        cmd.arg("--offline");
+/// Fails when the `merged` manifest inherits a dependency that its workspace
+/// root (itself, or the root above `manifest`) does not declare, unless ours
+/// or theirs already did. `sides` are base, ours and theirs; the bumps
+/// [`carry_bumps`] adds to the root count as declared.
+fn check_inheritance(merged: &str, sides: [&str; 3], manifest: &Path, out: &Path) -> Result<()> {
+    let parse = |text: &str| text.parse::<toml::Value>().ok();
+    let [Some(merged), Some(base), Some(ours), Some(theirs)] =
+        [merged, sides[0], sides[1], sides[2]].map(parse)
+    else {
+        return Ok(());
+    };
+    // A root manifest declares its own inherited entries; each side is
+    // checked against its own version of it.
+    let separate = if merged.get("workspace").is_some() {
+        None
+    } else {
+        let Some(root) = workspace_root(manifest)
+            .and_then(|root| fs::read_to_string(root).ok())
+            .and_then(|text| parse(&text))
+        else {
+            return Ok(());
+        };
+        Some(root)
+    };
+    let root = |doc| separate.as_ref().unwrap_or(doc);
+    let bumps = inheritance::bumps(&base, &ours, &theirs);
+    let sides = [&ours, &theirs].map(|side| (side, root(side)));
+    let problems: Vec<String> = new_unresolved(&merged, root(&merged), sides)
+        .into_iter()
+        .filter(|problem| !bumps.iter().any(|bump| bump.name == problem.name))
+        .map(|problem| problem.to_string())
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Schema {
+        file: Some(out.to_path_buf()),
+        problems,
     }
+    .into())
+}
 
-    let status = cmd
-        .status()
-        .with_context(|| format!("failed to execute cargo in {}", repo.display()))?;
+/// The dependencies `member` inherits that `root` does not declare, except
+/// those ours or theirs (each a member and its root) already lacked.
+fn new_unresolved(
+    member: &toml::Value,
+    root: &toml::Value,
+    sides: [(&toml::Value, &toml::Value); 2],
+) -> Vec<inheritance::Unresolved> {
+    let known: Vec<_> = sides
+        .into_iter()
+        .flat_map(|(member, root)| inheritance::unresolved(member, root))
+        .collect();
+    inheritance::unresolved(member, root)
+        .into_iter()
+        .filter(|problem| !known.contains(problem))
+        .collect()
+}
 
-    if !status.success() {
-        bail!(
-            "cargo command failed in {}: cargo {}",
-            repo.display(),
-            args.join(" ")
-        );
+/// Carries the requirement bumps of dependencies the other side moved to
+/// workspace inheritance into the `[workspace.dependencies]` of the
+/// workspace root above `manifest`.
+fn carry_bumps(base: &str, ours: &str, theirs: &str, manifest: &Path) -> Result<()> {
+    let parse = |text: &str| text.parse::<toml::Value>().ok();
+    let (Some(base), Some(ours), Some(theirs)) = (parse(base), parse(ours), parse(theirs)) else {
+        return Ok(());
+    };
+    let bumps = inheritance::bumps(&base, &ours, &theirs);
+    if bumps.is_empty() {
+        return Ok(());
+    }
+    let Some(root) = workspace_root(manifest) else {
+        for bump in &bumps {
+            eprintln!("warning: no workspace root to carry {bump} into");
+        }
+        return Ok(());
+    };
+    let text = fs::read_to_string(&root).map_err(|err| Error::io("reading", &root, &err))?;
+    let (updated, skipped) = inheritance::apply(&text, &bumps)
+        .map_err(|err| anyhow::anyhow!("cannot update {}: {err}", root.display()))?;
+    for bump in &bumps {
+        if skipped.contains(&bump) {
+            eprintln!(
+                "warning: {bump} not carried into {}: its entry there changed",
+                root.display()
+            );
+        } else {
+            eprintln!("carried {bump} into {}", root.display());
+        }
+    }
+    if updated != text {
+        cleanup::write_atomic(&root, updated).map_err(|err| Error::io("writing", &root, &err))?;
     }
-
     Ok(())
 }
 
-fn git_config(repo: &Path, key: &str, value: &str) -> Result<()> {
-    let status = Command::new("git")
-        .arg("-C")
-        .arg(repo)
-        .arg("config")
-        .arg("--local")
-        .arg(key)
-        .arg(value)
-        .status()
-        .with_context(|| format!("failed to run git config in {}", repo.display()))?;
+/// The stderr lines logging what `--on-conflict` settled; strategies that
+/// take a side are not overrides.
+fn overrides(resolutions: &[Resolution]) -> Vec<String> {
+    resolutions
+        .iter()
+        .filter(|resolution| resolution.rule == "on-conflict")
+        .filter_map(|resolution| {
+            let side = resolution.side?;
+            Some(format!("override {} (took {side})", resolution.path))
+        })
+        .collect()
+}
 
-    if !status.success() {
-        bail!("git config failed for key `{key}`");
+/// The unstable values both sides changed, which the merge took as one.
+fn unstable_notes(base: &str, ours: &str, theirs: &str) -> Vec<unstable::Note> {
+    let parse = |text: &str| text.parse::<toml::Value>().ok();
+    match (parse(base), parse(ours), parse(theirs)) {
+        (Some(base), Some(ours), Some(theirs)) => unstable::notes(&base, &ours, &theirs),
+        _ => Vec::new(),
     }
-
-    Ok(())
 }
 
-fn append_unique_line(path: &Path, line: &str) -> Result<()> {
-    let mut existing = if path.exists() {
-        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?
-    } else {
-        String::new()
+/// Warnings about the `[patch]` sections of the `merged` text of
+/// `manifest`, checked against the nearest `Cargo.lock` above it.
+fn patch_warnings(merged: &str, manifest: &Path) -> Vec<patches::PatchWarning> {
+    let Some(doc) = merged
+        .parse::<toml::Value>()
+        .ok()
+        .filter(|doc| doc.get("patch").is_some())
+    else {
+        return Vec::new();
     };
+    let lock = manifest
+        .ancestors()
+        .skip(1)
+        .find_map(|dir| fs::read_to_string(dir.join("Cargo.lock")).ok())
+        .and_then(|text| text.parse::<toml::Value>().ok());
+    patches::check(&doc, lock.as_ref())
+}
 
-    if existing.lines().any(|l| l.trim() == line) {
-        return Ok(());
+/// `merge-manifest` with directory inputs: merges every manifest under
+/// `--ours` and `--theirs` with its counterparts at the same relative path
+/// into that path under `--out`, takes manifests added or deleted on one
+/// side, and copies the `Cargo.lock` beside each. Every file is attempted;
+/// the first failure is returned after the others are reported. Returns the
+/// manifests written.
+fn merge_directories(args: MergeManifestArgs, timings: &mut Timings) -> Result<Vec<PathBuf>> {
+    if args.provenance.is_some() {
+        bail!("--provenance needs manifest files, not directories");
     }
+    for (flag, path) in [("--base", &args.base), ("--theirs", &args.theirs)] {
+        if !path.is_dir() {
+            bail!(
+                "{flag} {} must be a directory when --ours is one",
+                path.display()
+            );
+        }
+    }
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let scope = |root: &Path| {
+        Excludes::new(
+            root,
+            policy.exclude.as_deref().unwrap_or_default(),
+            policy.include.as_deref().unwrap_or_default(),
+        )
+        .map_err(anyhow::Error::msg)
+    };
+    let entries = batch::directory_entries(
+        &args.base,
+        &scope(&args.ours)?,
+        &scope(&args.theirs)?,
+        &args.out,
+    );
 
-    if !existing.is_empty() && !existing.ends_with('\n') {
-        existing.push('\n');
+    let is_manifest = |path: &Path| path.file_name().is_some_and(|name| name == "Cargo.toml");
+    let mut merged = Vec::new();
+    let mut failures = Vec::new();
+    for entry in entries {
+        let out = match &entry {
+            DirectoryEntry::Merge(job) => &job.out,
+            DirectoryEntry::Copy { out, .. }
+            | DirectoryEntry::Deleted { out }
+            | DirectoryEntry::Conflict { out, .. } => out,
+        };
+        if let Some(dir) = out.parent()
+            && let Err(err) = fs::create_dir_all(dir)
+        {
+            failures.push(Error::io("creating", dir, &err).into());
+            continue;
+        }
+        match entry {
+            DirectoryEntry::Merge(job) => {
+                let file_args = MergeManifestArgs {
+                    base: job.base,
+                    ours: job.ours,
+                    theirs: job.theirs,
+                    out: job.out.clone(),
+                    path: None,
+                    provenance: None,
+                    ..args.clone()
+                };
+                match merge_manifest_cmd(file_args, timings) {
+                    Ok(()) => merged.push(job.out),
+                    Err(err) => failures.push(err),
+                }
+            }
+            DirectoryEntry::Copy { from, out } => {
+                let same = fs::canonicalize(&from).ok() == fs::canonicalize(&out).ok();
+                let copied = if same {
+                    Ok(())
+                } else {
+                    fs::read(&from).and_then(|bytes| cleanup::write_atomic(&out, bytes))
+                };
+                match copied {
+                    Ok(()) => {
+                        eprintln!("copied   {} from {}", out.display(), from.display());
+                        if is_manifest(&out) {
+                            merged.push(out);
+                        }
+                    }
+                    Err(err) => failures.push(Error::io("copying", &from, &err).into()),
+                }
+            }
+            DirectoryEntry::Deleted { out } => match fs::remove_file(&out) {
+                Ok(()) => eprintln!("deleted  {}", out.display()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => failures.push(Error::io("deleting", &out, &err).into()),
+            },
+            DirectoryEntry::Conflict { out, reason } => {
+                failures.push(anyhow::anyhow!("{}: {reason}", out.display()));
+            }
+        }
     }
-    existing.push_str(line);
-    existing.push('\n');
+    let mut failures = failures.into_iter();
+    if let Some(first) = failures.next() {
+        for err in failures {
+            eprintln!("error: {err:#}");
+        }
+        return Err(first);
+    }
+    Ok(merged)
+}
 
-    fs::write(path, existing).with_context(|| format!("failed to write {}", path.display()))?;
+fn merge_cargo_config_cmd(args: MergeCargoConfigArgs, timings: &mut Timings) -> Result<()> {
+    let out = paths::normalize(&args.out);
+    let (base_text, ours_text, theirs_text) = timings.time("read", || {
+        Ok::<_, anyhow::Error>((
+            read_manifest(&args.base, args.lossy_input)?,
+            read_manifest(&args.ours, args.lossy_input)?,
+            read_manifest(&args.theirs, args.lossy_input)?,
+        ))
+    })?;
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let options = merge_options(
+        Path::new("."),
+        &policy,
+        &Branches::detect(Path::new("."), None, None),
+    )?;
+    let merged =
+        config::merge_config_texts(&base_text, &ours_text, &theirs_text, &options, timings)
+            .map_err(|err| Error::merge(err, [&args.base, &args.ours, &args.theirs], &args.ours))?
+            .text;
+    timings
+        .time("write", || {
+            cleanup::write_atomic(&out, input::encode(&merged, args.lossy_input))
+        })
+        .map_err(|err| Error::io("writing merged config", &out, &err))?;
+    Ok(())
+}
+
+fn edit_cmd(args: EditArgs) -> Result<()> {
+    let out = paths::normalize(&args.out);
+    let base_text = read_manifest(&args.base, false)?;
+    let ours_text = read_manifest(&args.ours, false)?;
+    let theirs_text = read_manifest(&args.theirs, false)?;
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let options = merge_options(
+        Path::new("."),
+        &policy,
+        &Branches::detect(Path::new("."), None, None),
+    )?;
+
+    let (merged, conflicts) =
+        edit::provisional_merge(&base_text, &ours_text, &theirs_text, &options)
+            .map_err(|err| Error::merge(err, [&args.base, &args.ours, &args.theirs], &args.ours))?;
+    let mut text = edit::annotate(&merged, &conflicts);
+    cleanup::write_atomic(&out, &text).map_err(|err| Error::io("writing", &out, &err))?;
+    if conflicts.is_empty() {
+        println!("merged {} without conflicts", out.display());
+        return Ok(());
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    loop {
+        let mut words = editor.split_whitespace();
+        let program = words.next().context("$EDITOR is empty")?;
+        let status = Command::new(program)
+            .args(words)
+            .arg(&out)
+            .status()
+            .with_context(|| format!("failed to run editor `{editor}`"))?;
+        if !status.success() {
+            bail!("editor `{editor}` exited with {status}");
+        }
+
+        let edited = fs::read_to_string(&out).map_err(|err| Error::io("reading", &out, &err))?;
+        let problem = match edit::validate(&edited) {
+            Ok(()) => {
+                println!(
+                    "resolved {} conflicts in {}",
+                    conflicts.len(),
+                    out.display()
+                );
+                return Ok(());
+            }
+            Err(problem) => problem,
+        };
+        if edited == text {
+            // Saved without changes: the user gave up on this file.
+            bail!("{} still has conflicts: {problem}", out.display());
+        }
+        eprintln!("{}: {problem}; reopening", out.display());
+        text = edited;
+    }
+}
+
+fn check_strict(merged: &str, out: &Path) -> Result<()> {
+    let doc: toml::Value = toml::from_str(merged).context("merged manifest is not valid TOML")?;
+    let unknown = strict::check(&doc);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Schema {
+        file: Some(out.to_path_buf()),
+        problems: unknown.iter().map(ToString::to_string).collect(),
+    }
+    .into())
+}
+
+/// Fails when the merged manifest's feature graph has problems that neither
+/// side had, so the merge itself broke it.
+fn check_features(merged: &str, sides: [&str; 2], out: &Path) -> Result<()> {
+    let parse = |text: &str| toml::from_str::<toml::Value>(text).map(|doc| features::check(&doc));
+    let problems = parse(merged).context("merged manifest is not valid TOML")?;
+    let known: Vec<FeatureProblem> = sides
+        .into_iter()
+        .filter_map(|side| parse(side).ok())
+        .flatten()
+        .collect();
+    let problems: Vec<String> = problems
+        .iter()
+        .filter(|problem| !known.contains(problem))
+        .map(ToString::to_string)
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Schema {
+        file: Some(out.to_path_buf()),
+        problems,
+    }
+    .into())
+}
+
+fn merge_with_cache(
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+    heuristics: &[String],
+    no_cache: bool,
+    timings: &mut Timings,
+) -> Result<MergeResult, MergeError> {
+    let cache = if no_cache {
+        None
+    } else {
+        ResultCache::for_repo(Path::new("."))
+    };
+    let config = options.cache_config(heuristics);
+    let key = ResultCache::key(base_text, ours_text, theirs_text, &config);
+
+    let hit = timings.time("cache lookup", || {
+        cache.as_ref().and_then(|cache| cache.get(&key))
+    });
+    if let Some(text) = hit {
+        return Ok(MergeResult {
+            text,
+            resolutions: Vec::new(),
+        });
+    }
+
+    let merged =
+        merge_manifest_texts_detailed(base_text, ours_text, theirs_text, options, timings)?;
+    if let Some(cache) = &cache
+        && let Err(err) = cache.put(&key, &merged.text)
+    {
+        eprintln!("warning: failed to cache merge result: {err}");
+    }
+
+    Ok(merged)
+}
+
+fn resolve_lock_cmd(args: ResolveLockArgs, timings: &mut Timings) -> Result<()> {
+    let repo = paths::normalize(&args.repo);
+    let sides = match (&args.base, &args.ours, &args.theirs) {
+        (Some(base), Some(ours), Some(theirs)) => {
+            Some([base, ours, theirs].map(|path| paths::normalize(path)))
+        }
+        _ => None,
+    };
+    if let Some([base, ours, theirs]) = &sides {
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| Error::io("reading lockfile", path, &err))
+        };
+        let (base_text, ours_text, theirs_text) = timings.time("read", || {
+            Ok::<_, Error>((read(base)?, read(ours)?, read(theirs)?))
+        })?;
+        if let Some(winner) = trivial_merge(&base_text, &ours_text, &theirs_text) {
+            if winner != ours_text {
+                cleanup::write_atomic(ours, winner)
+                    .map_err(|err| Error::io("writing lockfile", ours, &err))?;
+            }
+            return Ok(());
+        }
+    }
+    ensure_manifest_exists(&repo)?;
+    if !cargo_available(args.allow_no_cargo, "regenerating Cargo.lock")? {
+        return Ok(());
+    }
+    regenerate_lockfile(&repo, args.offline, timings)?;
+    // Git takes the merge result from --ours.
+    if let Some([_, ours, _]) = &sides {
+        let lockfile = repo.join("Cargo.lock");
+        fs::copy(&lockfile, ours).map_err(|err| Error::io("copying", &lockfile, &err))?;
+    }
+    if args.verify {
+        verify_workspace(
+            &repo,
+            args.jobs,
+            &args.changed,
+            args.no_verify_cache,
+            args.offline,
+            timings,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Whether cargo is on `PATH`. Without it, fails unless `allow_missing`,
+/// which warns that the `skipped` steps do not run.
+fn cargo_available(allow_missing: bool, skipped: &str) -> Result<bool> {
+    if cargo::available() {
+        return Ok(true);
+    }
+    if !allow_missing {
+        return Err(Error::NoCargo.into());
+    }
+    eprintln!("warning: cargo is not installed; skipped {skipped}");
+    Ok(false)
+}
+
+/// `cargo generate-lockfile` and the policy's pins, reporting forks the
+/// lockfile kept after their `[patch]` was merged away. Resolution runs in a
+/// [`Sandbox`]; the repository's `Cargo.lock` is only replaced once the
+/// result passes `cargo update --workspace --locked`.
+fn regenerate_lockfile(repo: &Path, offline: bool, timings: &mut Timings) -> Result<()> {
+    let pins = load_policy(repo, !offline)?.pins.unwrap_or_default();
+    let dangling_before = dangling_patches(repo);
+    let sandbox = timings.time("sandbox", || Sandbox::new(repo))?;
+    let scratch = sandbox.root();
+    timings.time("cargo generate-lockfile", || {
+        cargo::run(scratch, &["generate-lockfile"], offline)
+    })?;
+    let dangling_after = dangling_patches(scratch);
+    for change in dangling_before {
+        if !dangling_after.contains(&change) {
+            eprintln!("{change}");
+        }
+    }
+    let moves = timings.time("cargo update --precise", || {
+        cargo::apply_pins(scratch, &pins, offline)
+    })?;
+    timings.time("cargo update --locked", || {
+        cargo::run(scratch, &["update", "--workspace", "--locked"], offline)
+    })?;
+    sandbox.commit(repo)?;
+    for pinned in moves {
+        eprintln!("pinned   {pinned}");
+    }
+    Ok(())
+}
+
+/// Fails when the direct dependencies `merged` has and `base` did not are
+/// licensed outside the policy's `allowed-licenses`; see [`licenses`].
+fn check_licenses(
+    repo: &Path,
+    base: &Path,
+    merged: &Path,
+    lossy: bool,
+    offline: bool,
+    timings: &mut Timings,
+) -> Result<()> {
+    let Some(allowed) = load_policy(repo, !offline)?.allowed_licenses else {
+        return Ok(());
+    };
+    // A manifest added in a directory merge has no base.
+    let base = if base.exists() {
+        read_manifest(base, lossy)?
+    } else {
+        String::new()
+    };
+    let added = licenses::added_dependencies(&base, &read_manifest(merged, lossy)?)
+        .map_err(anyhow::Error::msg)?;
+    if added.is_empty() {
+        return Ok(());
+    }
+    let metadata = timings.time("cargo metadata", || {
+        cargo::stdout(repo, &["metadata", "--format-version", "1"], offline)
+    })?;
+    let violations = licenses::check(&metadata, &added, &allowed).map_err(anyhow::Error::msg)?;
+    if !violations.is_empty() {
+        return Err(Error::License {
+            file: merged.to_path_buf(),
+            violations: violations.iter().map(ToString::to_string).collect(),
+        }
+        .into());
+    }
+    let noun = if added.len() == 1 {
+        "dependency"
+    } else {
+        "dependencies"
+    };
+    eprintln!("checked  licenses of {} new {noun}", added.len());
+    Ok(())
+}
+
+/// `cargo check`, of the members affected by `changed` when `jobs` > 1.
+/// Skipped when the same state passed before, unless `no_cache`.
+fn verify_workspace(
+    repo: &Path,
+    jobs: Option<usize>,
+    changed: &[PathBuf],
+    no_cache: bool,
+    offline: bool,
+    timings: &mut Timings,
+) -> Result<()> {
+    let jobs = jobs.filter(|&jobs| jobs > 1);
+    let cached = (!no_cache)
+        .then(|| {
+            let scope = match jobs {
+                Some(jobs) => format!("check jobs={jobs} changed={changed:?}"),
+                None => "check".to_string(),
+            };
+            Some((
+                VerifyCache::for_repo(repo)?,
+                VerifyCache::key(repo, &scope)?,
+            ))
+        })
+        .flatten();
+    if let Some((cache, key)) = &cached
+        && cache.contains(key)
+    {
+        eprintln!(
+            "skipped  cargo check (manifests, lockfile and toolchain unchanged since it passed)"
+        );
+        return Ok(());
+    }
+
+    timings.time("cargo check", || match jobs {
+        Some(jobs) => verify::check_affected(repo, changed, jobs, offline),
+        None => cargo::run(repo, &["check", "-q"], offline),
+    })?;
+    if let Some((cache, key)) = &cached
+        && let Err(err) = cache.record(key)
+    {
+        eprintln!("warning: failed to record passing check: {err}");
+    }
+    Ok(())
+}
+
+/// Git-sourced lock entries the repository's manifest no longer references;
+/// none when either file is missing or invalid.
+fn dangling_patches(repo: &Path) -> Vec<estimate::LockChange> {
+    let read = |name: &str| -> Option<toml::Value> {
+        fs::read_to_string(repo.join(name)).ok()?.parse().ok()
+    };
+    match (read("Cargo.toml"), read("Cargo.lock")) {
+        (Some(manifest), Some(lock)) => estimate::dangling_patches(&manifest, &lock),
+        _ => Vec::new(),
+    }
+}
+
+impl MergeAllArgs {
+    /// The `merge-manifest` part of the run.
+    fn manifest_args(&self) -> MergeManifestArgs {
+        MergeManifestArgs {
+            base: self.base.clone(),
+            ours: self.ours.clone(),
+            theirs: self.theirs.clone(),
+            out: self.out.clone(),
+            path: None,
+            lossy_input: self.lossy_input,
+            no_cache: self.no_cache,
+            max_depth: self.max_depth,
+            min_confidence: self.min_confidence,
+            build_min_confidence: self.build_min_confidence,
+            build_prefer: self.build_prefer,
+            apply_resolutions: self.apply_resolutions.clone(),
+            online_hints: self.online_hints,
+            git_ancestry: self.git_ancestry,
+            strategies: self.strategies.clone(),
+            offline: self.offline,
+            label_ours: self.label_ours.clone(),
+            label_theirs: self.label_theirs.clone(),
+            strict: self.strict,
+            audit_trail: self.audit_trail,
+            strip_audit_trail: self.strip_audit_trail,
+            provenance: self.provenance.clone(),
+            all_conflicts: self.all_conflicts,
+            migrate_deprecated: self.migrate_deprecated,
+            no_newest_edition: self.no_newest_edition,
+            no_highest_version: self.no_highest_version,
+            sort: self.sort,
+            regenerate_cmd: self.regenerate_cmd.clone(),
+            write_conflicts: false,
+            on_conflict: OnConflict::Fail,
+            format: self.format,
+        }
+    }
+}
+
+fn merge_all_cmd(mut args: MergeAllArgs, timings: &mut Timings) -> Result<()> {
+    if args.check {
+        return check_merge_all(&args, timings);
+    }
+    // Checked before merging so a missing cargo does not fail halfway.
+    let cargo = cargo_available(
+        args.allow_no_cargo,
+        "regenerating Cargo.lock, the license check and verification",
+    )?;
+    if args.ours.is_dir() {
+        return merge_all_directories(&args, cargo, timings);
+    }
+    let repo = paths::normalize(&args.repo);
+    let out = args.out.clone();
+    let lockfile = repo.join("Cargo.lock");
+
+    // Resume where an earlier run with the same inputs and flags stopped.
+    let from_step = args.from_step.take();
+    let state_path = RunState::path(&repo, &out);
+    let read = |path: &Path| fs::read(path).unwrap_or_default();
+    let key = resume::digest(&[
+        format!("{args:?}").as_bytes(),
+        &read(&args.base),
+        &read(&args.theirs),
+    ]);
+    let ours = resume::file_digest(&args.ours).unwrap_or_default();
+    let recorded = RunState::load(&state_path);
+    let resume_at = recorded.as_ref().map_or(Step::Merge, |state| {
+        state.resume_at(
+            &key,
+            &ours,
+            resume::file_digest(&out).as_deref(),
+            resume::file_digest(&lockfile).as_deref(),
+        )
+    });
+    let start = from_step.unwrap_or(resume_at);
+    let mut state = match recorded {
+        Some(state) if resume_at > Step::Merge => state,
+        _ => RunState::new(key, ours),
+    };
+    if start > Step::Merge {
+        eprintln!("resuming merge-all at {start}");
+    }
+    let save = |state: &RunState| {
+        if let Err(err) = state.save(&state_path) {
+            eprintln!("warning: failed to record merge-all progress: {err}");
+        }
+    };
+
+    if start <= Step::Merge {
+        merge_manifest_cmd(args.manifest_args(), timings)?;
+        state.lock = None;
+    }
+    if start <= Step::Merge || state.merged.is_none() {
+        state.merged = resume::file_digest(&out);
+        save(&state);
+    }
+
+    ensure_manifest_exists(&repo)?;
+    if cargo && start <= Step::Lock {
+        regenerate_lockfile(&repo, args.offline, timings)?;
+        state.lock = resume::file_digest(&lockfile);
+        save(&state);
+    }
+    if cargo {
+        check_licenses(
+            &repo,
+            &args.base,
+            &out,
+            args.lossy_input,
+            args.offline,
+            timings,
+        )?;
+    }
+    if cargo && !args.skip_verify {
+        verify_workspace(
+            &repo,
+            args.jobs,
+            &[out],
+            args.no_verify_cache,
+            args.offline,
+            timings,
+        )?;
+    }
+
+    if let Err(err) = fs::remove_file(&state_path)
+        && err.kind() != std::io::ErrorKind::NotFound
+    {
+        eprintln!("warning: failed to remove {}: {err}", state_path.display());
+    }
+    Ok(())
+}
+
+/// `merge-all --check`: merges the manifests into a scratch file, as
+/// `merge-manifest` would (conflicts fail the same way), and reports the
+/// `Cargo.lock` changes the result is expected to force; see [`estimate`].
+/// Neither `--out` nor the lockfile is written.
+fn check_merge_all(args: &MergeAllArgs, timings: &mut Timings) -> Result<()> {
+    if args.ours.is_dir() {
+        bail!("--check needs manifest files, not directories");
+    }
+    let repo = paths::normalize(&args.repo);
+    let manifest_args = MergeManifestArgs {
+        no_cache: true,
+        provenance: None,
+        ..args.manifest_args()
+    };
+    let Some(merged) = merge_manifest(manifest_args, true, timings)? else {
+        bail!("a generated manifest cannot be checked");
+    };
+
+    let lockfile = repo.join("Cargo.lock");
+    let Ok(lock) = fs::read_to_string(&lockfile) else {
+        eprintln!(
+            "warning: no {}; lockfile changes not predicted",
+            lockfile.display()
+        );
+        return Ok(());
+    };
+    let changes = timings
+        .time("estimate", || {
+            estimate::estimate(&merged, &lock, &RegistryIndex::from_cargo_home())
+        })
+        .with_context(|| format!("cannot estimate changes to {}", lockfile.display()))?;
+    match args.format {
+        Format::Text if changes.is_empty() => println!("no Cargo.lock changes expected"),
+        Format::Text => {
+            for change in &changes {
+                println!("would {change}");
+            }
+        }
+        Format::SummaryMd => print!("\n{}", summary::lock_changes(&changes)),
+    }
+    Ok(())
+}
+
+/// `merge-all` with directory inputs: merges the manifests as
+/// `merge-manifest` does, then regenerates and verifies the lockfile of
+/// every merged manifest with a `Cargo.lock` beside it, unless there is no
+/// `cargo`. Runs are not resumed.
+fn merge_all_directories(args: &MergeAllArgs, cargo: bool, timings: &mut Timings) -> Result<()> {
+    let merged = merge_directories(args.manifest_args(), timings)?;
+    if !cargo {
+        return Ok(());
+    }
+    for manifest in merged {
+        let root = manifest
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if !root.join("Cargo.lock").is_file() {
+            continue;
+        }
+        regenerate_lockfile(root, args.offline, timings)?;
+        let base = args
+            .base
+            .join(manifest.strip_prefix(&args.out).unwrap_or(&manifest));
+        check_licenses(
+            root,
+            &base,
+            &manifest,
+            args.lossy_input,
+            args.offline,
+            timings,
+        )?;
+        if !args.skip_verify {
+            verify_workspace(
+                root,
+                args.jobs,
+                std::slice::from_ref(&manifest),
+                args.no_verify_cache,
+                args.offline,
+                timings,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn merge_batch_cmd(
+    args: MergeBatchArgs,
+    metrics_log: Option<&Path>,
+    timings: &mut Timings,
+) -> Result<()> {
+    let spec_text = read_manifest(&args.spec, false)?;
+    let spec_dir = args.spec.parent().unwrap_or(Path::new("."));
+    let scan_root = if spec_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        spec_dir
+    };
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let excludes = Excludes::new(
+        scan_root,
+        policy.exclude.as_deref().unwrap_or_default(),
+        policy.include.as_deref().unwrap_or_default(),
+    )
+    .map_err(anyhow::Error::msg)?;
+    let mut jobs = batch::parse_spec(&spec_text, spec_dir).map_err(anyhow::Error::msg)?;
+    for job in batch::discover_jobs(&spec_text, spec_dir, &excludes).map_err(anyhow::Error::msg)? {
+        if !jobs.iter().any(|listed| listed.out == job.out) {
+            jobs.push(job);
+        }
+    }
+    jobs.retain(|job| {
+        let excluded = excludes.is_excluded(&job.out);
+        if excluded {
+            eprintln!("skipped  {} (excluded)", job.out.display());
+        }
+        !excluded
+    });
+    let options = merge_options(
+        Path::new("."),
+        &policy,
+        &Branches::detect(Path::new("."), None, None),
+    )?;
+    let reconcile_mode = args.reconcile.or(policy.reconcile).unwrap_or_default();
+
+    // `out` usually overwrites `ours`; keep the pre-merge text to compare.
+    let before: Vec<Option<String>> = match reconcile_mode {
+        ReconcileMode::Off => Vec::new(),
+        _ => jobs
+            .iter()
+            .map(|job| fs::read_to_string(&job.ours).ok())
+            .collect(),
+    };
+
+    // Each member's sides, for the inheritance check once `out` has
+    // overwritten `ours`.
+    let sides: Vec<Option<[toml::Value; 2]>> = jobs
+        .iter()
+        .map(|job| {
+            let parse = |path: &Path| fs::read_to_string(path).ok()?.parse().ok();
+            Some([parse(&job.ours)?, parse(&job.theirs)?])
+        })
+        .collect();
+    let mut outcomes = timings.time("batch merge", || {
+        batch::run_batch(&jobs, &options, args.lossy_input, args.jobs)
+    });
+    for (job, outcome) in jobs.iter().zip(&mut outcomes) {
+        if let BatchOutcome::Conflict(conflict) = outcome {
+            Owners::new(&policy, Path::new("."), &job.ours).assign(conflict);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(log) = metrics_log {
+        log_batch(log, &jobs, &outcomes);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = metrics_log;
+
+    let unresolved = outcomes
+        .iter()
+        .filter(|outcome| !matches!(outcome, BatchOutcome::Merged(_)))
+        .count();
+    match args.format {
+        Format::Text => {
+            for (job, outcome) in jobs.iter().zip(&outcomes) {
+                match outcome {
+                    BatchOutcome::Merged(_) => println!("merged   {}", job.out.display()),
+                    BatchOutcome::Conflict(conflict) => {
+                        println!("conflict {}\n{conflict}", job.ours.display())
+                    }
+                    BatchOutcome::Failed(message) => {
+                        println!("failed   {}: {message}", job.ours.display())
+                    }
+                }
+            }
+        }
+        Format::SummaryMd => {
+            let rows: Vec<_> = jobs
+                .iter()
+                .zip(&outcomes)
+                .map(|(job, outcome)| {
+                    let outcome = match outcome {
+                        BatchOutcome::Merged(resolutions) => Outcome::Merged(resolutions),
+                        BatchOutcome::Conflict(conflict) => Outcome::Conflict(conflict),
+                        BatchOutcome::Failed(message) => Outcome::Failed(message),
+                    };
+                    (job.ours.display().to_string(), outcome)
+                })
+                .collect();
+            print!("{}", summary::markdown(&rows));
+        }
+    }
+
+    if unresolved > 0 {
+        let message = format!("{unresolved} of {} merges did not complete", jobs.len());
+        let conflicts: Vec<FileConflict> = jobs
+            .iter()
+            .zip(&outcomes)
+            .filter_map(|(job, outcome)| match outcome {
+                BatchOutcome::Conflict(conflict) => Some(FileConflict {
+                    file: job.ours.clone(),
+                    conflict: conflict.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+        // Conflicts only: the batch fails like a single conflicting merge.
+        if conflicts.len() == unresolved {
+            return Err(Error::ConflictSet { conflicts }).context(message);
+        }
+        bail!(message);
+    }
+
+    let members: Vec<Member> = jobs
+        .iter()
+        .zip(before)
+        .filter_map(|(job, before)| {
+            Some(Member {
+                path: job.out.clone(),
+                before: before?,
+                merged: fs::read_to_string(&job.out).ok()?,
+            })
+        })
+        .collect();
+    let reconciliation = timings.time("reconcile", || {
+        reconcile::reconcile(&members, reconcile_mode)
+    });
+    for (path, text) in &reconciliation.rewritten {
+        cleanup::write_atomic(path, text)
+            .with_context(|| format!("failed writing {}", path.display()))?;
+    }
+    for (name, req) in &reconciliation.harmonized {
+        println!("harmonized {name} to \"{req}\" across the workspace");
+    }
+    for divergence in &reconciliation.conflicts {
+        println!("{divergence}");
+    }
+    if !reconciliation.conflicts.is_empty() {
+        bail!(
+            "{} dependencies diverge across the merged workspace",
+            reconciliation.conflicts.len()
+        );
+    }
+
+    if args.hoist_duplicates {
+        hoist_duplicates(&args.workspace_root, &jobs, timings)?;
+    }
+    check_inherited(&args.workspace_root, &jobs, &sides)
+}
+
+/// Fails when a merged member inherits a dependency the merged workspace
+/// `root` does not declare, unless its ours or theirs already did against
+/// that side's root. `sides` holds each job's ours and theirs as read before
+/// the merge. Skipped when `root` is not a workspace root.
+fn check_inherited(
+    root: &Path,
+    jobs: &[batch::BatchJob],
+    sides: &[Option<[toml::Value; 2]>],
+) -> Result<()> {
+    let parse =
+        |path: &Path| -> Option<toml::Value> { fs::read_to_string(path).ok()?.parse().ok() };
+    let Some(merged_root) = parse(root).filter(|doc| doc.get("workspace").is_some()) else {
+        return Ok(());
+    };
+    // A root merged in the batch has sides of its own.
+    let canonical_root = fs::canonicalize(root).ok();
+    let root_sides = jobs
+        .iter()
+        .zip(sides)
+        .find(|(job, _)| {
+            canonical_root.is_some() && fs::canonicalize(&job.out).ok() == canonical_root
+        })
+        .and_then(|(_, sides)| sides.as_ref());
+    let [ours_root, theirs_root] = match root_sides {
+        Some([ours, theirs]) => [ours, theirs],
+        None => [&merged_root, &merged_root],
+    };
+    let mut problems = Vec::new();
+    for (job, sides) in jobs.iter().zip(sides) {
+        let Some(member) = parse(&job.out) else {
+            continue;
+        };
+        let unresolved = match sides {
+            Some([ours, theirs]) => new_unresolved(
+                &member,
+                &merged_root,
+                [(ours, ours_root), (theirs, theirs_root)],
+            ),
+            None => inheritance::unresolved(&member, &merged_root),
+        };
+        problems.extend(
+            unresolved
+                .into_iter()
+                .map(|problem| format!("{}: {problem}", job.out.display())),
+        );
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Schema {
+        file: Some(root.to_path_buf()),
+        problems,
+    }
+    .into())
+}
+
+fn hoist_duplicates(root: &Path, jobs: &[batch::BatchJob], timings: &mut Timings) -> Result<()> {
+    let mut members = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        members.push((job.out.clone(), read_manifest(&job.out, false)?));
+    }
+    // A root that is also a member is spelled as in the spec so the library
+    // rewrites its merged output in place.
+    let canonical_root = fs::canonicalize(root).ok();
+    let root = jobs
+        .iter()
+        .find(|job| canonical_root.is_some() && fs::canonicalize(&job.out).ok() == canonical_root)
+        .map_or(root, |job| job.out.as_path());
+    let root_text = read_manifest(root, false)?;
+
+    let hoisting = timings
+        .time("hoist", || {
+            reconcile::hoist_duplicates(root, &root_text, &members)
+        })
+        .map_err(anyhow::Error::msg)?;
+    for (path, text) in &hoisting.rewritten {
+        cleanup::write_atomic(path, text)
+            .with_context(|| format!("failed writing {}", path.display()))?;
+    }
+    for name in &hoisting.hoisted {
+        println!("hoisted {name} into [workspace.dependencies]");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+fn log_batch(log: &Path, jobs: &[batch::BatchJob], outcomes: &[BatchOutcome]) {
+    use cargo_merge_assist::metrics::{self, MergeRecord, Outcome};
+
+    for (job, outcome) in jobs.iter().zip(outcomes) {
+        let record = match outcome {
+            BatchOutcome::Merged(_) => MergeRecord::new("merge-batch", &job.ours, Outcome::Merged),
+            BatchOutcome::Conflict(conflict) => {
+                let mut record = MergeRecord::new("merge-batch", &job.ours, Outcome::Conflict);
+                record.add_conflicts([conflict]);
+                record
+            }
+            BatchOutcome::Failed(_) => {
+                let mut record = MergeRecord::new("merge-batch", &job.ours, Outcome::Error);
+                record.error = Some("io".to_string());
+                record
+            }
+        };
+        if let Err(err) = metrics::append(log, &record) {
+            eprintln!(
+                "warning: failed to write metrics log {}: {err}",
+                log.display()
+            );
+            return;
+        }
+    }
+}
+
+/// `triage`: reports the conflicts by partition and, given decisions,
+/// merges once more with them. The merged manifest is written only when no
+/// conflict is left; the rest are reported by partition again.
+fn triage_cmd(args: TriageArgs, timings: &mut Timings) -> Result<()> {
+    let inputs = [&args.base, &args.ours, &args.theirs].map(PathBuf::as_path);
+    let [base_text, ours_text, theirs_text] = [
+        read_manifest(&args.base, false)?,
+        read_manifest(&args.ours, false)?,
+        read_manifest(&args.theirs, false)?,
+    ];
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let branches = Branches::detect(Path::new("."), None, None);
+    let mut options = merge_options(Path::new("."), &policy, &branches)?;
+    options.all_conflicts = true;
+    let merge = |options: &MergeOptions, timings: &mut Timings| match merge_manifest_texts_detailed(
+        &base_text,
+        &ours_text,
+        &theirs_text,
+        options,
+        timings,
+    ) {
+        Ok(merged) => Ok(Ok(merged)),
+        Err(MergeError::Report(report)) => Ok(Err(report.conflicts)),
+        Err(MergeError::Conflict(conflict)) => Ok(Err(vec![conflict])),
+        Err(err) => Err(Error::merge(err, inputs, &args.ours)),
+    };
+
+    let Err(conflicts) = merge(&options, timings)? else {
+        println!("no conflicts; merge with merge-manifest");
+        return Ok(());
+    };
+    let partitions = triage::partition(conflicts);
+    print!("{}", triage::report(&partitions));
+
+    let mut decisions: Vec<(&triage::Partition, Strategy)> = Vec::new();
+    for (id, strategy) in &args.decisions {
+        let Some(partition) = partitions.iter().find(|partition| partition.id() == *id) else {
+            bail!("no partition `{id}` (see the list above)");
+        };
+        decisions.push((partition, *strategy));
+    }
+    if args.accept_suggested {
+        for partition in &partitions {
+            if let Some(strategy) = partition.suggested
+                && !decisions
+                    .iter()
+                    .any(|(decided, _)| decided.id() == partition.id())
+            {
+                decisions.push((partition, strategy));
+            }
+        }
+    }
+    if decisions.is_empty() {
+        println!("resolve partitions with --apply <partition>=<strategy> or --accept-suggested");
+        return Ok(());
+    }
+
+    // Ahead of the policy's strategies: decisions taken here are explicit.
+    let strategies: Vec<_> = decisions
+        .iter()
+        .flat_map(|(partition, strategy)| partition.strategies(*strategy))
+        .collect();
+    options.strategies.splice(0..0, strategies);
+    match merge(&options, timings)? {
+        Ok(merged) => {
+            let out = paths::normalize(args.out.as_deref().unwrap_or(&args.ours));
+            cleanup::write_atomic(&out, merged.text)
+                .map_err(|err| Error::io("writing merged manifest", &out, &err))?;
+            eprintln!(
+                "applied  {} decision(s); wrote {}",
+                decisions.len(),
+                out.display()
+            );
+            Ok(())
+        }
+        Err(conflicts) => {
+            println!("\nleft after the decisions:");
+            let left = triage::partition(conflicts.clone());
+            print!("{}", triage::report(&left));
+            let report = MergeError::Report(MergeReport { conflicts });
+            Err(Error::merge(report, inputs, &args.ours).into())
+        }
+    }
+}
+
+fn estimate_lock_cmd(args: EstimateLockArgs, timings: &mut Timings) -> Result<()> {
+    let repo = paths::normalize(&args.repo);
+    let manifest = match (&args.base, &args.ours, &args.theirs) {
+        (Some(base), Some(ours), Some(theirs)) => {
+            let base_text = read_manifest(base, false)?;
+            let ours_text = read_manifest(ours, false)?;
+            let theirs_text = read_manifest(theirs, false)?;
+            // Never fetches: a remote policy must already be cached.
+            let options = merge_options(
+                &repo,
+                &load_policy(&repo, false)?,
+                &Branches::detect(&repo, None, None),
+            )?;
+            merge_manifest_texts_timed(&base_text, &ours_text, &theirs_text, &options, timings)
+                .context("manifests cannot be merged")?
+        }
+        _ => {
+            ensure_manifest_exists(&repo)?;
+            read_manifest(&repo.join("Cargo.toml"), false)?
+        }
+    };
+    let lockfile = repo.join("Cargo.lock");
+    let lock = fs::read_to_string(&lockfile)
+        .with_context(|| format!("failed reading {}", lockfile.display()))?;
+
+    let changes = timings
+        .time("estimate", || {
+            estimate::estimate(&manifest, &lock, &RegistryIndex::from_cargo_home())
+        })
+        .with_context(|| format!("cannot estimate changes to {}", lockfile.display()))?;
+
+    if changes.is_empty() {
+        println!("no Cargo.lock changes expected");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{change}");
+    }
+    if args.exit_code {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn install_git_driver_cmd(args: InstallGitDriverArgs) -> Result<()> {
+    let repo = paths::normalize(&args.repo);
+    ensure_manifest_exists(&repo)?;
+
+    let gitattributes_path = repo.join(".gitattributes");
+    append_unique_line(
+        &gitattributes_path,
+        &format!("Cargo.toml merge={}", health::MANIFEST_DRIVER),
+    )?;
+    append_unique_line(
+        &gitattributes_path,
+        &format!("Cargo.lock merge={}", health::LOCK_DRIVER),
+    )?;
+    append_unique_line(
+        &gitattributes_path,
+        &format!(".cargo/config.toml merge={}", health::CONFIG_DRIVER),
+    )?;
+
+    git_config(
+        &repo,
+        &format!("merge.{}.name", health::MANIFEST_DRIVER),
+        "cargo-merge-assist semantic merge for Cargo.toml",
+    )?;
+    git_config(
+        &repo,
+        &format!("merge.{}.driver", health::MANIFEST_DRIVER),
+        "cargo-merge-assist merge-manifest --base %O --ours %A --theirs %B --out %A --path %P",
+    )?;
+    git_config(
+        &repo,
+        &format!("merge.{}.name", health::LOCK_DRIVER),
+        "cargo-merge-assist lockfile regeneration driver",
+    )?;
+    git_config(
+        &repo,
+        &format!("merge.{}.driver", health::LOCK_DRIVER),
+        "cargo-merge-assist resolve-lock --repo . --base %O --ours %A --theirs %B",
+    )?;
+    git_config(
+        &repo,
+        &format!("merge.{}.name", health::CONFIG_DRIVER),
+        "cargo-merge-assist semantic merge for .cargo/config.toml",
+    )?;
+    git_config(
+        &repo,
+        &format!("merge.{}.driver", health::CONFIG_DRIVER),
+        "cargo-merge-assist merge-cargo-config --base %O --ours %A --theirs %B --out %A",
+    )?;
+
+    println!("Installed merge driver into {}", repo.display());
+    println!("Added/updated {}", gitattributes_path.display());
+
+    Ok(())
+}
+
+fn init_cmd(args: InitArgs) -> Result<()> {
+    let repo = paths::normalize(&args.repo);
+    ensure_manifest_exists(&repo)?;
+    let survey = init::Survey::inspect(&repo).map_err(|err| anyhow::anyhow!(err))?;
+    print!("{}", init::describe(&survey));
+
+    // Without a terminal to ask on, the suggestions stand.
+    let interactive = !args.yes && io::stdin().is_terminal();
+    let ask = |question: &str, suggested: bool| -> Result<bool> {
+        if !interactive {
+            return Ok(suggested);
+        }
+        let choices = if suggested { "[Y/n]" } else { "[y/N]" };
+        loop {
+            print!("{question} {choices} ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer)? == 0 {
+                return Ok(suggested);
+            }
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "" => return Ok(suggested),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => println!("please answer y or n"),
+            }
+        }
+    };
+    let mut answers = init::Answers::defaults(&survey);
+    if survey.lockfile {
+        answers.verify = ask(
+            "Run `cargo check` after regenerating Cargo.lock in a merge?",
+            answers.verify,
+        )?;
+    }
+    answers.offline = ask(
+        "Keep merges offline (no registry or remote policy access)?",
+        answers.offline,
+    )?;
+    answers.semver_max = ask(
+        "Resolve dependency requirements both branches changed to the higher one?",
+        answers.semver_max,
+    )?;
+    if survey.workspace {
+        answers.reconcile = ask(
+            "Align a crate the members require at different versions to the highest?",
+            answers.reconcile,
+        )?;
+    }
+
+    let policy_path = repo.join(policy::REPO_POLICY_FILE);
+    let wrote_policy = !survey.policy || args.force;
+    if wrote_policy {
+        cleanup::write_atomic(&policy_path, init::policy(&answers))
+            .map_err(|err| Error::io("writing", &policy_path, &err))?;
+    }
+    let gitattributes_path = repo.join(".gitattributes");
+    for line in init::attributes(&survey) {
+        append_unique_line(&gitattributes_path, &line)?;
+    }
+    for (key, value) in init::drivers(&survey, &answers) {
+        git_config(&repo, &key, &value)?;
+    }
+
+    println!();
+    if wrote_policy {
+        println!("Wrote {}", policy_path.display());
+    } else {
+        println!(
+            "Kept the existing {} (--force replaces it)",
+            policy_path.display()
+        );
+    }
+    println!("Added/updated {}", gitattributes_path.display());
+    for line in init::attributes(&survey) {
+        println!("  {line}");
+    }
+    println!("Installed the merge drivers into the repository's git config");
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+fn serve_cmd(args: ServeArgs) -> Result<()> {
+    use cargo_merge_assist::serve;
+
+    if let Some(addr) = args.http {
+        let token = args
+            .token
+            .or_else(|| std::env::var("CARGO_MERGE_ASSIST_TOKEN").ok())
+            .filter(|token| !token.is_empty());
+        if token.is_none() {
+            eprintln!("warning: no --token set; HTTP merge endpoints are unauthenticated");
+        }
+        let config = serve::http::HttpConfig {
+            token,
+            repo_root: args.repo_root,
+        };
+        return serve::http::serve_http(&addr, config)
+            .with_context(|| format!("HTTP server on {addr} failed"));
+    }
+
+    if !args.stdio {
+        bail!("no transport selected; pass --stdio or --http <ADDR>");
+    }
+    serve::serve_stdio().context("stdio server failed")
+}
+
+#[cfg(feature = "serve")]
+fn preview_cmd(args: PreviewArgs) -> Result<()> {
+    use cargo_merge_assist::serve::preview::{Preview, serve_preview};
+
+    let policy = load_policy(Path::new("."), !args.offline)?;
+    let options = merge_options(
+        Path::new("."),
+        &policy,
+        &Branches::detect(Path::new("."), None, None),
+    )?;
+    let preview = Preview::new(
+        read_manifest(&args.base, false)?,
+        read_manifest(&args.ours, false)?,
+        read_manifest(&args.theirs, false)?,
+        options,
+        paths::normalize(&args.resolutions),
+    );
+    serve_preview(&args.http, preview)
+        .with_context(|| format!("preview server on {} failed", args.http))
+}
+
+#[cfg(feature = "self-update")]
+fn self_update_cmd(args: SelfUpdateArgs) -> Result<()> {
+    use cargo_merge_assist::self_update::{UpdateStatus, self_update};
+
+    match self_update(args.check, args.force)? {
+        UpdateStatus::UpToDate { version } => {
+            println!("cargo-merge-assist {version} is up to date")
+        }
+        UpdateStatus::Available { version } => {
+            println!(
+                "cargo-merge-assist {version} is available; run `cargo-merge-assist self-update`"
+            )
+        }
+        UpdateStatus::Updated { from, to } => println!("Updated cargo-merge-assist {from} -> {to}"),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+fn stats_cmd(args: StatsArgs, log: Option<&Path>) -> Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use cargo_merge_assist::stats::{self, Range};
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let bound = |text: &Option<String>| {
+        text.as_deref()
+            .map(|text| stats::parse_time(text, now))
+            .transpose()
+            .map_err(anyhow::Error::msg)
+    };
+    let range = Range {
+        since: bound(&args.since)?,
+        until: bound(&args.until)?,
+    };
+
+    let text = match log {
+        Some(log) => fs::read_to_string(log).map_err(|err| Error::io("reading", log, &err))?,
+        None if args.git => String::new(),
+        None => bail!(
+            "no metrics log; pass --metrics-log or set CARGO_MERGE_ASSIST_METRICS_LOG (or use --git)"
+        ),
+    };
+    let mut summary = stats::aggregate(&text, range, args.top);
+    if args.git {
+        summary.history_merges = Some(stats::history_merges(&args.repo, range)?);
+    }
+    match args.format {
+        StatsFormat::Table => print!("{}", stats::table(&summary)),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+    }
+    Ok(())
+}
+
+fn parse_confidence(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(confidence),
+        _ => Err("expected a number between 0 and 1".to_string()),
+    }
+}
+
+fn parse_decision(value: &str) -> Result<(String, Strategy), String> {
+    let (partition, strategy) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected PARTITION=STRATEGY, got `{value}`"))?;
+    Ok((partition.to_string(), strategy.parse()?))
+}
+
+fn parse_side(value: &str) -> Result<Side, String> {
+    value.parse()
+}
+
+fn load_policy(repo: &Path, allow_fetch: bool) -> Result<Policy> {
+    let loaded = policy::load(repo, allow_fetch)?;
+    for warning in &loaded.warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(loaded.policy)
+}
+
+/// The policy's merge options plus the repository's recorded decisions.
+fn merge_options(repo: &Path, policy: &Policy, branches: &Branches) -> Result<MergeOptions> {
+    let mut options = policy.merge_options();
+    options.preferences = policy.preferences(branches);
+    options.suppressions = suppress::load(repo).map_err(anyhow::Error::msg)?;
+    Ok(options)
+}
+
+/// Reads a resolutions file, as JSON when it looks like JSON.
+fn load_resolutions(path: &Path) -> Result<Vec<Choice>> {
+    let text = fs::read_to_string(path).map_err(|err| Error::io("reading", path, &err))?;
+    let json =
+        path.extension().is_some_and(|ext| ext == "json") || text.trim_start().starts_with('{');
+    let choices = if json {
+        serde_json::from_str::<serde_json::Value>(&text)
+            .map_err(|err| err.to_string())
+            .and_then(|json| toml::Value::try_from(json).map_err(|err| err.to_string()))
+            .and_then(|doc| resolutions::from_value(&doc))
+    } else {
+        resolutions::parse(&text)
+    };
+    choices
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("invalid resolutions file {}", path.display()))
+}
+
+fn read_manifest(path: &Path, lossy: bool) -> Result<String> {
+    let bytes = fs::read(path).map_err(|err| Error::io("reading", path, &err))?;
+    input::decode(&bytes, lossy).with_context(|| {
+        if lossy {
+            format!("failed decoding {}", path.display())
+        } else {
+            format!(
+                "failed decoding {}; pass --lossy-input to keep undecodable bytes as they are",
+                path.display()
+            )
+        }
+    })
+}
+
+fn ensure_manifest_exists(repo: &Path) -> Result<()> {
+    let manifest = repo.join("Cargo.toml");
+    if !manifest.exists() {
+        bail!(
+            "{} does not contain Cargo.toml; pass --repo with a Rust project root",
+            repo.display()
+        );
+    }
+    Ok(())
+}
+
+fn git_config(repo: &Path, key: &str, value: &str) -> Result<()> {
+    let args = ["config", "--local", key, value];
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run git config in {}", repo.display()))?;
+
+    if !output.status.success() {
+        return Err(Error::Git {
+            args: args.map(str::to_string).to_vec(),
+            exit: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn append_unique_line(path: &Path, line: &str) -> Result<()> {
+    let mut existing = if path.exists() {
+        fs::read_to_string(path).map_err(|err| Error::io("reading", path, &err))?
+    } else {
+        String::new()
+    };
+
+    if existing.lines().any(|l| l.trim() == line) {
+        return Ok(());
+    }
+
+    // Keep the file's existing line endings (e.g. CRLF checkouts on Windows).
+    let newline = if existing.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        existing.push_str(newline);
+    }
+    existing.push_str(line);
+    existing.push_str(newline);
+
+    cleanup::write_atomic(path, existing).map_err(|err| Error::io("writing", path, &err))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cargo-merge-assist-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create scratch dir");
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn append_unique_line_skips_existing_entries() {
+        let path = scratch_file("dedupe.gitattributes");
+        fs::write(&path, "*.rs text\nCargo.toml merge=x\n").unwrap();
+
+        append_unique_line(&path, "Cargo.toml merge=x").unwrap();
+        append_unique_line(&path, "Cargo.lock merge=y").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "*.rs text\nCargo.toml merge=x\nCargo.lock merge=y\n"
+        );
+    }
+
+    #[test]
+    fn append_unique_line_preserves_crlf() {
+        let path = scratch_file("crlf.gitattributes");
+        fs::write(&path, "*.rs text\r\nCargo.toml merge=x").unwrap();
+
+        append_unique_line(&path, "Cargo.toml merge=x").unwrap();
+        append_unique_line(&path, "Cargo.lock merge=y").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "*.rs text\r\nCargo.toml merge=x\r\nCargo.lock merge=y\r\n"
+        );
+    }
+
+    #[test]
+    fn merges_directory_trees_with_one_sided_files_and_lockfiles() {
+        let root = scratch_file("trees");
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, text: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        let manifest = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n";
+        let gone = "[package]\nname = \"gone\"\n";
+        for tree in ["base", "ours", "theirs"] {
+            write(&format!("{tree}/Cargo.lock"), &format!("# {tree}\n"));
+        }
+        write("base/Cargo.toml", manifest);
+        write("ours/Cargo.toml", &format!("{manifest}log = \"0.4\"\n"));
+        write(
+            "theirs/Cargo.toml",
+            &manifest.replace("\"1\"", "\"1.0.200\""),
+        );
+        write("base/gone/Cargo.toml", gone);
+        write("ours/gone/Cargo.toml", gone);
+        write("out/gone/Cargo.toml", gone);
+        write("ours/added/Cargo.toml", "[package]\nname = \"added\"\n");
+        write("theirs/new/Cargo.toml", "[package]\nname = \"new\"\n");
+        write("theirs/new/Cargo.lock", "# new\n");
+
+        let merge = || {
+            let tree = |name: &str| root.join(name).display().to_string();
+            let cli = Cli::parse_from([
+                "cargo-merge-assist",
+                "merge-manifest",
+                "--base",
+                &tree("base"),
+                "--ours",
+                &tree("ours"),
+                "--theirs",
+                &tree("theirs"),
+                "--out",
+                &tree("out"),
+                "--offline",
+                "--no-cache",
+            ]);
+            let Commands::MergeManifest(args) = cli.command else {
+                unreachable!();
+            };
+            merge_directories(args, &mut Timings::default())
+        };
+        let merged = merge().unwrap();
+        let read = |path: &str| fs::read_to_string(root.join("out").join(path)).ok();
+        assert_eq!(
+            merged,
+            ["Cargo.toml", "added/Cargo.toml", "new/Cargo.toml"]
+                .map(|path| root.join("out").join(path))
+        );
+        assert_eq!(
+            read("Cargo.toml").unwrap(),
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0.200\"\nlog = \"0.4\"\n"
+        );
+        // Lockfiles are carried over to be regenerated.
+        assert_eq!(read("Cargo.lock").as_deref(), Some("# ours\n"));
+        assert_eq!(read("new/Cargo.lock").as_deref(), Some("# new\n"));
+        // Theirs deleted `gone`, which ours left alone.
+        assert_eq!(read("gone/Cargo.toml"), None);
+
+        // A deletion against an edit is not settled.
+        write(
+            "ours/gone/Cargo.toml",
+            &format!("{gone}version = \"0.2.0\"\n"),
+        );
+        let err = merge().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("deleted by theirs but changed by the other side"),
+            "{err:#}"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn resolves_one_sided_lockfile_changes_without_cargo() {
+        // No manifest: the fast path must not get as far as needing one.
+        let dir = scratch_file("lock-fast-path");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write("base.lock", "# base\n");
+        write("theirs.lock", "# theirs\n");
+        let resolve = || {
+            let path = |name: &str| dir.join(name).display().to_string();
+            let cli = Cli::parse_from([
+                "cargo-merge-assist".to_string(),
+                "resolve-lock".to_string(),
+                "--repo".to_string(),
+                dir.display().to_string(),
+                "--base".to_string(),
+                path("base.lock"),
+                "--ours".to_string(),
+                path("ours.lock"),
+                "--theirs".to_string(),
+                path("theirs.lock"),
+            ]);
+            let Commands::ResolveLock(args) = cli.command else {
+                unreachable!()
+            };
+            resolve_lock_cmd(args, &mut Timings::default())
+        };
+
+        write("ours.lock", "# base\n");
+        resolve().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("ours.lock")).unwrap(),
+            "# theirs\n"
+        );
+        write("theirs.lock", "# base\n");
+        write("ours.lock", "# ours\n");
+        resolve().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("ours.lock")).unwrap(),
+            "# ours\n"
+        );
+        // Both changed: that takes cargo, and a manifest.
+        write("theirs.lock", "# theirs\n");
+        assert!(resolve().is_err());
+
+        assert!(
+            Cli::try_parse_from(["cargo-merge-assist", "resolve-lock", "--base", "a"]).is_err()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logs_only_on_conflict_overrides() {
+        let base = "[package]\nname = \"a\"\n\n[dependencies]\nlog = \"0.4\"\n";
+        let ours = base.replace("\"0.4\"", "\"0.4.20\"");
+        let theirs = base.replace("\"0.4\"", "\"0.4.21\"");
+        let merge = |heuristics: Vec<Heuristic>| {
+            let options = MergeOptions {
+                heuristics,
+                ..MergeOptions::default()
+            };
+            merge_manifest_texts_detailed(base, &ours, &theirs, &options, &mut Timings::default())
+                .unwrap()
+        };
+
+        // `--strategy ours` settles the conflict before the fallback.
+        let merged = merge(vec![
+            Strategy::Ours.heuristic(),
+            strategy::on_conflict_theirs,
+        ]);
+        assert_eq!(merged.text, ours);
+        assert_eq!(overrides(&merged.resolutions), Vec::<String>::new());
+
+        let merged = merge(vec![strategy::on_conflict_theirs]);
+        assert_eq!(merged.text, theirs);
+        assert_eq!(
+            overrides(&merged.resolutions),
+            ["override dependencies.log (took theirs)"]
+        );
+    }
+
+    /// The problems of a schema error.
+    fn schema_problems(result: Result<()>) -> Vec<String> {
+        match result.map_err(|err| err.downcast::<Error>()) {
+            Ok(()) => Vec::new(),
+            Err(Ok(Error::Schema { problems, .. })) => problems,
+            Err(err) => panic!("expected a schema error, got {err:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_only_new_unresolved_inheritance() {
+        // One side drops `rand` from the root's own workspace dependencies,
+        // the other starts inheriting it.
+        let base = "[workspace.dependencies]\nserde = \"1\"\nrand = \"0.8\"\n\n\
+                    [dependencies]\nserde.workspace = true\n";
+        let ours = base.replace("rand = \"0.8\"\n", "");
+        let theirs = format!("{base}rand.workspace = true\n");
+        let merged = format!("{ours}rand.workspace = true\n");
+        let out = Path::new("Cargo.toml");
+        let problems =
+            schema_problems(check_inheritance(&merged, [base, &ours, &theirs], out, out));
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].contains("`dependencies.rand`"), "{problems:?}");
+
+        // Against a separate root, what the sides already lacked is theirs.
+        let dir = scratch_file("inheritance");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("member")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\n\n[workspace.dependencies]\nserde = \"1\"\n",
+        )
+        .unwrap();
+        let member = dir.join("member/Cargo.toml");
+        let base = "[dependencies]\nserde.workspace = true\n";
+        let ours = format!("{base}log.workspace = true\n");
+        let theirs = format!("{base}rand = \"0.8\"\n");
+        let merged = format!("{ours}rand = \"0.8\"\n");
+        let result = check_inheritance(&merged, [base, &ours, &theirs], &member, &member);
+        assert_eq!(schema_problems(result), Vec::<String>::new());
+
+        // In a batch, each member is checked against the merged root, and
+        // the sides against their own roots.
+        let root = dir.join("Cargo.toml");
+        let root_ours = "[workspace]\n\n[workspace.dependencies]\nserde = \"1\"\n";
+        let root_theirs = format!("{root_ours}rand = \"0.8\"\n");
+        fs::write(dir.join("root-theirs.toml"), &root_theirs).unwrap();
+        fs::write(&root, root_ours).unwrap();
+        fs::write(&member, format!("{ours}rand.workspace = true\n")).unwrap();
+        let job = |ours: &Path, theirs: &str| batch::BatchJob {
+            base: dir.join("unused.toml"),
+            ours: ours.to_path_buf(),
+            theirs: dir.join(theirs),
+            out: ours.to_path_buf(),
+        };
+        let jobs = [
+            job(&root, "root-theirs.toml"),
+            job(&member, "member-theirs.toml"),
+        ];
+        let parse = |text: &str| text.parse::<toml::Value>().unwrap();
+        let sides = [
+            Some([parse(root_ours), parse(&root_theirs)]),
+            Some([
+                parse(&ours),
+                parse(&format!("{base}rand.workspace = true\n")),
+            ]),
+        ];
+        let problems = schema_problems(check_inherited(&root, &jobs, &sides));
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(
+            problems[0].contains("member") && problems[0].contains("`dependencies.rand`"),
+            "{problems:?}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn append_unique_line_accepts_verbatim_paths() {
+        let path = scratch_file("verbatim.gitattributes");
+        let verbatim = PathBuf::from(format!(
+            r"\\?\{}",
+            std::path::absolute(&path).unwrap().display()
+        ));
+
+        append_unique_line(&paths::normalize(&verbatim), "Cargo.lock merge=y").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Cargo.lock merge=y\n");
+    }
+}
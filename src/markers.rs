@@ -0,0 +1,198 @@
+//! Git-style conflict markers (`merge-manifest --write-conflicts`).
+//!
+//! When a merge conflicts, git leaves the file with everything that merged
+//! cleanly plus a marked block per conflicting hunk. [`with_markers`] does
+//! the same at the level of manifest entries: the result is the merge with
+//! every conflict resolved to ours, and each conflicting entry (its whole
+//! `key = value` line, or its whole `[section]`) is wrapped as
+//!
+//! ```text
+//! <<<<<<< ours
+//! serde = "1.0.200"
+//! =======
+//! serde = "1.0.199"
+//! >>>>>>> theirs
+//! ```
+//!
+//! with theirs' text for the entry taken from the merge resolved to theirs.
+//! An entry one side deleted has an empty half.
+
+use std::ops::Range;
+
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::edit::provisional_merge_to;
+use crate::merge::{MergeConflict, MergeError, MergeOptions, Side};
+
+/// Merges `base`, `ours` and `theirs`, marking each conflicting entry with
+/// both sides' text between `<<<<<<< {labels[0]}` and `>>>>>>> {labels[1]}`.
+/// Returns the text and the conflicts; without conflicts the text is the
+/// plain merge.
+pub fn with_markers(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+    labels: [&str; 2],
+) -> Result<(String, Vec<MergeConflict>), MergeError> {
+    let (ours_text, conflicts) = provisional_merge_to(Side::Ours, base, ours, theirs, options)?;
+    if conflicts.is_empty() {
+        return Ok((ours_text, conflicts));
+    }
+    let (theirs_text, _) = provisional_merge_to(Side::Theirs, base, ours, theirs, options)?;
+    let newline = if ours_text.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    // Blocks by the range of ours' text they replace.
+    let mut blocks: Vec<(Range<usize>, String)> = Vec::new();
+    for conflict in &conflicts {
+        let path: Vec<&str> = conflict.path.split('.').collect();
+        let ours_range = match entry_lines(&ours_text, &path) {
+            Some(range) => range,
+            // Ours deleted the entry: mark it at the end of its table.
+            None => {
+                let end = (1..path.len())
+                    .rev()
+                    .find_map(|len| entry_lines(&ours_text, &path[..len]))
+                    .map_or(ours_text.len(), |range| range.end);
+                end..end
+            }
+        };
+        if blocks.iter().any(|(range, _)| overlaps(range, &ours_range)) {
+            continue;
+        }
+        let theirs_part = entry_lines(&theirs_text, &path).map_or("", |range| &theirs_text[range]);
+        let mut block = format!("<<<<<<< {}{newline}", labels[0]);
+        for (idx, part) in [&ours_text[ours_range.clone()], theirs_part]
+            .into_iter()
+            .enumerate()
+        {
+            if idx == 1 {
+                block.push_str(&format!("======={newline}"));
+            }
+            block.push_str(part);
+            // The file's last line may lack one.
+            if !part.is_empty() && !part.ends_with('\n') {
+                block.push_str(newline);
+            }
+        }
+        block.push_str(&format!(">>>>>>> {}{newline}", labels[1]));
+        blocks.push((ours_range, block));
+    }
+
+    blocks.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut text = ours_text;
+    for (range, block) in blocks {
+        text.replace_range(range, &block);
+    }
+    Ok((text, conflicts))
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    if a.is_empty() || b.is_empty() {
+        return a.start == b.start;
+    }
+    a.start < b.end && b.start < a.end
+}
+
+/// The whole lines of `text` holding the entry at `path`: the `key = value`
+/// line(s) of the value it is in, or a `[section]` with its body. `None`
+/// when the document has no such entry.
+fn entry_lines(text: &str, path: &[&str]) -> Option<Range<usize>> {
+    let doc = ImDocument::parse(text).ok()?;
+    let mut table: &dyn TableLike = doc.as_table();
+    for (depth, key) in path.iter().enumerate() {
+        let (key, item) = table.get_key_value(key)?;
+        let last = depth + 1 == path.len();
+        let span = match item {
+            Item::Value(value) => Some(key.span()?.start..value.span()?.end),
+            Item::Table(section) if last => section.span().or_else(|| dotted_span(section)),
+            Item::ArrayOfTables(rows) if last => rows.span(),
+            Item::Table(section) => {
+                table = section;
+                continue;
+            }
+            _ => None,
+        };
+        return span.map(|span| whole_lines(text, span));
+    }
+    None
+}
+
+/// The span from the first to the last entry of a dotted table
+/// (`serde.workspace = true`), which has no span of its own.
+fn dotted_span(table: &toml_edit::Table) -> Option<Range<usize>> {
+    let spans: Vec<Range<usize>> = table
+        .iter()
+        .filter_map(|(key, item)| {
+            let (key, _) = table.get_key_value(key)?;
+            let end = match item {
+                Item::Value(value) => value.span()?.end,
+                Item::Table(inner) => dotted_span(inner)?.end,
+                _ => return None,
+            };
+            Some(key.span()?.start..end)
+        })
+        .collect();
+    let start = spans.iter().map(|span| span.start).min()?;
+    let end = spans.iter().map(|span| span.end).max()?;
+    Some(start..end)
+}
+
+/// `span` widened to whole lines, including the final newline.
+fn whole_lines(text: &str, span: Range<usize>) -> Range<usize> {
+    let start = text[..span.start].rfind('\n').map_or(0, |idx| idx + 1);
+    let end = text[span.end..]
+        .find('\n')
+        .map_or(text.len(), |idx| span.end + idx + 1);
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_conflicting_entries_in_git_style_markers() {
+        let base = "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\nlog = \"0.4\"\nanyhow = \"1\"\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("version = \"1\"", "version = \"1.0.200\"")
+            .replace("anyhow = \"1\"\n", "");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("version = \"1\"", "version = \"1.0.199\"")
+            .replace("0.4", "0.4.21")
+            .replace("anyhow = \"1\"", "anyhow = \"1.0.80\"");
+
+        let (text, conflicts) = with_markers(
+            base,
+            &ours,
+            &theirs,
+            // Keep the version bumps a conflict.
+            &MergeOptions {
+                highest_version: false,
+                ..MergeOptions::default()
+            },
+            ["ours", "theirs"],
+        )
+        .unwrap();
+        assert_eq!(conflicts.len(), 3);
+        let expected = "[package]\n\
+                        <<<<<<< ours\nversion = \"0.2.0\"\n=======\nversion = \"0.3.0\"\n>>>>>>> theirs\n\n\
+                        [dependencies]\n\
+                        <<<<<<< ours\nserde = { version = \"1.0.200\", features = [\"derive\"] }\n\
+                        =======\nserde = { version = \"1.0.199\", features = [\"derive\"] }\n>>>>>>> theirs\n\
+                        log = \"0.4.21\"\n\
+                        <<<<<<< ours\n=======\nanyhow = \"1.0.80\"\n>>>>>>> theirs\n";
+        assert_eq!(text, expected);
+
+        let (clean, conflicts) =
+            with_markers(base, &ours, base, &MergeOptions::default(), ["a", "b"]).unwrap();
+        assert_eq!(clean, ours);
+        assert!(conflicts.is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+//! Set merging of the `[workspace]` member lists.
+//!
+//! Branches that each add a crate both append to `workspace.members`, and one
+//! often removes a crate or switches to a glob while the other adds one. The
+//! lists `members`, `default-members` and `exclude` are therefore merged as
+//! sets of paths against base: ours' entries minus those theirs removed, then
+//! the entries theirs added, at their sorted position when ours keeps the
+//! list sorted and at the end otherwise. A glob such as `crates/*` stands for
+//! the concrete paths it matches: an added path it already covers is not
+//! listed again, an added glob replaces the paths it covers, and a path one
+//! side replaced with a covering glob does not count as removed.
+
+use toml::Value;
+
+/// The `[workspace]` keys holding lists of member paths.
+const LISTS: [&str; 3] = ["members", "default-members", "exclude"];
+
+/// The merged list at `path` when it is one of [`LISTS`] and all three
+/// sides are lists of strings (base may be missing); `None` leaves it to
+/// the usual merge.
+pub(crate) fn union(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Value> {
+    let ["workspace", list] = path else {
+        return None;
+    };
+    if !LISTS.contains(list) {
+        return None;
+    }
+    let base = match base {
+        None => Vec::new(),
+        Some(base) => strings(base)?,
+    };
+    let ours = strings(ours?)?;
+    let theirs = strings(theirs?)?;
+
+    let sorted = ours.is_sorted();
+    let mut merged: Vec<&str> = ours
+        .iter()
+        .copied()
+        // Dropped unless theirs kept it, as written or through a glob.
+        .filter(|entry| !base.contains(entry) || listed(&theirs, entry))
+        .collect();
+    for entry in theirs {
+        if base.contains(&entry) || ours.contains(&entry) || listed(&merged, entry) {
+            continue;
+        }
+        merged.retain(|other| !covers(entry, other));
+        if sorted {
+            let at = merged.partition_point(|other| *other < entry);
+            merged.insert(at, entry);
+        } else {
+            merged.push(entry);
+        }
+    }
+    Some(Value::Array(
+        merged.into_iter().map(|entry| entry.into()).collect(),
+    ))
+}
+
+/// The items of `value` when it is a list of strings.
+fn strings(value: &Value) -> Option<Vec<&str>> {
+    value.as_array()?.iter().map(Value::as_str).collect()
+}
+
+/// Whether `entry` is in `list`, as written or through a glob.
+fn listed(list: &[&str], entry: &str) -> bool {
+    list.iter()
+        .any(|other| *other == entry || covers(other, entry))
+}
+
+/// Whether the glob `pattern` matches the concrete path `path`.
+fn covers(pattern: &str, path: &str) -> bool {
+    let is_glob = |text: &str| text.contains(['*', '?', '[']);
+    if !is_glob(pattern) || is_glob(path) {
+        return false;
+    }
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    segments(&pattern, &path)
+}
+
+/// Whether the pattern segments match the path segments; `**` matches any
+/// number of segments.
+fn segments(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            segments(rest, path) || (!path.is_empty() && segments(pattern, &path[1..]))
+        }
+        (Some((first, rest)), Some((name, names))) => {
+            wildcard(first, name) && segments(rest, names)
+        }
+        _ => false,
+    }
+}
+
+/// Whether one path segment matches a pattern segment with `*` and `?`.
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    let mut name_chars = name.chars();
+    match pattern_chars.next() {
+        None => name.is_empty(),
+        Some('*') => {
+            wildcard(pattern_chars.as_str(), name)
+                || (name_chars.next().is_some() && wildcard(pattern, name_chars.as_str()))
+        }
+        Some('?') => {
+            name_chars.next().is_some() && wildcard(pattern_chars.as_str(), name_chars.as_str())
+        }
+        Some(c) => {
+            name_chars.next() == Some(c) && wildcard(pattern_chars.as_str(), name_chars.as_str())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge_manifest_texts;
+
+    #[test]
+    fn merges_member_lists_as_sets() {
+        let base = "[workspace]\nmembers = [\"crates/a\", \"crates/old\"]\n\
+                    default-members = [\"crates/a\"]\n";
+        let ours = "[workspace]\nmembers = [\"crates/a\", \"crates/b\"]\n\
+                    default-members = [\"crates/a\", \"tools/*\"]\n";
+        let theirs = "[workspace]\nmembers = [\"crates/a\", \"crates/old\", \"crates/aa\", \"crates/c\"]\n\
+                      default-members = [\"crates/a\", \"tools/gen\"]\n";
+
+        let merged: toml::Table = merge_manifest_texts(base, ours, theirs)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected: toml::Table = "[workspace]\n\
+             members = [\"crates/a\", \"crates/aa\", \"crates/b\", \"crates/c\"]\n\
+             default-members = [\"crates/a\", \"tools/*\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn added_globs_replace_the_paths_they_cover() {
+        let base = Value::from(vec!["crates/a", "xtask"]);
+        let ours = Value::from(vec!["xtask", "crates/a", "crates/b"]);
+        let theirs = Value::from(vec!["crates/*", "xtask"]);
+
+        let merged = union(
+            &["workspace", "members"],
+            Some(&base),
+            Some(&ours),
+            Some(&theirs),
+        );
+        assert_eq!(merged, Some(Value::from(vec!["xtask", "crates/*"])));
+        assert!(covers("crates/**/*-sys", "crates/ffi/zlib-sys"));
+        assert!(!covers("crates/*", "crates/ffi/zlib-sys"));
+        assert_eq!(
+            union(&["package", "include"], None, Some(&ours), Some(&theirs)),
+            None
+        );
+    }
+}
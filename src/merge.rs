@@ -1,13 +1,141 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use sha2::{Digest, Sha256};
 use toml::Value;
 
+use crate::ancestry;
+use crate::audit;
+use crate::cfgs;
+use crate::cluster::{self, Decisions};
+use crate::deprecated;
+use crate::features;
+use crate::fields;
+use crate::flags;
+use crate::inheritance;
+use crate::layout;
+use crate::lints;
+use crate::locations;
+use crate::members;
+use crate::metadata;
+use crate::moves;
+use crate::passthrough;
+use crate::pins;
+use crate::renames;
+use crate::resolutions::Choice;
+use crate::shorthand;
+use crate::style::{self, OutputStyle};
+use crate::suppress::Suppression;
+use crate::targets;
+use crate::timings::Timings;
+use crate::unstable;
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MergeConflict {
     pub path: String,
     pub base: Option<Value>,
     pub ours: Option<Value>,
     pub theirs: Option<Value>,
+    /// Stable identifier of this conflict; see [`fingerprint`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fingerprint: String,
+    /// Conflicts on the same dependency in other tables (dev, build, target
+    /// or workspace dependencies); together they are one logical conflict.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub related: Vec<MergeConflict>,
+    /// What kind of divergence this is; renames are detected in dependency
+    /// tables.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "ConflictKind::is_content")
+    )]
+    pub kind: ConflictKind,
+    /// Suggested reviewers, from the policy's `[owners]` and `codeowners`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub reviewers: Vec<String>,
+}
+
+/// The kind of a [`MergeConflict`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ConflictKind {
+    /// The sides changed the value differently, or one changed it and the
+    /// other deleted it.
+    #[default]
+    Content,
+    /// `side` renamed the dependency to `to`; the other side deleted it.
+    /// The conflict's value for `side` is the renamed entry.
+    RenameDelete { side: Side, to: String },
+    /// Both sides renamed the dependency, to different keys. The conflict's
+    /// values are the renamed entries.
+    RenameRename { ours: String, theirs: String },
+    /// The union of both sides' lists would hold `count` entries, more than
+    /// the `limit` crates.io accepts (`keywords`, `categories`).
+    Limit { limit: usize, count: usize },
+    /// The other side deleted the feature; `side` started enabling it from
+    /// the feature `by`. The conflict's values are the feature's.
+    Referenced { side: Side, by: String },
+}
+
+impl ConflictKind {
+    pub fn is_content(&self) -> bool {
+        *self == ConflictKind::Content
+    }
+}
+
+impl MergeConflict {
+    /// A conflict at `path` with no related conflicts.
+    pub fn new(
+        path: String,
+        base: Option<Value>,
+        ours: Option<Value>,
+        theirs: Option<Value>,
+    ) -> Self {
+        let fingerprint = fingerprint(&path, base.as_ref(), ours.as_ref(), theirs.as_ref());
+        Self {
+            path,
+            base,
+            ours,
+            theirs,
+            fingerprint,
+            related: Vec::new(),
+            kind: ConflictKind::Content,
+            reviewers: Vec::new(),
+        }
+    }
+}
+
+/// Identifies a conflict across merges: a hash of its path and its three
+/// values, so the same divergence gets the same ID every time it recurs
+/// (and a new one once any value changes). Tables are hashed with sorted
+/// keys, so formatting and key order do not matter.
+pub fn fingerprint(
+    path: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(path);
+    for value in [base, ours, theirs] {
+        hasher.update([0]);
+        hasher.update(render_value(value));
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
 }
 
 impl std::fmt::Display for MergeConflict {
@@ -23,12 +151,771 @@ impl std::fmt::Display for MergeConflict {
             render_value(self.base.as_ref()),
             render_value(self.ours.as_ref()),
             render_value(self.theirs.as_ref())
-        )
+        )?;
+        match &self.kind {
+            ConflictKind::Content => {}
+            ConflictKind::RenameDelete { side, to } => {
+                let other = if *side == Side::Ours {
+                    Side::Theirs
+                } else {
+                    Side::Ours
+                };
+                write!(
+                    f,
+                    "\n  rename: {side} renamed it to `{to}`, {other} deleted it"
+                )?;
+            }
+            ConflictKind::RenameRename { ours, theirs } => write!(
+                f,
+                "\n  rename: ours renamed it to `{ours}`, theirs to `{theirs}`"
+            )?,
+            ConflictKind::Limit { limit, count } => write!(
+                f,
+                "\n  limit : together the sides list {count} entries; crates.io accepts at most {limit}"
+            )?,
+            ConflictKind::Referenced { side, by } => {
+                let other = if *side == Side::Ours {
+                    Side::Theirs
+                } else {
+                    Side::Ours
+                };
+                write!(
+                    f,
+                    "\n  used  : {side} started enabling it from `features.{by}`, {other} deleted it"
+                )?;
+            }
+        }
+        if !self.fingerprint.is_empty() {
+            write!(f, "\n  id    : {}", self.fingerprint)?;
+        }
+        if !self.reviewers.is_empty() {
+            write!(f, "\n  owners: {}", self.reviewers.join(", "))?;
+        }
+        for related in &self.related {
+            write!(
+                f,
+                "\n  also conflicts at `{}`: ours {}, theirs {}",
+                related.path,
+                render_value(related.ours.as_ref()),
+                render_value(related.theirs.as_ref())
+            )?;
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for MergeConflict {}
 
+/// Every conflict of a merge run with [`MergeOptions::all_conflicts`], in
+/// the order they were found.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MergeReport {
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl std::fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = self.conflicts.len();
+        write!(
+            f,
+            "{count} semantic conflict{}",
+            if count == 1 { "" } else { "s" }
+        )?;
+        for conflict in &self.conflicts {
+            write!(f, "\n{conflict}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MergeReport {}
+
+/// One of the three merge inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Side {
+    Base,
+    Ours,
+    Theirs,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Side::Base => "base",
+            Side::Ours => "ours",
+            Side::Theirs => "theirs",
+        })
+    }
+}
+
+impl std::str::FromStr for Side {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "base" => Ok(Side::Base),
+            "ours" => Ok(Side::Ours),
+            "theirs" => Ok(Side::Theirs),
+            other => Err(format!(
+                "unknown side `{other}` (expected `base`, `ours` or `theirs`)"
+            )),
+        }
+    }
+}
+
+/// A merge input is not valid TOML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseError {
+    /// Which input failed to parse.
+    pub side: Side,
+    /// The parser's description of the problem.
+    pub message: String,
+    /// 1-based line of the error, when the parser reported a location.
+    pub line: Option<usize>,
+    /// 1-based column (in characters) of the error.
+    pub column: Option<usize>,
+    /// The offending source line.
+    pub snippet: Option<String>,
+}
+
+impl ParseError {
+    fn new(side: Side, text: &str, err: &toml::de::Error) -> Self {
+        let mut parsed = ParseError {
+            side,
+            message: err.message().trim().to_string(),
+            line: None,
+            column: None,
+            snippet: None,
+        };
+        if let Some(span) = err.span() {
+            let offset = span.start.min(text.len());
+            let line_start = text[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+            let line_end = text[offset..]
+                .find('\n')
+                .map_or(text.len(), |idx| offset + idx);
+            parsed.line = Some(text[..offset].matches('\n').count() + 1);
+            parsed.column = Some(text[line_start..offset].chars().count() + 1);
+            parsed.snippet = Some(
+                text[line_start..line_end]
+                    .trim_end_matches('\r')
+                    .to_string(),
+            );
+        }
+        parsed
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not valid TOML: {}", self.side, self.message)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "\n  --> line {line}, column {column}")?;
+        }
+        if let (Some(snippet), Some(column)) = (&self.snippet, self.column) {
+            write!(f, "\n   | {snippet}\n   | {:>column$}", "^")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a merge produced no output.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum MergeError {
+    /// One of the inputs is not valid TOML.
+    Parse(ParseError),
+    /// Both sides changed the same value differently.
+    Conflict(MergeConflict),
+    /// Every conflict, when merging with [`MergeOptions::all_conflicts`].
+    Report(MergeReport),
+    /// The documents nest deeper than [`MergeOptions::max_depth`].
+    DepthLimit { path: String, limit: usize },
+    /// The merged document cannot be written back as TOML.
+    Serialize { message: String },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::Parse(err) => err.fmt(f),
+            MergeError::Conflict(conflict) => conflict.fmt(f),
+            MergeError::Report(report) => report.fmt(f),
+            MergeError::DepthLimit { path, limit } => write!(
+                f,
+                "`{path}` is nested more than {limit} tables deep; refusing to merge"
+            ),
+            MergeError::Serialize { message } => {
+                write!(f, "merged manifest cannot be written as TOML: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl From<MergeConflict> for MergeError {
+    fn from(conflict: MergeConflict) -> Self {
+        MergeError::Conflict(conflict)
+    }
+}
+
+/// Default for [`MergeOptions::max_depth`]; real manifests stay far below it.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// An automatic resolution proposed for a value both sides changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proposal {
+    /// The resolved value; `None` deletes the key.
+    pub value: Option<Value>,
+    /// Name of the rule that proposed it, for reports.
+    pub rule: &'static str,
+    /// How sure the rule is, from 0.0 (guess) to 1.0 (certain).
+    pub confidence: f64,
+}
+
+/// A value both sides changed that the merge resolved automatically.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution {
+    /// Dotted path of the value.
+    pub path: String,
+    /// The side whose value was taken; `None` when the rule produced a value
+    /// neither side had.
+    pub side: Option<Side>,
+    /// The proposing rule, or `cluster` when the value followed the decision
+    /// made for the rest of its dependency.
+    pub rule: &'static str,
+}
+
+/// A heuristic consulted before reporting a conflict. It receives the keys
+/// leading to the value and the base/ours/theirs values.
+pub type Heuristic =
+    fn(&[&str], Option<&Value>, Option<&Value>, Option<&Value>) -> Option<Proposal>;
+
+/// How a member switching to `lints.workspace = true` on one side is merged
+/// with member-level lint edits on the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LintInheritance {
+    /// Keep the inherited form and carry the other side's lints into
+    /// `[workspace.lints]` of the same manifest.
+    #[default]
+    Inherit,
+    /// Merge `[lints]` key by key like any other table.
+    Conflict,
+}
+
+impl std::str::FromStr for LintInheritance {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "inherit" => Ok(Self::Inherit),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown lint inheritance `{other}` (expected `inherit` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// What happens when both sides set the same lint to different levels, in
+/// `[lints]` or `[workspace.lints]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LintLevels {
+    /// Take the stricter level: `forbid` over `deny` over `warn` over
+    /// `allow`.
+    #[default]
+    Strictest,
+    /// Take the looser level.
+    Loosest,
+    /// Report a conflict.
+    Conflict,
+}
+
+impl std::str::FromStr for LintLevels {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strictest" => Ok(Self::Strictest),
+            "loosest" => Ok(Self::Loosest),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown lint levels mode `{other}` (expected `strictest`, `loosest` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// What happens when a side moves a pinned dependency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PinMode {
+    /// Replace the moved requirement with `=<pin>`.
+    #[default]
+    Override,
+    /// Report the move as a conflict.
+    Conflict,
+}
+
+impl std::str::FromStr for PinMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "override" => Ok(Self::Override),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown pin mode `{other}` (expected `override` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// What happens when one side renames a dependency the other deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenameDelete {
+    /// Report a [`ConflictKind::RenameDelete`] conflict.
+    #[default]
+    Conflict,
+    /// The deletion wins: the renamed entry is dropped too.
+    Delete,
+    /// The rename wins.
+    Rename,
+}
+
+impl std::str::FromStr for RenameDelete {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "conflict" => Ok(Self::Conflict),
+            "delete" => Ok(Self::Delete),
+            "rename" => Ok(Self::Rename),
+            other => Err(format!(
+                "unknown rename-delete mode `{other}` (expected `conflict`, `delete` or `rename`)"
+            )),
+        }
+    }
+}
+
+/// Whether git dependency `rev`s both sides changed are settled by ancestry,
+/// see [`crate::ancestry`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GitAncestry {
+    /// Report a conflict.
+    #[default]
+    Off,
+    /// Take the descendant commit, looked up in cargo's git clones.
+    Local,
+    /// Like `Local`, fetching the commits cargo does not have.
+    Fetch,
+}
+
+/// What happens when both sides set `rust-version` to different versions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RustVersionMode {
+    /// Report a conflict.
+    #[default]
+    Conflict,
+    /// Take the higher version.
+    Max,
+    /// Take the lower version.
+    Min,
+}
+
+impl std::str::FromStr for RustVersionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "conflict" => Ok(Self::Conflict),
+            "max" => Ok(Self::Max),
+            "min" => Ok(Self::Min),
+            other => Err(format!(
+                "unknown rust-version mode `{other}` (expected `conflict`, `max` or `min`)"
+            )),
+        }
+    }
+}
+
+/// What happens when both sides set `publish` differently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PublishMode {
+    /// Take the more restrictive setting: `false` over a registry list over
+    /// `true` (or no `publish`); two lists keep the registries in both.
+    #[default]
+    Restrictive,
+    /// Report a conflict.
+    Conflict,
+}
+
+impl std::str::FromStr for PublishMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "restrictive" => Ok(Self::Restrictive),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown publish mode `{other}` (expected `restrictive` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// How conflicting `license` and `license-file` changes are settled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LicenseMode {
+    /// Only by explicit decisions, as if the fields were
+    /// [protected](MergeOptions::protected).
+    #[default]
+    Manual,
+    /// Like any other conflict, so strategies and heuristics may settle it.
+    Conflict,
+}
+
+impl std::str::FromStr for LicenseMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "manual" => Ok(Self::Manual),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown license mode `{other}` (expected `manual` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// What happens when both sides set `description` differently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DescriptionMode {
+    /// Take the longer description; a deleted one counts as empty.
+    #[default]
+    Longer,
+    /// Report a conflict.
+    Conflict,
+}
+
+impl std::str::FromStr for DescriptionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "longer" => Ok(Self::Longer),
+            "conflict" => Ok(Self::Conflict),
+            other => Err(format!(
+                "unknown description mode `{other}` (expected `longer` or `conflict`)"
+            )),
+        }
+    }
+}
+
+/// How two different changes to a `[profile.*]` setting are settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileMode {
+    /// Report a conflict.
+    Conflict,
+    /// Take the higher value; of named levels and booleans the stronger one,
+    /// so `true` wins over `false`.
+    Max,
+    /// Take the lower value; `false` wins over `true`.
+    Min,
+}
+
+impl std::str::FromStr for ProfileMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "conflict" => Ok(Self::Conflict),
+            "max" => Ok(Self::Max),
+            "min" => Ok(Self::Min),
+            other => Err(format!(
+                "unknown profile mode `{other}` (expected `conflict`, `max` or `min`)"
+            )),
+        }
+    }
+}
+
+/// Handling of settings both sides changed in a `[profile.*]` table, its
+/// `build-override` or one of its `package` overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileRules {
+    /// `opt-level`, `Max` by default. `"s"` and `"z"` only compare with each
+    /// other.
+    pub opt_level: ProfileMode,
+    /// `codegen-units`, `Min` by default.
+    pub codegen_units: ProfileMode,
+    /// `debug`, by level from `false` (`"none"`) to `true` (`"full"`).
+    pub debug: ProfileMode,
+    /// `lto`, by level: `"off"`, `false`, `"thin"`, then `true` (`"fat"`).
+    pub lto: ProfileMode,
+    /// `strip`, by level: `false` (`"none"`), `"debuginfo"`, then `true`
+    /// (`"symbols"`).
+    pub strip: ProfileMode,
+}
+
+impl Default for ProfileRules {
+    fn default() -> Self {
+        Self {
+            opt_level: ProfileMode::Max,
+            codegen_units: ProfileMode::Min,
+            debug: ProfileMode::Conflict,
+            lto: ProfileMode::Conflict,
+            strip: ProfileMode::Conflict,
+        }
+    }
+}
+
+impl ProfileRules {
+    /// The mode for `setting` (`opt-level`, `lto`, ...); `None` for settings
+    /// without a rule.
+    pub fn mode(&self, setting: &str) -> Option<ProfileMode> {
+        let mut rules = *self;
+        rules.mode_mut(setting).copied()
+    }
+
+    /// The mode for `setting`, to change it.
+    pub fn mode_mut(&mut self, setting: &str) -> Option<&mut ProfileMode> {
+        match setting {
+            "opt-level" => Some(&mut self.opt_level),
+            "codegen-units" => Some(&mut self.codegen_units),
+            "debug" => Some(&mut self.debug),
+            "lto" => Some(&mut self.lto),
+            "strip" => Some(&mut self.strip),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for [`merge_manifest_texts_with`].
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// Maximum table nesting the merge descends into. Deeper documents
+    /// (generated or malicious) fail with [`MergeError::DepthLimit`] instead
+    /// of exhausting the stack.
+    pub max_depth: usize,
+    /// Heuristics tried in order on conflicting values; the first proposal
+    /// wins.
+    pub heuristics: Vec<Heuristic>,
+    /// Heuristics for conflicts at particular paths, tried before
+    /// `heuristics`. Only the first entry matching a path is consulted.
+    pub strategies: Vec<PathStrategy>,
+    /// Proposals less confident than this are reported as conflicts.
+    pub min_confidence: f64,
+    /// Replaces `min_confidence` for `[build-dependencies]`, including
+    /// target-specific ones. When set, a crate's build dependency entries
+    /// are also decided apart from its other entries, so a heuristic
+    /// resolution of `[dependencies]` does not carry over to them.
+    pub build_min_confidence: Option<f64>,
+    /// Append an [`audit`](crate::audit) footer recording automatic
+    /// resolutions to the merged text.
+    pub audit: bool,
+    /// Remove audit footers left in the inputs by earlier merges (implied by
+    /// `audit`).
+    pub strip_audit: bool,
+    /// Handling of members adopting `[workspace.lints]`.
+    pub lint_inheritance: LintInheritance,
+    /// Handling of two different level changes to the same lint.
+    pub lint_levels: LintLevels,
+    /// Prepared resolutions for this merge, applied before anything else.
+    pub resolutions: Vec<Choice>,
+    /// Recorded decisions for known conflicts, applied before heuristics.
+    pub suppressions: Vec<Suppression>,
+    /// Sides to take for conflicts at matching paths, applied after recorded
+    /// decisions and before heuristics.
+    pub preferences: Vec<Preference>,
+    /// Dotted path patterns (`*` matching any run of characters) whose
+    /// conflicts, in the matching values and everything below them, are
+    /// only resolved by `resolutions` and `suppressions`, never by
+    /// preferences, strategies, built-in rules or heuristics.
+    pub protected: Vec<String>,
+    /// Exact versions crates must stay at, by crate name.
+    pub pins: BTreeMap<String, String>,
+    /// Handling of sides moving a pinned crate.
+    pub pin_mode: PinMode,
+    /// Handling of a dependency renamed on one side and deleted on the
+    /// other.
+    pub rename_delete: RenameDelete,
+    /// Rename deprecated sections (`[project]`, `[dev_dependencies]`,
+    /// `[build_dependencies]`) to their current names even when neither
+    /// side migrated them.
+    pub migrate_deprecated: bool,
+    /// Settling of git dependency `rev`s both sides changed.
+    pub git_ancestry: GitAncestry,
+    /// Walk the whole document and fail with every conflict
+    /// ([`MergeError::Report`]) instead of stopping at the first.
+    pub all_conflicts: bool,
+    /// Resolve two different `edition` bumps to the newer edition.
+    pub newest_edition: bool,
+    /// Resolve two different `package.version` bumps to the higher version.
+    pub highest_version: bool,
+    /// Handling of two different `rust-version` changes.
+    pub rust_version: RustVersionMode,
+    /// Handling of two different `publish` changes.
+    pub publish: PublishMode,
+    /// Handling of conflicting `license` and `license-file` changes.
+    pub license: LicenseMode,
+    /// Handling of two different `description` changes.
+    pub description: DescriptionMode,
+    /// Handling of two different changes to a profile setting.
+    pub profile: ProfileRules,
+    /// Format the merged document in this style instead of as the inputs
+    /// were. Even one-sided changes then go through the merge.
+    pub style: Option<OutputStyle>,
+    /// Write the keys of every table in alphabetical order rather than in
+    /// ours' order.
+    pub sort: bool,
+}
+
+/// Resolve conflicts at paths matching `path` with `heuristic`.
+#[derive(Debug, Clone)]
+pub struct PathStrategy {
+    /// Dotted path; `*` matches any run of characters, dots included.
+    pub path: String,
+    pub heuristic: Heuristic,
+    /// The strategy's name, identifying it in cache keys.
+    pub name: String,
+}
+
+/// Resolve conflicts at paths matching `path` to `side`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preference {
+    /// Dotted path; `*` matches any run of characters, dots included.
+    pub path: String,
+    pub side: Side,
+    /// Reported as the resolution's rule.
+    pub rule: &'static str,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            heuristics: Vec::new(),
+            strategies: Vec::new(),
+            min_confidence: 0.0,
+            build_min_confidence: None,
+            audit: false,
+            strip_audit: false,
+            lint_inheritance: LintInheritance::default(),
+            lint_levels: LintLevels::default(),
+            resolutions: Vec::new(),
+            suppressions: Vec::new(),
+            preferences: Vec::new(),
+            protected: Vec::new(),
+            pins: BTreeMap::new(),
+            pin_mode: PinMode::default(),
+            rename_delete: RenameDelete::default(),
+            migrate_deprecated: false,
+            git_ancestry: GitAncestry::default(),
+            all_conflicts: false,
+            newest_edition: true,
+            highest_version: true,
+            rust_version: RustVersionMode::default(),
+            publish: PublishMode::default(),
+            license: LicenseMode::default(),
+            description: DescriptionMode::default(),
+            profile: ProfileRules::default(),
+            style: None,
+            sort: false,
+        }
+    }
+}
+
+impl MergeOptions {
+    /// Every setting that can change the merged output, as the `config` of
+    /// [`ResultCache::key`](crate::cache::ResultCache::key). Heuristics are
+    /// function pointers whose addresses differ from run to run, so the
+    /// caller names them in `heuristics`; path strategies go by their names.
+    pub fn cache_config(&self, heuristics: &[String]) -> String {
+        // No `..`: a new field does not compile until it is listed here.
+        let Self {
+            max_depth,
+            heuristics: _,
+            strategies,
+            min_confidence,
+            build_min_confidence,
+            audit,
+            strip_audit,
+            lint_inheritance,
+            lint_levels,
+            resolutions,
+            suppressions,
+            preferences,
+            protected,
+            pins,
+            pin_mode,
+            rename_delete,
+            migrate_deprecated,
+            git_ancestry,
+            all_conflicts,
+            newest_edition,
+            highest_version,
+            rust_version,
+            publish,
+            license,
+            description,
+            profile,
+            style,
+            sort,
+        } = self;
+        let strategies: Vec<_> = strategies
+            .iter()
+            .map(|strategy| (&strategy.path, &strategy.name))
+            .collect();
+        let settings: [(&str, &dyn std::fmt::Debug); 28] = [
+            ("max-depth", max_depth),
+            ("heuristics", &heuristics),
+            ("strategies", &strategies),
+            ("min-confidence", min_confidence),
+            ("build-min-confidence", build_min_confidence),
+            ("audit", audit),
+            ("strip-audit", strip_audit),
+            ("lint-inheritance", lint_inheritance),
+            ("lint-levels", lint_levels),
+            ("prepared", resolutions),
+            ("recorded", suppressions),
+            ("preferences", preferences),
+            ("protected", protected),
+            ("pins", pins),
+            ("pin-mode", pin_mode),
+            ("rename-delete", rename_delete),
+            ("migrate-deprecated", migrate_deprecated),
+            ("git-ancestry", git_ancestry),
+            ("all-conflicts", all_conflicts),
+            ("newest-edition", newest_edition),
+            ("highest-version", highest_version),
+            ("rust-version", rust_version),
+            ("publish", publish),
+            ("license", license),
+            ("description", description),
+            ("profile", profile),
+            ("style", style),
+            ("sort", sort),
+        ];
+        settings
+            .iter()
+            .map(|(name, value)| format!("{name}={value:?}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
 fn render_value(v: Option<&Value>) -> String {
     match v {
         Some(value) => value.to_string(),
@@ -36,113 +923,719 @@ fn render_value(v: Option<&Value>) -> String {
     }
 }
 
+/// Resolves merges that need no parsing: when both sides are byte-identical
+/// or only one side differs from base, the other side is the result verbatim.
+pub fn trivial_merge<'a>(
+    base_text: &str,
+    ours_text: &'a str,
+    theirs_text: &'a str,
+) -> Option<&'a str> {
+    if ours_text == theirs_text || theirs_text == base_text {
+        Some(ours_text)
+    } else if ours_text == base_text {
+        Some(theirs_text)
+    } else {
+        None
+    }
+}
+
 pub fn merge_manifest_texts(
     base_text: &str,
     ours_text: &str,
     theirs_text: &str,
-) -> Result<String, MergeConflict> {
-    let base: Value = toml::from_str(base_text).map_err(|_| MergeConflict {
-        path: "<parse:base>".to_string(),
-        base: None,
-        ours: None,
-        theirs: None,
-    })?;
-    let ours: Value = toml::from_str(ours_text).map_err(|_| MergeConflict {
-        path: "<parse:ours>".to_string(),
-        base: None,
-        ours: None,
-        theirs: None,
-    })?;
-    let theirs: Value = toml::from_str(theirs_text).map_err(|_| MergeConflict {
-        path: "<parse:theirs>".to_string(),
-        base: None,
-        ours: None,
-        theirs: None,
+) -> Result<String, MergeError> {
+    merge_manifest_texts_with(base_text, ours_text, theirs_text, &MergeOptions::default())
+}
+
+pub fn merge_manifest_texts_with(
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+) -> Result<String, MergeError> {
+    merge_manifest_texts_timed(
+        base_text,
+        ours_text,
+        theirs_text,
+        options,
+        &mut Timings::default(),
+    )
+}
+
+/// Like [`merge_manifest_texts_with`], recording the `parse`, `merge` and
+/// `serialize` phases into `timings`.
+pub fn merge_manifest_texts_timed(
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+    timings: &mut Timings,
+) -> Result<String, MergeError> {
+    merge_manifest_texts_detailed(base_text, ours_text, theirs_text, options, timings)
+        .map(|merge| merge.text)
+}
+
+/// A successful merge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeResult {
+    /// The merged manifest.
+    pub text: String,
+    /// Values both sides changed that were resolved automatically.
+    pub resolutions: Vec<Resolution>,
+}
+
+/// Like [`merge_manifest_texts_timed`], also returning the automatic
+/// resolutions the merge made.
+pub fn merge_manifest_texts_detailed(
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+    timings: &mut Timings,
+) -> Result<MergeResult, MergeError> {
+    merge_texts(
+        Document::Manifest,
+        base_text,
+        ours_text,
+        theirs_text,
+        options,
+        timings,
+    )
+}
+
+/// The kind of TOML file being merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Document {
+    /// `Cargo.toml`: unknown top-level keys are passed through and lint
+    /// inheritance is reconciled.
+    Manifest,
+    /// Any other Cargo file (`.cargo/config.toml`), merged table by table.
+    Other,
+}
+
+pub(crate) fn merge_texts(
+    document: Document,
+    base_text: &str,
+    ours_text: &str,
+    theirs_text: &str,
+    options: &MergeOptions,
+    timings: &mut Timings,
+) -> Result<MergeResult, MergeError> {
+    let stripped;
+    let (base_text, ours_text, theirs_text) = if options.audit || options.strip_audit {
+        stripped = [base_text, ours_text, theirs_text].map(audit::strip);
+        (&*stripped[0], &*stripped[1], &*stripped[2])
+    } else {
+        (base_text, ours_text, theirs_text)
+    };
+    // A one-sided change can still move a pinned crate or need migrating.
+    let rewrite = document == Document::Manifest
+        && (!options.pins.is_empty() || options.migrate_deprecated || options.sort)
+        || options.style.is_some();
+    if !rewrite && let Some(winner) = trivial_merge(base_text, ours_text, theirs_text) {
+        return Ok(MergeResult {
+            text: winner.to_string(),
+            resolutions: Vec::new(),
+        });
+    }
+
+    let parse = |side: Side, text: &str| -> Result<Value, MergeError> {
+        toml::from_str(text).map_err(|err| MergeError::Parse(ParseError::new(side, text, &err)))
+    };
+    let (mut base, mut ours, mut theirs) = timings.time("parse", || {
+        Ok::<_, MergeError>((
+            parse(Side::Base, base_text)?,
+            parse(Side::Ours, ours_text)?,
+            parse(Side::Theirs, theirs_text)?,
+        ))
     })?;
 
-    let merged = merge_value("", Some(&base), Some(&ours), Some(&theirs))?
+    // Target keys and deprecated section names are settled first, the
+    // latter in ours' text too so the renamed sections keep their place.
+    let mut pinned = Vec::new();
+    let renamed;
+    let ours_text = if document == Document::Manifest {
+        pinned = cfgs::unify(&mut base, &mut ours, &mut theirs);
+        pinned.extend(deprecated::normalize(
+            options.migrate_deprecated,
+            &mut base,
+            &mut ours,
+            &mut theirs,
+        ));
+        renamed = deprecated::apply_to_text(ours_text, &ours);
+        renamed.as_deref().unwrap_or(ours_text)
+    } else {
+        ours_text
+    };
+
+    // The output is written by editing ours' document, see `layout`.
+    let original = ours.clone();
+
+    // Conflicts found so far, when collecting them all.
+    let mut collected = options.all_conflicts.then(Vec::new);
+
+    // Unknown top-level keys are merged whole and copied verbatim.
+    let mut shorthand = Vec::new();
+    let [unknown_base, unknown_ours, unknown_theirs] = if document == Document::Manifest {
+        let unknown = [&mut base, &mut ours, &mut theirs].map(passthrough::take_unknown);
+        lints::inherit(options.lint_inheritance, &mut base, &mut ours, &mut theirs);
+        locations::normalize(&mut base, &ours, &mut theirs);
+        pinned.extend(pins::enforce(
+            options.pin_mode,
+            &options.pins,
+            &base,
+            &mut ours,
+            &mut theirs,
+            collected.as_mut(),
+        )?);
+        pinned.extend(renames::reconcile(
+            options.rename_delete,
+            &base,
+            &mut ours,
+            &mut theirs,
+            collected.as_mut(),
+        )?);
+        pinned.extend(moves::follow(&mut base, &mut ours, &mut theirs));
+        pinned.extend(ancestry::resolve(
+            options.git_ancestry,
+            &base,
+            &mut ours,
+            &mut theirs,
+        ));
+        shorthand = shorthand::expand(&mut base, &mut ours, &mut theirs);
+        pinned.extend(inheritance::migrate(&mut base, &mut ours, &mut theirs));
+        flags::normalize(&mut base, &ours, &mut theirs);
+        unknown
+    } else {
+        Default::default()
+    };
+
+    let decisions = cluster::decide(options, &base, &ours, &theirs);
+    let merged = timings
+        .time("merge", || {
+            merge_value(
+                options,
+                &decisions,
+                &mut Vec::new(),
+                Some(&base),
+                Some(&ours),
+                Some(&theirs),
+                collected.as_mut(),
+            )
+        })
+        .map_err(|err| match err {
+            MergeError::Conflict(mut conflict) => {
+                conflict.related = cluster::related_conflicts(
+                    options, &decisions, &base, &ours, &theirs, &conflict,
+                );
+                MergeError::Conflict(conflict)
+            }
+            err => err,
+        })?
         .expect("root merge always returns a document");
+    let unknown = passthrough::merge(
+        [&unknown_base, &unknown_ours, &unknown_theirs],
+        collected.as_mut(),
+    )?;
+    if let Some(mut conflicts) = collected
+        && !conflicts.is_empty()
+    {
+        // A pin conflict can recur when the merge reaches the same entry.
+        let mut seen = BTreeSet::new();
+        conflicts.retain(|conflict| seen.insert(conflict.path.clone()));
+        let conflicts = cluster::group(options, conflicts);
+        return Err(MergeError::Report(MergeReport { conflicts }));
+    }
 
-    let mut output = toml::to_string_pretty(&merged).map_err(|_| MergeConflict {
-        path: "<serialize>".to_string(),
-        base: None,
-        ours: None,
-        theirs: None,
-    })?;
+    let mut output = timings
+        .time("serialize", || {
+            let mut merged = Value::try_from(&merged)?;
+            shorthand::collapse(&mut merged, &shorthand);
+            let verbatim: Vec<String> = unknown.keys().cloned().collect();
+            if let Some(table) = merged.as_table_mut() {
+                table.extend(unknown);
+            }
+            layout::render(ours_text, theirs_text, &original, &merged, &verbatim)
+                .or_else(|_| toml::to_string_pretty(&merged))
+        })
+        .map_err(|err: toml::ser::Error| MergeError::Serialize {
+            message: err.to_string(),
+        })?;
 
+    // Edited entries end in `\n`; match the line endings ours used.
+    output = output.replace("\r\n", "\n");
     if !output.ends_with('\n') {
         output.push('\n');
     }
+    if options.sort && document == Document::Manifest {
+        output = layout::sort(&output);
+    }
+    if let Some(style) = &options.style {
+        output = style::apply(&output, style);
+    }
+    let newline = match options.style.as_ref().and_then(|style| style.newline) {
+        Some(newline) => newline.as_str(),
+        None if uses_crlf(ours_text) => "\r\n",
+        None => "\n",
+    };
+    if newline == "\r\n" {
+        output = output.replace('\n', newline);
+    }
+    let mut resolutions = pinned;
+    resolutions.extend(decisions.resolutions());
+    if options.audit {
+        output.push_str(&audit::footer(&resolutions, newline));
+    }
 
-    Ok(output)
+    Ok(MergeResult {
+        text: output,
+        resolutions,
+    })
 }
 
-fn merge_value(
-    path: &str,
-    base: Option<&Value>,
-    ours: Option<&Value>,
-    theirs: Option<&Value>,
-) -> Result<Option<Value>, MergeConflict> {
+/// Whether `text` terminates its lines with CRLF (judged by the first line).
+fn uses_crlf(text: &str) -> bool {
+    text.find('\n')
+        .is_some_and(|idx| text[..idx].ends_with('\r'))
+}
+
+/// A merged node that borrows from the inputs.
+///
+/// Subtrees taken unchanged from one side stay references into that side's
+/// document; only tables whose children come from different sides are
+/// assembled, and even those hold references. The result is serialized
+/// directly, so merging never copies input values.
+pub(crate) enum Merged<'a> {
+    Borrowed(&'a Value),
+    /// A value produced by a heuristic or a list union rather than taken
+    /// from an input.
+    Owned(Value),
+    Table(Vec<(&'a str, Merged<'a>)>),
+    /// Build targets merged by name, see [`targets`].
+    Array(Vec<Merged<'a>>),
+}
+
+impl Serialize for Merged<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Merged::Borrowed(value) => value.serialize(serializer),
+            Merged::Owned(value) => value.serialize(serializer),
+            Merged::Table(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Merged::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+/// Merges one node; `path` holds the keys leading to it and is only joined
+/// into a string when a conflict is reported. Conflicts are pushed to
+/// `collected` when given, keeping ours' value in their place, and returned
+/// as errors otherwise.
+pub(crate) fn merge_value<'a>(
+    options: &MergeOptions,
+    decisions: &Decisions,
+    path: &mut Vec<&'a str>,
+    base: Option<&'a Value>,
+    ours: Option<&'a Value>,
+    theirs: Option<&'a Value>,
+    mut collected: Option<&mut Vec<MergeConflict>>,
+) -> Result<Option<Merged<'a>>, MergeError> {
     if ours == theirs {
-        return Ok(ours.cloned());
+        return Ok(ours.map(Merged::Borrowed));
     }
 
     if ours == base {
-        return Ok(theirs.cloned());
+        return Ok(theirs.map(Merged::Borrowed));
     }
 
     if theirs == base {
-        return Ok(ours.cloned());
+        return Ok(ours.map(Merged::Borrowed));
+    }
+
+    // A dependency flag left out means its default.
+    if let Some(merged) = flags::merge(path, base, ours, theirs) {
+        return Ok(merged.map(Merged::Borrowed));
+    }
+
+    // Unstable manifest surface merges as one value until its semantics are
+    // known.
+    if unstable::recognize(path, [base, ours, theirs]).is_some() {
+        return settle(
+            options,
+            decisions,
+            path,
+            [base, ours, theirs],
+            collected,
+            ConflictKind::Content,
+        );
+    }
+
+    // Package lists (`include`, `keywords`, ...) merge as sets, within the
+    // limits crates.io sets.
+    let over_limit = match fields::package_list(path, base, ours, theirs) {
+        Some(Ok(items)) if !is_protected(options, path) => {
+            return Ok(Some(Merged::Owned(items)));
+        }
+        Some(Err(kind)) => Some(kind),
+        _ => None,
+    };
+
+    // Feature enable lists merge as sets of entries.
+    if !is_protected(options, path)
+        && let Some(merged) = features::union(path, base, ours, theirs)
+    {
+        return Ok(Some(Merged::Owned(merged)));
+    }
+
+    // `[workspace]` member lists merge as sets of paths.
+    if !is_protected(options, path)
+        && let Some(merged) = members::union(path, base, ours, theirs)
+    {
+        return Ok(Some(Merged::Owned(merged)));
+    }
+
+    // Other lists of strings that are sets (a dependency's `features`, ...)
+    // and both sides only added to are united. Ordered lists, such as
+    // arguments, conflict.
+    if let (Some(Value::Array(ours_items)), Some(Value::Array(theirs_items))) = (ours, theirs)
+        && over_limit.is_none()
+        && is_set_list(path)
+        && !is_protected(options, path)
+        && let Some(items) = union_of_additions(base, ours_items, theirs_items)
+    {
+        return Ok(Some(Merged::Owned(Value::Array(items))));
+    }
+
+    // Tool configuration in `[workspace.metadata]` only conflicts on clashing
+    // values.
+    if !is_protected(options, path)
+        && let Some(merged) = metadata::union(path, base, ours, theirs)
+    {
+        return Ok(Some(Merged::Owned(merged)));
+    }
+
+    // Build targets are merged entry by entry, paired by name.
+    if let [section] = path[..]
+        && targets::SECTIONS.contains(&section)
+        && let [Some(base_targets), Some(ours_targets), Some(theirs_targets)] =
+            [base, ours, theirs].map(targets::named)
+    {
+        let mut names: Vec<&str> = ours_targets.iter().map(|(name, _)| *name).collect();
+        for (name, _) in &theirs_targets {
+            if !names.contains(name) {
+                names.push(name);
+            }
+        }
+        let get = |targets: &[(&'a str, &'a Value)], name: &str| {
+            targets
+                .iter()
+                .find(|(other, _)| *other == name)
+                .map(|(_, target)| *target)
+        };
+        let mut out = Vec::with_capacity(names.len());
+        for name in names {
+            path.push(name);
+            let merged = merge_value(
+                options,
+                decisions,
+                path,
+                get(&base_targets, name),
+                get(&ours_targets, name),
+                get(&theirs_targets, name),
+                collected.as_deref_mut(),
+            )?;
+            path.pop();
+            out.extend(merged);
+        }
+        return Ok((!out.is_empty()).then_some(Merged::Array(out)));
     }
 
     match (ours, theirs) {
         (Some(Value::Table(ours_table)), Some(Value::Table(theirs_table))) => {
-            let mut keys = BTreeSet::new();
-            keys.extend(ours_table.keys().cloned());
-            keys.extend(theirs_table.keys().cloned());
+            if path.len() >= options.max_depth {
+                return Err(MergeError::DepthLimit {
+                    path: path.join("."),
+                    limit: options.max_depth,
+                });
+            }
 
-            if let Some(Value::Table(base_table)) = base {
-                keys.extend(base_table.keys().cloned());
+            let base_table = base.and_then(Value::as_table);
+            // Deleted features the other side started to enable are not
+            // left to the deletion.
+            let dangling = match path[..] {
+                ["features"] => features::dangling(base, ours, theirs),
+                _ => Vec::new(),
+            };
+
+            let mut keys = BTreeSet::new();
+            keys.extend(ours_table.keys().map(String::as_str));
+            keys.extend(theirs_table.keys().map(String::as_str));
+            if let Some(base_table) = base_table {
+                keys.extend(base_table.keys().map(String::as_str));
             }
 
-            let mut out = toml::map::Map::new();
+            let mut out = Vec::with_capacity(keys.len());
 
             for key in keys {
-                let key_path = join_path(path, &key);
-                let base_child = base
-                    .and_then(|v| v.as_table())
-                    .and_then(|table| table.get(&key));
-                let ours_child = ours_table.get(&key);
-                let theirs_child = theirs_table.get(&key);
-
-                if let Some(value) = merge_value(&key_path, base_child, ours_child, theirs_child)? {
-                    out.insert(key, value);
+                let base_child = base_table.and_then(|table| table.get(key));
+                let ours_child = ours_table.get(key);
+                let theirs_child = theirs_table.get(key);
+
+                path.push(key);
+                let merged = match dangling.iter().find(|(name, ..)| *name == key) {
+                    Some((_, side, by)) => settle(
+                        options,
+                        decisions,
+                        path,
+                        [base_child, ours_child, theirs_child],
+                        collected.as_deref_mut(),
+                        ConflictKind::Referenced {
+                            side: *side,
+                            by: by.to_string(),
+                        },
+                    ),
+                    None => merge_value(
+                        options,
+                        decisions,
+                        path,
+                        base_child,
+                        ours_child,
+                        theirs_child,
+                        collected.as_deref_mut(),
+                    ),
+                }?;
+                path.pop();
+
+                if let Some(value) = merged {
+                    out.push((key, value));
                 }
             }
 
-            Ok(Some(Value::Table(out)))
+            Ok(Some(Merged::Table(out)))
         }
-        _ => Err(MergeConflict {
-            path: path.to_string(),
-            base: base.cloned(),
-            ours: ours.cloned(),
-            theirs: theirs.cloned(),
-        }),
+        _ => settle(
+            options,
+            decisions,
+            path,
+            [base, ours, theirs],
+            collected,
+            over_limit.unwrap_or_default(),
+        ),
     }
 }
 
-fn join_path(base: &str, key: &str) -> String {
-    if base.is_empty() {
-        key.to_string()
-    } else {
-        format!("{base}.{key}")
+/// Resolves a value (base, ours, theirs) the sides changed differently that
+/// is not merged structurally, or reports it as a conflict of `kind`.
+fn settle<'a>(
+    options: &MergeOptions,
+    decisions: &Decisions,
+    path: &[&'a str],
+    [base, ours, theirs]: [Option<&'a Value>; 3],
+    collected: Option<&mut Vec<MergeConflict>>,
+    kind: ConflictKind,
+) -> Result<Option<Merged<'a>>, MergeError> {
+    let prepared = options
+        .resolutions
+        .iter()
+        .find_map(|choice| choice.proposal(&path.join("."), base, ours, theirs));
+    let recorded = || {
+        (!options.suppressions.is_empty())
+            .then(|| fingerprint(&path.join("."), base, ours, theirs))
+            .and_then(|id| {
+                options
+                    .suppressions
+                    .iter()
+                    .find(|suppression| suppression.fingerprint == id)
+            })
+            .map(|suppression| take(suppression.side, "recorded", base, ours, theirs))
+    };
+    let preferred = || {
+        let joined = path.join(".");
+        options
+            .preferences
+            .iter()
+            .find(|preference| matches_pattern(&preference.path, &joined))
+            .map(|preference| take(preference.side, preference.rule, base, ours, theirs))
+    };
+    let min_confidence = match options.build_min_confidence {
+        Some(threshold) if cluster::is_build_dependency(path) => threshold,
+        _ => options.min_confidence,
+    };
+    let dispatched = || {
+        let joined = path.join(".");
+        options
+            .strategies
+            .iter()
+            .find(|strategy| matches_pattern(&strategy.path, &joined))
+            .and_then(|strategy| (strategy.heuristic)(path, base, ours, theirs))
+    };
+    let explicit = prepared.or_else(recorded);
+    if explicit.is_some() || !is_protected(options, path) {
+        let proposal = explicit.or_else(preferred).or_else(|| {
+            dispatched()
+                .or_else(|| fields::propose(options, path, base, ours, theirs))
+                .or_else(|| {
+                    options
+                        .heuristics
+                        .iter()
+                        .find_map(|heuristic| heuristic(path, base, ours, theirs))
+                })
+                .filter(|proposal| proposal.confidence >= min_confidence)
+        });
+        if let Some(merged) = decisions.resolve(path, ours, theirs, proposal) {
+            return Ok(merged);
+        }
+    }
+    let mut conflict = MergeConflict::new(
+        path.join("."),
+        base.cloned(),
+        ours.cloned(),
+        theirs.cloned(),
+    );
+    conflict.kind = kind;
+    match collected {
+        Some(collected) => {
+            collected.push(conflict);
+            Ok(ours.map(Merged::Borrowed))
+        }
+        None => Err(MergeError::Conflict(conflict)),
+    }
+}
+
+/// Whether the list at `path` is a set, where order carries no meaning: a
+/// dependency's `features`, a target's `required-features` and
+/// `cargo-features`.
+fn is_set_list(path: &[&str]) -> bool {
+    match path {
+        ["cargo-features"] | [.., "required-features"] => true,
+        [.., table, _, "features"] => matches!(
+            *table,
+            "dependencies"
+                | "dev-dependencies"
+                | "build-dependencies"
+                | "dev_dependencies"
+                | "build_dependencies"
+        ),
+        _ => false,
     }
 }
 
+/// Ours' items followed by those theirs added, when all three are lists of
+/// strings and neither side removed or reordered an item of `base`.
+fn union_of_additions(
+    base: Option<&Value>,
+    ours: &[Value],
+    theirs: &[Value],
+) -> Option<Vec<Value>> {
+    let base: &[Value] = match base {
+        None => &[],
+        Some(Value::Array(items)) => items,
+        Some(_) => return None,
+    };
+    if !base.iter().chain(ours).chain(theirs).all(Value::is_str) {
+        return None;
+    }
+    let keeps_base = |side: &[Value]| {
+        let mut rest = side.iter();
+        base.iter().all(|item| rest.any(|other| other == item))
+    };
+    if !keeps_base(ours) || !keeps_base(theirs) {
+        return None;
+    }
+    let mut merged = ours.to_vec();
+    for item in theirs {
+        if !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    Some(merged)
+}
+
+/// A certain proposal taking `side`'s value.
+fn take(
+    side: Side,
+    rule: &'static str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Proposal {
+    let value = match side {
+        Side::Base => base,
+        Side::Ours => ours,
+        Side::Theirs => theirs,
+    };
+    Proposal {
+        value: value.cloned(),
+        rule,
+        confidence: 1.0,
+    }
+}
+
+/// Whether `path` or a table containing it matches a pattern of
+/// [`MergeOptions::protected`], or `path` is a license field settled by
+/// hand ([`LicenseMode::Manual`]).
+fn is_protected(options: &MergeOptions, path: &[&str]) -> bool {
+    (options.license == LicenseMode::Manual && fields::is_license(path))
+        || !options.protected.is_empty()
+            && (1..=path.len()).any(|len| {
+                let joined = path[..len].join(".");
+                options
+                    .protected
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &joined))
+            })
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of
+/// characters (`release/*`, `dependencies.*`).
+pub(crate) fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn matches_wildcard_patterns() {
+        assert!(matches_pattern("release/*", "release/1.2"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern(
+            "dependencies.*.version",
+            "dependencies.serde.version"
+        ));
+        assert!(matches_pattern("a*b*c", "abbc"));
+        assert!(!matches_pattern("release/*", "main"));
+        assert!(!matches_pattern("main", "main2"));
+        assert!(!matches_pattern("a*ab", "ab"));
+    }
+
     #[test]
     fn merges_independent_dependency_changes() {
         let base = r#"
@@ -200,6 +1693,275 @@ serde = "1.0.199"
 "#;
 
         let err = merge_manifest_texts(base, ours, theirs).expect_err("merge must conflict");
-        assert_eq!(err.path, "dependencies.serde");
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.serde");
+    }
+
+    #[test]
+    fn reports_parse_errors_with_location() {
+        let base = "[package]\nname = \"demo\"\n";
+        let theirs = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n";
+        let ours = "[package]\nname = \"demo\"\nversion = 0.2.0\"\n";
+
+        let err = merge_manifest_texts(base, ours, theirs).expect_err("ours is invalid");
+        let MergeError::Parse(parse) = err else {
+            panic!("expected a parse error, got {err:?}");
+        };
+        assert_eq!(parse.side, Side::Ours);
+        assert_eq!(parse.line, Some(3));
+        assert_eq!(parse.snippet.as_deref(), Some("version = 0.2.0\""));
+        assert!(!parse.message.is_empty());
+        assert!(parse.to_string().starts_with("ours is not valid TOML"));
+    }
+
+    #[test]
+    fn refuses_to_descend_past_depth_limit() {
+        let options = MergeOptions {
+            max_depth: 3,
+            ..MergeOptions::default()
+        };
+        let base = "package.metadata.c.d = 1\nz = 0\n";
+        let ours = "package.metadata.c.d = 2\nz = 0\n";
+        let theirs = "package.metadata.c.d = 1\npackage.metadata.c.e = 5\nz = 1\n";
+
+        let err = merge_manifest_texts_with(base, ours, theirs, &options)
+            .expect_err("merge must stop at the depth limit");
+        assert!(
+            matches!(&err, MergeError::DepthLimit { path, limit: 3 } if path == "package.metadata.c"),
+            "{err:?}"
+        );
+
+        let shallow = MergeOptions {
+            max_depth: 4,
+            ..MergeOptions::default()
+        };
+        let merged = merge_manifest_texts_with(base, ours, theirs, &shallow).unwrap();
+        assert!(merged.contains("z = 1"));
+    }
+
+    #[test]
+    fn applies_heuristics_only_above_min_confidence() {
+        fn prefer_ours(
+            path: &[&str],
+            _base: Option<&Value>,
+            ours: Option<&Value>,
+            _theirs: Option<&Value>,
+        ) -> Option<Proposal> {
+            (path == ["dependencies", "serde"]).then(|| Proposal {
+                value: ours.cloned(),
+                rule: "prefer-ours",
+                confidence: 0.5,
+            })
+        }
+
+        let base = "[dependencies]\nserde = \"1\"\n";
+        let ours = "[dependencies]\nserde = \"1.0.200\"\n";
+        let theirs = "[dependencies]\nserde = \"1.0.199\"\n";
+        let mut options = MergeOptions {
+            heuristics: vec![prefer_ours],
+            min_confidence: 0.4,
+            ..MergeOptions::default()
+        };
+
+        let merged = merge_manifest_texts_with(base, ours, theirs, &options).unwrap();
+        assert!(merged.contains("serde = \"1.0.200\""));
+
+        options.min_confidence = 0.6;
+        let err = merge_manifest_texts_with(base, ours, theirs, &options).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+
+    #[test]
+    fn copies_winning_side_verbatim_without_parsing() {
+        let base = "[package]\nname = \"demo\"\n";
+        let ours = "# keep me\n[package]\nname   = \"demo\"\nversion = \"0.2.0\"\n";
+        let garbage = "this is [not toml";
+
+        assert_eq!(merge_manifest_texts(base, ours, base).unwrap(), ours);
+        assert_eq!(merge_manifest_texts(base, base, ours).unwrap(), ours);
+        assert_eq!(merge_manifest_texts(base, ours, ours).unwrap(), ours);
+        assert_eq!(
+            merge_manifest_texts(garbage, garbage, ours).unwrap(),
+            ours,
+            "unchanged inputs are never parsed"
+        );
+        assert_eq!(trivial_merge(base, ours, garbage), None);
+    }
+
+    #[test]
+    fn leaves_protected_conflicts_to_explicit_decisions() {
+        let base = "[package]\nversion = \"0.1.0\"\npublish = [\"a\"]\n\n\
+                    [patch.crates-io]\nserde = { git = \"https://example.com/serde\" }\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("serde\" }", "serde\", branch = \"x\" }");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("serde\" }", "serde\", branch = \"y\" }");
+        let mut options = MergeOptions {
+            heuristics: vec![crate::strategy::prefer_theirs],
+            protected: vec!["patch.*".to_string()],
+            all_conflicts: true,
+            ..MergeOptions::default()
+        };
+
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Report(report) = err else {
+            panic!("expected a report, got {err:?}");
+        };
+        let paths: Vec<&str> = report.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["patch.crates-io.serde.branch"]);
+
+        // Nor are additions on both sides united.
+        options.protected.push("package.publish".to_string());
+        let ours = ours.replace("[\"a\"]", "[\"a\", \"b\"]");
+        let theirs = theirs.replace("[\"a\"]", "[\"a\", \"c\"]");
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Report(report) = err else {
+            panic!("expected a report, got {err:?}");
+        };
+        assert_eq!(report.conflicts.len(), 2);
+
+        options.resolutions = vec![Choice {
+            fingerprint: None,
+            path: Some("patch.crates-io.serde.branch".to_string()),
+            take: crate::resolutions::Take::Side(Side::Ours),
+        }];
+        options.protected.pop();
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("branch = \"x\""), "{merged}");
+        assert!(merged.contains("version = \"0.3.0\""), "{merged}");
+    }
+
+    #[test]
+    fn reports_every_conflict_with_all_conflicts() {
+        let base = "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n\n[hints]\nx = 1\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("\"1\"", "\"1.0.200\"")
+            .replace("x = 1", "x = 2");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("\"1\"", "\"1.0.199\"")
+            .replace("x = 1", "x = 3");
+        let options = MergeOptions {
+            all_conflicts: true,
+            highest_version: false,
+            ..MergeOptions::default()
+        };
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Report(report) = err else {
+            panic!("expected a report, got {err:?}");
+        };
+        let paths: Vec<&str> = report.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["dependencies.serde", "package.version", "hints"]);
+        assert_eq!(report.conflicts[1].theirs, Some(Value::from("0.3.0")));
+        assert!(report.to_string().starts_with("3 semantic conflicts\n"));
+
+        // Without conflicts the mode merges as usual.
+        let merged = merge_manifest_texts_with(base, &ours, base, &options).unwrap();
+        assert_eq!(merged, ours);
+    }
+
+    #[test]
+    fn unites_lists_both_sides_only_added_to() {
+        let base = "[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\n\n\
+                    [features]\nfull = [\"a\", \"x\"]\n";
+        let ours = base
+            .replace("[\"derive\"]", "[\"derive\", \"rc\"]")
+            .replace("[\"a\", \"x\"]", "[\"a\", \"x\", \"b\"]");
+        let theirs = base
+            .replace("[\"derive\"]", "[\"std\", \"derive\"]")
+            .replace("[\"a\", \"x\"]", "[\"a\", \"c\", \"x\"]");
+
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert!(
+            merged.contains("features = [\"derive\", \"rc\", \"std\"]"),
+            "{merged}"
+        );
+        assert!(
+            merged.contains("full = [\"a\", \"x\", \"b\", \"c\"]"),
+            "{merged}"
+        );
+
+        // Removing or reordering base's items is still a conflict, except in
+        // a feature's enable list, which merges as a set.
+        let theirs = base.replace("[\"derive\"]", "[\"std\"]");
+        let err = merge_manifest_texts(base, &ours, &theirs).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+        let theirs = base.replace("[\"a\", \"x\"]", "[\"x\", \"a\"]");
+        let merged = merge_manifest_texts(base, &ours, &theirs).unwrap();
+        assert!(merged.contains("full = [\"a\", \"x\", \"b\"]"), "{merged}");
+
+        // Ordered lists, such as a tool's arguments, are not sets.
+        let base = "[package.metadata.runner]\nargs = [\"--a\"]\n";
+        let ours = base.replace("[\"--a\"]", "[\"--a\", \"--b\", \"1\"]");
+        let theirs = base.replace("[\"--a\"]", "[\"--a\", \"--b\", \"2\"]");
+        let err = merge_manifest_texts(base, &ours, &theirs).unwrap_err();
+        assert!(matches!(err, MergeError::Conflict(_)), "{err:?}");
+    }
+
+    #[test]
+    fn preserves_crlf_line_endings_of_ours() {
+        let base = "[dependencies]\r\nserde = \"1\"\r\n";
+        let ours = "[dependencies]\r\nserde = \"1\"\r\nclap = \"4\"\r\n";
+        let theirs = "[dependencies]\nserde = \"1\"\nanyhow = \"1\"\n";
+
+        let merged = merge_manifest_texts(base, ours, theirs).expect("merge should succeed");
+        assert!(merged.contains("clap = \"4\"\r\n"));
+        assert!(merged.contains("anyhow = \"1\"\r\n"));
+        assert!(!merged.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn keeps_lf_when_ours_uses_lf() {
+        let base = "a = 1\r\n";
+        let ours = "a = 2\n";
+        let merged = merge_manifest_texts(base, ours, base).expect("merge should succeed");
+        assert_eq!(merged, "a = 2\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn conflict_round_trips_through_serde() {
+        let conflict = MergeConflict::new(
+            "dependencies.serde".to_string(),
+            Some(Value::String("1".to_string())),
+            Some(Value::String("1.0.200".to_string())),
+            None,
+        );
+
+        let encoded = toml::to_string(&conflict).expect("conflict should serialize");
+        let decoded: MergeConflict = toml::from_str(&encoded).expect("conflict should deserialize");
+        assert_eq!(decoded.path, conflict.path);
+        assert_eq!(decoded.ours, conflict.ours);
+        assert_eq!(decoded.theirs, None);
+        assert_eq!(decoded.fingerprint, conflict.fingerprint);
+    }
+
+    #[test]
+    fn cache_config_covers_settings_but_not_heuristic_addresses() {
+        let options = MergeOptions {
+            heuristics: vec![crate::strategy::prefer_ours],
+            ..MergeOptions::default()
+        };
+        let names = ["ours".to_string()];
+        let config = options.cache_config(&names);
+        assert_eq!(config, options.clone().cache_config(&names));
+        assert!(config.contains("heuristics=[\"ours\"]"), "{config}");
+        assert!(!config.contains("0x"), "{config}");
+
+        let sorted = MergeOptions {
+            sort: true,
+            ..options.clone()
+        };
+        assert_ne!(sorted.cache_config(&names), config);
+        let pinned = MergeOptions {
+            pins: BTreeMap::from([("log".to_string(), "0.4.20".to_string())]),
+            ..options
+        };
+        assert_ne!(pinned.cache_config(&names), config);
     }
 }
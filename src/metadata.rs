@@ -0,0 +1,93 @@
+//! Deep merging of `[workspace.metadata]`.
+//!
+//! Tools keep their configuration under `[workspace.metadata.<tool>]`
+//! (cargo-dist, cargo-release, xtask settings), and release automation on
+//! one branch edits it while feature work on another does. None of it is
+//! read by cargo, so it is merged as a union: tables key by key, a key one
+//! side deleted and the other changed keeps the change, and lists take the
+//! items either side added minus those either side removed. Only a value
+//! both sides set differently, scalar against scalar or of different types,
+//! is a conflict.
+
+use toml::Value;
+
+/// The merged value at `path` when it lies in `[workspace.metadata]` and
+/// the union settles it; `None` leaves it to the usual merge.
+pub(crate) fn union(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Value> {
+    if !matches!(path, ["workspace", "metadata", _, ..]) {
+        return None;
+    }
+    match (ours, theirs) {
+        (Some(Value::Array(ours)), Some(Value::Array(theirs))) => {
+            Some(Value::Array(lists(base, ours, theirs)))
+        }
+        // Deleted on one side, changed on the other.
+        (None, Some(kept)) | (Some(kept), None) => Some(kept.clone()),
+        _ => None,
+    }
+}
+
+/// Ours' items that theirs kept, then the items theirs added.
+pub(crate) fn lists(base: Option<&Value>, ours: &[Value], theirs: &[Value]) -> Vec<Value> {
+    let base: &[Value] = match base {
+        Some(Value::Array(items)) => items,
+        _ => &[],
+    };
+    let removed = |side: &[Value], item: &Value| base.contains(item) && !side.contains(item);
+    let mut merged: Vec<Value> = ours
+        .iter()
+        .filter(|item| !removed(theirs, item))
+        .cloned()
+        .collect();
+    for item in theirs {
+        if !ours.contains(item) && !base.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeError, merge_manifest_texts};
+
+    #[test]
+    fn unites_workspace_metadata() {
+        let base = "[workspace.metadata.dist]\ncargo-dist-version = \"0.20.0\"\n\
+                    targets = [\"x86_64-unknown-linux-gnu\", \"aarch64-apple-darwin\"]\n\
+                    installers = [\"shell\"]\n\
+                    [workspace.metadata.release]\nsign-tag = true\n";
+        let ours = "[workspace.metadata.dist]\ncargo-dist-version = \"0.21.0\"\n\
+                    targets = [\"x86_64-unknown-linux-gnu\", \"x86_64-pc-windows-msvc\"]\n\
+                    installers = [\"shell\"]\n";
+        let theirs = "[workspace.metadata.dist]\ncargo-dist-version = \"0.20.0\"\n\
+                      targets = [\"x86_64-unknown-linux-gnu\", \"aarch64-apple-darwin\", \"aarch64-unknown-linux-gnu\"]\n\
+                      installers = [\"powershell\"]\n\
+                      [workspace.metadata.release]\nsign-tag = true\npublish = false\n\
+                      [workspace.metadata.xtask]\ndocs = true\n";
+
+        let merged = merge_manifest_texts(base, ours, theirs).unwrap();
+        let merged: toml::Table = merged.parse().unwrap();
+        let expected: toml::Table = "[workspace.metadata.dist]\ncargo-dist-version = \"0.21.0\"\n\
+             targets = [\"x86_64-unknown-linux-gnu\", \"x86_64-pc-windows-msvc\", \"aarch64-unknown-linux-gnu\"]\n\
+             installers = [\"powershell\"]\n\
+             [workspace.metadata.release]\nsign-tag = true\npublish = false\n\
+             [workspace.metadata.xtask]\ndocs = true\n"
+            .parse()
+            .unwrap();
+        assert_eq!(merged, expected);
+
+        let theirs = "[workspace.metadata.dist]\ncargo-dist-version = \"0.22.0\"\n";
+        let err = merge_manifest_texts(base, ours, theirs).unwrap_err();
+        assert!(
+            matches!(&err, MergeError::Conflict(conflict)
+                if conflict.path == "workspace.metadata.dist.cargo-dist-version"),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Opt-in JSONL log of merge outcomes.
+//!
+//! Each merge appends one JSON object per line, so logs from many
+//! repositories (or many concurrent git merge drivers) can be concatenated and
+//! aggregated with standard tools. Nothing is written unless a log path is
+//! configured.
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::merge::{MergeConflict, MergeError};
+use crate::timings::Timings;
+
+/// How a merge ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Merged,
+    Conflict,
+    Error,
+}
+
+/// One line of the metrics log.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Subcommand that ran the merge, e.g. `merge-manifest`.
+    pub command: String,
+    /// The "ours" manifest being merged.
+    pub manifest: String,
+    pub outcome: Outcome,
+    /// What resolved a successful merge: `trivial`, `cache` or `semantic`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    pub conflicts: usize,
+    /// Paths of the conflicting values, e.g. `dependencies.serde`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflict_paths: Vec<String>,
+    /// Why an `error` outcome failed: `parse`, `depth_limit`, `schema`,
+    /// `cargo`, `git`, `io` or `license`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Milliseconds per phase, as reported by `--timings`.
+    pub durations_ms: BTreeMap<&'static str, f64>,
+}
+
+impl MergeRecord {
+    pub fn new(command: &str, manifest: &Path, outcome: Outcome) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        Self {
+            timestamp,
+            command: command.to_string(),
+            manifest: manifest.display().to_string(),
+            outcome,
+            resolution: None,
+            conflicts: usize::from(outcome == Outcome::Conflict),
+            conflict_paths: Vec::new(),
+            error: None,
+            durations_ms: BTreeMap::new(),
+        }
+    }
+
+    /// Record for a merge that failed with `err`.
+    pub fn failed(command: &str, manifest: &Path, err: &MergeError) -> Self {
+        let (outcome, kind) = match err {
+            MergeError::Conflict(_) | MergeError::Report(_) => (Outcome::Conflict, None),
+            MergeError::Parse(_) => (Outcome::Error, Some("parse")),
+            MergeError::DepthLimit { .. } => (Outcome::Error, Some("depth_limit")),
+            MergeError::Serialize { .. } => (Outcome::Error, Some("serialize")),
+        };
+        let mut record = Self::new(command, manifest, outcome);
+        match err {
+            MergeError::Conflict(conflict) => record.add_conflicts([conflict]),
+            MergeError::Report(report) => record.add_conflicts(&report.conflicts),
+            _ => {}
+        }
+        record.error = kind.map(str::to_string);
+        record
+    }
+
+    /// Record for a command that failed with `err`.
+    pub fn error(command: &str, manifest: &Path, err: &Error) -> Self {
+        let kind = match err {
+            Error::ConflictSet { conflicts } => {
+                let mut record = Self::new(command, manifest, Outcome::Conflict);
+                record.add_conflicts(conflicts.iter().map(|conflict| &conflict.conflict));
+                return record;
+            }
+            Error::Parse { .. } => "parse",
+            Error::Schema { .. } => "schema",
+            Error::Cargo { .. } | Error::CargoSet { .. } | Error::NoCargo => "cargo",
+            Error::Git { .. } => "git",
+            Error::Io { .. } => "io",
+            Error::License { .. } => "license",
+        };
+        let mut record = Self::new(command, manifest, Outcome::Error);
+        record.error = Some(kind.to_string());
+        record
+    }
+
+    /// Records `conflicts` and the conflicts related to them.
+    pub fn add_conflicts<'a>(&mut self, conflicts: impl IntoIterator<Item = &'a MergeConflict>) {
+        self.conflict_paths = conflicts
+            .into_iter()
+            .flat_map(|conflict| std::iter::once(conflict).chain(&conflict.related))
+            .map(|conflict| conflict.path.clone())
+            .collect();
+        self.conflicts = self.conflict_paths.len();
+    }
+
+    pub fn with_timings(mut self, timings: &Timings) -> Self {
+        self.durations_ms = timings
+            .phases()
+            .iter()
+            .map(|(phase, elapsed)| (*phase, elapsed.as_secs_f64() * 1e3))
+            .collect();
+        self
+    }
+}
+
+/// Appends `record` to the log at `path`, creating it if needed.
+pub fn append(path: &Path, record: &MergeRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record).map_err(io::Error::other)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // A single write keeps lines from concurrent drivers from interleaving.
+    file.write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::merge::merge_manifest_texts;
+
+    #[test]
+    fn appends_one_json_object_per_merge() {
+        let path = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-metrics-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let mut timings = Timings::default();
+        timings.record("parse", Duration::from_millis(2));
+        let mut merged =
+            MergeRecord::new("merge-manifest", Path::new("Cargo.toml"), Outcome::Merged)
+                .with_timings(&timings);
+        merged.resolution = Some("semantic".to_string());
+        append(&path, &merged).unwrap();
+
+        let err = merge_manifest_texts("a = 1\n", "a = 2\n", "a = 3\n").unwrap_err();
+        append(
+            &path,
+            &MergeRecord::failed("merge-all", Path::new("x/Cargo.toml"), &err),
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["outcome"], "merged");
+        assert_eq!(lines[0]["resolution"], "semantic");
+        assert_eq!(lines[0]["conflicts"], 0);
+        assert_eq!(lines[0]["durations_ms"]["parse"], 2.0);
+        assert_eq!(lines[1]["outcome"], "conflict");
+        assert_eq!(lines[1]["conflicts"], 1);
+        assert_eq!(lines[1]["conflict_paths"], serde_json::json!(["a"]));
+        assert!(lines[1].get("error").is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Dependencies moved between dependency tables.
+//!
+//! Moving `criterion` from `[dependencies]` to `[dev-dependencies]` deletes
+//! one entry and adds another. Merged key by key, a move on one side against
+//! an edit of the old entry on the other (a version bump) is a delete/modify
+//! conflict. Before merging, such an entry is moved in the base and on the
+//! other side too, so the edit merges into the new location (rule `move`).
+//! A move is an entry deleted from one dependency table, `[target.*]` ones
+//! included, and added under the same key to exactly one other, for the same
+//! package.
+
+use toml::{Table, Value};
+
+use crate::merge::Resolution;
+use crate::pins::{crate_name, dependency_tables, lookup, lookup_mut};
+
+/// Moves the entries one side moved in the base and on the other side,
+/// where the other side changed them. Returns the resolutions.
+pub(crate) fn follow(base: &mut Value, ours: &mut Value, theirs: &mut Value) -> Vec<Resolution> {
+    let tables: Vec<Vec<String>> = dependency_tables([base, ours, theirs])
+        .into_iter()
+        .filter(|table| table.first().map(String::as_str) != Some("workspace"))
+        .collect();
+    let entry = |doc: &Value, table: &[String], key: &str| {
+        lookup(doc, table)
+            .and_then(Value::as_table)
+            .and_then(|deps| deps.get(key))
+            .cloned()
+    };
+
+    // (table moved from, table moved to, key, whether ours is the one to follow)
+    let mut moves = Vec::new();
+    for from in &tables {
+        let Some(base_deps) = lookup(base, from).and_then(Value::as_table) else {
+            continue;
+        };
+        for (key, base_entry) in base_deps {
+            for (mover, other, ours_follows) in
+                [(&*ours, &*theirs, false), (&*theirs, &*ours, true)]
+            {
+                let Some(other_entry) = entry(other, from, key) else {
+                    continue;
+                };
+                if entry(mover, from, key).is_some() || other_entry == *base_entry {
+                    continue;
+                }
+                let targets: Vec<&Vec<String>> = tables
+                    .iter()
+                    .filter(|to| {
+                        entry(base, to, key).is_none()
+                            && entry(other, to, key).is_none()
+                            && entry(mover, to, key).is_some_and(|moved| {
+                                crate_name(&moved, key) == crate_name(base_entry, key)
+                            })
+                    })
+                    .collect();
+                if let [to] = targets[..] {
+                    moves.push((from.clone(), to.clone(), key.clone(), ours_follows));
+                }
+            }
+        }
+    }
+
+    let mut resolutions = Vec::new();
+    for (from, to, key, ours_follows) in moves {
+        let other = if ours_follows {
+            &mut *ours
+        } else {
+            &mut *theirs
+        };
+        for doc in [&mut *base, other] {
+            let Some(moved) = lookup_mut(doc, &from)
+                .and_then(Value::as_table_mut)
+                .and_then(|deps| deps.remove(&key))
+            else {
+                continue;
+            };
+            if let Some(deps) = table_mut(doc, &to) {
+                deps.insert(key.clone(), moved);
+            }
+        }
+        resolutions.push(Resolution {
+            path: format!("{}.{key}", to.join(".")),
+            side: None,
+            rule: "move",
+        });
+    }
+    resolutions
+}
+
+/// The table at `path` in `doc`, created when missing.
+fn table_mut<'a>(doc: &'a mut Value, path: &[String]) -> Option<&'a mut Table> {
+    path.iter().try_fold(doc.as_table_mut()?, |table, key| {
+        table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeOptions, merge_manifest_texts_detailed};
+    use crate::timings::Timings;
+
+    #[test]
+    fn applies_edits_to_moved_dependencies() {
+        let base = "[dependencies]\nserde = \"1\"\ncriterion = \"0.4\"\n";
+        let moved = "[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\ncriterion = \"0.4\"\n";
+        let bumped = "[dependencies]\nserde = \"1\"\ncriterion = \"0.5\"\n";
+        let options = MergeOptions::default();
+
+        let result =
+            merge_manifest_texts_detailed(base, moved, bumped, &options, &mut Timings::default())
+                .unwrap();
+        assert_eq!(
+            result.text,
+            "[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\ncriterion = \"0.5\"\n"
+        );
+        assert_eq!(result.resolutions[0].path, "dev-dependencies.criterion");
+        assert_eq!(result.resolutions[0].rule, "move");
+
+        let result =
+            merge_manifest_texts_detailed(base, bumped, moved, &options, &mut Timings::default())
+                .unwrap();
+        assert_eq!(
+            result.text,
+            "[dependencies]\nserde = \"1\"\n\n[dev-dependencies]\ncriterion = \"0.5\"\n"
+        );
+    }
+}
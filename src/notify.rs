@@ -0,0 +1,229 @@
+//! Conflict notifications (`[[notify]]` in the merge policy).
+//!
+//! In protected-branch automation (merge queues, release bots) a merge
+//! driver runs unattended, and a manifest conflict only shows up as a failed
+//! job. Every [`NotifyHook`] whose `branches` match the branch merged into is
+//! sent the summary report of a `merge-manifest` or `merge-all` run that
+//! ended in unresolved conflicts: as a Slack or Matrix (hookshot) message, or
+//! as a JSON object for other receivers. Reports are posted with `curl`; a
+//! failed post is a warning, never a failed merge.
+
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::{Value, json};
+
+use crate::merge::MergeConflict;
+use crate::policy::{Branches, HookFormat, NotifyHook};
+use crate::summary::{self, Outcome};
+
+/// Seconds a webhook gets to answer.
+const TIMEOUT_SECS: &str = "10";
+
+/// A merge that ended in unresolved conflicts.
+#[derive(Debug, Clone, Copy)]
+pub struct Report<'a> {
+    /// The manifest being merged (ours).
+    pub manifest: &'a str,
+    pub branches: &'a Branches,
+    /// [`Outcome::Conflict`] or [`Outcome::Conflicts`].
+    pub outcome: Outcome<'a>,
+}
+
+impl Report<'_> {
+    /// Every conflict, related ones included.
+    fn conflicts(&self) -> Vec<&MergeConflict> {
+        match self.outcome {
+            Outcome::Conflict(conflict) => {
+                std::iter::once(conflict).chain(&conflict.related).collect()
+            }
+            Outcome::Conflicts(conflicts) => conflicts.iter().collect(),
+            Outcome::Merged(_) | Outcome::Failed(_) => Vec::new(),
+        }
+    }
+
+    fn headline(&self) -> String {
+        let branch = |branch: &Option<String>| {
+            branch
+                .as_deref()
+                .map_or("an unknown branch".to_string(), |branch| {
+                    format!("`{branch}`")
+                })
+        };
+        format!(
+            "Unresolved conflicts in `{}` merging {} into {}",
+            self.manifest,
+            branch(&self.branches.theirs),
+            branch(&self.branches.ours)
+        )
+    }
+}
+
+/// A report could not be posted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyError {
+    /// The webhook's scheme and host; the rest of the URL often holds a
+    /// secret.
+    pub host: String,
+    pub detail: String,
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to notify {}: {}", self.host, self.detail)
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+/// Posts `report` to every hook applying to the branch merged into; returns
+/// the posts that failed.
+pub fn notify(hooks: &[NotifyHook], report: &Report<'_>) -> Vec<NotifyError> {
+    hooks
+        .iter()
+        .filter(|hook| hook.applies_to(report.branches.ours.as_deref()))
+        .filter_map(|hook| send(&hook.url, &payload(hook.format, report)).err())
+        .collect()
+}
+
+/// The body posted to a hook in `format`.
+pub fn payload(format: HookFormat, report: &Report<'_>) -> Value {
+    let table = || summary::markdown(&[(report.manifest.to_string(), report.outcome)]);
+    match format {
+        // Slack renders neither tables nor headings: list the paths instead.
+        HookFormat::Slack => {
+            let mut text = format!("*{}*", report.headline());
+            for conflict in report.conflicts() {
+                text.push_str(&format!(
+                    "\n• `{}` (`{}`)",
+                    conflict.path, conflict.fingerprint
+                ));
+                if !conflict.reviewers.is_empty() {
+                    text.push_str(&format!(" – {}", conflict.reviewers.join(" ")));
+                }
+            }
+            json!({ "text": text })
+        }
+        HookFormat::Matrix => json!({
+            "text": format!("{}\n\n{}", report.headline(), table()),
+        }),
+        HookFormat::Json => json!({
+            "event": "conflict",
+            "manifest": report.manifest,
+            "ours": report.branches.ours,
+            "theirs": report.branches.theirs,
+            "conflicts": report.conflicts(),
+            "summary": table(),
+        }),
+    }
+}
+
+/// POSTs `payload` as JSON to `url`.
+pub fn send(url: &str, payload: &Value) -> Result<(), NotifyError> {
+    let error = |detail: String| NotifyError {
+        host: host(url),
+        detail,
+    };
+    let mut child = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--output",
+            "/dev/null",
+        ])
+        .args(["--proto", "=http,https", "--max-time", TIMEOUT_SECS])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| error(format!("could not run curl: {err}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(payload.to_string().as_bytes())
+            .map_err(|err| error(format!("could not send the report: {err}")))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| error(format!("curl failed: {err}")))?;
+    if !output.status.success() {
+        // curl echoes the URL in some messages.
+        let stderr = String::from_utf8_lossy(&output.stderr).replace(url, &host(url));
+        return Err(error(stderr.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// `url` up to the end of its host, e.g. `https://hooks.slack.com`.
+fn host(url: &str) -> String {
+    let start = url.find("://").map_or(0, |index| index + 3);
+    let end = url[start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |index| start + index);
+    url[..end].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value as Toml;
+
+    use super::*;
+
+    #[test]
+    fn formats_reports_per_hook() {
+        let conflict = MergeConflict::new(
+            "dependencies.serde".to_string(),
+            Some(Toml::from("1")),
+            Some(Toml::from("1.0.200")),
+            Some(Toml::from("1.0.199")),
+        );
+        let branches = Branches {
+            ours: Some("main".to_string()),
+            theirs: Some("release/1.2".to_string()),
+        };
+        let report = Report {
+            manifest: "Cargo.toml",
+            branches: &branches,
+            outcome: Outcome::Conflict(&conflict),
+        };
+
+        let slack = payload(HookFormat::Slack, &report);
+        assert_eq!(
+            slack["text"],
+            format!(
+                "*Unresolved conflicts in `Cargo.toml` merging `release/1.2` into `main`*\n\
+                 • `dependencies.serde` (`{}`)",
+                conflict.fingerprint
+            )
+        );
+        let matrix = payload(HookFormat::Matrix, &report);
+        assert!(
+            matrix["text"]
+                .as_str()
+                .unwrap()
+                .contains("| `Cargo.toml` | **conflict** |"),
+            "{matrix}"
+        );
+        let json = payload(HookFormat::Json, &report);
+        assert_eq!(json["ours"], "main");
+        assert_eq!(json["conflicts"][0]["path"], "dependencies.serde");
+        assert_eq!(json["conflicts"][0]["theirs"], "1.0.199");
+
+        assert_eq!(
+            host("https://hooks.slack.com/services/T0/B0/secret"),
+            "https://hooks.slack.com"
+        );
+        let hook = NotifyHook {
+            url: "https://example.com/hook".to_string(),
+            format: HookFormat::Json,
+            branches: vec!["release/*".to_string()],
+        };
+        assert!(hook.applies_to(Some("release/1.2")));
+        assert!(!hook.applies_to(Some("main")));
+        assert!(!hook.applies_to(None));
+    }
+}
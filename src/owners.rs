@@ -0,0 +1,164 @@
+//! Suggested reviewers for unresolved conflicts (`[owners]` and
+//! `codeowners` in the merge policy).
+//!
+//! A conflict left for a person is routed to whoever owns the conflicting
+//! part of the manifest: the owners of every `[owners]` pattern matching its
+//! dotted path, or a table containing it. Conflicts no pattern covers fall
+//! back, with `codeowners = true`, to the owners the repository's CODEOWNERS
+//! file assigns the manifest itself.
+
+use std::fs;
+use std::path::Path;
+
+use ignore::gitignore::GitignoreBuilder;
+
+use crate::merge::{MergeConflict, matches_pattern};
+use crate::policy::Policy;
+
+/// Where GitHub and GitLab look for CODEOWNERS, in order.
+const CODEOWNERS_FILES: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Owners of the paths of one manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Owners {
+    /// `[owners]`: owners by dotted path pattern.
+    rules: Vec<(String, Vec<String>)>,
+    /// Owners of the manifest file, for paths no rule covers.
+    fallback: Vec<String>,
+}
+
+impl Owners {
+    /// The owners `policy` assigns within `manifest`, a path relative to
+    /// `repo` (the repository root).
+    pub fn new(policy: &Policy, repo: &Path, manifest: &Path) -> Self {
+        let rules = policy
+            .owners
+            .iter()
+            .flatten()
+            .map(|(pattern, owners)| (pattern.clone(), owners.clone()))
+            .collect();
+        let fallback = if policy.codeowners == Some(true) {
+            codeowners(repo, manifest)
+        } else {
+            Vec::new()
+        };
+        Self { rules, fallback }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.fallback.is_empty()
+    }
+
+    /// Owners of the value at the dotted `path`, without duplicates.
+    pub fn reviewers(&self, path: &str) -> Vec<String> {
+        let segments: Vec<&str> = path.split('.').collect();
+        let mut reviewers: Vec<String> = Vec::new();
+        for (pattern, owners) in &self.rules {
+            let matches = (1..=segments.len())
+                .any(|len| matches_pattern(pattern, &segments[..len].join(".")));
+            if matches {
+                for owner in owners {
+                    if !reviewers.contains(owner) {
+                        reviewers.push(owner.clone());
+                    }
+                }
+            }
+        }
+        if reviewers.is_empty() {
+            reviewers = self.fallback.clone();
+        }
+        reviewers
+    }
+
+    /// Sets the reviewers of `conflict` and of its related conflicts.
+    pub fn assign(&self, conflict: &mut MergeConflict) {
+        conflict.reviewers = self.reviewers(&conflict.path);
+        for related in &mut conflict.related {
+            self.assign(related);
+        }
+    }
+}
+
+/// Owners of `file` in the first CODEOWNERS file of `repo`: those of the
+/// last line matching it, as GitHub reads the file. Empty without a file or
+/// a matching line.
+pub fn codeowners(repo: &Path, file: &Path) -> Vec<String> {
+    let Some(text) = CODEOWNERS_FILES
+        .iter()
+        .find_map(|name| fs::read_to_string(repo.join(name)).ok())
+    else {
+        return Vec::new();
+    };
+    let file = file.strip_prefix(repo).unwrap_or(file);
+    let file = file.strip_prefix(".").unwrap_or(file);
+    let mut owners = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        let mut builder = GitignoreBuilder::new("");
+        let Ok(matcher) = builder.add_line(None, pattern).and_then(|b| b.build()) else {
+            continue;
+        };
+        if matcher.matched_path_or_any_parents(file, false).is_ignore() {
+            owners = fields
+                .take_while(|field| !field.starts_with('#'))
+                .map(str::to_string)
+                .collect();
+        }
+    }
+    owners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_conflicts_by_path_then_codeowners() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-owners-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(repo.join(".github")).unwrap();
+        fs::write(
+            repo.join(".github/CODEOWNERS"),
+            "* @org/everyone\n/crates/net/ @org/net # networking\ncrates/net/Cargo.toml @alice\n",
+        )
+        .unwrap();
+        let policy = Policy::parse(
+            "codeowners = true\n[owners]\n\"dependencies.tokio\" = [\"@org/async\"]\n\
+             \"dependencies.*\" = [\"@org/deps\", \"@org/async\"]\n",
+            "repo",
+        )
+        .unwrap();
+
+        let owners = Owners::new(&policy, &repo, Path::new("crates/net/Cargo.toml"));
+        assert_eq!(
+            owners.reviewers("dependencies.tokio.version"),
+            ["@org/deps", "@org/async"]
+        );
+        assert_eq!(owners.reviewers("package.version"), ["@alice"]);
+
+        let owners = Owners::new(&policy, &repo, Path::new("crates/net/src/Cargo.toml"));
+        assert_eq!(owners.reviewers("package.version"), ["@org/net"]);
+        let owners = Owners::new(&policy, &repo, Path::new("Cargo.toml"));
+        assert_eq!(owners.reviewers("package.version"), ["@org/everyone"]);
+
+        let mut conflict = MergeConflict::new("dependencies.tokio".into(), None, None, None);
+        conflict.related.push(MergeConflict::new(
+            "workspace.package.version".into(),
+            None,
+            None,
+            None,
+        ));
+        owners.assign(&mut conflict);
+        assert_eq!(conflict.reviewers, ["@org/deps", "@org/async"]);
+        assert_eq!(conflict.related[0].reviewers, ["@org/everyone"]);
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
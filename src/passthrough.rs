@@ -0,0 +1,166 @@
+//! Forward-compatible handling of top-level keys this tool does not know.
+//!
+//! New Cargo releases add manifest sections (and unstable features add more)
+//! long before a merge tool learns their schema. Guessing how to merge them
+//! key by key could silently combine edits that only make sense together, so
+//! an unknown top-level key is merged as one value: if only one side changed
+//! it, that side wins, and if both changed it differently it is a conflict.
+//!
+//! When theirs wins such a key, its text is copied into the output verbatim,
+//! with its comments and formatting, in the place ours had the key (or after
+//! everything else when ours did not have it).
+
+use toml::{Table, Value};
+
+use crate::merge::MergeConflict;
+
+/// Top-level keys of the Cargo manifest format that are merged semantically.
+pub const KNOWN_TOP_LEVEL_KEYS: &[&str] = &[
+    "badges",
+    "bench",
+    "bin",
+    "build-dependencies",
+    "build_dependencies",
+    "cargo-features",
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "example",
+    "features",
+    "lib",
+    "lints",
+    "package",
+    "patch",
+    "profile",
+    "project",
+    "replace",
+    "target",
+    "test",
+    "workspace",
+];
+
+/// Removes and returns the unknown top-level keys of `doc`.
+pub(crate) fn take_unknown(doc: &mut Value) -> Table {
+    let Some(table) = doc.as_table_mut() else {
+        return Table::new();
+    };
+    let unknown: Vec<String> = table
+        .keys()
+        .filter(|key| !KNOWN_TOP_LEVEL_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    unknown
+        .into_iter()
+        .filter_map(|key| table.remove(&key).map(|value| (key, value)))
+        .collect()
+}
+
+/// Three-way merges the unknown keys taken out of each side, each as one
+/// value. Conflicts are pushed to `collected` when given, keeping ours'
+/// value.
+pub(crate) fn merge(
+    [base, ours, theirs]: [&Table; 3],
+    mut collected: Option<&mut Vec<MergeConflict>>,
+) -> Result<Table, MergeConflict> {
+    let mut merged = Table::new();
+    for key in ours.keys().chain(theirs.keys()) {
+        if merged.contains_key(key) {
+            continue;
+        }
+        let [b, o, t] = [base, ours, theirs].map(|table| table.get(key));
+        let winner = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            let conflict = MergeConflict::new(key.to_string(), b.cloned(), o.cloned(), t.cloned());
+            match collected.as_deref_mut() {
+                Some(collected) => {
+                    collected.push(conflict);
+                    o
+                }
+                None => return Err(conflict),
+            }
+        };
+        if let Some(value) = winner {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, merge_manifest_texts};
+
+    const BASE: &str = r#"cargo-features = ["edition2024"]
+future-flag = { level = 2 } # keep me
+
+[package]
+name = "demo"
+version = "0.1.0"
+
+# Hints for a future cargo.
+[hints]
+mostly-unused = true
+note = """
+[not a header]
+"""
+
+[dependencies]
+serde = "1"
+
+[[unstable-section]]
+name = 'first'   # trailing comment
+list = [
+  "a",   # inside an array
+  "b",
+]
+"#;
+
+    #[test]
+    fn copies_unknown_sections_verbatim() {
+        let ours = BASE.replace("version = \"0.1.0\"", "version = \"0.2.0\"");
+        let theirs = BASE
+            .replace("serde = \"1\"", "serde = \"1\"\nlog = \"0.4\"")
+            .replace("mostly-unused = true", "mostly-unused   =   false");
+
+        let merged = merge_manifest_texts(BASE, &ours, &theirs).unwrap();
+        assert!(merged.contains("version = \"0.2.0\""), "{merged}");
+        assert!(merged.contains("log = \"0.4\""), "{merged}");
+
+        let hints_start = theirs.find("# Hints").unwrap();
+        let hints_end = theirs.find("[dependencies]").unwrap();
+        assert!(merged.contains(&theirs[hints_start..hints_end]), "{merged}");
+        let unstable = &BASE[BASE.find("[[unstable-section]]").unwrap()..];
+        assert!(merged.ends_with(unstable), "{merged}");
+        assert_eq!(merged, theirs.replace("0.1.0", "0.2.0"));
+
+        let original: Value = theirs.parse().unwrap();
+        let round_trip: Value = merged.parse().unwrap();
+        assert_eq!(round_trip["hints"], original["hints"]);
+        assert_eq!(round_trip["unstable-section"], original["unstable-section"]);
+        assert_eq!(round_trip["future-flag"], original["future-flag"]);
+    }
+
+    #[test]
+    fn merges_unknown_keys_as_whole_values() {
+        // Edits to different keys of an unknown table still conflict.
+        let ours = BASE.replace("mostly-unused = true", "mostly-unused = false");
+        let theirs = BASE.replace("note = \"\"\"", "extra = 1\nnote = \"\"\"");
+        let err = merge_manifest_texts(BASE, &ours, &theirs).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "hints");
+
+        // A side deleting an unknown section the other left alone wins.
+        let start = BASE.find("[[unstable-section]]").unwrap();
+        let ours = BASE[..start].to_string();
+        let theirs = BASE.replace("serde = \"1\"", "serde = \"1.0.200\"");
+        let merged = merge_manifest_texts(BASE, &ours, &theirs).unwrap();
+        assert!(!merged.contains("unstable-section"), "{merged}");
+        assert!(merged.contains("# Hints for a future cargo."), "{merged}");
+    }
+}
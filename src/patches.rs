@@ -0,0 +1,189 @@
+//! Checks of the merged `[patch]` sections.
+//!
+//! Branches often add `[patch.crates-io]` overrides while debugging, and the
+//! tables merge entry by entry like any other. What the merge cannot see is
+//! whether the union still makes sense: a patch whose crate the other side
+//! removed from the dependency graph no longer applies (cargo only warns
+//! about it once the lockfile is regenerated), and two entries patching the
+//! same crate to different git revisions leave it unclear which one the
+//! branches meant. Both are reported as warnings after `merge-manifest`.
+
+use std::fmt;
+
+use toml::Value;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// A questionable entry of the merged `[patch]` sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchWarning {
+    /// `patch.<source>.<key>` patches `name`, which neither the manifest's
+    /// dependencies nor the lockfile contain.
+    Unused {
+        source: String,
+        key: String,
+        name: String,
+    },
+    /// Entries patch `name` to different git revisions: the dotted path of
+    /// each entry, with the revision it names.
+    Revisions {
+        name: String,
+        entries: Vec<(String, String)>,
+    },
+}
+
+impl fmt::Display for PatchWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unused { source, key, name } => write!(
+                f,
+                "`patch.{source}.{key}` patches `{name}`, which is not in the dependency graph"
+            ),
+            Self::Revisions { name, entries } => {
+                write!(f, "`{name}` is patched to different git revisions:")?;
+                for (path, revision) in entries {
+                    write!(f, " `{path}` ({revision})")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Checks the `[patch]` sections of `manifest` against its dependencies and,
+/// when known, the packages of its lockfile `lock`.
+pub fn check(manifest: &Value, lock: Option<&Value>) -> Vec<PatchWarning> {
+    let Some(sources) = manifest.get("patch").and_then(Value::as_table) else {
+        return Vec::new();
+    };
+    let mut known: Vec<&str> = dependency_tables(manifest)
+        .into_iter()
+        .flat_map(|table| table.iter().map(|(key, spec)| crate_name(key, spec)))
+        .collect();
+    known.extend(
+        lock.and_then(|lock| lock.get("package"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|package| package.get("name")?.as_str()),
+    );
+
+    let mut warnings = Vec::new();
+    // Patched crates in order of appearance, with their git entries.
+    let mut revisions: Vec<(&str, Vec<(String, String)>)> = Vec::new();
+    for (source, table) in sources {
+        let Some(table) = table.as_table() else {
+            continue;
+        };
+        for (key, spec) in table {
+            let name = crate_name(key, spec);
+            if !known.contains(&name) {
+                warnings.push(PatchWarning::Unused {
+                    source: source.clone(),
+                    key: key.clone(),
+                    name: name.to_string(),
+                });
+            }
+            let Some(revision) = git_revision(spec) else {
+                continue;
+            };
+            let entry = (format!("patch.{source}.{key}"), revision);
+            match revisions.iter_mut().find(|(patched, _)| *patched == name) {
+                Some((_, entries)) => entries.push(entry),
+                None => revisions.push((name, vec![entry])),
+            }
+        }
+    }
+    for (name, entries) in revisions {
+        if entries
+            .iter()
+            .any(|(_, revision)| *revision != entries[0].1)
+        {
+            warnings.push(PatchWarning::Revisions {
+                name: name.to_string(),
+                entries,
+            });
+        }
+    }
+    warnings
+}
+
+/// The crate an entry under `key` refers to: its `package`, else the key.
+fn crate_name<'a>(key: &'a str, spec: &'a Value) -> &'a str {
+    spec.get("package").and_then(Value::as_str).unwrap_or(key)
+}
+
+/// The repository and revision a git entry points at, e.g.
+/// `https://github.com/serde-rs/serde rev 1a2b3c`; `None` for other entries.
+fn git_revision(spec: &Value) -> Option<String> {
+    let git = spec.get("git")?.as_str()?;
+    let reference = ["rev", "tag", "branch"]
+        .into_iter()
+        .find_map(|kind| Some(format!("{kind} {}", spec.get(kind)?.as_str()?)))
+        .unwrap_or_else(|| "default branch".to_string());
+    Some(format!("{git} {reference}"))
+}
+
+fn dependency_tables(doc: &Value) -> Vec<&toml::Table> {
+    let mut tables: Vec<&toml::Table> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|key| doc.get(*key)?.as_table())
+        .collect();
+    if let Some(targets) = doc.get("target").and_then(Value::as_table) {
+        for target in targets.values() {
+            tables.extend(
+                DEPENDENCY_TABLES
+                    .iter()
+                    .filter_map(|key| target.get(*key)?.as_table()),
+            );
+        }
+    }
+    tables.extend(
+        doc.get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(Value::as_table),
+    );
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_unused_patches_and_diverging_revisions() {
+        let manifest: Value = r#"
+[dependencies]
+serde = "1"
+json = { package = "serde_json", version = "1" }
+
+[patch.crates-io]
+serde = { git = "https://github.com/serde-rs/serde", rev = "1a2b3c" }
+serde-fork = { git = "https://github.com/serde-rs/serde", branch = "fix", package = "serde" }
+serde_json = { git = "https://github.com/serde-rs/json" }
+itoa = { path = "../itoa" }
+ryu = { path = "../ryu" }
+tokio = { git = "https://github.com/tokio-rs/tokio" }
+"#
+        .parse()
+        .unwrap();
+        let lock: Value = "[[package]]\nname = \"itoa\"\nversion = \"1.0.11\"\n"
+            .parse()
+            .unwrap();
+
+        let warnings = check(&manifest, Some(&lock));
+        let messages: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "`patch.crates-io.ryu` patches `ryu`, which is not in the dependency graph",
+                "`patch.crates-io.tokio` patches `tokio`, which is not in the dependency graph",
+                "`serde` is patched to different git revisions: \
+                 `patch.crates-io.serde` (https://github.com/serde-rs/serde rev 1a2b3c) \
+                 `patch.crates-io.serde-fork` (https://github.com/serde-rs/serde branch fix)",
+            ]
+        );
+        // Without a lockfile only direct dependencies are known.
+        assert_eq!(check(&manifest, None).len(), 4);
+    }
+}
@@ -0,0 +1,105 @@
+//! Normalization for user-supplied paths.
+//!
+//! On Windows, `--repo`/`--out` may arrive as verbatim (`\\?\C:\repo`) or
+//! drive-relative (`C:repo`) paths. Git and Cargo handle neither reliably, so
+//! both are turned into ordinary absolute paths before use. Elsewhere paths are
+//! returned unchanged.
+
+use std::path::{Path, PathBuf};
+
+/// Returns a form of `path` that is safe to hand to `git -C` and Cargo.
+pub fn normalize(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        windows::normalize(path)
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::path::{Component, Path, PathBuf, Prefix};
+
+    /// Paths at or beyond this length only work in verbatim form.
+    const MAX_PATH: usize = 260;
+
+    pub(super) fn normalize(path: &Path) -> PathBuf {
+        let path = strip_verbatim(path);
+        if is_drive_relative(&path) {
+            std::path::absolute(&path).unwrap_or(path)
+        } else {
+            path
+        }
+    }
+
+    fn strip_verbatim(path: &Path) -> PathBuf {
+        let Some(text) = path.to_str() else {
+            return path.to_path_buf();
+        };
+        let Some(Component::Prefix(prefix)) = path.components().next() else {
+            return path.to_path_buf();
+        };
+
+        let stripped = match prefix.kind() {
+            Prefix::VerbatimDisk(_) => text[r"\\?\".len()..].to_string(),
+            Prefix::VerbatimUNC(..) => format!(r"\\{}", &text[r"\\?\UNC\".len()..]),
+            _ => return path.to_path_buf(),
+        };
+        // Verbatim syntax is what makes long paths and `.`/`..` literals work;
+        // only drop it when the plain form means the same thing.
+        if stripped.len() >= MAX_PATH || stripped.split('\\').any(|c| c == "." || c == "..") {
+            return path.to_path_buf();
+        }
+        PathBuf::from(stripped)
+    }
+
+    fn is_drive_relative(path: &Path) -> bool {
+        let mut components = path.components();
+        matches!(
+            components.next(),
+            Some(Component::Prefix(prefix)) if matches!(prefix.kind(), Prefix::Disk(_))
+        ) && !matches!(components.next(), Some(Component::RootDir))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn strips_verbatim_disk_and_unc_prefixes() {
+            assert_eq!(
+                normalize(Path::new(r"\\?\C:\work\repo")),
+                PathBuf::from(r"C:\work\repo")
+            );
+            assert_eq!(
+                normalize(Path::new(r"\\?\UNC\server\share\repo")),
+                PathBuf::from(r"\\server\share\repo")
+            );
+        }
+
+        #[test]
+        fn keeps_verbatim_form_for_long_paths() {
+            let long = format!(r"\\?\C:\{}", "a".repeat(MAX_PATH));
+            assert_eq!(normalize(Path::new(&long)), PathBuf::from(long));
+        }
+
+        #[test]
+        fn resolves_drive_relative_paths() {
+            let resolved = normalize(Path::new("C:repo"));
+            assert!(resolved.is_absolute(), "{}", resolved.display());
+            assert!(resolved.ends_with("repo"));
+        }
+
+        #[test]
+        fn leaves_regular_paths_alone() {
+            assert_eq!(normalize(Path::new(r"C:\repo")), PathBuf::from(r"C:\repo"));
+            assert_eq!(
+                normalize(Path::new(r"repo\sub")),
+                PathBuf::from(r"repo\sub")
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Organization-wide version pins (`[pins]` in the merge policy).
+//!
+//! A pinned crate must stay at an exact version, e.g. a dependency frozen
+//! after a security review. When either side of a merge moves the
+//! requirement of a pinned dependency to anything but `=<pin>`, the move is
+//! overridden with the pin ([`PinMode::Override`], rule `pin`) or reported as
+//! a conflict ([`PinMode::Conflict`]). Requirements neither side touched are
+//! left alone, as are dependencies inherited from the workspace.
+
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+use crate::merge::{MergeConflict, MergeError, PinMode, Resolution};
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Enforces `pins` (crate name to exact version) on `ours` and `theirs`
+/// before they are merged. Returns the overridden requirements. Conflicts
+/// are pushed to `collected` when given.
+pub(crate) fn enforce(
+    mode: PinMode,
+    pins: &BTreeMap<String, String>,
+    base: &Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+    mut collected: Option<&mut Vec<MergeConflict>>,
+) -> Result<Vec<Resolution>, MergeError> {
+    let mut resolutions = Vec::new();
+    if pins.is_empty() {
+        return Ok(resolutions);
+    }
+    for table in dependency_tables([base, ours, theirs]) {
+        let mut names: Vec<String> = [&*ours, &*theirs]
+            .iter()
+            .filter_map(|doc| lookup(doc, &table).and_then(Value::as_table))
+            .flat_map(|deps| deps.keys().cloned())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        for name in names {
+            let mut path = table.clone();
+            path.push(name);
+            let entries = [base, &*ours, &*theirs].map(|doc| lookup(doc, &path));
+            let Some(pin) = entries
+                .iter()
+                .flatten()
+                .find_map(|entry| pins.get(crate_name(entry, &path[path.len() - 1])))
+            else {
+                continue;
+            };
+            let required = format!("={pin}");
+            let base_req = entries[0].and_then(requirement);
+            let moved = |entry: Option<&Value>| {
+                let req = entry.and_then(requirement);
+                req.is_some() && req != base_req && req != Some(required.as_str())
+            };
+            let (ours_moved, theirs_moved) = (moved(entries[1]), moved(entries[2]));
+            if !ours_moved && !theirs_moved {
+                continue;
+            }
+            if mode == PinMode::Conflict {
+                let conflict = MergeConflict::new(
+                    path.join("."),
+                    entries[0].cloned(),
+                    entries[1].cloned(),
+                    entries[2].cloned(),
+                );
+                match collected.as_deref_mut() {
+                    Some(collected) => {
+                        collected.push(conflict);
+                        continue;
+                    }
+                    None => return Err(MergeError::Conflict(conflict)),
+                }
+            }
+            for (doc, moved) in [(&mut *ours, ours_moved), (&mut *theirs, theirs_moved)] {
+                if moved && let Some(entry) = lookup_mut(doc, &path) {
+                    set_requirement(entry, &required);
+                }
+            }
+            resolutions.push(Resolution {
+                path: path.join("."),
+                side: None,
+                rule: "pin",
+            });
+        }
+    }
+    Ok(resolutions)
+}
+
+/// Key paths of every dependency table present in any of `docs`.
+pub(crate) fn dependency_tables(docs: [&Value; 3]) -> Vec<Vec<String>> {
+    let mut tables: Vec<Vec<String>> = DEPENDENCY_TABLES
+        .iter()
+        .map(|table| vec![table.to_string()])
+        .collect();
+    tables.push(vec!["workspace".to_string(), "dependencies".to_string()]);
+    let mut targets: Vec<&String> = docs
+        .iter()
+        .filter_map(|doc| doc.get("target").and_then(Value::as_table))
+        .flat_map(|targets| targets.keys())
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    for target in targets {
+        tables.extend(
+            DEPENDENCY_TABLES
+                .iter()
+                .map(|table| vec!["target".to_string(), target.clone(), table.to_string()]),
+        );
+    }
+    tables
+}
+
+pub(crate) fn lookup<'a>(doc: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(doc, |value, key| value.get(key))
+}
+
+pub(crate) fn lookup_mut<'a>(doc: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    path.iter().try_fold(doc, |value, key| value.get_mut(key))
+}
+
+/// The crate an entry refers to: its `package` when renamed, else its key.
+pub(crate) fn crate_name<'a>(entry: &'a Value, key: &'a str) -> &'a str {
+    entry.get("package").and_then(Value::as_str).unwrap_or(key)
+}
+
+fn requirement(entry: &Value) -> Option<&str> {
+    match entry {
+        Value::String(req) => Some(req),
+        Value::Table(table) => table.get("version").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+fn set_requirement(entry: &mut Value, required: &str) {
+    match entry {
+        Value::Table(table) => {
+            table.insert("version".to_string(), Value::String(required.to_string()));
+        }
+        other => *other = Value::String(required.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeError, MergeOptions, PinMode, merge_manifest_texts_with};
+
+    #[test]
+    fn overrides_or_flags_moves_of_pinned_crates() {
+        let base = "[dependencies]\nopenssl = \"=0.10.55\"\nlog = \"0.4\"\n\n\
+                    [target.'cfg(unix)'.dependencies]\nssl = { package = \"openssl\", version = \"=0.10.55\" }\n";
+        let ours = base.replace("log = \"0.4\"", "log = \"0.4.21\"");
+        let theirs = base.replace("=0.10.55", "0.10.66");
+        let mut options = MergeOptions::default();
+        options
+            .pins
+            .insert("openssl".to_string(), "0.10.55".to_string());
+
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("openssl = \"=0.10.55\""), "{merged}");
+        assert!(merged.contains("version = \"=0.10.55\""), "{merged}");
+        assert!(merged.contains("log = \"0.4.21\""), "{merged}");
+
+        // A one-sided change is enforced too, not taken verbatim.
+        let merged = merge_manifest_texts_with(base, base, &theirs, &options).unwrap();
+        assert!(!merged.contains("0.10.66"), "{merged}");
+
+        options.pin_mode = PinMode::Conflict;
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.openssl");
+    }
+}
@@ -0,0 +1,1295 @@
+//! Layered merge policy files.
+//!
+//! Settings are read, lowest precedence first, from:
+//!
+//! 1. built-in defaults;
+//! 2. an organization policy named by `git config merge.cargo-merge-assist.policy`
+//!    (a path, relative to the repository root, or an `https://` URL);
+//! 3. the repository's own `.cargo-merge-assist.toml`;
+//! 4. command-line flags.
+//!
+//! Because the organization layer comes from git config it can be set once in
+//! system or global config and picked up by every repository. Remote policies
+//! are cached under `<git common dir>/cargo-merge-assist/policy/` and
+//! refetched after [`URL_POLICY_TTL`]; when a refetch fails the cached copy
+//! is used. Unknown keys are ignored so older releases keep working with
+//! policies written for newer ones.
+//!
+//! `[[branch-rules]]` apply only when the branches being merged match, e.g.
+//! taking theirs for `package.version` when a `release/*` branch is merged
+//! into `main`; see [`Branches::detect`] for how the branches are found.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+use toml::Value;
+
+use crate::cache::git_common_dir;
+use crate::cleanup;
+use crate::merge::{
+    DescriptionMode, LicenseMode, LintInheritance, LintLevels, MergeOptions, PathStrategy, PinMode,
+    Preference, ProfileMode, ProfileRules, PublishMode, RenameDelete, RustVersionMode, Side,
+    matches_pattern,
+};
+use crate::reconcile::ReconcileMode;
+use crate::strategy::Strategy;
+use crate::style::OutputStyle;
+
+/// Repository-local policy file, at the repository root.
+pub const REPO_POLICY_FILE: &str = ".cargo-merge-assist.toml";
+/// Git config key naming the organization policy.
+pub const POLICY_GIT_KEY: &str = "merge.cargo-merge-assist.policy";
+/// How long a fetched remote policy is used before it is fetched again.
+pub const URL_POLICY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Merge settings from one or more policy layers; `None` means "not set".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    pub max_depth: Option<usize>,
+    pub min_confidence: Option<f64>,
+    /// Post-batch workspace reconciliation (`off`, `report`, `highest`).
+    pub reconcile: Option<ReconcileMode>,
+    /// Paths skipped when scanning for manifests (`.gitignore` syntax).
+    pub exclude: Option<Vec<String>>,
+    /// Paths scanned even if excluded by default or by `exclude`.
+    pub include: Option<Vec<String>>,
+    /// Members adopting `[workspace.lints]` (`inherit`, `conflict`).
+    pub lint_inheritance: Option<LintInheritance>,
+    /// Both sides changed the level of a lint (`strictest`, `loosest`,
+    /// `conflict`).
+    pub lint_levels: Option<LintLevels>,
+    /// Sides to prefer when merging particular branches.
+    pub branch_rules: Option<Vec<BranchRule>>,
+    /// Paths whose conflicts are never resolved automatically.
+    pub protected: Option<Vec<String>>,
+    /// SPDX identifiers dependencies a merge adds may be licensed under.
+    pub allowed_licenses: Option<Vec<String>>,
+    /// `[pins]`: exact versions crates must stay at, by crate name.
+    pub pins: Option<BTreeMap<String, String>>,
+    /// Moves of pinned crates are overridden or conflicts (`override`,
+    /// `conflict`).
+    pub pin_mode: Option<PinMode>,
+    /// A dependency renamed on one side and deleted on the other
+    /// (`conflict`, `delete`, `rename`).
+    pub rename_delete: Option<RenameDelete>,
+    /// Both sides changed `rust-version` (`conflict`, `max`, `min`).
+    pub rust_version: Option<RustVersionMode>,
+    /// `package.publish`: two different `publish` changes (`restrictive`,
+    /// `conflict`).
+    pub publish: Option<PublishMode>,
+    /// `package.license`: conflicting license changes (`manual`, `conflict`).
+    pub license: Option<LicenseMode>,
+    /// `package.description`: two different descriptions (`longer`,
+    /// `conflict`).
+    pub description: Option<DescriptionMode>,
+    /// `[profile]`: handling of two different changes to a profile setting,
+    /// by setting (`opt-level`, `codegen-units`, `debug`, `lto`, `strip`).
+    pub profile: Option<BTreeMap<String, ProfileMode>>,
+    /// `build-dependencies.min-confidence`: threshold for build dependencies.
+    pub build_min_confidence: Option<f64>,
+    /// `build-dependencies.prefer`: side conflicting build dependencies
+    /// resolve to.
+    pub build_prefer: Option<Side>,
+    /// `[strategies]`: strategy resolving conflicts at matching paths, by
+    /// dotted path pattern.
+    pub strategies: Option<BTreeMap<String, Strategy>>,
+    /// `[style]`: formatting enforced on merged manifests.
+    pub style: Option<OutputStyle>,
+    /// `[[notify]]`: webhooks told about merges ending in conflicts.
+    pub notify: Option<Vec<NotifyHook>>,
+    /// `[owners]`: reviewers suggested for conflicts at matching paths, by
+    /// dotted path pattern.
+    pub owners: Option<BTreeMap<String, Vec<String>>>,
+    /// Suggest the CODEOWNERS owners of the manifest for conflicts no
+    /// `[owners]` pattern covers.
+    pub codeowners: Option<bool>,
+}
+
+/// Paths [`Policy::build_prefer`] applies to.
+pub const BUILD_DEPENDENCY_PATHS: [&str; 2] =
+    ["build-dependencies.*", "target.*.build-dependencies.*"];
+
+/// `[[branch-rules]]`: when `theirs` is merged into `ours`, conflicts at
+/// `paths` resolve to `prefer`. Branch and path patterns may use `*`; an
+/// omitted branch pattern matches any branch, known or not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchRule {
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+    pub prefer: Side,
+    /// Dotted manifest paths, e.g. `package.version` or `dependencies.*`.
+    pub paths: Vec<String>,
+}
+
+/// `[[notify]]`: a webhook receiving a report of every `merge-manifest` or
+/// `merge-all` run that ends in unresolved conflicts while merging into one
+/// of `branches`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyHook {
+    /// `http://` or `https://` URL the report is posted to.
+    pub url: String,
+    pub format: HookFormat,
+    /// Patterns (may use `*`) of the branches merged into that are reported;
+    /// empty for every branch.
+    pub branches: Vec<String>,
+}
+
+impl NotifyHook {
+    /// Whether a merge into `branch` is reported.
+    pub fn applies_to(&self, branch: Option<&str>) -> bool {
+        self.branches.is_empty()
+            || branch.is_some_and(|branch| {
+                self.branches
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, branch))
+            })
+    }
+}
+
+/// Body posted to a [`NotifyHook`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HookFormat {
+    /// Slack incoming webhook message (`{"text": ...}`).
+    Slack,
+    /// Matrix hookshot generic webhook message (`{"text": ...}`, Markdown).
+    Matrix,
+    /// The report as a JSON object.
+    #[default]
+    Json,
+}
+
+impl std::str::FromStr for HookFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "slack" => Ok(Self::Slack),
+            "matrix" => Ok(Self::Matrix),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown notify format `{other}` (expected `slack`, `matrix` or `json`)"
+            )),
+        }
+    }
+}
+
+/// The branches being merged, where known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Branches {
+    /// The branch merged into.
+    pub ours: Option<String>,
+    /// The branch being merged.
+    pub theirs: Option<String>,
+}
+
+impl Policy {
+    /// Parses a policy file. `origin` names it in errors.
+    pub fn parse(text: &str, origin: &str) -> Result<Self, PolicyError> {
+        let invalid = |detail: String| PolicyError::Invalid {
+            origin: origin.to_string(),
+            detail,
+        };
+        let doc: Value = text
+            .parse()
+            .map_err(|err: toml::de::Error| invalid(err.message().to_string()))?;
+
+        let max_depth = match doc.get("max-depth") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_integer()
+                    .and_then(|depth| usize::try_from(depth).ok())
+                    .ok_or_else(|| invalid("`max-depth` must be a non-negative integer".into()))?,
+            ),
+        };
+        let min_confidence = match doc.get("min-confidence") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|n| n as f64))
+                    .filter(|confidence| (0.0..=1.0).contains(confidence))
+                    .ok_or_else(|| invalid("`min-confidence` must be between 0 and 1".into()))?,
+            ),
+        };
+        let reconcile = match doc.get("reconcile") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`reconcile` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let lint_inheritance = match doc.get("lint-inheritance") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`lint-inheritance` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let lint_levels = match doc.get("lint-levels") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`lint-levels` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let patterns = |key: &str| match doc.get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .as_array()
+                .and_then(|patterns| {
+                    patterns
+                        .iter()
+                        .map(|pattern| pattern.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map(Some)
+                .ok_or_else(|| invalid(format!("`{key}` must be an array of strings"))),
+        };
+        let branch_rules = match doc.get("branch-rules") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_array()
+                    .ok_or_else(|| "`branch-rules` must be an array of tables".to_string())
+                    .and_then(|rules| rules.iter().map(branch_rule).collect())
+                    .map_err(invalid)?,
+            ),
+        };
+        let notify = match doc.get("notify") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_array()
+                    .ok_or_else(|| "`notify` must be an array of tables".to_string())
+                    .and_then(|hooks| hooks.iter().map(notify_hook).collect())
+                    .map_err(invalid)?,
+            ),
+        };
+        let pin_mode = match doc.get("pin-mode") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`pin-mode` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let rename_delete = match doc.get("rename-delete") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`rename-delete` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let rust_version = match doc.get("rust-version") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`rust-version` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        let pins = match doc.get("pins") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_table()
+                    .ok_or_else(|| "`pins` must be a table".to_string())
+                    .and_then(|pins| {
+                        pins.iter()
+                            .map(|(name, version)| pin(name, version))
+                            .collect()
+                    })
+                    .map_err(invalid)?,
+            ),
+        };
+        let strategies = match doc.get("strategies") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_table()
+                    .ok_or_else(|| "`strategies` must be a table".to_string())
+                    .and_then(|strategies| {
+                        strategies
+                            .iter()
+                            .map(|(path, strategy)| path_strategy(path, strategy))
+                            .collect()
+                    })
+                    .map_err(invalid)?,
+            ),
+        };
+        let owners = match doc.get("owners") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_table()
+                    .ok_or_else(|| "`owners` must be a table".to_string())
+                    .and_then(|owners| {
+                        owners
+                            .iter()
+                            .map(|(path, owners)| path_owners(path, owners))
+                            .collect()
+                    })
+                    .map_err(invalid)?,
+            ),
+        };
+        let codeowners = match doc.get("codeowners") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_bool()
+                    .ok_or_else(|| invalid("`codeowners` must be a boolean".into()))?,
+            ),
+        };
+        let style = match doc.get("style") {
+            None => None,
+            Some(value) => Some(style(value).map_err(invalid)?),
+        };
+        let package = doc.get("package");
+        if package.is_some_and(|package| !package.is_table()) {
+            return Err(invalid("`package` must be a table".into()));
+        }
+        let package_mode = |key: &str| match package.and_then(|package| package.get(key)) {
+            None => Ok(None),
+            Some(value) => value
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| invalid(format!("`package.{key}` must be a string"))),
+        };
+        let publish = match package_mode("publish")? {
+            None => None,
+            Some(mode) => Some(mode.parse().map_err(invalid)?),
+        };
+        let license = match package_mode("license")? {
+            None => None,
+            Some(mode) => Some(mode.parse().map_err(invalid)?),
+        };
+        let description = match package_mode("description")? {
+            None => None,
+            Some(mode) => Some(mode.parse().map_err(invalid)?),
+        };
+        let profile = match doc.get("profile") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_table()
+                    .ok_or_else(|| "`profile` must be a table".to_string())
+                    .and_then(|settings| {
+                        settings
+                            .iter()
+                            .map(|(setting, mode)| profile_mode(setting, mode))
+                            .collect()
+                    })
+                    .map_err(invalid)?,
+            ),
+        };
+        let build = doc.get("build-dependencies");
+        if build.is_some_and(|build| !build.is_table()) {
+            return Err(invalid("`build-dependencies` must be a table".into()));
+        }
+        let build_min_confidence = match build.and_then(|build| build.get("min-confidence")) {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|n| n as f64))
+                    .filter(|confidence| (0.0..=1.0).contains(confidence))
+                    .ok_or_else(|| {
+                        invalid(
+                            "`build-dependencies.min-confidence` must be between 0 and 1".into(),
+                        )
+                    })?,
+            ),
+        };
+        let build_prefer = match build.and_then(|build| build.get("prefer")) {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| "`build-dependencies.prefer` must be a string".to_string())
+                    .and_then(str::parse)
+                    .map_err(invalid)?,
+            ),
+        };
+        Ok(Self {
+            max_depth,
+            min_confidence,
+            reconcile,
+            exclude: patterns("exclude")?,
+            include: patterns("include")?,
+            lint_inheritance,
+            lint_levels,
+            branch_rules,
+            protected: patterns("protected")?,
+            allowed_licenses: patterns("allowed-licenses")?,
+            pins,
+            pin_mode,
+            rename_delete,
+            rust_version,
+            publish,
+            license,
+            description,
+            profile,
+            build_min_confidence,
+            build_prefer,
+            strategies,
+            style,
+            notify,
+            owners,
+            codeowners,
+        })
+    }
+
+    /// `self` with every setting that `upper` defines replaced by `upper`'s.
+    /// Pins are combined per crate, so a repository can add pins but only
+    /// change, not drop, the organization's; strategies and owners likewise
+    /// per path, profile rules per setting. Protected paths and webhooks of
+    /// both layers apply.
+    pub fn overlay(self, upper: Policy) -> Policy {
+        let pins = match (self.pins, upper.pins) {
+            (Some(mut pins), Some(upper)) => {
+                pins.extend(upper);
+                Some(pins)
+            }
+            (pins, upper) => upper.or(pins),
+        };
+        let strategies = match (self.strategies, upper.strategies) {
+            (Some(mut strategies), Some(upper)) => {
+                strategies.extend(upper);
+                Some(strategies)
+            }
+            (strategies, upper) => upper.or(strategies),
+        };
+        let profile = match (self.profile, upper.profile) {
+            (Some(mut profile), Some(upper)) => {
+                profile.extend(upper);
+                Some(profile)
+            }
+            (profile, upper) => upper.or(profile),
+        };
+        let owners = match (self.owners, upper.owners) {
+            (Some(mut owners), Some(upper)) => {
+                owners.extend(upper);
+                Some(owners)
+            }
+            (owners, upper) => upper.or(owners),
+        };
+        let protected = match (self.protected, upper.protected) {
+            (Some(mut protected), Some(upper)) => {
+                protected.extend(upper);
+                Some(protected)
+            }
+            (protected, upper) => upper.or(protected),
+        };
+        let notify = match (self.notify, upper.notify) {
+            (Some(mut notify), Some(upper)) => {
+                notify.extend(upper);
+                Some(notify)
+            }
+            (notify, upper) => upper.or(notify),
+        };
+        Policy {
+            max_depth: upper.max_depth.or(self.max_depth),
+            min_confidence: upper.min_confidence.or(self.min_confidence),
+            reconcile: upper.reconcile.or(self.reconcile),
+            exclude: upper.exclude.or(self.exclude),
+            include: upper.include.or(self.include),
+            lint_inheritance: upper.lint_inheritance.or(self.lint_inheritance),
+            lint_levels: upper.lint_levels.or(self.lint_levels),
+            branch_rules: upper.branch_rules.or(self.branch_rules),
+            protected,
+            allowed_licenses: upper.allowed_licenses.or(self.allowed_licenses),
+            pins,
+            pin_mode: upper.pin_mode.or(self.pin_mode),
+            rename_delete: upper.rename_delete.or(self.rename_delete),
+            rust_version: upper.rust_version.or(self.rust_version),
+            publish: upper.publish.or(self.publish),
+            license: upper.license.or(self.license),
+            description: upper.description.or(self.description),
+            profile,
+            build_min_confidence: upper.build_min_confidence.or(self.build_min_confidence),
+            build_prefer: upper.build_prefer.or(self.build_prefer),
+            strategies,
+            style: upper.style.or(self.style),
+            notify,
+            owners,
+            codeowners: upper.codeowners.or(self.codeowners),
+        }
+    }
+
+    pub fn merge_options(&self) -> MergeOptions {
+        let mut options = MergeOptions::default();
+        if let Some(max_depth) = self.max_depth {
+            options.max_depth = max_depth;
+        }
+        if let Some(min_confidence) = self.min_confidence {
+            options.min_confidence = min_confidence;
+        }
+        if let Some(lint_inheritance) = self.lint_inheritance {
+            options.lint_inheritance = lint_inheritance;
+        }
+        if let Some(lint_levels) = self.lint_levels {
+            options.lint_levels = lint_levels;
+        }
+        if let Some(protected) = &self.protected {
+            options.protected = protected.clone();
+        }
+        if let Some(pins) = &self.pins {
+            options.pins = pins.clone();
+        }
+        if let Some(pin_mode) = self.pin_mode {
+            options.pin_mode = pin_mode;
+        }
+        if let Some(rename_delete) = self.rename_delete {
+            options.rename_delete = rename_delete;
+        }
+        if let Some(rust_version) = self.rust_version {
+            options.rust_version = rust_version;
+        }
+        if let Some(publish) = self.publish {
+            options.publish = publish;
+        }
+        if let Some(license) = self.license {
+            options.license = license;
+        }
+        if let Some(description) = self.description {
+            options.description = description;
+        }
+        for (setting, mode) in self.profile.iter().flatten() {
+            if let Some(slot) = options.profile.mode_mut(setting) {
+                *slot = *mode;
+            }
+        }
+        options.build_min_confidence = self.build_min_confidence;
+        options.style = self.style.clone();
+        if let Some(strategies) = &self.strategies {
+            let mut strategies: Vec<(&String, &Strategy)> = strategies.iter().collect();
+            // Exact paths first, then the patterns with the most literal
+            // characters, so `dependencies.serde` wins over `dependencies.*`.
+            strategies.sort_by_key(|(path, _)| {
+                (path.contains('*'), Reverse(path.replace('*', "").len()))
+            });
+            options.strategies = strategies
+                .into_iter()
+                .map(|(path, strategy)| PathStrategy {
+                    path: path.clone(),
+                    heuristic: strategy.heuristic(),
+                    name: strategy.to_string(),
+                })
+                .collect();
+        }
+        options
+    }
+
+    /// Preferences from the branch rules matching `branches`, in policy
+    /// order, then from `build-dependencies.prefer`.
+    pub fn preferences(&self, branches: &Branches) -> Vec<Preference> {
+        let matches = |pattern: &Option<String>, branch: &Option<String>| match (pattern, branch) {
+            (None, _) => true,
+            (Some(pattern), Some(branch)) => matches_pattern(pattern, branch),
+            (Some(_), None) => false,
+        };
+        self.branch_rules
+            .iter()
+            .flatten()
+            .filter(|rule| {
+                matches(&rule.ours, &branches.ours) && matches(&rule.theirs, &branches.theirs)
+            })
+            .flat_map(|rule| {
+                rule.paths.iter().map(|path| Preference {
+                    path: path.clone(),
+                    side: rule.prefer,
+                    rule: "branch-policy",
+                })
+            })
+            .chain(self.build_prefer.into_iter().flat_map(build_preferences))
+            .collect()
+    }
+}
+
+/// Preferences resolving every conflicting build dependency to `side`.
+pub fn build_preferences(side: Side) -> impl Iterator<Item = Preference> {
+    BUILD_DEPENDENCY_PATHS
+        .into_iter()
+        .map(move |path| Preference {
+            path: path.to_string(),
+            side,
+            rule: "build-policy",
+        })
+}
+
+fn pin(name: &str, version: &Value) -> Result<(String, String), String> {
+    let version = version
+        .as_str()
+        .ok_or_else(|| format!("`pins.{name}` must be a string"))?;
+    let version = version.strip_prefix('=').unwrap_or(version);
+    semver::Version::parse(version)
+        .map_err(|err| format!("`pins.{name}` is not an exact version: {err}"))?;
+    Ok((name.to_string(), version.to_string()))
+}
+
+fn notify_hook(value: &Value) -> Result<NotifyHook, String> {
+    let table = value.as_table().ok_or("`notify` entries must be tables")?;
+    let url = table
+        .get("url")
+        .and_then(Value::as_str)
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .ok_or("`notify.url` must be an http:// or https:// URL")?
+        .to_string();
+    let format = match table.get("format") {
+        None => HookFormat::default(),
+        Some(format) => format
+            .as_str()
+            .ok_or_else(|| "`notify.format` must be a string".to_string())?
+            .parse()?,
+    };
+    let branches = match table.get("branches") {
+        None => Vec::new(),
+        Some(branches) => branches
+            .as_array()
+            .and_then(|branches| {
+                branches
+                    .iter()
+                    .map(|branch| branch.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or("`notify.branches` must be an array of strings")?,
+    };
+    Ok(NotifyHook {
+        url,
+        format,
+        branches,
+    })
+}
+
+fn style(value: &Value) -> Result<OutputStyle, String> {
+    let table = value.as_table().ok_or("`style` must be a table")?;
+    let mut style = OutputStyle::default();
+    if let Some(width) = table.get("column-width") {
+        style.column_width = width
+            .as_integer()
+            .and_then(|width| usize::try_from(width).ok())
+            .ok_or("`style.column-width` must be a non-negative integer")?;
+    }
+    if let Some(indent) = table.get("indent") {
+        style.indent = indent
+            .as_str()
+            .filter(|indent| indent.chars().all(|c| c == ' ' || c == '\t'))
+            .ok_or("`style.indent` must be a string of spaces or tabs")?
+            .to_string();
+    }
+    if let Some(trailing_comma) = table.get("trailing-comma") {
+        style.trailing_comma = trailing_comma
+            .as_bool()
+            .ok_or("`style.trailing-comma` must be a boolean")?;
+    }
+    if let Some(newline) = table.get("newline") {
+        style.newline = Some(
+            newline
+                .as_str()
+                .ok_or_else(|| "`style.newline` must be a string".to_string())?
+                .parse()?,
+        );
+    }
+    Ok(style)
+}
+
+fn path_strategy(path: &str, strategy: &Value) -> Result<(String, Strategy), String> {
+    let strategy = strategy
+        .as_str()
+        .ok_or_else(|| format!("`strategies.\"{path}\"` must be a string"))?;
+    Ok((path.to_string(), strategy.parse()?))
+}
+
+fn profile_mode(setting: &str, mode: &Value) -> Result<(String, ProfileMode), String> {
+    if ProfileRules::default().mode(setting).is_none() {
+        return Err(format!(
+            "`profile.{setting}` has no rule (expected `opt-level`, `codegen-units`, `debug`, `lto` or `strip`)"
+        ));
+    }
+    let mode = mode
+        .as_str()
+        .ok_or_else(|| format!("`profile.{setting}` must be a string"))?;
+    Ok((setting.to_string(), mode.parse()?))
+}
+
+fn path_owners(path: &str, owners: &Value) -> Result<(String, Vec<String>), String> {
+    let owners = owners
+        .as_array()
+        .and_then(|owners| {
+            owners
+                .iter()
+                .map(|owner| owner.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| format!("`owners.\"{path}\"` must be an array of strings"))?;
+    Ok((path.to_string(), owners))
+}
+
+fn branch_rule(value: &Value) -> Result<BranchRule, String> {
+    let table = value
+        .as_table()
+        .ok_or("`branch-rules` entries must be tables")?;
+    let string = |key: &str| match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|text| Some(text.to_string()))
+            .ok_or(format!("`branch-rules.{key}` must be a string")),
+    };
+    let prefer = match string("prefer")?.as_deref() {
+        Some("base") => Side::Base,
+        Some("ours") => Side::Ours,
+        Some("theirs") => Side::Theirs,
+        _ => return Err("`branch-rules.prefer` must be \"base\", \"ours\" or \"theirs\"".into()),
+    };
+    let paths = table
+        .get("paths")
+        .and_then(Value::as_array)
+        .and_then(|paths| {
+            paths
+                .iter()
+                .map(|path| path.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|paths| !paths.is_empty())
+        .ok_or("`branch-rules.paths` must be a non-empty array of strings")?;
+    Ok(BranchRule {
+        ours: string("ours")?,
+        theirs: string("theirs")?,
+        prefer,
+        paths,
+    })
+}
+
+impl Branches {
+    /// The branches for a merge in `repo`. Labels (git's `%X`/`%Y`) win
+    /// unless they are the placeholder `HEAD`; otherwise ours is the
+    /// checked-out branch and theirs the name git passes to merge drivers
+    /// in a `GITHEAD_<commit>` environment variable.
+    pub fn detect(repo: &Path, ours_label: Option<&str>, theirs_label: Option<&str>) -> Self {
+        let label = |label: Option<&str>| {
+            label
+                .filter(|label| !label.is_empty() && *label != "HEAD")
+                .map(str::to_string)
+        };
+        let ours = label(ours_label).or_else(|| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["symbolic-ref", "--short", "-q", "HEAD"])
+                .output()
+                .ok()?;
+            let branch = String::from_utf8(output.stdout).ok()?.trim().to_string();
+            (output.status.success() && !branch.is_empty()).then_some(branch)
+        });
+        let theirs = label(theirs_label).or_else(|| {
+            let mut heads = env::vars()
+                .filter(|(key, value)| key.starts_with("GITHEAD_") && value != "HEAD")
+                .map(|(_, value)| value);
+            // Ambiguous with several (an octopus merge).
+            heads.next().filter(|_| heads.next().is_none())
+        });
+        Branches { ours, theirs }
+    }
+}
+
+/// The effective policy of a repository.
+#[derive(Debug, Clone, Default)]
+pub struct LoadedPolicy {
+    pub policy: Policy,
+    /// Problems that did not prevent loading, e.g. a stale remote policy.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    Read { origin: String, detail: String },
+    Invalid { origin: String, detail: String },
+    Fetch { url: String, detail: String },
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { origin, detail } => write!(f, "failed reading policy {origin}: {detail}"),
+            Self::Invalid { origin, detail } => write!(f, "invalid policy {origin}: {detail}"),
+            Self::Fetch { url, detail } => write!(f, "failed to fetch policy {url}: {detail}"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Loads the organization and repository layers for `repo`. Without
+/// `allow_fetch` remote policies come only from the local cache.
+pub fn load(repo: &Path, allow_fetch: bool) -> Result<LoadedPolicy, PolicyError> {
+    let mut loaded = LoadedPolicy::default();
+
+    if let Some(location) = git_config_value(repo, POLICY_GIT_KEY) {
+        if location.starts_with("http://") {
+            return Err(PolicyError::Fetch {
+                url: location,
+                detail: "plain HTTP is refused; use an https:// URL".to_string(),
+            });
+        }
+        let text = if is_url(&location) {
+            remote_policy(repo, &location, allow_fetch, &mut loaded.warnings)?
+        } else {
+            let path = repo.join(&location);
+            fs::read_to_string(&path).map_err(|err| PolicyError::Read {
+                origin: path.display().to_string(),
+                detail: err.to_string(),
+            })?
+        };
+        loaded.policy = Policy::parse(&text, &location)?;
+    }
+
+    let local = repo.join(REPO_POLICY_FILE);
+    match fs::read_to_string(&local) {
+        Ok(text) => {
+            let layer = Policy::parse(&text, &local.display().to_string())?;
+            loaded.policy = loaded.policy.overlay(layer);
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(PolicyError::Read {
+                origin: local.display().to_string(),
+                detail: err.to_string(),
+            });
+        }
+    }
+
+    Ok(loaded)
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://")
+}
+
+fn remote_policy(
+    repo: &Path,
+    url: &str,
+    allow_fetch: bool,
+    warnings: &mut Vec<String>,
+) -> Result<String, PolicyError> {
+    let cached = git_common_dir(repo).map(|dir| {
+        dir.join("cargo-merge-assist")
+            .join("policy")
+            .join(cache_name(url))
+    });
+    let cached_text = cached
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok());
+    let fresh = cached
+        .as_ref()
+        .and_then(|path| fs::metadata(path).ok()?.modified().ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < URL_POLICY_TTL);
+
+    if let Some(text) = &cached_text
+        && (fresh || !allow_fetch)
+    {
+        return Ok(text.clone());
+    }
+    if !allow_fetch {
+        return Err(PolicyError::Fetch {
+            url: url.to_string(),
+            detail: "not cached and fetching is disabled".to_string(),
+        });
+    }
+
+    match fetch(url) {
+        Ok(text) => {
+            // Reject a broken download before it replaces a good cached copy.
+            Policy::parse(&text, url)?;
+            if let Some(path) = &cached
+                && let Err(err) = store(path, &text)
+            {
+                warnings.push(format!("could not cache policy {url}: {err}"));
+            }
+            Ok(text)
+        }
+        Err(err) => match cached_text {
+            Some(text) => {
+                warnings.push(format!("{err}; using cached copy"));
+                Ok(text)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+fn store(path: &Path, text: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    cleanup::write_atomic(path, text)
+}
+
+fn cache_name(url: &str) -> String {
+    let digest = Sha256::digest(url.as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{hex}.toml")
+}
+
+fn fetch(url: &str) -> Result<String, PolicyError> {
+    let error = |detail: String| PolicyError::Fetch {
+        url: url.to_string(),
+        detail,
+    };
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        // Neither the URL nor a redirect may leave HTTPS.
+        .args(["--proto", "=https", "--proto-redir", "=https"])
+        .args(["--max-time", "30"])
+        .arg(url)
+        .output()
+        .map_err(|err| error(format!("could not run curl: {err}")))?;
+    if !output.status.success() {
+        return Err(error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|_| error("policy is not UTF-8".to_string()))
+}
+
+fn git_config_value(repo: &Path, key: &str) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["config", "--get", key])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upper_layers_override_only_what_they_set() {
+        let org = Policy::parse("max-depth = 16\nfuture-setting = true\n", "org").unwrap();
+        assert_eq!(org.max_depth, Some(16));
+
+        let repo = Policy::parse("", "repo").unwrap();
+        assert_eq!(org.clone().overlay(repo).max_depth, Some(16));
+
+        let repo = Policy::parse(
+            "max-depth = 8\nmin-confidence = 0.9\nlint-inheritance = \"conflict\"\nlint-levels = \"loosest\"\nrename-delete = \"rename\"\nrust-version = \"max\"\n\
+             [package]\npublish = \"conflict\"\nlicense = \"conflict\"\n\
+             [profile]\nlto = \"max\"\nopt-level = \"conflict\"\n",
+            "repo",
+        )
+        .unwrap();
+        let options = org.overlay(repo).merge_options();
+        assert_eq!(options.max_depth, 8);
+        assert_eq!(options.min_confidence, 0.9);
+        assert_eq!(options.lint_inheritance, LintInheritance::Conflict);
+        assert_eq!(options.lint_levels, LintLevels::Loosest);
+        assert_eq!(options.rename_delete, RenameDelete::Rename);
+        assert_eq!(options.rust_version, RustVersionMode::Max);
+        assert_eq!(options.publish, PublishMode::Conflict);
+        assert_eq!(options.license, LicenseMode::Conflict);
+        assert_eq!(options.description, DescriptionMode::Longer);
+        assert_eq!(options.profile.lto, ProfileMode::Max);
+        assert_eq!(options.profile.opt_level, ProfileMode::Conflict);
+        assert_eq!(options.profile.codegen_units, ProfileMode::Min);
+        assert!(Policy::parse("[profile]\nincremental = \"max\"\n", "repo").is_err());
+    }
+
+    #[test]
+    fn rejects_wrongly_typed_settings() {
+        let err = Policy::parse("max-depth = \"deep\"\n", "org.toml").unwrap_err();
+        assert!(err.to_string().contains("org.toml"), "{err}");
+        assert!(Policy::parse("min-confidence = 1.5\n", "org.toml").is_err());
+        assert!(Policy::parse("reconcile = \"newest\"\n", "org.toml").is_err());
+        assert!(Policy::parse("lint-inheritance = \"always\"\n", "org.toml").is_err());
+        assert!(Policy::parse("lint-levels = \"deny\"\n", "org.toml").is_err());
+        assert!(Policy::parse("rename-delete = \"keep\"\n", "org.toml").is_err());
+        assert!(Policy::parse("rust-version = \"newest\"\n", "org.toml").is_err());
+        assert!(Policy::parse("[[notify]]\nurl = \"hooks.example\"\n", "org.toml").is_err());
+        let policy = Policy::parse(
+            "[[notify]]\nurl = \"https://hooks.example/x\"\nformat = \"matrix\"\n",
+            "org.toml",
+        )
+        .unwrap();
+        assert_eq!(
+            policy.notify,
+            Some(vec![NotifyHook {
+                url: "https://hooks.example/x".to_string(),
+                format: HookFormat::Matrix,
+                branches: Vec::new(),
+            }])
+        );
+        assert!(Policy::parse("exclude = \"vendor/\"\n", "org.toml").is_err());
+        assert!(Policy::parse("[style]\nnewline = \"cr\"\n", "org.toml").is_err());
+        assert!(Policy::parse("[style]\nindent = \"--\"\n", "org.toml").is_err());
+        let policy = Policy::parse("exclude = [\"legacy/\"]\n", "org.toml").unwrap();
+        assert_eq!(policy.exclude, Some(vec!["legacy/".to_string()]));
+    }
+
+    #[test]
+    fn keeps_build_dependencies_conservative() {
+        fn newer(
+            _path: &[&str],
+            _base: Option<&Value>,
+            ours: Option<&Value>,
+            theirs: Option<&Value>,
+        ) -> Option<crate::merge::Proposal> {
+            let (ours, theirs) = (ours?.as_str()?, theirs?.as_str()?);
+            Some(crate::merge::Proposal {
+                value: Some(Value::String(ours.max(theirs).to_string())),
+                rule: "newer",
+                confidence: 0.8,
+            })
+        }
+
+        let base = "[dependencies]\ncc = \"1.0.80\"\n\n[build-dependencies]\ncc = \"1.0.80\"\n\n\
+                    [target.'cfg(unix)'.build-dependencies]\npkg-config = \"0.3.20\"\n";
+        let ours = base.replace("1.0.80", "1.0.83");
+        let theirs = base.replace("1.0.80", "1.0.90");
+
+        let policy = Policy::parse("[build-dependencies]\nmin-confidence = 0.9\n", "repo").unwrap();
+        let mut options = policy.merge_options();
+        options.heuristics.push(newer);
+        let err =
+            crate::merge::merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let crate::merge::MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "build-dependencies.cc");
+
+        let policy = Policy::parse(
+            "[build-dependencies]\nmin-confidence = 0.9\nprefer = \"base\"\n",
+            "repo",
+        )
+        .unwrap();
+        options.preferences = policy.preferences(&Branches::default());
+        let theirs = theirs.replace("0.3.20", "0.3.30");
+        let ours = ours.replace("0.3.20", "0.3.25");
+        let merged =
+            crate::merge::merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(
+            merged.starts_with(
+                "[dependencies]\ncc = \"1.0.90\"\n\n[build-dependencies]\ncc = \"1.0.80\""
+            ),
+            "{merged}"
+        );
+        assert!(merged.contains("pkg-config = \"0.3.20\""), "{merged}");
+        assert!(Policy::parse("[build-dependencies]\nprefer = \"lower\"\n", "repo").is_err());
+    }
+
+    #[test]
+    fn combines_pins_per_crate() {
+        let org = Policy::parse(
+            "pin-mode = \"conflict\"\n[pins]\nopenssl = \"0.10.55\"\nring = \"=0.17.8\"\n",
+            "org",
+        )
+        .unwrap();
+        let repo = Policy::parse("[pins]\nring = \"0.17.14\"\n", "repo").unwrap();
+        let options = org.overlay(repo).merge_options();
+        assert_eq!(options.pin_mode, PinMode::Conflict);
+        assert_eq!(
+            options.pins.into_iter().collect::<Vec<_>>(),
+            [
+                ("openssl".to_string(), "0.10.55".to_string()),
+                ("ring".to_string(), "0.17.14".to_string()),
+            ]
+        );
+        assert!(Policy::parse("[pins]\nopenssl = \"0.10\"\n", "org").is_err());
+        assert!(Policy::parse("pin-mode = \"warn\"\n", "org").is_err());
+    }
+
+    #[test]
+    fn dispatches_strategies_by_path() {
+        let org = Policy::parse(
+            "[strategies]\n\"dependencies.*\" = \"semver-max\"\n\"package.version\" = \"theirs\"\n",
+            "org",
+        )
+        .unwrap();
+        let repo = Policy::parse(
+            "[strategies]\n\"package.version\" = \"semver-max\"\n\
+             \"dependencies.*\" = \"semver-intersection\"\n\
+             \"package.metadata.ci.*\" = \"ours\"\n",
+            "repo",
+        )
+        .unwrap();
+        let options = org.overlay(repo).merge_options();
+        let paths: Vec<(&str, &str)> = options
+            .strategies
+            .iter()
+            .map(|strategy| (strategy.path.as_str(), strategy.name.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                ("package.version", "semver-max"),
+                ("package.metadata.ci.*", "ours"),
+                ("dependencies.*", "semver-intersection"),
+            ]
+        );
+
+        let base = "[package]\nversion = \"1.0.0\"\nhomepage = \"app\"\n\n[package.metadata.ci]\nimage = \"a\"\n\n\
+                    [dependencies]\nserde = \"1\"\n";
+        let ours = base
+            .replace("1.0.0", "1.1.0")
+            .replace("\"a\"", "\"b\"")
+            .replace("\"1\"", "\">=1.2, <2\"");
+        let theirs = base
+            .replace("1.0.0", "1.0.1")
+            .replace("\"a\"", "\"c\"")
+            .replace("\"1\"", "\"^1.4\"");
+        let merged =
+            crate::merge::merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("version = \"1.1.0\""), "{merged}");
+        assert!(merged.contains("image = \"b\""), "{merged}");
+        assert!(merged.contains("serde = \"^1.4\""), "{merged}");
+
+        // Strategies apply only where they are listed.
+        let ours = ours.replace("\"app\"", "\"https://a.example\"");
+        let theirs = theirs.replace("\"app\"", "\"https://b.example\"");
+        let err =
+            crate::merge::merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let crate::merge::MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "package.homepage");
+        assert!(Policy::parse("[strategies]\n\"a.*\" = \"newest\"\n", "repo").is_err());
+    }
+
+    #[test]
+    fn applies_branch_rules_matching_the_merge() {
+        let policy = Policy::parse(
+            r#"
+[[branch-rules]]
+ours = "main"
+theirs = "release/*"
+prefer = "theirs"
+paths = ["package.version"]
+
+[[branch-rules]]
+theirs = "deps/*"
+prefer = "ours"
+paths = ["dependencies.*"]
+"#,
+            "repo",
+        )
+        .unwrap();
+        let branches = |ours: Option<&str>, theirs: &str| Branches {
+            ours: ours.map(str::to_string),
+            theirs: Some(theirs.to_string()),
+        };
+
+        let preferences = policy.preferences(&branches(Some("main"), "release/1.4"));
+        assert_eq!(
+            preferences,
+            [Preference {
+                path: "package.version".to_string(),
+                side: Side::Theirs,
+                rule: "branch-policy",
+            }]
+        );
+        assert!(
+            policy
+                .preferences(&branches(None, "release/1.4"))
+                .is_empty()
+        );
+        assert_eq!(policy.preferences(&branches(None, "deps/serde")).len(), 1);
+
+        let base = "[package]\nversion = \"1.3.0\"\n";
+        let options = MergeOptions {
+            preferences,
+            ..policy.merge_options()
+        };
+        let merged = crate::merge::merge_manifest_texts_with(
+            base,
+            &base.replace("1.3.0", "1.3.1"),
+            &base.replace("1.3.0", "1.4.0"),
+            &options,
+        )
+        .unwrap();
+        assert!(merged.contains("version = \"1.4.0\""), "{merged}");
+
+        assert!(
+            Policy::parse(
+                "[[branch-rules]]\nprefer = \"newest\"\npaths = [\"a\"]\n",
+                "repo"
+            )
+            .is_err()
+        );
+        assert!(
+            Policy::parse("[[branch-rules]]\nprefer = \"ours\"\npaths = []\n", "repo").is_err()
+        );
+    }
+
+    #[test]
+    fn layers_org_policy_from_git_config_under_repo_file() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-policy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(repo.join("org.toml"), "max-depth = 12\n").unwrap();
+        git(&["config", POLICY_GIT_KEY, "org.toml"]);
+
+        assert_eq!(load(&repo, false).unwrap().policy.max_depth, Some(12));
+
+        fs::write(repo.join(REPO_POLICY_FILE), "max-depth = 20\n").unwrap();
+        assert_eq!(load(&repo, false).unwrap().policy.max_depth, Some(20));
+
+        git(&[
+            "config",
+            POLICY_GIT_KEY,
+            "https://policies.invalid/org.toml",
+        ]);
+        let err = load(&repo, false).unwrap_err();
+        assert!(matches!(err, PolicyError::Fetch { .. }), "{err}");
+
+        // Not even looked up in the cache: it could only have come from a
+        // plain HTTP fetch.
+        git(&["config", POLICY_GIT_KEY, "http://policies.invalid/org.toml"]);
+        let err = load(&repo, true).unwrap_err();
+        assert!(err.to_string().contains("plain HTTP"), "{err}");
+
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Where each value of a merged manifest came from (`--provenance`).
+//!
+//! The map has one entry per leaf of the merged document, keyed by its
+//! dotted path: a value that is not a table, or an empty table. Arrays,
+//! including arrays of tables such as `[[bin]]`, are leaves. The origin is
+//!
+//! - `policy:<rule>` when a rule resolved the value or a table containing it
+//!   (the [`Resolution`] recorded for it, e.g. `policy:semver-max`),
+//! - `ours` or `theirs` when the value is the one that side changed it to
+//!   (`ours` when both made the same change),
+//! - `base` when neither side changed it,
+//! - `policy:merge` when the merge assembled it from both sides, as with
+//!   united lists.
+//!
+//! Bots explaining a merge in a pull request and audit tooling read it to
+//! reconstruct how the manifest was put together.
+
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+use crate::merge::Resolution;
+
+/// The origin of every leaf of `merged`, the merge of `base`, `ours` and
+/// `theirs` that made `resolutions`.
+pub fn provenance(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    merged: &str,
+    resolutions: &[Resolution],
+) -> Result<BTreeMap<String, String>, toml::de::Error> {
+    let [base, ours, theirs, merged] = [base, ours, theirs, merged].map(str::parse::<Value>);
+    let sides = [base?, ours?, theirs?];
+    let mut origins = BTreeMap::new();
+    let mut path = Vec::new();
+    visit(&merged?, &sides, resolutions, &mut path, &mut origins);
+    Ok(origins)
+}
+
+fn visit<'a>(
+    value: &'a Value,
+    sides: &[Value; 3],
+    resolutions: &[Resolution],
+    path: &mut Vec<&'a str>,
+    origins: &mut BTreeMap<String, String>,
+) {
+    if let Some(table) = value.as_table()
+        && !table.is_empty()
+    {
+        for (key, child) in table {
+            path.push(key);
+            visit(child, sides, resolutions, path, origins);
+            path.pop();
+        }
+        return;
+    }
+    let dotted = path.join(".");
+    let origin = origin(value, &dotted, path, sides, resolutions);
+    origins.insert(dotted, origin);
+}
+
+fn origin(
+    value: &Value,
+    dotted: &str,
+    path: &[&str],
+    sides: &[Value; 3],
+    resolutions: &[Resolution],
+) -> String {
+    let resolved = resolutions
+        .iter()
+        .filter(|resolution| {
+            dotted == resolution.path
+                || dotted
+                    .strip_prefix(&resolution.path)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .max_by_key(|resolution| resolution.path.len());
+    if let Some(resolution) = resolved {
+        return format!("policy:{}", resolution.rule);
+    }
+
+    let [base, ours, theirs] = sides.each_ref().map(|side| lookup(side, path));
+    let value = Some(value);
+    let origin = if value == ours && ours != base {
+        "ours"
+    } else if value == theirs && theirs != base {
+        "theirs"
+    } else if value == base {
+        "base"
+    } else {
+        "policy:merge"
+    };
+    origin.to_string()
+}
+
+fn lookup<'a>(doc: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(doc, |value, key| value.get(*key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeOptions, merge_manifest_texts_detailed};
+    use crate::strategy::Strategy;
+    use crate::timings::Timings;
+
+    #[test]
+    fn traces_every_value_to_its_origin() {
+        let base = "[package]\nname = \"a\"\nversion = \"0.1.0\"\n\n\
+                    [dependencies]\nserde = \"1.0.100\"\nlog = \"0.4\"\n\n[features]\ndefault = [\"std\"]\n";
+        let ours = "[package]\nname = \"a\"\nversion = \"0.2.0\"\n\n\
+                    [dependencies]\nserde = \"1.0.150\"\nlog = \"0.4\"\n\n[features]\ndefault = [\"std\", \"rc\"]\n";
+        let theirs = "[package]\nname = \"a\"\nversion = \"0.1.0\"\n\n\
+                      [dependencies]\nserde = \"1.0.200\"\nlog = \"0.4\"\nrand = \"0.8\"\n\n\
+                      [features]\ndefault = [\"std\", \"alloc\"]\n";
+        let mut options = MergeOptions::default();
+        options.heuristics.push(Strategy::SemverMax.heuristic());
+        let merged =
+            merge_manifest_texts_detailed(base, ours, theirs, &options, &mut Timings::default())
+                .unwrap();
+
+        let origins = provenance(base, ours, theirs, &merged.text, &merged.resolutions).unwrap();
+        let origins: Vec<(&str, &str)> = origins
+            .iter()
+            .map(|(path, origin)| (path.as_str(), origin.as_str()))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                ("dependencies.log", "base"),
+                ("dependencies.rand", "theirs"),
+                ("dependencies.serde", "policy:semver-max"),
+                ("features.default", "policy:merge"),
+                ("package.name", "base"),
+                ("package.version", "ours"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,581 @@
+//! Workspace-wide requirement consistency after a batch merge.
+//!
+//! Each member manifest merges cleanly on its own, yet two members can end up
+//! requiring different versions of the same crate (one branch bumped `serde`
+//! in `a/`, the other in `b/`). After a batch, requirements are compared
+//! across the merged members; a crate whose requirements diverge where they
+//! did not before the merge is either harmonized to the highest requirement
+//! or reported as a workspace-level conflict.
+//!
+//! [`hoist_duplicates`] goes further: a dependency declared identically in
+//! several members moves into the root's `[workspace.dependencies]`, and the
+//! members inherit it with `workspace = true`, so the next bump touches one
+//! line instead of racing in every member.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use semver::{Op, VersionReq};
+use toml::Value;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// What to do with divergent requirements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReconcileMode {
+    /// Skip the pass.
+    Off,
+    /// Report divergences as workspace conflicts.
+    #[default]
+    Report,
+    /// Rewrite members to the highest requirement where that is unambiguous;
+    /// report the rest.
+    Highest,
+}
+
+impl FromStr for ReconcileMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(Self::Off),
+            "report" => Ok(Self::Report),
+            "highest" => Ok(Self::Highest),
+            other => Err(format!(
+                "unknown reconcile mode `{other}` (expected off, report or highest)"
+            )),
+        }
+    }
+}
+
+/// A member manifest before and after the merge.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub path: PathBuf,
+    /// The member's "ours" manifest, before merging.
+    pub before: String,
+    pub merged: String,
+}
+
+/// A crate required at different versions by different members.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub name: String,
+    /// Each member's requirement(s), in member order.
+    pub requirements: Vec<(PathBuf, String)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workspace conflict: `{}` is required as", self.name)?;
+        for (path, req) in &self.requirements {
+            write!(f, "\n  {req:<12} in {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [`reconcile`].
+#[derive(Debug, Default)]
+pub struct Reconciliation {
+    /// Divergences left unresolved.
+    pub conflicts: Vec<Divergence>,
+    /// Crates harmonized, with the requirement every member now uses.
+    pub harmonized: Vec<(String, String)>,
+    /// New contents for members that were rewritten.
+    pub rewritten: Vec<(PathBuf, String)>,
+}
+
+/// Compares requirements across `members`. Members that fail to parse are
+/// skipped; they were reported by the merge itself.
+pub fn reconcile(members: &[Member], mode: ReconcileMode) -> Reconciliation {
+    let mut result = Reconciliation::default();
+    if mode == ReconcileMode::Off {
+        return result;
+    }
+
+    let mut docs: Vec<(usize, Value)> = Vec::new();
+    let mut before = BTreeMap::<String, Vec<String>>::new();
+    let mut after = BTreeMap::<String, Vec<(PathBuf, String)>>::new();
+    for (idx, member) in members.iter().enumerate() {
+        let (Ok(old), Ok(new)) = (
+            member.before.parse::<Value>(),
+            member.merged.parse::<Value>(),
+        ) else {
+            continue;
+        };
+        for (name, req) in requirements(&old) {
+            before.entry(name).or_default().push(req);
+        }
+        for (name, req) in requirements(&new) {
+            after
+                .entry(name)
+                .or_default()
+                .push((member.path.clone(), req));
+        }
+        docs.push((idx, new));
+    }
+
+    for (name, requirements) in after {
+        let mut distinct: Vec<&str> = requirements.iter().map(|(_, req)| req.as_str()).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let mut previous: Vec<&str> = before
+            .get(&name)
+            .map(|reqs| reqs.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        previous.sort_unstable();
+        previous.dedup();
+        // Divergence that predates the merge is taken to be deliberate.
+        if distinct.len() < 2 || distinct == previous {
+            continue;
+        }
+
+        let target = match mode {
+            ReconcileMode::Highest => highest(&distinct),
+            _ => None,
+        };
+        match target {
+            Some(target) => {
+                for (idx, doc) in &mut docs {
+                    if set_requirement(doc, &name, &target) {
+                        let path = &members[*idx].path;
+                        result.rewritten.retain(|(rewritten, _)| rewritten != path);
+                        result
+                            .rewritten
+                            .push((path.clone(), render(doc, members, *idx)));
+                    }
+                }
+                result.harmonized.push((name, target));
+            }
+            None => result.conflicts.push(Divergence { name, requirements }),
+        }
+    }
+    result
+}
+
+/// The highest of `reqs` if they are all single comparators with the same
+/// operator (`^1.0.1` vs `^1.0.3`); mixed or compound requirements are left
+/// for a human.
+fn highest(reqs: &[&str]) -> Option<String> {
+    let mut parsed = Vec::new();
+    for req in reqs {
+        let parsed_req = VersionReq::parse(req).ok()?;
+        let [comparator] = parsed_req.comparators.as_slice() else {
+            return None;
+        };
+        let key = (
+            comparator.major,
+            comparator.minor.unwrap_or(0),
+            comparator.patch.unwrap_or(0),
+        );
+        parsed.push((comparator.op, key, *req));
+    }
+    let op = parsed.first()?.0;
+    if parsed.iter().any(|(other, _, _)| *other != op) || !matches!(op, Op::Caret | Op::Tilde) {
+        return None;
+    }
+    parsed
+        .into_iter()
+        .max_by_key(|(_, key, _)| *key)
+        .map(|(_, _, req)| req.to_string())
+}
+
+/// `(name, requirement)` for every registry dependency in `doc`.
+fn requirements(doc: &Value) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for table in dependency_tables(doc) {
+        for (key, spec) in table {
+            let req = match spec {
+                Value::String(req) => Some(req.as_str()),
+                Value::Table(detail)
+                    if !detail.contains_key("path") && !detail.contains_key("git") =>
+                {
+                    detail.get("version").and_then(Value::as_str)
+                }
+                _ => None,
+            };
+            if let Some(req) = req {
+                let name = spec.get("package").and_then(Value::as_str).unwrap_or(key);
+                found.push((name.to_string(), req.to_string()));
+            }
+        }
+    }
+    found
+}
+
+/// Rewrites every requirement on `name` in `doc` to `req`; true if changed.
+fn set_requirement(doc: &mut Value, name: &str, req: &str) -> bool {
+    let mut changed = false;
+    for table in dependency_tables_mut(doc) {
+        for (key, spec) in table.iter_mut() {
+            let renamed = spec.get("package").and_then(Value::as_str);
+            if renamed.unwrap_or(key) != name {
+                continue;
+            }
+            let slot = match spec {
+                Value::String(_) => spec,
+                Value::Table(detail) => match detail.get_mut("version") {
+                    Some(version) => version,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if slot.as_str() != Some(req) {
+                *slot = Value::String(req.to_string());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+fn dependency_tables(doc: &Value) -> Vec<&toml::Table> {
+    let mut tables: Vec<&toml::Table> = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|key| doc.get(*key)?.as_table())
+        .collect();
+    if let Some(targets) = doc.get("target").and_then(Value::as_table) {
+        for target in targets.values() {
+            tables.extend(
+                DEPENDENCY_TABLES
+                    .iter()
+                    .filter_map(|key| target.get(*key)?.as_table()),
+            );
+        }
+    }
+    tables
+}
+
+fn dependency_tables_mut(doc: &mut Value) -> Vec<&mut toml::Table> {
+    let Some(root) = doc.as_table_mut() else {
+        return Vec::new();
+    };
+    let mut tables = Vec::new();
+    for (key, value) in root.iter_mut() {
+        let Value::Table(table) = value else {
+            continue;
+        };
+        if DEPENDENCY_TABLES.contains(&key.as_str()) {
+            tables.push(table);
+        } else if key == "target" {
+            for (_, target) in table.iter_mut() {
+                let Value::Table(target) = target else {
+                    continue;
+                };
+                for (key, value) in target.iter_mut() {
+                    if let (true, Value::Table(table)) =
+                        (DEPENDENCY_TABLES.contains(&key.as_str()), value)
+                    {
+                        tables.push(table);
+                    }
+                }
+            }
+        }
+    }
+    tables
+}
+
+/// Result of [`hoist_duplicates`].
+#[derive(Debug, Default)]
+pub struct Hoisting {
+    /// Dependencies now inherited from `[workspace.dependencies]`.
+    pub hoisted: Vec<String>,
+    /// New contents for the root and the members that were rewritten.
+    pub rewritten: Vec<(PathBuf, String)>,
+}
+
+/// Moves dependencies declared identically by two or more `members` into
+/// the `[workspace.dependencies]` of the workspace root manifest `root`,
+/// which may itself be one of the members.
+///
+/// Path dependencies are left alone (their paths are member-relative), as
+/// are names whose existing workspace entry differs. `optional` stays on the
+/// member, where cargo expects it.
+pub fn hoist_duplicates(
+    root: &Path,
+    root_text: &str,
+    members: &[(PathBuf, String)],
+) -> Result<Hoisting, String> {
+    let mut docs = Vec::with_capacity(members.len() + 1);
+    for (path, text) in members {
+        let doc: Value = text.parse().map_err(|err: toml::de::Error| {
+            format!("invalid manifest {}: {}", path.display(), err.message())
+        })?;
+        docs.push(doc);
+    }
+    let root_idx = match members.iter().position(|(path, _)| path == root) {
+        Some(idx) => idx,
+        None => {
+            docs.push(root_text.parse().map_err(|err: toml::de::Error| {
+                format!("invalid manifest {}: {}", root.display(), err.message())
+            })?);
+            members.len()
+        }
+    };
+
+    // Shared (optional-stripped) specs per dependency key, with the members
+    // declaring them.
+    let mut candidates: BTreeMap<String, Option<(Value, Vec<usize>)>> = BTreeMap::new();
+    for (idx, doc) in docs.iter().enumerate().take(members.len()) {
+        for table in dependency_tables(doc) {
+            for (key, spec) in table {
+                let shared = hoistable(spec);
+                let entry = candidates
+                    .entry(key.clone())
+                    .or_insert_with(|| shared.clone().map(|spec| (spec, Vec::new())));
+                match (entry.as_mut(), shared) {
+                    (Some((existing, owners)), Some(shared)) if *existing == shared => {
+                        if !owners.contains(&idx) {
+                            owners.push(idx);
+                        }
+                    }
+                    _ => *entry = None,
+                }
+            }
+        }
+    }
+
+    let Some(workspace) = docs[root_idx]
+        .get_mut("workspace")
+        .and_then(Value::as_table_mut)
+    else {
+        return Err(format!("{} has no [workspace] table", root.display()));
+    };
+    let inherited = workspace
+        .entry("dependencies")
+        .or_insert_with(|| Value::Table(toml::Table::new()));
+    let Value::Table(inherited) = inherited else {
+        return Err("`workspace.dependencies` is not a table".to_string());
+    };
+    let mut hoisted = Vec::new();
+    for (key, candidate) in candidates {
+        let Some((spec, owners)) = candidate else {
+            continue;
+        };
+        if owners.len() < 2 {
+            continue;
+        }
+        match inherited.get(&key) {
+            Some(existing) if *existing != spec => continue,
+            Some(_) => {}
+            None => {
+                inherited.insert(key.clone(), spec);
+            }
+        }
+        hoisted.push(key);
+    }
+
+    let mut result = Hoisting::default();
+    if hoisted.is_empty() {
+        return Ok(result);
+    }
+    for (idx, doc) in docs.iter_mut().enumerate() {
+        let mut changed = idx == root_idx;
+        for table in dependency_tables_mut(doc) {
+            for (key, spec) in table.iter_mut() {
+                if !hoisted.contains(key) || hoistable(spec).is_none() {
+                    continue;
+                }
+                let mut inherit = toml::Table::new();
+                inherit.insert("workspace".to_string(), Value::Boolean(true));
+                if let Some(optional) = spec.get("optional") {
+                    inherit.insert("optional".to_string(), optional.clone());
+                }
+                *spec = Value::Table(inherit);
+                changed = true;
+            }
+        }
+        if changed {
+            let (path, text) = members.get(idx).map_or((root, root_text), |(path, text)| {
+                (path.as_path(), text.as_str())
+            });
+            result
+                .rewritten
+                .push((path.to_path_buf(), render_like(doc, text)));
+        }
+    }
+    result.hoisted = hoisted;
+    Ok(result)
+}
+
+/// `spec` without member-only keys, if it can live in the workspace.
+fn hoistable(spec: &Value) -> Option<Value> {
+    match spec {
+        Value::String(_) => Some(spec.clone()),
+        Value::Table(detail)
+            if !detail.contains_key("path") && !detail.contains_key("workspace") =>
+        {
+            let mut detail = detail.clone();
+            detail.remove("optional");
+            Some(Value::Table(detail))
+        }
+        _ => None,
+    }
+}
+
+fn render(doc: &Value, members: &[Member], idx: usize) -> String {
+    render_like(doc, &members[idx].merged)
+}
+
+/// Serializes `doc`, keeping the line endings of `original`.
+fn render_like(doc: &Value, original: &str) -> String {
+    let mut text = toml::to_string_pretty(doc).unwrap_or_else(|_| original.to_string());
+    if original.contains("\r\n") {
+        text = text.replace('\n', "\r\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(path: &str, before: &str, merged: &str) -> Member {
+        Member {
+            path: PathBuf::from(path),
+            before: before.to_string(),
+            merged: merged.to_string(),
+        }
+    }
+
+    #[test]
+    fn reports_divergence_introduced_by_the_merge() {
+        let members = [
+            member(
+                "a/Cargo.toml",
+                "[dependencies]\nserde = \"1.0.100\"\nrand = \"0.8\"\n",
+                "[dependencies]\nserde = \"1.0.200\"\nrand = \"0.8\"\n",
+            ),
+            member(
+                "b/Cargo.toml",
+                "[dependencies]\nserde = \"1.0.100\"\nrand = \"0.7\"\n",
+                "[dev-dependencies]\nserde = { version = \"1.0.100\" }\nrand = \"0.7\"\n",
+            ),
+        ];
+
+        let result = reconcile(&members, ReconcileMode::Report);
+        assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+        assert_eq!(result.conflicts[0].name, "serde");
+        assert!(result.conflicts[0].to_string().contains("b/Cargo.toml"));
+        assert!(result.rewritten.is_empty());
+
+        assert!(reconcile(&members, ReconcileMode::Off).conflicts.is_empty());
+    }
+
+    #[test]
+    fn harmonizes_to_the_highest_compatible_requirement() {
+        let members = [
+            member(
+                "a/Cargo.toml",
+                "[dependencies]\nserde = \"1.0.100\"\n",
+                "[dependencies]\nserde = \"1.0.200\"\n",
+            ),
+            member(
+                "b/Cargo.toml",
+                "[dependencies]\nserde = \"1.0.100\"\n",
+                "[dependencies]\nserde = { version = \"1.0.150\", features = [\"derive\"] }\n",
+            ),
+            member(
+                "c/Cargo.toml",
+                "[dependencies]\ntoml = \"0.8\"\n",
+                "[dependencies]\ntoml = \"=0.8.2\"\n[dev-dependencies]\ntoml = \"0.8\"\n",
+            ),
+        ];
+
+        let result = reconcile(&members, ReconcileMode::Highest);
+        assert_eq!(
+            result.harmonized,
+            [("serde".to_string(), "1.0.200".to_string())]
+        );
+        assert_eq!(result.rewritten.len(), 1);
+        assert_eq!(result.rewritten[0].0, PathBuf::from("b/Cargo.toml"));
+        assert!(result.rewritten[0].1.contains("version = \"1.0.200\""));
+        assert!(result.rewritten[0].1.contains("derive"));
+
+        // `=0.8.2` pins on purpose; mixed operators stay a conflict.
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].name, "toml");
+    }
+
+    #[test]
+    fn hoists_identical_declarations_into_the_workspace() {
+        let root = "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\n";
+        let members = [
+            (
+                PathBuf::from("a/Cargo.toml"),
+                "[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\nlog = \"0.4\"\nlocal = { path = \"../local\" }\n".to_string(),
+            ),
+            (
+                PathBuf::from("b/Cargo.toml"),
+                "[dependencies]\nlocal = { path = \"../local\" }\n[dev-dependencies]\nserde = { version = \"1\", features = [\"derive\"], optional = true }\nlog = \"0.4.20\"\n".to_string(),
+            ),
+            (
+                PathBuf::from("c/Cargo.toml"),
+                "[dependencies]\nlog = \"0.4\"\n".to_string(),
+            ),
+        ];
+
+        let hoisting = hoist_duplicates(Path::new("Cargo.toml"), root, &members).unwrap();
+        // `log` differs in b, `local` is a path dependency.
+        assert_eq!(hoisting.hoisted, ["serde"]);
+
+        let rewritten: BTreeMap<_, Value> = hoisting
+            .rewritten
+            .into_iter()
+            .map(|(path, text)| (path, text.parse().unwrap()))
+            .collect();
+        assert_eq!(rewritten.len(), 3);
+        let root = &rewritten[&PathBuf::from("Cargo.toml")];
+        assert_eq!(
+            root["workspace"]["dependencies"]["serde"]["features"][0].as_str(),
+            Some("derive")
+        );
+        let a = &rewritten[&PathBuf::from("a/Cargo.toml")]["dependencies"]["serde"];
+        assert_eq!(a.get("workspace").and_then(Value::as_bool), Some(true));
+        let b = &rewritten[&PathBuf::from("b/Cargo.toml")]["dev-dependencies"]["serde"];
+        assert_eq!(b.get("optional").and_then(Value::as_bool), Some(true));
+        assert!(b.get("features").is_none());
+    }
+
+    #[test]
+    fn hoists_from_a_root_that_is_also_a_member() {
+        let root = "[workspace]\n\n[package]\nname = \"app\"\n\n[dependencies]\nlog = \"0.4\"\n";
+        let members = [
+            (PathBuf::from("Cargo.toml"), root.to_string()),
+            (
+                PathBuf::from("lib/Cargo.toml"),
+                "[dependencies]\nlog = \"0.4\"\n".to_string(),
+            ),
+        ];
+
+        let hoisting = hoist_duplicates(Path::new("Cargo.toml"), root, &members).unwrap();
+        assert_eq!(hoisting.hoisted, ["log"]);
+        let (path, text) = &hoisting.rewritten[0];
+        assert_eq!(path, Path::new("Cargo.toml"));
+        let root: Value = text.parse().unwrap();
+        assert_eq!(
+            root["workspace"]["dependencies"]["log"].as_str(),
+            Some("0.4")
+        );
+        assert_eq!(
+            root["dependencies"]["log"]["workspace"].as_bool(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn hoisting_requires_a_workspace_root() {
+        let err = hoist_duplicates(Path::new("Cargo.toml"), "[package]\nname = \"a\"\n", &[])
+            .unwrap_err();
+        assert!(err.contains("[workspace]"), "{err}");
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("highest".parse(), Ok(ReconcileMode::Highest));
+        assert!("newest".parse::<ReconcileMode>().is_err());
+    }
+}
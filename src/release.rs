@@ -0,0 +1,287 @@
+//! Release-relevant changes of a merge, for release automation.
+//!
+//! Tools in the style of `cargo-smart-release` or `release-plz` plan version
+//! bumps and changelog entries from what changed in a manifest. Given the
+//! manifest before a merge (usually ours) and the merged one, [`changes`]
+//! reports the package version bump and every added, removed or modified
+//! dependency as typed values, so the merge result can feed the release plan
+//! without diffing TOML again. Enable the `serde` feature to serialize them.
+
+use std::fmt;
+
+use semver::Version;
+use toml::Value;
+
+use crate::pins::{crate_name, dependency_tables, lookup};
+
+/// What a merge changed that matters to a release.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReleaseChanges {
+    /// The `package.version` change, if any.
+    pub version: Option<VersionChange>,
+    /// The `workspace.package.version` change, if any.
+    pub workspace_version: Option<VersionChange>,
+    /// Dependency changes, in table then key order.
+    pub dependencies: Vec<DependencyChange>,
+}
+
+impl ReleaseChanges {
+    /// Whether the merge changed nothing release-relevant.
+    pub fn is_empty(&self) -> bool {
+        self.version.is_none() && self.workspace_version.is_none() && self.dependencies.is_empty()
+    }
+}
+
+/// A changed version. `from` or `to` is `None` when the version is missing
+/// or not a plain string (e.g. inherited with `version.workspace = true`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VersionChange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// The kind of bump, when both versions are valid semver.
+    pub bump: Option<Bump>,
+}
+
+/// How a version moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    /// Only the pre-release or build metadata changed.
+    Prerelease,
+    /// The version went down.
+    Downgrade,
+}
+
+impl Bump {
+    /// The bump from `from` to `to`; `None` when they are equal.
+    pub fn between(from: &Version, to: &Version) -> Option<Self> {
+        Some(if to < from {
+            Bump::Downgrade
+        } else if to.major != from.major {
+            Bump::Major
+        } else if to.minor != from.minor {
+            Bump::Minor
+        } else if to.patch != from.patch {
+            Bump::Patch
+        } else if to != from {
+            Bump::Prerelease
+        } else {
+            return None;
+        })
+    }
+}
+
+/// A dependency entry the merge added, removed or modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DependencyChange {
+    /// Dotted path of the table, e.g. `dependencies` or
+    /// `target.cfg(unix).dev-dependencies`.
+    pub table: String,
+    /// The entry's key.
+    pub key: String,
+    /// The crate it refers to (its `package` when renamed).
+    pub package: String,
+    pub kind: DependencyChangeKind,
+}
+
+/// How a dependency entry changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum DependencyChangeKind {
+    Added {
+        requirement: Option<String>,
+    },
+    Removed {
+        requirement: Option<String>,
+    },
+    /// The version requirement changed, and possibly more of the entry.
+    Requirement {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// The requirement is unchanged but other fields (features, source,
+    /// `optional`, ...) are not.
+    Modified,
+}
+
+/// A manifest given to [`changes`] is not valid TOML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangesError {
+    Before(String),
+    After(String),
+}
+
+impl fmt::Display for ChangesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Before(detail) => write!(f, "invalid manifest before the merge: {detail}"),
+            Self::After(detail) => write!(f, "invalid merged manifest: {detail}"),
+        }
+    }
+}
+
+impl std::error::Error for ChangesError {}
+
+/// The release-relevant changes from the manifest text `before` to `after`.
+pub fn changes(before: &str, after: &str) -> Result<ReleaseChanges, ChangesError> {
+    let before: Value =
+        toml::from_str(before).map_err(|err| ChangesError::Before(err.message().to_string()))?;
+    let after: Value =
+        toml::from_str(after).map_err(|err| ChangesError::After(err.message().to_string()))?;
+
+    let version = |table: &[&str]| {
+        let path: Vec<String> = table
+            .iter()
+            .chain(&["version"])
+            .map(ToString::to_string)
+            .collect();
+        version_change(lookup(&before, &path), lookup(&after, &path))
+    };
+    let mut changes = ReleaseChanges {
+        version: version(&["package"]),
+        workspace_version: version(&["workspace", "package"]),
+        dependencies: Vec::new(),
+    };
+
+    let empty = toml::Table::new();
+    for table in dependency_tables([&before, &after, &after]) {
+        let [old, new] = [&before, &after].map(|doc| {
+            lookup(doc, &table)
+                .and_then(Value::as_table)
+                .unwrap_or(&empty)
+        });
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let kind = match (old.get(key), new.get(key)) {
+                (None, Some(entry)) => DependencyChangeKind::Added {
+                    requirement: requirement(entry),
+                },
+                (Some(entry), None) => DependencyChangeKind::Removed {
+                    requirement: requirement(entry),
+                },
+                (Some(old), Some(new)) if old != new => {
+                    let (from, to) = (requirement(old), requirement(new));
+                    if from == to {
+                        DependencyChangeKind::Modified
+                    } else {
+                        DependencyChangeKind::Requirement { from, to }
+                    }
+                }
+                _ => continue,
+            };
+            let entry = new.get(key).or_else(|| old.get(key)).expect("listed above");
+            changes.dependencies.push(DependencyChange {
+                table: table.join("."),
+                key: key.clone(),
+                package: crate_name(entry, key).to_string(),
+                kind,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+fn version_change(from: Option<&Value>, to: Option<&Value>) -> Option<VersionChange> {
+    if from == to {
+        return None;
+    }
+    let (from, to) = (
+        from.and_then(Value::as_str).map(str::to_string),
+        to.and_then(Value::as_str).map(str::to_string),
+    );
+    let parse = |version: &Option<String>| Version::parse(version.as_deref()?).ok();
+    let bump = match (parse(&from), parse(&to)) {
+        (Some(from), Some(to)) => Bump::between(&from, &to),
+        _ => None,
+    };
+    Some(VersionChange { from, to, bump })
+}
+
+fn requirement(entry: &Value) -> Option<String> {
+    match entry {
+        Value::String(req) => Some(req.clone()),
+        Value::Table(table) => table
+            .get("version")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_version_bumps_and_dependency_changes() {
+        let before = "[package]\nname = \"app\"\nversion = \"1.2.3\"\n\n\
+                      [dependencies]\nserde = \"1.0.100\"\nrand = \"0.8\"\n\
+                      log = { version = \"0.4\" }\n\n\
+                      [target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n";
+        let after = "[package]\nname = \"app\"\nversion = \"1.3.0\"\n\n\
+                     [dependencies]\nserde = \"1.0.200\"\n\
+                     log = { version = \"0.4\", features = [\"std\"] }\n\
+                     rng = { package = \"rand_core\", version = \"0.6\" }\n\n\
+                     [target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n";
+
+        let changes = changes(before, after).unwrap();
+        assert_eq!(
+            changes.version,
+            Some(VersionChange {
+                from: Some("1.2.3".to_string()),
+                to: Some("1.3.0".to_string()),
+                bump: Some(Bump::Minor),
+            })
+        );
+        assert_eq!(changes.workspace_version, None);
+        let summary: Vec<(&str, &str, &DependencyChangeKind)> = changes
+            .dependencies
+            .iter()
+            .map(|change| (change.key.as_str(), change.package.as_str(), &change.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("log", "log", &DependencyChangeKind::Modified),
+                (
+                    "rand",
+                    "rand",
+                    &DependencyChangeKind::Removed {
+                        requirement: Some("0.8".to_string())
+                    }
+                ),
+                (
+                    "rng",
+                    "rand_core",
+                    &DependencyChangeKind::Added {
+                        requirement: Some("0.6".to_string())
+                    }
+                ),
+                (
+                    "serde",
+                    "serde",
+                    &DependencyChangeKind::Requirement {
+                        from: Some("1.0.100".to_string()),
+                        to: Some("1.0.200".to_string())
+                    }
+                ),
+            ]
+        );
+
+        assert!(self::changes(after, after).unwrap().is_empty());
+        assert!(matches!(
+            self::changes(before, "[package"),
+            Err(ChangesError::After(_))
+        ));
+    }
+}
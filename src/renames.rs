@@ -0,0 +1,236 @@
+//! Renamed dependencies.
+//!
+//! Renaming a dependency (`rand` to `rand08 = { package = "rand", ... }`)
+//! deletes one key and adds another. Merged key by key, a rename on one side
+//! against a deletion on the other keeps the renamed entry and silently undoes
+//! the deletion, and two different renames keep both entries. Before merging,
+//! each side's deletions are paired with its additions in the same table when
+//! both refer to the same package, the same git repository or path, or an
+//! otherwise identical spec. A rename against a deletion is then settled by
+//! [`RenameDelete`]; two different renames are a conflict.
+
+use std::collections::BTreeSet;
+
+use toml::{Table, Value};
+
+use crate::merge::{ConflictKind, MergeConflict, MergeError, RenameDelete, Resolution, Side};
+use crate::pins::{crate_name, dependency_tables, lookup, lookup_mut};
+
+/// Detects renames in the dependency tables of `ours` and `theirs` and
+/// settles those the plain merge would get wrong, removing renamed entries
+/// that lost. Returns the automatic resolutions. Conflicts are pushed to
+/// `collected` when given, keeping ours' side.
+pub(crate) fn reconcile(
+    mode: RenameDelete,
+    base: &Value,
+    ours: &mut Value,
+    theirs: &mut Value,
+    mut collected: Option<&mut Vec<MergeConflict>>,
+) -> Result<Vec<Resolution>, MergeError> {
+    let mut resolutions = Vec::new();
+    for table in dependency_tables([base, ours, theirs]) {
+        let Some(base_deps) = lookup(base, &table).and_then(Value::as_table) else {
+            continue;
+        };
+        let empty = Table::new();
+        let deps = [&*ours, &*theirs].map(|doc| {
+            lookup(doc, &table)
+                .and_then(Value::as_table)
+                .unwrap_or(&empty)
+        });
+        let renamed = deps.map(|side| renames(base_deps, side));
+        let path = |key: &str| format!("{}.{key}", table.join("."));
+
+        // Renamed entries that lost, removed once the tables are released.
+        let mut dropped: Vec<(Side, String)> = Vec::new();
+        for (from, base_entry) in base_deps {
+            let [ours_to, theirs_to] = renamed.each_ref().map(|pairs| {
+                pairs
+                    .iter()
+                    .find(|(old, _)| old == from)
+                    .map(|(_, new)| *new)
+            });
+            let (side, to) = match (ours_to, theirs_to) {
+                (Some(ours_to), Some(theirs_to)) if ours_to != theirs_to => {
+                    let mut conflict = MergeConflict::new(
+                        path(from),
+                        Some(base_entry.clone()),
+                        deps[0].get(ours_to).cloned(),
+                        deps[1].get(theirs_to).cloned(),
+                    );
+                    conflict.kind = ConflictKind::RenameRename {
+                        ours: ours_to.to_string(),
+                        theirs: theirs_to.to_string(),
+                    };
+                    match collected.as_deref_mut() {
+                        Some(collected) => collected.push(conflict),
+                        None => return Err(MergeError::Conflict(conflict)),
+                    }
+                    dropped.push((Side::Theirs, theirs_to.to_string()));
+                    continue;
+                }
+                (Some(to), None) if !deps[1].contains_key(from) => (Side::Ours, to),
+                (None, Some(to)) if !deps[0].contains_key(from) => (Side::Theirs, to),
+                _ => continue,
+            };
+            let (deleter, renamed_entry) = match side {
+                Side::Ours => (Side::Theirs, deps[0].get(to)),
+                _ => (Side::Ours, deps[1].get(to)),
+            };
+            match mode {
+                RenameDelete::Conflict => {
+                    let (ours_entry, theirs_entry) = match side {
+                        Side::Ours => (renamed_entry.cloned(), None),
+                        _ => (None, renamed_entry.cloned()),
+                    };
+                    let mut conflict = MergeConflict::new(
+                        path(from),
+                        Some(base_entry.clone()),
+                        ours_entry,
+                        theirs_entry,
+                    );
+                    conflict.kind = ConflictKind::RenameDelete {
+                        side,
+                        to: to.to_string(),
+                    };
+                    match collected.as_deref_mut() {
+                        Some(collected) => collected.push(conflict),
+                        None => return Err(MergeError::Conflict(conflict)),
+                    }
+                    if side == Side::Theirs {
+                        dropped.push((side, to.to_string()));
+                    }
+                }
+                RenameDelete::Delete => {
+                    dropped.push((side, to.to_string()));
+                    resolutions.push(Resolution {
+                        path: path(from),
+                        side: Some(deleter),
+                        rule: "rename-delete",
+                    });
+                }
+                RenameDelete::Rename => resolutions.push(Resolution {
+                    path: path(to),
+                    side: Some(side),
+                    rule: "rename-delete",
+                }),
+            }
+        }
+
+        for (side, key) in dropped {
+            let doc = match side {
+                Side::Ours => &mut *ours,
+                _ => &mut *theirs,
+            };
+            if let Some(Value::Table(deps)) = lookup_mut(doc, &table) {
+                deps.remove(&key);
+            }
+        }
+    }
+    Ok(resolutions)
+}
+
+/// Pairs of (old key, new key) for the entries `side` renamed from `base`.
+fn renames<'a>(base: &'a Table, side: &'a Table) -> Vec<(&'a str, &'a str)> {
+    let mut taken = BTreeSet::new();
+    let mut pairs = Vec::new();
+    for (from, old) in base {
+        if side.contains_key(from) {
+            continue;
+        }
+        let to = side.iter().find(|(to, new)| {
+            !base.contains_key(*to)
+                && !taken.contains(to.as_str())
+                && same_dependency(from, old, to, new)
+        });
+        if let Some((to, _)) = to {
+            taken.insert(to.as_str());
+            pairs.push((from.as_str(), to.as_str()));
+        }
+    }
+    pairs
+}
+
+/// Whether the entry `new` under key `to` is the entry `old` under `from`
+/// renamed: the same package, the same git repository or path, or the same
+/// spec apart from `package`. Bare version requirements and entries
+/// inherited from the workspace only match by package.
+fn same_dependency(from: &str, old: &Value, to: &str, new: &Value) -> bool {
+    if crate_name(old, from) == crate_name(new, to) {
+        return true;
+    }
+    let (Value::Table(old), Value::Table(new)) = (old, new) else {
+        return false;
+    };
+    if old.contains_key("workspace") {
+        return false;
+    }
+    let source = |key: &str| old.get(key).is_some() && old.get(key) == new.get(key);
+    if source("git") || source("path") {
+        return true;
+    }
+    let spec = |table: &Table| -> Table {
+        table
+            .iter()
+            .filter(|(key, _)| *key != "package")
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    };
+    let old = spec(old);
+    old.keys().any(|key| key != "version") && old == spec(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{
+        ConflictKind, MergeError, MergeOptions, RenameDelete, Side, merge_manifest_texts_with,
+    };
+
+    #[test]
+    fn settles_renames_against_deletions_and_other_renames() {
+        let base = "[dependencies]\nrand = { version = \"0.8\", features = [\"small_rng\"] }\nlog = \"0.4\"\n";
+        let deleted = "[dependencies]\nlog = \"0.4\"\n";
+        let renamed = "[dependencies]\nrand08 = { package = \"rand\", version = \"0.8\", features = [\"small_rng\"] }\nlog = \"0.4\"\n";
+        let mut options = MergeOptions::default();
+
+        let err = merge_manifest_texts_with(base, deleted, renamed, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.rand");
+        assert_eq!(
+            conflict.kind,
+            ConflictKind::RenameDelete {
+                side: Side::Theirs,
+                to: "rand08".to_string()
+            }
+        );
+        assert!(conflict.ours.is_none() && conflict.theirs.is_some());
+
+        options.rename_delete = RenameDelete::Delete;
+        let merged = merge_manifest_texts_with(base, deleted, renamed, &options).unwrap();
+        assert_eq!(merged, deleted);
+        options.rename_delete = RenameDelete::Rename;
+        let merged = merge_manifest_texts_with(base, renamed, deleted, &options).unwrap();
+        assert_eq!(merged, renamed);
+
+        // Renames to different keys conflict.
+        let other = renamed.replace("rand08", "rng");
+        let err = merge_manifest_texts_with(base, &other, renamed, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(
+            conflict.kind,
+            ConflictKind::RenameRename {
+                ours: "rng".to_string(),
+                theirs: "rand08".to_string()
+            }
+        );
+
+        // The same rename on both sides merges cleanly.
+        let bumped = renamed.replace("0.4", "0.4.21");
+        let merged = merge_manifest_texts_with(base, renamed, &bumped, &options).unwrap();
+        assert_eq!(merged, bumped);
+    }
+}
@@ -0,0 +1,200 @@
+//! Prepared conflict resolutions.
+//!
+//! A resolutions file records, per conflict, which value to take:
+//!
+//! ```toml
+//! [[resolution]]
+//! fingerprint = "c8fe904205d0c901"
+//! path = "dependencies.serde"
+//! take = "theirs"
+//! ```
+//!
+//! An entry matches a conflict by `fingerprint` or, when it has none, by
+//! `path`, and resolves it to an input's value (`take = "base"`, `"ours"`
+//! or `"theirs"`) or to an explicit `value`. `preview --http` writes such a
+//! file as choices are made in the browser; `--apply-resolutions` applies
+//! one during the merge (rule `prepared`), ahead of recorded decisions and
+//! heuristics. The same structure is accepted as JSON,
+//! `{"resolution": [{"path": "...", "value": ...}]}`, via [`from_value`].
+
+use std::fmt::Write;
+
+use toml::Value;
+
+use crate::merge::{Proposal, Side, fingerprint};
+
+/// The value chosen for one conflict.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Choice {
+    /// The conflict's [fingerprint](crate::merge::fingerprint).
+    pub fingerprint: Option<String>,
+    /// Dotted path of the conflicting value.
+    pub path: Option<String>,
+    pub take: Take,
+}
+
+/// What a conflict resolves to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Take {
+    /// One input's value.
+    Side(Side),
+    /// A value of its own, e.g. a requirement neither side had.
+    Value(Value),
+}
+
+impl Choice {
+    /// The proposal resolving the conflict at `path` with these values, if
+    /// this choice is for it.
+    pub(crate) fn proposal(
+        &self,
+        path: &str,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+    ) -> Option<Proposal> {
+        let matches = match (&self.fingerprint, &self.path) {
+            (Some(id), _) => *id == fingerprint(path, base, ours, theirs),
+            (None, Some(chosen)) => chosen == path,
+            (None, None) => false,
+        };
+        if !matches {
+            return None;
+        }
+        let value = match &self.take {
+            Take::Side(Side::Base) => base.cloned(),
+            Take::Side(Side::Ours) => ours.cloned(),
+            Take::Side(Side::Theirs) => theirs.cloned(),
+            Take::Value(value) => Some(value.clone()),
+        };
+        Some(Proposal {
+            value,
+            rule: "prepared",
+            confidence: 1.0,
+        })
+    }
+}
+
+/// Parses a resolutions file.
+pub fn parse(text: &str) -> Result<Vec<Choice>, String> {
+    let doc: Value = text
+        .parse()
+        .map_err(|err: toml::de::Error| err.message().trim().to_string())?;
+    from_value(&doc)
+}
+
+/// Reads resolutions from a parsed document, e.g. one converted from JSON.
+pub fn from_value(doc: &Value) -> Result<Vec<Choice>, String> {
+    let Some(entries) = doc.get("resolution") else {
+        return Ok(Vec::new());
+    };
+    let entries = entries
+        .as_array()
+        .ok_or("`resolution` must be an array of tables")?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            let invalid = |detail: &str| format!("resolution {}: {detail}", idx + 1);
+            let string = |key: &str| match entry.get(key) {
+                None => Ok(None),
+                Some(value) => value
+                    .as_str()
+                    .map(|text| Some(text.to_string()))
+                    .ok_or_else(|| invalid(&format!("`{key}` must be a string"))),
+            };
+            let fingerprint = string("fingerprint")?.map(|id| id.to_ascii_lowercase());
+            let path = string("path")?;
+            if fingerprint.is_none() && path.is_none() {
+                return Err(invalid("needs a `fingerprint` or a `path`"));
+            }
+            let take = match (string("take")?, entry.get("value")) {
+                (Some(side), None) => {
+                    Take::Side(side.parse().map_err(|err: String| invalid(&err))?)
+                }
+                (None, Some(value)) => Take::Value(value.clone()),
+                _ => return Err(invalid("needs exactly one of `take` and `value`")),
+            };
+            Ok(Choice {
+                fingerprint,
+                path,
+                take,
+            })
+        })
+        .collect()
+}
+
+/// Renders `choices` as a resolutions file.
+pub fn render(choices: &[Choice]) -> String {
+    let mut out = String::new();
+    for (idx, choice) in choices.iter().enumerate() {
+        if idx > 0 {
+            out.push('\n');
+        }
+        out.push_str("[[resolution]]\n");
+        if let Some(fingerprint) = &choice.fingerprint {
+            let _ = writeln!(out, "fingerprint = {}", Value::from(fingerprint.as_str()));
+        }
+        if let Some(path) = &choice.path {
+            let _ = writeln!(out, "path = {}", Value::from(path.as_str()));
+        }
+        let _ = match &choice.take {
+            Take::Side(side) => writeln!(out, "take = \"{side}\""),
+            Take::Value(value) => writeln!(out, "value = {value}"),
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeOptions, merge_manifest_texts_with};
+
+    #[test]
+    fn applies_prepared_resolutions_by_fingerprint_or_path() {
+        let base = "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("\"1\"", "\"1.0.200\"");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("\"1\"", "\"1.0.199\"");
+        let id = fingerprint(
+            "package.version",
+            Some(&Value::from("0.1.0")),
+            Some(&Value::from("0.2.0")),
+            Some(&Value::from("0.3.0")),
+        );
+        let choices = vec![
+            Choice {
+                fingerprint: Some(id),
+                path: Some("package.version".to_string()),
+                take: Take::Side(Side::Theirs),
+            },
+            Choice {
+                fingerprint: None,
+                path: Some("dependencies.serde".to_string()),
+                take: Take::Value(Value::from("1.0.210")),
+            },
+        ];
+        let text = render(&choices);
+        assert_eq!(parse(&text).unwrap(), choices);
+
+        let options = MergeOptions {
+            resolutions: choices,
+            ..MergeOptions::default()
+        };
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("version = \"0.3.0\""), "{merged}");
+        assert!(merged.contains("serde = \"1.0.210\""), "{merged}");
+    }
+
+    #[test]
+    fn rejects_incomplete_entries() {
+        let err = parse("[[resolution]]\ntake = \"ours\"\n").unwrap_err();
+        assert_eq!(err, "resolution 1: needs a `fingerprint` or a `path`");
+        assert!(parse("[[resolution]]\npath = \"a\"\ntake = \"ours\"\nvalue = 1\n").is_err());
+        assert!(parse("[[resolution]]\npath = \"a\"\ntake = \"mine\"\n").is_err());
+        assert_eq!(parse("").unwrap(), []);
+    }
+}
@@ -0,0 +1,197 @@
+//! Resumable `merge-all` runs.
+//!
+//! `merge-all` merges the manifest, regenerates `Cargo.lock` and verifies the
+//! result. When verification fails, rerunning used to repeat the merge and
+//! the whole lock regeneration. Each finished [`Step`] is now recorded in a
+//! small state file under `<git common dir>/cargo-merge-assist/merge-all/`
+//! (one per output manifest), together with hashes of the inputs and of what
+//! the step produced. A rerun with the same inputs and settings continues
+//! with the first step whose result is missing or was changed since. The file
+//! is removed once a run succeeds.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::git_common_dir;
+use crate::cleanup;
+
+/// A stage of `merge-all`, in execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    /// Three-way merge of the manifest into `--out`.
+    Merge,
+    /// `cargo generate-lockfile` and pins.
+    Lock,
+    /// `cargo check`.
+    Verify,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Step::Merge => "merge",
+            Step::Lock => "lock",
+            Step::Verify => "verify",
+        })
+    }
+}
+
+impl std::str::FromStr for Step {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "merge" => Ok(Step::Merge),
+            "lock" => Ok(Step::Lock),
+            "verify" => Ok(Step::Verify),
+            other => Err(format!(
+                "unknown step `{other}` (expected `merge`, `lock` or `verify`)"
+            )),
+        }
+    }
+}
+
+/// What a `merge-all` run has finished so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunState {
+    /// Hash of the settings and the base and theirs inputs.
+    pub key: String,
+    /// Hash of ours as the run found it.
+    pub ours: String,
+    /// Hash of the merged manifest, once [`Step::Merge`] finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged: Option<String>,
+    /// Hash of `Cargo.lock`, once [`Step::Lock`] finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<String>,
+}
+
+impl RunState {
+    /// A fresh state for a run with `key` whose ours input hashes to `ours`.
+    pub fn new(key: String, ours: String) -> Self {
+        Self {
+            key,
+            ours,
+            merged: None,
+            lock: None,
+        }
+    }
+
+    /// Where the state of runs writing `out` in the repository at `repo` is
+    /// kept.
+    pub fn path(repo: &Path, out: &Path) -> PathBuf {
+        let dir = match git_common_dir(repo) {
+            Some(dir) => dir.join("cargo-merge-assist").join("merge-all"),
+            None => std::env::temp_dir().join("cargo-merge-assist-merge-all"),
+        };
+        let out = fs::canonicalize(out).unwrap_or_else(|_| out.to_path_buf());
+        dir.join(format!(
+            "{}.toml",
+            &digest(&[out.to_string_lossy().as_bytes()])[..16]
+        ))
+    }
+
+    /// The recorded state at `path`, if any and readable.
+    pub fn load(path: &Path) -> Option<Self> {
+        toml::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self).map_err(std::io::Error::other)?;
+        cleanup::write_atomic(path, text)
+    }
+
+    /// The step a rerun continues with: the first one whose recorded result
+    /// is missing or no longer matches. `key` and `ours` describe the rerun;
+    /// its ours may already hold the merge when it is also the output.
+    /// `merged` and `lock` hash the current output and lockfile.
+    pub fn resume_at(
+        &self,
+        key: &str,
+        ours: &str,
+        merged: Option<&str>,
+        lock: Option<&str>,
+    ) -> Step {
+        let same_run =
+            self.key == key && (self.ours == ours || self.merged.as_deref() == Some(ours));
+        if !same_run || self.merged.is_none() || self.merged.as_deref() != merged {
+            Step::Merge
+        } else if self.lock.is_none() || self.lock.as_deref() != lock {
+            Step::Lock
+        } else {
+            Step::Verify
+        }
+    }
+}
+
+/// Hex SHA-256 of `parts`, each length-prefixed.
+pub fn digest(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// [`digest`] of the file at `path`; `None` when it cannot be read.
+pub fn file_digest(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|bytes| digest(&[&bytes]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_at_the_first_step_without_a_valid_result() {
+        let mut state = RunState::new("key".to_string(), "ours".to_string());
+        assert_eq!(state.resume_at("key", "ours", None, None), Step::Merge);
+
+        state.merged = Some("merged".to_string());
+        // Ours is the output: the rerun finds the merge in it.
+        assert_eq!(
+            state.resume_at("key", "merged", Some("merged"), None),
+            Step::Lock
+        );
+        assert_eq!(
+            state.resume_at("other", "merged", Some("merged"), None),
+            Step::Merge
+        );
+        // The merged manifest was edited since.
+        assert_eq!(
+            state.resume_at("key", "ours", Some("edited"), None),
+            Step::Merge
+        );
+
+        state.lock = Some("lock".to_string());
+        assert_eq!(
+            state.resume_at("key", "ours", Some("merged"), Some("lock")),
+            Step::Verify
+        );
+        assert_eq!(
+            state.resume_at("key", "ours", Some("merged"), Some("relocked")),
+            Step::Lock
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("cargo-merge-assist-resume-{}", std::process::id()))
+            .join("state.toml");
+        state.save(&path).unwrap();
+        assert_eq!(RunState::load(&path), Some(state));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!("lock".parse::<Step>(), Ok(Step::Lock));
+    }
+}
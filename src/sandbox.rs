@@ -0,0 +1,391 @@
+//! Regenerating `Cargo.lock` away from the working tree.
+//!
+//! `cargo generate-lockfile` rewrites the lockfile in place and the policy's
+//! pins rewrite it again, so a build or IDE running alongside the merge could
+//! read a lockfile that is only half resolved, or race cargo for it. Instead
+//! the lockfile is resolved in a [`Sandbox`]: a scratch copy of the workspace
+//! under `<git common dir>/cargo-merge-assist/sandbox/` (the system temporary
+//! directory outside a repository). It holds what resolution reads: every
+//! in-scope manifest, `Cargo.lock` and empty placeholders for the target
+//! files cargo infers from the layout. Path dependencies on manifests that
+//! were not copied (vendored, or outside the repository) point at the
+//! originals. Once the result is resolved and checked, [`Sandbox::commit`]
+//! replaces the repository's `Cargo.lock` with one rename.
+//!
+//! A sandbox under the repository's `.git` sees the same `.cargo/config.toml`
+//! and toolchain files as the repository; one elsewhere gets copies of those
+//! at the repository root.
+//!
+//! [`Sandbox::standalone`] holds contents that have no workspace on disk at
+//! all, for [`crate::lock`].
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::cache::git_common_dir;
+use crate::cleanup::{self, Pending};
+use crate::discover::Excludes;
+use crate::error::Error;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Files cargo reads besides the manifests, copied into a sandbox outside
+/// the repository.
+const SETTINGS: [&str; 4] = [
+    ".cargo/config.toml",
+    ".cargo/config",
+    "rust-toolchain.toml",
+    "rust-toolchain",
+];
+
+/// Numbers the sandboxes of one process.
+static SANDBOXES: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch copy of a workspace, removed when dropped (or when the process
+/// is interrupted).
+pub struct Sandbox {
+    root: PathBuf,
+    _cleanup: Pending,
+}
+
+impl Sandbox {
+    /// Copies the workspace at `repo` into a new sandbox.
+    pub fn new(repo: &Path) -> Result<Self, Error> {
+        let repo = fs::canonicalize(repo).map_err(|err| Error::io("reading", repo, &err))?;
+        let sandbox = Self::create(git_common_dir(&repo));
+        sandbox
+            .populate(&repo)
+            .map_err(|err| Error::io("copying the workspace into", &sandbox.root, &err))?;
+        Ok(sandbox)
+    }
+
+    /// A sandbox holding only `manifest` and `lock`. cargo needs the targets
+    /// a package declares to exist, so each gets an empty file.
+    pub fn standalone(manifest: &str, lock: &str) -> Result<Self, Error> {
+        let sandbox = Self::create(None);
+        let root = &sandbox.root;
+        let mut files = vec![
+            (PathBuf::from("Cargo.toml"), manifest),
+            (PathBuf::from("Cargo.lock"), lock),
+        ];
+        if let Ok(doc) = manifest.parse::<DocumentMut>() {
+            files.extend(declared_targets(&doc).into_iter().map(|file| (file, "")));
+        }
+        for (file, contents) in files {
+            sandbox
+                .write(root, &root.join(file), contents.as_bytes())
+                .map_err(|err| Error::io("writing into", root, &err))?;
+        }
+        Ok(sandbox)
+    }
+
+    /// A new, empty sandbox under the git common dir `git_dir`, else the
+    /// system temporary directory.
+    fn create(git_dir: Option<PathBuf>) -> Self {
+        let parent = match git_dir {
+            Some(dir) => dir.join("cargo-merge-assist").join("sandbox"),
+            None => std::env::temp_dir().join("cargo-merge-assist-sandbox"),
+        };
+        let root = parent.join(format!(
+            "{}-{}",
+            std::process::id(),
+            SANDBOXES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        Self {
+            _cleanup: cleanup::remove_dir_on_interrupt(&root),
+            root,
+        }
+    }
+
+    /// The directory to run cargo in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Replaces `repo`'s `Cargo.lock` with the sandbox's.
+    pub fn commit(&self, repo: &Path) -> Result<(), Error> {
+        let resolved = self.root.join("Cargo.lock");
+        let lock = fs::read(&resolved).map_err(|err| Error::io("reading", &resolved, &err))?;
+        let lockfile = repo.join("Cargo.lock");
+        cleanup::write_atomic(&lockfile, lock).map_err(|err| Error::io("writing", &lockfile, &err))
+    }
+
+    fn populate(&self, repo: &Path) -> io::Result<()> {
+        let excludes = Excludes::new(repo, &[], &[]).map_err(io::Error::other)?;
+        let manifests: Vec<PathBuf> = excludes
+            .manifests()
+            .into_iter()
+            .filter(|manifest| !manifest.starts_with(&self.root))
+            .collect();
+        let copied: BTreeSet<PathBuf> = manifests
+            .iter()
+            .filter_map(|manifest| manifest.parent()?.strip_prefix(repo).ok())
+            .map(Path::to_path_buf)
+            .collect();
+
+        for manifest in &manifests {
+            let (Some(dir), Ok(relative)) = (manifest.parent(), manifest.strip_prefix(repo)) else {
+                continue;
+            };
+            let text = fs::read_to_string(manifest)?;
+            let text = match text.parse::<DocumentMut>() {
+                Ok(mut doc) => {
+                    redirect(&mut doc, repo, dir, &copied);
+                    placeholders(&doc, dir)
+                        .iter()
+                        .try_for_each(|file| self.write(repo, file, b""))?;
+                    doc.to_string()
+                }
+                // Copied as is for cargo to report.
+                Err(_) => text,
+            };
+            self.write(repo, &repo.join(relative), text.as_bytes())?;
+        }
+
+        let mut files = vec!["Cargo.lock"];
+        if !self.root.starts_with(repo) {
+            files.extend(SETTINGS);
+        }
+        for file in files {
+            match fs::read(repo.join(file)) {
+                Ok(bytes) => self.write(repo, &repo.join(file), &bytes)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `contents` to the sandbox's copy of `file`, a path under `repo`.
+    fn write(&self, repo: &Path, file: &Path, contents: &[u8]) -> io::Result<()> {
+        let copy = self.root.join(file.strip_prefix(repo).unwrap_or(file));
+        if let Some(dir) = copy.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(copy, contents)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+        // Only succeeds once no other sandbox is left.
+        if let Some(parent) = self.root.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+}
+
+/// Points the path dependencies of the manifest in `dir` whose manifest is
+/// not among the `copied` directories (relative to `repo`) at the original.
+fn redirect(doc: &mut DocumentMut, repo: &Path, dir: &Path, copied: &BTreeSet<PathBuf>) {
+    let mut tables: Vec<&mut dyn TableLike> = Vec::new();
+    for (key, item) in doc.as_table_mut().iter_mut() {
+        let Some(table) = item.as_table_like_mut() else {
+            continue;
+        };
+        match key.get() {
+            "target" => {
+                for (_, target) in table.iter_mut() {
+                    for (kind, deps) in target
+                        .as_table_like_mut()
+                        .into_iter()
+                        .flat_map(|t| t.iter_mut())
+                    {
+                        if DEPENDENCY_TABLES.contains(&kind.get()) {
+                            tables.extend(deps.as_table_like_mut());
+                        }
+                    }
+                }
+            }
+            "workspace" => tables.extend(
+                table
+                    .get_mut("dependencies")
+                    .and_then(|deps| deps.as_table_like_mut()),
+            ),
+            "patch" => tables.extend(
+                table
+                    .iter_mut()
+                    .filter_map(|(_, source)| source.as_table_like_mut()),
+            ),
+            "replace" => tables.push(table),
+            kind if DEPENDENCY_TABLES.contains(&kind) => tables.push(table),
+            _ => {}
+        }
+    }
+
+    for table in tables {
+        for (_, spec) in table.iter_mut() {
+            let Some(spec) = spec.as_table_like_mut() else {
+                continue;
+            };
+            let Some(path) = spec.get("path").and_then(|path| path.as_str()) else {
+                continue;
+            };
+            let original = lexical(&dir.join(path));
+            let is_copied = original
+                .strip_prefix(repo)
+                .is_ok_and(|relative| copied.contains(relative));
+            if !is_copied && let Some(original) = original.to_str() {
+                spec.insert("path", toml_edit::value(original));
+            }
+        }
+    }
+}
+
+/// `src/lib.rs`, `src/main.rs` and `src/bin/*.rs` of the package in `dir`
+/// as far as they exist, when its manifest declares no `[lib]` or `[[bin]]`
+/// and cargo would otherwise find no targets.
+fn placeholders(doc: &DocumentMut, dir: &Path) -> Vec<PathBuf> {
+    if !doc.contains_key("package") || doc.contains_key("lib") || doc.contains_key("bin") {
+        return Vec::new();
+    }
+    let mut files: Vec<PathBuf> = ["src/lib.rs", "src/main.rs"]
+        .into_iter()
+        .map(|file| dir.join(file))
+        .filter(|file| file.is_file())
+        .collect();
+    files.extend(
+        fs::read_dir(dir.join("src").join("bin"))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|file| file.extension().is_some_and(|ext| ext == "rs")),
+    );
+    files
+}
+
+/// The target files of the package `doc` describes, relative to it: the
+/// default library, the build script, and every `[lib]`, `[[bin]]`,
+/// `[[example]]`, `[[test]]` and `[[bench]]` by its `path` or name. Paths
+/// leaving the package are left out.
+fn declared_targets(doc: &DocumentMut) -> Vec<PathBuf> {
+    let Some(package) = doc.get("package") else {
+        return Vec::new();
+    };
+    let mut files = vec![PathBuf::from("src/lib.rs")];
+    files.extend(
+        package
+            .get("build")
+            .and_then(|build| build.as_str())
+            .map(PathBuf::from),
+    );
+    files.extend(
+        doc.get("lib")
+            .and_then(|lib| lib.get("path"))
+            .and_then(|path| path.as_str())
+            .map(PathBuf::from),
+    );
+    for (kind, dir) in [
+        ("bin", "src/bin"),
+        ("example", "examples"),
+        ("test", "tests"),
+        ("bench", "benches"),
+    ] {
+        let targets = doc
+            .get(kind)
+            .and_then(|targets| targets.as_array_of_tables());
+        for target in targets.into_iter().flatten() {
+            let path = target.get("path").and_then(|path| path.as_str());
+            let name = target.get("name").and_then(|name| name.as_str());
+            match (path, name) {
+                (Some(path), _) => files.push(PathBuf::from(path)),
+                (None, Some(name)) => files.push(Path::new(dir).join(format!("{name}.rs"))),
+                (None, None) => {}
+            }
+        }
+    }
+    files.retain(|file| {
+        file.components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    });
+    files
+}
+
+/// `path` with `.` and `..` components resolved lexically.
+fn lexical(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_manifests_and_points_uncopied_paths_at_originals() {
+        let scratch = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-sandbox-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&scratch);
+        let repo = scratch.join("repo");
+        for dir in ["crates/a/src/bin", "crates/b", "vendor/c", "../outside"] {
+            fs::create_dir_all(repo.join(dir)).unwrap();
+        }
+        fs::write(
+            repo.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[patch.crates-io]\nc = { path = \"vendor/c\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join("crates/a/Cargo.toml"),
+            "[package]\nname = \"a\"\n\n[dependencies]\nb = { path = \"../b\" }\n\
+             d = { path = \"../../../outside\" }\n",
+        )
+        .unwrap();
+        fs::write(repo.join("crates/a/src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(repo.join("crates/a/src/bin/tool.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            repo.join("crates/b/Cargo.toml"),
+            "[package]\nname = \"b\"\n",
+        )
+        .unwrap();
+        fs::write(repo.join("Cargo.lock"), "version = 4\n").unwrap();
+        let repo = fs::canonicalize(&repo).unwrap();
+
+        let sandbox = Sandbox::new(&repo).unwrap();
+        let copy = sandbox.root().to_path_buf();
+        let read = |file: &str| fs::read_to_string(copy.join(file)).unwrap();
+        let vendored = repo.join("vendor/c");
+        assert!(read("Cargo.toml").contains(&format!("c = {{ path = {:?} }}", vendored)));
+        let outside = lexical(&repo.join("../outside"));
+        assert_eq!(
+            read("crates/a/Cargo.toml"),
+            format!(
+                "[package]\nname = \"a\"\n\n[dependencies]\nb = {{ path = \"../b\" }}\n\
+                 d = {{ path = {outside:?} }}\n"
+            )
+        );
+        assert_eq!(read("crates/a/src/main.rs"), "");
+        assert_eq!(read("crates/a/src/bin/tool.rs"), "");
+        assert_eq!(read("Cargo.lock"), "version = 4\n");
+        assert!(!copy.join("vendor").exists());
+
+        fs::write(copy.join("Cargo.lock"), "version = 4\n# resolved\n").unwrap();
+        sandbox.commit(&repo).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.join("Cargo.lock")).unwrap(),
+            "version = 4\n# resolved\n"
+        );
+        drop(sandbox);
+        assert!(!copy.exists());
+        fs::remove_dir_all(&scratch).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! `self-update`: replace the running binary with the latest GitHub release.
+//!
+//! Release assets are raw binaries named `cargo-merge-assist-<target>[.exe]`,
+//! each with a `.sha256` companion (the same layout `cargo binstall` uses via
+//! `[package.metadata.binstall]`). Downloads go through `curl`, and the
+//! checksum is verified before the current executable is replaced.
+//!
+//! The checksum comes from the same release as the binary, so it only
+//! catches a truncated or corrupted download. It is not a signature: the
+//! binary is only as trustworthy as the HTTPS connection to GitHub and the
+//! release itself. Releases are not signed yet; checking a signature against
+//! an embedded public key is what would guard against a tampered release.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+use crate::cleanup;
+
+const REPO: &str = "gyuro/cargo-merge-assist";
+const BIN_NAME: &str = "cargo-merge-assist";
+
+/// Target triple this binary was built for.
+pub const TARGET: &str = env!("CARGO_MERGE_ASSIST_TARGET");
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Download { url: String, detail: String },
+    BadRelease(String),
+    ChecksumMismatch { expected: String, actual: String },
+    Io(io::Error),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download { url, detail } => write!(f, "failed to download {url}: {detail}"),
+            Self::BadRelease(detail) => write!(f, "unexpected release metadata: {detail}"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch for downloaded binary (expected {expected}, got {actual})"
+            ),
+            Self::Io(err) => write!(f, "failed to replace executable: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+impl From<io::Error> for UpdateError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Outcome of [`self_update`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum UpdateStatus {
+    UpToDate { version: String },
+    Available { version: String },
+    Updated { from: String, to: String },
+}
+
+/// Checks the latest release and, unless `check_only`, installs it over the
+/// running executable. `force` reinstalls the latest release when it is the
+/// running version; an older release is never installed.
+pub fn self_update(check_only: bool, force: bool) -> Result<UpdateStatus, UpdateError> {
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = latest_release_version()?;
+
+    if !should_install(&latest, current, force) {
+        return Ok(UpdateStatus::UpToDate {
+            version: current.to_string(),
+        });
+    }
+    if check_only {
+        return Ok(UpdateStatus::Available { version: latest });
+    }
+
+    let asset_url = format!(
+        "https://github.com/{REPO}/releases/download/v{latest}/{}",
+        asset_name(TARGET)
+    );
+    let binary = fetch(&asset_url)?;
+    let checksum = String::from_utf8(fetch(&format!("{asset_url}.sha256"))?)
+        .map_err(|_| UpdateError::BadRelease("checksum file is not UTF-8".to_string()))?;
+    verify_checksum(&binary, &checksum)?;
+
+    let exe = std::env::current_exe()?;
+    replace_executable(&exe, &binary)?;
+
+    Ok(UpdateStatus::Updated {
+        from: current.to_string(),
+        to: latest,
+    })
+}
+
+fn latest_release_version() -> Result<String, UpdateError> {
+    let url = format!("https://api.github.com/repos/{REPO}/releases/latest");
+    let body = fetch(&url)?;
+    let release: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|err| UpdateError::BadRelease(format!("invalid JSON: {err}")))?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| UpdateError::BadRelease("missing tag_name".to_string()))?;
+    Ok(tag.trim_start_matches('v').to_string())
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, UpdateError> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=https"])
+        .arg(url)
+        .output()
+        .map_err(|err| UpdateError::Download {
+            url: url.to_string(),
+            detail: format!("could not run curl: {err}"),
+        })?;
+    if !output.status.success() {
+        return Err(UpdateError::Download {
+            url: url.to_string(),
+            detail: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+fn asset_name(target: &str) -> String {
+    let ext = if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    format!("{BIN_NAME}-{target}{ext}")
+}
+
+/// Whether `latest` replaces `current`: when newer, or when the same and
+/// `force`d. A build newer than the latest release (a local or pre-release
+/// build) is never downgraded.
+fn should_install(latest: &str, current: &str, force: bool) -> bool {
+    is_newer(latest, current) || (force && !is_newer(current, latest))
+}
+
+/// Compares dotted numeric versions; pre-release suffixes are ignored.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parse(version: &str) -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    }
+    parse(candidate) > parse(current)
+}
+
+/// Checks `data` against a `sha256sum`-style file (`<hex>  <name>` or bare
+/// hex): an integrity check against corrupted downloads, not authentication.
+fn verify_checksum(data: &[u8], checksum_file: &str) -> Result<(), UpdateError> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .filter(|hex| hex.len() == 64)
+        .ok_or_else(|| UpdateError::BadRelease("malformed checksum file".to_string()))?
+        .to_ascii_lowercase();
+    let actual: String = Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+
+    if actual != expected {
+        return Err(UpdateError::ChecksumMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+fn replace_executable(exe: &Path, binary: &[u8]) -> io::Result<()> {
+    let staged = sibling(exe, "new");
+    let _guard = cleanup::remove_on_interrupt(&staged);
+    fs::write(&staged, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Windows cannot overwrite a running executable, but it can rename it.
+    #[cfg(windows)]
+    {
+        let old = sibling(exe, "old");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+
+    fs::rename(&staged, exe).inspect_err(|_| {
+        let _ = fs::remove_file(&staged);
+    })
+}
+
+fn sibling(exe: &Path, suffix: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{suffix}"));
+    exe.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_release_versions() {
+        assert!(is_newer("0.2.0", "0.1.9"));
+        assert!(is_newer("0.10.0", "0.9.0"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1.0-rc.1", "0.1.0"));
+    }
+
+    #[test]
+    fn forces_reinstalls_but_never_downgrades() {
+        assert!(should_install("0.2.0", "0.1.0", false));
+        assert!(!should_install("0.1.0", "0.1.0", false));
+        assert!(should_install("0.1.0", "0.1.0", true));
+        assert!(!should_install("0.1.0", "0.2.0", true));
+        assert!(!should_install("0.1.0", "0.2.0-dev", true));
+    }
+
+    #[test]
+    fn names_assets_per_target() {
+        assert_eq!(
+            asset_name("x86_64-unknown-linux-gnu"),
+            "cargo-merge-assist-x86_64-unknown-linux-gnu"
+        );
+        assert_eq!(
+            asset_name("x86_64-pc-windows-msvc"),
+            "cargo-merge-assist-x86_64-pc-windows-msvc.exe"
+        );
+    }
+
+    #[test]
+    fn verifies_sha256_checksums() {
+        let sum = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert!(verify_checksum(b"abc", &format!("{sum}  cargo-merge-assist\n")).is_ok());
+        assert!(matches!(
+            verify_checksum(b"abd", sum),
+            Err(UpdateError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            verify_checksum(b"abc", "nope"),
+            Err(UpdateError::BadRelease(_))
+        ));
+    }
+
+    #[test]
+    fn replaces_executable_in_place() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-merge-assist-upd-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let exe = dir.join("tool");
+        fs::write(&exe, b"old").unwrap();
+
+        replace_executable(&exe, b"new").unwrap();
+
+        assert_eq!(fs::read(&exe).unwrap(), b"new");
+        assert!(!sibling(&exe, "new").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
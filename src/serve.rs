@@ -0,0 +1,418 @@
+//! Long-running server mode.
+//!
+//! `serve --stdio` speaks JSON-RPC 2.0 with one message per line, so IDEs and
+//! daemons can keep a warm process instead of spawning one per merge.
+//!
+//! Methods:
+//! - `mergeManifest` `{ base, ours, theirs }` → `{ status: "merged", merged }`
+//!   or `{ status: "conflict", conflict }`
+//!   (inputs that cannot be merged at all yield error code `-32000`)
+//! - `simulateMerge` `{ base, ours, theirs }` → `{ status: "clean",
+//!   resolutions, changes, summary }` or `{ status: "conflicts", conflicts,
+//!   summary }`: whether the merge would go through and what it would change
+//!   against ours, without the merged text (for forge badges)
+//! - `mergeLock` `{ manifest, base, ours, theirs, offline? }` →
+//!   `{ status: "merged", merged }`: the `Cargo.lock` contents merged for
+//!   `manifest`, the merged manifest; see [`crate::lock`] (lockfiles that
+//!   cannot be resolved yield error code `-32000`)
+//! - `shutdown` → `null`, then the server stops reading
+//!
+//! `serve --http` exposes the same merges over HTTP; see [`http`].
+//! `preview --http` serves a page for reviewing a single merge; see
+//! [`preview`].
+
+pub mod http;
+pub mod preview;
+
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::lock::merge_lock_texts;
+use crate::merge::{
+    MergeConflict, MergeError, MergeOptions, merge_manifest_texts, merge_manifest_texts_detailed,
+};
+use crate::release;
+use crate::summary::{self, Outcome};
+use crate::timings::Timings;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Implementation-defined server error: the inputs cannot be merged.
+const MERGE_FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct MergeManifestParams {
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+#[derive(Deserialize)]
+struct MergeLockParams {
+    manifest: String,
+    base: String,
+    ours: String,
+    theirs: String,
+    #[serde(default)]
+    offline: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum MergeOutcome {
+    Merged { merged: String },
+    Conflict { conflict: MergeConflict },
+}
+
+/// Runs a merge; conflicts are a regular outcome, other failures are errors.
+fn merge_outcome(base: &str, ours: &str, theirs: &str) -> Result<Value, MergeError> {
+    let outcome = match merge_manifest_texts(base, ours, theirs) {
+        Ok(merged) => MergeOutcome::Merged { merged },
+        Err(MergeError::Conflict(conflict)) => MergeOutcome::Conflict { conflict },
+        Err(err) => return Err(err),
+    };
+    Ok(serde_json::to_value(outcome).expect("merge outcome is always serializable"))
+}
+
+/// Merges the lockfiles of `manifest`; unlike manifests, they never
+/// conflict.
+fn lock_outcome(
+    manifest: &str,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    offline: bool,
+) -> Result<Value, crate::error::Error> {
+    let merged = merge_lock_texts(manifest, base, ours, theirs, offline)?;
+    Ok(serde_json::to_value(MergeOutcome::Merged { merged })
+        .expect("merge outcome is always serializable"))
+}
+
+/// Runs a merge of `manifest` for its outcome only: every conflict, or the
+/// automatic resolutions and release-relevant changes against ours.
+fn simulate_outcome(
+    manifest: &str,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+) -> Result<Value, MergeError> {
+    let options = MergeOptions {
+        all_conflicts: true,
+        ..MergeOptions::default()
+    };
+    let conflicts = match merge_manifest_texts_detailed(
+        base,
+        ours,
+        theirs,
+        &options,
+        &mut Timings::default(),
+    ) {
+        Ok(result) => {
+            let resolutions: Vec<Value> = result
+                .resolutions
+                .iter()
+                .map(|resolution| {
+                    json!({
+                        "path": resolution.path,
+                        "side": resolution.side,
+                        "rule": resolution.rule,
+                    })
+                })
+                .collect();
+            let rows = [(manifest.to_string(), Outcome::Merged(&result.resolutions))];
+            return Ok(json!({
+                "status": "clean",
+                "resolutions": resolutions,
+                "changes": release::changes(ours, &result.text).ok(),
+                "summary": summary::markdown(&rows),
+            }));
+        }
+        Err(MergeError::Report(report)) => report.conflicts,
+        Err(MergeError::Conflict(conflict)) => vec![conflict],
+        Err(err) => return Err(err),
+    };
+    let rows = [(manifest.to_string(), Outcome::Conflicts(&conflicts))];
+    Ok(json!({
+        "status": "conflicts",
+        "conflicts": conflicts,
+        "summary": summary::markdown(&rows),
+    }))
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Serves JSON-RPC requests from stdin until EOF or `shutdown`.
+pub fn serve_stdio() -> io::Result<()> {
+    serve_lines(io::stdin().lock(), io::stdout().lock())
+}
+
+/// Serves newline-delimited JSON-RPC requests from `reader`, writing one
+/// response line per request (notifications get none) and flushing after each.
+pub fn serve_lines<R: BufRead, W: Write>(reader: R, mut writer: W) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, stop) = handle_message(&line);
+        if let Some(response) = response {
+            writeln!(writer, "{response}")?;
+            writer.flush()?;
+        }
+        if stop {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn handle_message(line: &str) -> (Option<Value>, bool) {
+    let raw: Value = match serde_json::from_str(line) {
+        Ok(raw) => raw,
+        Err(err) => return (Some(error_response(Value::Null, PARSE_ERROR, err)), false),
+    };
+    let request: Request = match serde_json::from_value(raw) {
+        Ok(request) => request,
+        Err(err) => {
+            return (
+                Some(error_response(Value::Null, INVALID_REQUEST, err)),
+                false,
+            );
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        let id = request.id.unwrap_or(Value::Null);
+        return (
+            Some(error_response(
+                id,
+                INVALID_REQUEST,
+                "jsonrpc must be \"2.0\"",
+            )),
+            false,
+        );
+    }
+
+    let stop = request.method == "shutdown";
+    let result = dispatch(&request.method, request.params);
+
+    // Requests without an id are notifications and get no response.
+    let response = request.id.map(|id| match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => error_response(id, err.code, err.message),
+    });
+    (response, stop)
+}
+
+fn dispatch(method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "mergeManifest" => {
+            let params: MergeManifestParams =
+                serde_json::from_value(params).map_err(|err| RpcError {
+                    code: INVALID_PARAMS,
+                    message: err.to_string(),
+                })?;
+            merge_outcome(&params.base, &params.ours, &params.theirs).map_err(|err| RpcError {
+                code: MERGE_FAILED,
+                message: err.to_string(),
+            })
+        }
+        "simulateMerge" => {
+            let params: MergeManifestParams =
+                serde_json::from_value(params).map_err(|err| RpcError {
+                    code: INVALID_PARAMS,
+                    message: err.to_string(),
+                })?;
+            simulate_outcome("Cargo.toml", &params.base, &params.ours, &params.theirs).map_err(
+                |err| RpcError {
+                    code: MERGE_FAILED,
+                    message: err.to_string(),
+                },
+            )
+        }
+        "mergeLock" => {
+            let params: MergeLockParams =
+                serde_json::from_value(params).map_err(|err| RpcError {
+                    code: INVALID_PARAMS,
+                    message: err.to_string(),
+                })?;
+            lock_outcome(
+                &params.manifest,
+                &params.base,
+                &params.ours,
+                &params.theirs,
+                params.offline,
+            )
+            .map_err(|err| RpcError {
+                code: MERGE_FAILED,
+                message: err.to_string(),
+            })
+        }
+        "shutdown" => Ok(Value::Null),
+        other => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("unknown method `{other}`"),
+        }),
+    }
+}
+
+/// Whether `given` is the secret `expected`, comparing every byte so the
+/// time taken does not leak the length of a matching prefix.
+fn same_secret(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn error_response(id: Value, code: i64, message: impl ToString) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message.to_string() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> Vec<Value> {
+        let mut out = Vec::new();
+        serve_lines(input.as_bytes(), &mut out).expect("serve should not fail on in-memory io");
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn merges_manifest_and_reports_conflicts() {
+        let merge = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "mergeManifest",
+            "params": { "base": "[package]\nx = 1\n", "ours": "[package]\nx = 2\n", "theirs": "[package]\nx = 1\n" },
+        });
+        let conflict = json!({
+            "jsonrpc": "2.0", "id": 2, "method": "mergeManifest",
+            "params": { "base": "[package]\nx = 1\n", "ours": "[package]\nx = 2\n", "theirs": "[package]\nx = 3\n" },
+        });
+
+        let responses = run(&format!("{merge}\n{conflict}\n"));
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["status"], "merged");
+        assert_eq!(responses[0]["result"]["merged"], "[package]\nx = 2\n");
+        assert_eq!(responses[1]["result"]["status"], "conflict");
+        assert_eq!(responses[1]["result"]["conflict"]["path"], "package.x");
+    }
+
+    #[test]
+    fn reports_protocol_errors_and_stops_on_shutdown() {
+        let input = concat!(
+            "not json\n",
+            r#"{"jsonrpc":"2.0","id":1,"method":"mergeWorkspace","params":{}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"mergeManifest","params":{"base":""}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"shutdown"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+            "\n",
+        );
+
+        let responses = run(input);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn merges_lockfiles() {
+        let lock = |log: &str| {
+            format!("version = 3\n\n[[package]]\nname = \"log\"\nversion = \"{log}\"\n")
+        };
+        let merge = |id: u32, base: &str, ours: &str, theirs: &str| {
+            json!({
+                "jsonrpc": "2.0", "id": id, "method": "mergeLock",
+                "params": {
+                    "manifest": "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+                    "base": base, "ours": ours, "theirs": theirs, "offline": true,
+                },
+            })
+        };
+        let trivial = merge(1, &lock("0.4.20"), &lock("0.4.20"), &lock("0.4.21"));
+        let resolved = merge(2, &lock("0.4.20"), &lock("0.4.21"), &lock("0.4.22"));
+        let broken = merge(3, "", "[[package]", "version = 3\n");
+
+        let responses = run(&format!("{trivial}\n{resolved}\n{broken}\n"));
+        assert_eq!(responses[0]["result"]["status"], "merged");
+        assert_eq!(responses[0]["result"]["merged"], lock("0.4.21"));
+        let merged = responses[1]["result"]["merged"].as_str().unwrap();
+        assert!(
+            merged.contains("name = \"app\"") && !merged.contains("log"),
+            "{merged}"
+        );
+        assert_eq!(responses[2]["error"]["code"], MERGE_FAILED);
+    }
+
+    #[test]
+    fn simulates_merges_without_output() {
+        let simulate = |id: u32, ours: &str, theirs: &str| {
+            json!({
+                "jsonrpc": "2.0", "id": id, "method": "simulateMerge",
+                "params": {
+                    "base": "[package]\nversion = \"0.1.0\"\n[dependencies]\nlog = \"0.4\"\n",
+                    "ours": ours,
+                    "theirs": theirs,
+                },
+            })
+        };
+        let clean = simulate(
+            1,
+            "[package]\nversion = \"0.2.0\"\n[dependencies]\nlog = \"0.4\"\n",
+            "[package]\nversion = \"0.1.1\"\n[dependencies]\nlog = \"0.4\"\nserde = \"1\"\n",
+        );
+        let conflicting = simulate(
+            2,
+            "[package]\nversion = \"0.1.0\"\n[dependencies]\nlog = \"0.4.20\"\n",
+            "[package]\nversion = \"0.1.0\"\n[dependencies]\nlog = \"0.4.21\"\n",
+        );
+
+        let responses = run(&format!("{clean}\n{conflicting}\n"));
+        let clean = &responses[0]["result"];
+        assert_eq!(clean["status"], "clean");
+        assert!(clean.get("merged").is_none());
+        assert_eq!(clean["resolutions"][0]["path"], "package.version");
+        assert_eq!(clean["resolutions"][0]["side"], "ours");
+        assert_eq!(clean["changes"]["dependencies"][0]["package"], "serde");
+        assert!(
+            clean["summary"]
+                .as_str()
+                .unwrap()
+                .contains("1 resolved automatically")
+        );
+        let conflicting = &responses[1]["result"];
+        assert_eq!(conflicting["status"], "conflicts");
+        assert_eq!(conflicting["conflicts"][0]["path"], "dependencies.log");
+    }
+}
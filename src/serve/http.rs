@@ -0,0 +1,539 @@
+//! HTTP transport for merge-as-a-service.
+//!
+//! Endpoints (JSON in, JSON out):
+//! - `GET /health` → `{ "status": "ok" }`
+//! - `POST /v1/merge-manifest` with either inline contents
+//!   `{ base, ours, theirs }` or repository refs
+//!   `{ repo, base_ref, ours_ref, theirs_ref, path? }`; the response body is
+//!   the same outcome object as the JSON-RPC `mergeManifest` method.
+//! - `POST /v1/simulate` with the same body → the outcome object of the
+//!   JSON-RPC `simulateMerge` method: whether the merge would go through and
+//!   what it would change, without the merged text.
+//! - `POST /v1/merge-lock` with either inline contents
+//!   `{ manifest, base, ours, theirs, offline? }` or repository refs
+//!   `{ repo, base_ref, ours_ref, theirs_ref, path?, offline? }`; the
+//!   response body is the outcome object of the JSON-RPC `mergeLock` method.
+//!   With refs, the manifest is the merge of the `Cargo.toml` beside `path`,
+//!   and a conflict there is the outcome.
+//!
+//! Request bodies larger than [`MAX_BODY`] are refused with `413`.
+//!
+//! When a token is configured every request except `/health` must carry
+//! `Authorization: Bearer <token>`. Repository refs are only accepted when a
+//! repository root is configured, and `repo` is resolved inside it.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Response, Server};
+
+use super::{MergeOutcome, lock_outcome, merge_outcome, same_secret, simulate_outcome};
+use crate::merge::{MergeError, merge_manifest_texts};
+
+/// Largest request body read, in bytes; the three lockfiles of a large
+/// workspace fit well within it.
+pub const MAX_BODY: u64 = 32 * 1024 * 1024;
+
+/// Server settings for `serve --http`.
+#[derive(Debug, Clone, Default)]
+pub struct HttpConfig {
+    /// Bearer token required on every merge request.
+    pub token: Option<String>,
+    /// Directory containing repositories addressable by `repo` + refs.
+    pub repo_root: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MergeManifestBody {
+    Inline {
+        base: String,
+        ours: String,
+        theirs: String,
+    },
+    Refs {
+        repo: String,
+        base_ref: String,
+        ours_ref: String,
+        theirs_ref: String,
+        #[serde(default = "default_manifest_path")]
+        path: String,
+    },
+}
+
+fn default_manifest_path() -> String {
+    "Cargo.toml".to_string()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MergeLockBody {
+    Inline {
+        manifest: String,
+        base: String,
+        ours: String,
+        theirs: String,
+        #[serde(default)]
+        offline: bool,
+    },
+    Refs {
+        repo: String,
+        base_ref: String,
+        ours_ref: String,
+        theirs_ref: String,
+        #[serde(default = "default_lock_path")]
+        path: String,
+        #[serde(default)]
+        offline: bool,
+    },
+}
+
+fn default_lock_path() -> String {
+    "Cargo.lock".to_string()
+}
+
+/// Binds `addr` and serves requests until the process exits.
+pub fn serve_http(addr: &str, config: HttpConfig) -> io::Result<()> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    eprintln!("listening on http://{}", server.server_addr());
+
+    for mut request in server.incoming_requests() {
+        let auth = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.as_str().to_string());
+        let declared = request.body_length();
+        let (status, payload) = match read_body(request.as_reader(), declared, MAX_BODY) {
+            Ok(body) => route(
+                &config,
+                request.method(),
+                request.url(),
+                auth.as_deref(),
+                &body,
+            ),
+            Err(error) => error,
+        };
+
+        let header =
+            Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+        let response = Response::from_string(payload.to_string())
+            .with_status_code(status)
+            .with_header(header);
+        if let Err(err) = request.respond(response) {
+            eprintln!("warning: failed to send response: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a request body of at most `limit` bytes, whether its length was
+/// `declared` up front or not; the error is the response to send instead.
+pub(super) fn read_body(
+    reader: impl Read,
+    declared: Option<usize>,
+    limit: u64,
+) -> Result<String, (u16, Value)> {
+    let too_large = || {
+        (
+            413,
+            error_body(format!("request body exceeds {limit} bytes")),
+        )
+    };
+    if declared.is_some_and(|length| length as u64 > limit) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    reader
+        .take(limit + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| (400, error_body(format!("unreadable request body: {err}"))))?;
+    if body.len() as u64 > limit {
+        return Err(too_large());
+    }
+    String::from_utf8(body).map_err(|_| (400, error_body("request body is not UTF-8")))
+}
+
+fn route(
+    config: &HttpConfig,
+    method: &Method,
+    url: &str,
+    auth: Option<&str>,
+    body: &str,
+) -> (u16, Value) {
+    let path = url.split('?').next().unwrap_or(url);
+
+    match (method, path) {
+        (Method::Get, "/health") => (200, json!({ "status": "ok" })),
+        (Method::Post, "/v1/merge-manifest" | "/v1/simulate") => {
+            if !authorized(config, auth) {
+                return (401, error_body("missing or invalid bearer token"));
+            }
+            let simulate = path == "/v1/simulate";
+            match serde_json::from_str::<MergeManifestBody>(body) {
+                Ok(body) => merge_manifest(config, body, simulate),
+                Err(err) => (400, error_body(format!("invalid request body: {err}"))),
+            }
+        }
+        (Method::Post, "/v1/merge-lock") => {
+            if !authorized(config, auth) {
+                return (401, error_body("missing or invalid bearer token"));
+            }
+            match serde_json::from_str::<MergeLockBody>(body) {
+                Ok(body) => merge_lock(config, body),
+                Err(err) => (400, error_body(format!("invalid request body: {err}"))),
+            }
+        }
+        (_, "/health" | "/v1/merge-manifest" | "/v1/simulate" | "/v1/merge-lock") => {
+            (405, error_body("method not allowed"))
+        }
+        _ => (404, error_body("not found")),
+    }
+}
+
+/// Merges the manifests `body` names, or only simulates the merge.
+fn merge_manifest(config: &HttpConfig, body: MergeManifestBody, simulate: bool) -> (u16, Value) {
+    let respond = |path: &str, base: &str, ours: &str, theirs: &str| {
+        let outcome = if simulate {
+            simulate_outcome(path, base, ours, theirs)
+        } else {
+            merge_outcome(base, ours, theirs)
+        };
+        match outcome {
+            Ok(outcome) => (200, outcome),
+            Err(err) => (422, error_body(err.to_string())),
+        }
+    };
+    match body {
+        MergeManifestBody::Inline { base, ours, theirs } => {
+            respond(&default_manifest_path(), &base, &ours, &theirs)
+        }
+        MergeManifestBody::Refs {
+            repo,
+            base_ref,
+            ours_ref,
+            theirs_ref,
+            path,
+        } => {
+            let Some(root) = &config.repo_root else {
+                return (
+                    400,
+                    error_body("repository refs are disabled; start the server with --repo-root"),
+                );
+            };
+            let texts = resolve_repo(root, &repo).and_then(|repo| {
+                Ok((
+                    git_show(&repo, &base_ref, &path)?,
+                    git_show(&repo, &ours_ref, &path)?,
+                    git_show(&repo, &theirs_ref, &path)?,
+                ))
+            });
+            match texts {
+                Ok((base, ours, theirs)) => respond(&path, &base, &ours, &theirs),
+                Err(message) => (422, error_body(message)),
+            }
+        }
+    }
+}
+
+/// Merges the lockfiles `body` names.
+fn merge_lock(config: &HttpConfig, body: MergeLockBody) -> (u16, Value) {
+    let respond =
+        |manifest: &str, base: &str, ours: &str, theirs: &str, offline: bool| match lock_outcome(
+            manifest, base, ours, theirs, offline,
+        ) {
+            Ok(outcome) => (200, outcome),
+            Err(err) => (422, error_body(err.to_string())),
+        };
+    match body {
+        MergeLockBody::Inline {
+            manifest,
+            base,
+            ours,
+            theirs,
+            offline,
+        } => respond(&manifest, &base, &ours, &theirs, offline),
+        MergeLockBody::Refs {
+            repo,
+            base_ref,
+            ours_ref,
+            theirs_ref,
+            path,
+            offline,
+        } => {
+            let Some(root) = &config.repo_root else {
+                return (
+                    400,
+                    error_body("repository refs are disabled; start the server with --repo-root"),
+                );
+            };
+            let manifest_path = match path.rsplit_once('/') {
+                Some((dir, _)) => format!("{dir}/Cargo.toml"),
+                None => default_manifest_path(),
+            };
+            let texts = resolve_repo(root, &repo).and_then(|repo| {
+                let show = |path: &str| {
+                    Ok::<_, String>([
+                        git_show(&repo, &base_ref, path)?,
+                        git_show(&repo, &ours_ref, path)?,
+                        git_show(&repo, &theirs_ref, path)?,
+                    ])
+                };
+                Ok((show(&manifest_path)?, show(&path)?))
+            });
+            let ([base, ours, theirs], locks) = match texts {
+                Ok(texts) => texts,
+                Err(message) => return (422, error_body(message)),
+            };
+            let manifest = match merge_manifest_texts(&base, &ours, &theirs) {
+                Ok(manifest) => manifest,
+                Err(MergeError::Conflict(conflict)) => {
+                    let outcome = MergeOutcome::Conflict { conflict };
+                    return (
+                        200,
+                        serde_json::to_value(outcome)
+                            .expect("merge outcome is always serializable"),
+                    );
+                }
+                Err(err) => return (422, error_body(err.to_string())),
+            };
+            let [base, ours, theirs] = locks;
+            respond(&manifest, &base, &ours, &theirs, offline)
+        }
+    }
+}
+
+fn authorized(config: &HttpConfig, auth: Option<&str>) -> bool {
+    let Some(expected) = &config.token else {
+        return true;
+    };
+    let Some(given) = auth.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    same_secret(given, expected)
+}
+
+fn resolve_repo(root: &Path, repo: &str) -> Result<PathBuf, String> {
+    let root = root
+        .canonicalize()
+        .map_err(|err| format!("repository root {} is unusable: {err}", root.display()))?;
+    let candidate = root
+        .join(repo)
+        .canonicalize()
+        .map_err(|_| format!("unknown repository `{repo}`"))?;
+    if !candidate.starts_with(&root) {
+        return Err(format!(
+            "repository `{repo}` is outside the repository root"
+        ));
+    }
+    Ok(candidate)
+}
+
+fn git_show(repo: &Path, rev: &str, path: &str) -> Result<String, String> {
+    if rev.is_empty() || rev.starts_with('-') || rev.contains(':') {
+        return Err(format!("invalid ref `{rev}`"));
+    }
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .arg("show")
+        .arg(format!("{rev}:{path}"))
+        .output()
+        .map_err(|err| format!("failed to run git: {err}"))?;
+    if !output.status.success() {
+        return Err(format!("cannot read `{path}` at `{rev}`"));
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("`{path}` at `{rev}` is not UTF-8"))
+}
+
+fn error_body(message: impl Into<String>) -> Value {
+    json!({ "error": message.into() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn post(config: &HttpConfig, auth: Option<&str>, body: Value) -> (u16, Value) {
+        route(
+            config,
+            &Method::Post,
+            "/v1/merge-manifest",
+            auth,
+            &body.to_string(),
+        )
+    }
+
+    #[test]
+    fn merges_inline_contents() {
+        let (status, body) = post(
+            &HttpConfig::default(),
+            None,
+            json!({ "base": "a = 1\n", "ours": "a = 2\n", "theirs": "a = 1\n" }),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "merged");
+        assert_eq!(body["merged"], "a = 2\n");
+    }
+
+    #[test]
+    fn simulates_inline_contents() {
+        let (status, body) = route(
+            &HttpConfig::default(),
+            &Method::Post,
+            "/v1/simulate",
+            None,
+            &json!({ "base": "a = 1\n", "ours": "a = 2\n", "theirs": "a = 3\n" }).to_string(),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "conflicts");
+        assert_eq!(body["conflicts"][0]["path"], "a");
+    }
+
+    #[test]
+    fn merges_lockfiles_from_contents_or_refs() {
+        let lock = "version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n";
+        let (status, body) = route(
+            &HttpConfig::default(),
+            &Method::Post,
+            "/v1/merge-lock",
+            None,
+            &json!({ "manifest": "", "base": "", "ours": lock, "theirs": "" }).to_string(),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["merged"], lock);
+
+        let root = std::env::temp_dir().join(format!(
+            "cargo-merge-assist-http-lock-{}",
+            std::process::id()
+        ));
+        let repo = root.join("repo");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(repo.join("crate")).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        let commit = |branch: &str, log: &str, lock: &str| {
+            git(&["checkout", "-q", "-B", branch, "base"]);
+            fs::write(
+                repo.join("crate/Cargo.toml"),
+                format!("[package]\nname = \"app\"\n\n[dependencies]\nlog = \"{log}\"\n"),
+            )
+            .unwrap();
+            fs::write(repo.join("crate/Cargo.lock"), lock).unwrap();
+            git(&["add", "-A"]);
+            git(&["commit", "-q", "-m", branch]);
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "root"]);
+        git(&["branch", "base"]);
+        commit("base", "0.4", "");
+        commit("ours", "0.4.20", lock);
+        commit("theirs", "0.4.21", "");
+
+        let config = HttpConfig {
+            repo_root: Some(root.clone()),
+            ..HttpConfig::default()
+        };
+        let refs = |theirs: &str| {
+            json!({
+                "repo": "repo", "base_ref": "base", "ours_ref": "ours",
+                "theirs_ref": theirs, "path": "crate/Cargo.lock",
+            })
+            .to_string()
+        };
+        let (status, body) = route(
+            &config,
+            &Method::Post,
+            "/v1/merge-lock",
+            None,
+            &refs("theirs"),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "conflict");
+        assert_eq!(body["conflict"]["path"], "dependencies.log");
+        let (status, body) = route(
+            &config,
+            &Method::Post,
+            "/v1/merge-lock",
+            None,
+            &refs("base"),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["merged"], lock);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn caps_request_bodies() {
+        assert_eq!(read_body(&b"{}"[..], Some(2), 2).unwrap(), "{}");
+        assert_eq!(read_body(&b"{}"[..], Some(3), 2).unwrap_err().0, 413);
+        // A chunked body has no declared length; it is cut off while read.
+        assert_eq!(read_body(&b"{ }"[..], None, 2).unwrap_err().0, 413);
+        assert_eq!(read_body(&b"\xff"[..], None, 2).unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn enforces_bearer_token() {
+        let config = HttpConfig {
+            token: Some("s3cret".to_string()),
+            ..HttpConfig::default()
+        };
+        let body = json!({ "base": "", "ours": "", "theirs": "" });
+
+        assert_eq!(post(&config, None, body.clone()).0, 401);
+        assert_eq!(post(&config, Some("Bearer nope!!"), body.clone()).0, 401);
+        assert_eq!(post(&config, Some("Bearer s3cret"), body).0, 200);
+        assert_eq!(
+            route(&config, &Method::Get, "/health", None, "").0,
+            200,
+            "health checks stay unauthenticated"
+        );
+    }
+
+    #[test]
+    fn rejects_refs_without_repo_root_and_unknown_routes() {
+        let (status, _) = post(
+            &HttpConfig::default(),
+            None,
+            json!({ "repo": "x", "base_ref": "a", "ours_ref": "b", "theirs_ref": "c" }),
+        );
+        assert_eq!(status, 400);
+        assert_eq!(
+            route(&HttpConfig::default(), &Method::Get, "/nope", None, "").0,
+            404
+        );
+        assert_eq!(
+            route(
+                &HttpConfig::default(),
+                &Method::Get,
+                "/v1/merge-manifest",
+                None,
+                ""
+            )
+            .0,
+            405
+        );
+    }
+
+    #[test]
+    fn refuses_repositories_outside_root() {
+        let root = std::env::temp_dir();
+        assert!(resolve_repo(&root, "..").is_err());
+        assert!(git_show(&root, "--output=/tmp/x", "Cargo.toml").is_err());
+    }
+}
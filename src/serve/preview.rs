@@ -0,0 +1,460 @@
+//! Conflict resolution preview for code review (`preview --http`).
+//!
+//! Serves one HTML page showing the three inputs, the merged result and each
+//! conflict with buttons to take ours or theirs. Every choice is written to
+//! a [resolutions file](crate::resolutions) right away and folded into the
+//! result shown; conflicts not decided yet keep ours there, as with `edit`.
+//!
+//! Routes:
+//! - `GET /` → the page
+//! - `POST /choose` with the form fields `fingerprint`, `take`
+//!   (`ours`/`theirs`) and `nonce` → records the choice and redirects to `/`
+//!
+//! The `nonce` is drawn per server and only appears on the page, which other
+//! sites cannot read, so a form they post to `/choose` is refused (`403`).
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use tiny_http::{Header, Method, Response, Server};
+
+use super::http::{MAX_BODY, read_body};
+use super::same_secret;
+use crate::cleanup;
+use crate::edit::provisional_merge;
+use crate::merge::{MergeConflict, MergeError, MergeOptions, Side};
+use crate::resolutions::{self, Choice, Take};
+use crate::suppress::Suppression;
+
+/// A merge under review and the choices made so far.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    base: String,
+    ours: String,
+    theirs: String,
+    options: MergeOptions,
+    /// Where choices are written.
+    path: PathBuf,
+    choices: Vec<Choice>,
+    /// Required with every choice; see the module documentation.
+    nonce: String,
+}
+
+impl Preview {
+    pub fn new(
+        base: String,
+        ours: String,
+        theirs: String,
+        options: MergeOptions,
+        path: PathBuf,
+    ) -> Self {
+        Self {
+            base,
+            ours,
+            theirs,
+            options,
+            path,
+            choices: Vec::new(),
+            nonce: session_nonce(),
+        }
+    }
+
+    /// The conflicts of the merge, and its result with the choices applied.
+    fn merge(&self) -> Result<(Vec<MergeConflict>, String), MergeError> {
+        let (_, mut conflicts) =
+            provisional_merge(&self.base, &self.ours, &self.theirs, &self.options)?;
+        let mut options = self.options.clone();
+        options
+            .suppressions
+            .extend(self.choices.iter().filter_map(|choice| {
+                let Take::Side(side) = choice.take else {
+                    return None;
+                };
+                Some(Suppression {
+                    fingerprint: choice.fingerprint.clone()?,
+                    side,
+                })
+            }));
+        let (merged, revealed) = provisional_merge(&self.base, &self.ours, &self.theirs, &options)?;
+        // A choice can uncover conflicts further down, e.g. inside a table
+        // taken from theirs.
+        for conflict in revealed {
+            if !conflicts
+                .iter()
+                .any(|known| known.fingerprint == conflict.fingerprint)
+            {
+                conflicts.push(conflict);
+            }
+        }
+        Ok((conflicts, merged))
+    }
+
+    /// Records `side` for the conflict `fingerprint` and rewrites the
+    /// resolutions file.
+    pub fn choose(&mut self, fingerprint: &str, side: Side) -> io::Result<()> {
+        let conflicts = self
+            .merge()
+            .map(|(conflicts, _)| conflicts)
+            .unwrap_or_default();
+        let Some(conflict) = conflicts
+            .iter()
+            .find(|conflict| conflict.fingerprint == fingerprint)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no conflict with id {fingerprint}"),
+            ));
+        };
+        let choice = Choice {
+            fingerprint: Some(conflict.fingerprint.clone()),
+            path: Some(conflict.path.clone()),
+            take: Take::Side(side),
+        };
+        match self
+            .choices
+            .iter_mut()
+            .find(|known| known.fingerprint == choice.fingerprint)
+        {
+            Some(known) => *known = choice,
+            None => self.choices.push(choice),
+        }
+        cleanup::write_atomic(&self.path, resolutions::render(&self.choices))
+    }
+
+    /// The review page.
+    pub fn page(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+             <title>cargo-merge-assist preview</title>\n<style>\
+             body{font-family:sans-serif;margin:2em}pre{background:#f4f4f4;padding:.5em;overflow:auto}\
+             section{border:1px solid #ccc;padding:.5em 1em;margin:1em 0}\
+             .chosen{font-weight:bold}form{display:inline}\
+             </style></head><body>\n<h1>Merge preview</h1>\n",
+        );
+        let (conflicts, merged) = match self.merge() {
+            Ok(merge) => merge,
+            Err(err) => {
+                let _ = writeln!(
+                    html,
+                    "<p>The inputs cannot be merged: {}</p>",
+                    escape(&err.to_string())
+                );
+                (Vec::new(), String::new())
+            }
+        };
+
+        let decided = |conflict: &MergeConflict| {
+            self.choices
+                .iter()
+                .find_map(|choice| match (&choice.fingerprint, &choice.take) {
+                    (Some(id), Take::Side(side)) if *id == conflict.fingerprint => Some(*side),
+                    _ => None,
+                })
+        };
+        let open = conflicts
+            .iter()
+            .filter(|conflict| decided(conflict).is_none())
+            .count();
+        let _ = writeln!(
+            html,
+            "<h2>Conflicts ({} of {} open)</h2>",
+            open,
+            conflicts.len()
+        );
+        for conflict in &conflicts {
+            let chosen = decided(conflict);
+            let _ = writeln!(
+                html,
+                "<section id=\"{id}\"><h3><code>{path}</code> <small>id {id}</small></h3>",
+                id = escape(&conflict.fingerprint),
+                path = escape(&conflict.path)
+            );
+            let rows = [
+                (Side::Base, &conflict.base),
+                (Side::Ours, &conflict.ours),
+                (Side::Theirs, &conflict.theirs),
+            ];
+            html.push_str("<table>\n");
+            for (side, value) in rows {
+                let value = value
+                    .as_ref()
+                    .map_or("<deleted>".to_string(), ToString::to_string);
+                let class = if chosen == Some(side) {
+                    " class=\"chosen\""
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    html,
+                    "<tr{class}><th>{side}</th><td><pre>{}</pre></td></tr>",
+                    escape(&value)
+                );
+            }
+            html.push_str("</table>\n");
+            for side in [Side::Ours, Side::Theirs] {
+                let _ = writeln!(
+                    html,
+                    "<form method=\"post\" action=\"/choose\">\
+                     <input type=\"hidden\" name=\"fingerprint\" value=\"{}\">\
+                     <input type=\"hidden\" name=\"nonce\" value=\"{}\">\
+                     <button name=\"take\" value=\"{side}\">Accept {side}</button></form>",
+                    escape(&conflict.fingerprint),
+                    self.nonce
+                );
+            }
+            let status = match chosen {
+                Some(side) => format!("resolved: {side}"),
+                None => "open (ours shown below)".to_string(),
+            };
+            let _ = writeln!(html, " <em>{status}</em></section>");
+        }
+
+        let _ = writeln!(
+            html,
+            "<h2>Result</h2>\n<pre>{}</pre>\n<h2>Inputs</h2>",
+            escape(&merged)
+        );
+        for (name, text) in [
+            ("base", &self.base),
+            ("ours", &self.ours),
+            ("theirs", &self.theirs),
+        ] {
+            let _ = writeln!(
+                html,
+                "<details><summary>{name}</summary><pre>{}</pre></details>",
+                escape(text)
+            );
+        }
+        let _ = writeln!(
+            html,
+            "<p>Choices are saved to <code>{}</code>.</p>\n</body></html>",
+            escape(&self.path.display().to_string())
+        );
+        html
+    }
+}
+
+/// A response: status, content type and body, plus a redirect target.
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    location: Option<&'static str>,
+}
+
+impl Reply {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Reply {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+            location: None,
+        }
+    }
+}
+
+/// Binds `addr` and serves the preview until the process exits.
+pub fn serve_preview(addr: &str, mut preview: Preview) -> io::Result<()> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    eprintln!("preview at http://{}/", server.server_addr());
+
+    for mut request in server.incoming_requests() {
+        let declared = request.body_length();
+        let reply = match read_body(request.as_reader(), declared, MAX_BODY) {
+            Ok(body) => route(&mut preview, request.method(), request.url(), &body),
+            Err((status, error)) => Reply::text(status, error["error"].as_str().unwrap_or("")),
+        };
+
+        let mut response = Response::from_string(reply.body)
+            .with_status_code(reply.status)
+            .with_header(
+                Header::from_bytes("Content-Type", reply.content_type)
+                    .expect("static header is valid"),
+            );
+        if let Some(location) = reply.location {
+            response = response.with_header(
+                Header::from_bytes("Location", location).expect("static header is valid"),
+            );
+        }
+        if let Err(err) = request.respond(response) {
+            eprintln!("warning: failed to send response: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+fn route(preview: &mut Preview, method: &Method, url: &str, body: &str) -> Reply {
+    let path = url.split('?').next().unwrap_or(url);
+    match (method, path) {
+        (Method::Get, "/") => Reply {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: preview.page(),
+            location: None,
+        },
+        (Method::Post, "/choose") => {
+            let field = |name: &str| {
+                body.split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.trim())
+            };
+            if !field("nonce").is_some_and(|nonce| same_secret(nonce, &preview.nonce)) {
+                return Reply::text(403, "missing or stale `nonce`; reload the page");
+            }
+            let side = match field("take") {
+                Some("ours") => Side::Ours,
+                Some("theirs") => Side::Theirs,
+                _ => return Reply::text(400, "`take` must be ours or theirs"),
+            };
+            let Some(fingerprint) = field("fingerprint") else {
+                return Reply::text(400, "missing `fingerprint`");
+            };
+            match preview.choose(fingerprint, side) {
+                Ok(()) => Reply {
+                    status: 303,
+                    content_type: "text/plain; charset=utf-8",
+                    body: String::new(),
+                    location: Some("/"),
+                },
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    Reply::text(404, err.to_string())
+                }
+                Err(err) => Reply::text(
+                    500,
+                    format!("failed writing {}: {err}", preview.path.display()),
+                ),
+            }
+        }
+        (_, "/" | "/choose") => Reply::text(405, "method not allowed"),
+        _ => Reply::text(404, "not found"),
+    }
+}
+
+/// 128 bits in hex that nothing outside this process can predict: the
+/// standard library seeds each [`RandomState`] from the operating system.
+fn session_nonce() -> String {
+    let mut hasher = Sha256::new();
+    for _ in 0..2 {
+        hasher.update(RandomState::new().hash_one(SystemTime::now()).to_le_bytes());
+    }
+    hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn records_choices_and_shows_their_result() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-merge-assist-preview-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let base = "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+        let ours = base
+            .replace("0.1.0", "0.2.0")
+            .replace("\"1\"", "\"1.0.200\"");
+        let theirs = base
+            .replace("0.1.0", "0.3.0")
+            .replace("\"1\"", "\"1.0.199\"");
+        let mut preview = Preview::new(
+            base.to_string(),
+            ours,
+            theirs,
+            // Keep the version bumps a conflict.
+            MergeOptions {
+                highest_version: false,
+                ..MergeOptions::default()
+            },
+            dir.join("resolutions.toml"),
+        );
+
+        let page = route(&mut preview, &Method::Get, "/", "").body;
+        assert!(page.contains("<h2>Conflicts (2 of 2 open)</h2>"), "{page}");
+        assert!(page.contains("<code>package.version</code>"), "{page}");
+        assert!(page.contains("&quot;0.3.0&quot;"), "{page}");
+
+        let nonce = preview.nonce.clone();
+        assert_eq!(nonce.len(), 32);
+        assert!(
+            page.contains(&format!("name=\"nonce\" value=\"{nonce}\"")),
+            "{page}"
+        );
+        assert_ne!(
+            Preview::new(
+                String::new(),
+                String::new(),
+                String::new(),
+                MergeOptions::default(),
+                dir.clone()
+            )
+            .nonce,
+            nonce
+        );
+
+        let (conflicts, _) = preview.merge().unwrap();
+        let id = &conflicts[1].fingerprint;
+        let mut choose = |form: &str| route(&mut preview, &Method::Post, "/choose", form);
+        // A form posted from another site cannot know the nonce.
+        assert_eq!(choose(&format!("fingerprint={id}&take=theirs")).status, 403);
+        assert_eq!(
+            choose(&format!("fingerprint={id}&take=theirs&nonce=00")).status,
+            403
+        );
+        for take in ["take=base", "take=", "take=Ours", ""] {
+            let reply = choose(&format!("fingerprint={id}&{take}&nonce={nonce}"));
+            assert_eq!(reply.status, 400, "{take}");
+        }
+        assert_eq!(choose(&format!("take=ours&nonce={nonce}")).status, 400);
+        let reply = choose(&format!("fingerprint=00&take=ours&nonce={nonce}"));
+        assert_eq!(reply.status, 404);
+        assert!(!preview.path.exists());
+
+        let reply = route(
+            &mut preview,
+            &Method::Post,
+            "/choose",
+            &format!("fingerprint={id}&take=theirs&nonce={nonce}"),
+        );
+        assert_eq!((reply.status, reply.location), (303, Some("/")));
+        assert_eq!(
+            fs::read_to_string(&preview.path).unwrap(),
+            format!(
+                "[[resolution]]\nfingerprint = \"{id}\"\npath = \"package.version\"\ntake = \"theirs\"\n"
+            )
+        );
+        let page = preview.page();
+        assert!(page.contains("(1 of 2 open)"), "{page}");
+        assert!(page.contains("version = &quot;0.3.0&quot;"), "{page}");
+        assert_eq!(route(&mut preview, &Method::Get, "/choose", "").status, 405);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+//! Dependency shorthand (`serde = "1"`) against the table form.
+//!
+//! `serde = "1"` and `serde = { version = "1" }` are the same dependency. A
+//! branch that rewrites the shorthand as a table to add features would
+//! otherwise conflict with any requirement change on the other branch, a
+//! string against a table. Before merging, the string forms of an entry that
+//! is a table on some side are expanded to `{ version = ... }`, so the
+//! requirement and the added fields merge key by key. A merged entry left
+//! with only `version` where ours used the shorthand is collapsed back to
+//! it.
+
+use toml::Value;
+
+use crate::pins::{dependency_tables, lookup, lookup_mut};
+
+/// Expands shorthand entries whose form differs between the inputs. Returns
+/// the paths of the entries ours wrote as shorthand, for [`collapse`].
+pub(crate) fn expand(base: &mut Value, ours: &mut Value, theirs: &mut Value) -> Vec<Vec<String>> {
+    let mut shorthand = Vec::new();
+    for table in dependency_tables([base, ours, theirs]) {
+        let mut keys: Vec<String> = [&*base, &*ours, &*theirs]
+            .iter()
+            .filter_map(|doc| lookup(doc, &table).and_then(Value::as_table))
+            .flat_map(|entries| entries.keys().cloned())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys {
+            let mut path = table.clone();
+            path.push(key);
+            let entries = [&*base, &*ours, &*theirs].map(|doc| lookup(doc, &path));
+            let mixed = entries.iter().flatten().any(|entry| entry.is_table())
+                && entries.iter().flatten().any(|entry| entry.is_str());
+            if !mixed {
+                continue;
+            }
+            if entries[1].is_some_and(Value::is_str) {
+                shorthand.push(path.clone());
+            }
+            for doc in [&mut *base, &mut *ours, &mut *theirs] {
+                if let Some(entry) = lookup_mut(doc, &path)
+                    && let Value::String(requirement) = entry
+                {
+                    let mut table = toml::Table::new();
+                    table.insert("version".to_string(), Value::String(requirement.clone()));
+                    *entry = Value::Table(table);
+                }
+            }
+        }
+    }
+    shorthand
+}
+
+/// Turns the entries at `paths` back into shorthand where `merged` has only
+/// their `version`.
+pub(crate) fn collapse(merged: &mut Value, paths: &[Vec<String>]) {
+    for path in paths {
+        if let Some(entry) = lookup_mut(merged, path)
+            && let Some(table) = entry.as_table()
+            && table.len() == 1
+            && let Some(Value::String(requirement)) = table.get("version")
+        {
+            *entry = Value::String(requirement.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeOptions, merge_manifest_texts_with};
+
+    #[test]
+    fn merges_shorthand_with_table_form() {
+        let base = "[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+        let ours = "[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\n\
+                    log = \"0.4.20\"\n";
+        let theirs = "[dependencies]\nserde = \"1.0.200\"\nlog = { version = \"0.4\" }\n";
+        let options = MergeOptions::default();
+
+        let merged = merge_manifest_texts_with(base, ours, theirs, &options).unwrap();
+        assert_eq!(
+            merged,
+            "[dependencies]\nserde = { version = \"1.0.200\", features = [\"derive\"] }\n\
+             log = \"0.4.20\"\n"
+        );
+        let merged = merge_manifest_texts_with(base, theirs, ours, &options).unwrap();
+        assert!(
+            merged.contains("log = { version = \"0.4.20\" }"),
+            "{merged}"
+        );
+    }
+}
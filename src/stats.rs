@@ -0,0 +1,316 @@
+//! Aggregates over the [metrics log](crate::metrics) (`stats`).
+//!
+//! Answers how many Cargo merges happened in a time range, how many of them
+//! completed without manual resolution, which dependencies conflict most and
+//! how long merges take. Records outside the range, and lines that are not
+//! merge records, are skipped, so logs concatenated from several machines
+//! can be fed in as they are.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::dependency_of;
+use crate::error::Error;
+
+/// A time range over record timestamps (seconds since the Unix epoch); both
+/// ends are inclusive and optional.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Range {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl Range {
+    fn contains(&self, timestamp: u64) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp <= until)
+    }
+}
+
+/// Merge statistics for a range.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// Logged merges in the range.
+    pub merges: usize,
+    pub merged: usize,
+    pub conflicts: usize,
+    pub errors: usize,
+    /// Share of merges that ran to completion (merged or conflicting) that
+    /// needed no manual resolution; `None` without any.
+    pub auto_resolution_rate: Option<f64>,
+    /// Successful merges by what resolved them: `trivial`, `cache` or
+    /// `semantic`.
+    pub resolutions: BTreeMap<String, usize>,
+    /// Dependencies by the number of merges they conflicted in, most first.
+    pub conflict_prone: Vec<DependencyConflicts>,
+    /// Mean total duration of the merges that recorded timings.
+    pub average_ms: Option<f64>,
+    /// Merge commits in the range that changed Cargo files relative to every
+    /// parent, when history was mined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_merges: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyConflicts {
+    pub dependency: String,
+    pub merges: usize,
+}
+
+/// The fields of a [`MergeRecord`](crate::metrics::MergeRecord) read back.
+#[derive(Debug, Deserialize)]
+struct Record {
+    timestamp: u64,
+    outcome: String,
+    #[serde(default)]
+    resolution: Option<String>,
+    #[serde(default)]
+    conflict_paths: Vec<String>,
+    #[serde(default)]
+    durations_ms: BTreeMap<String, f64>,
+}
+
+/// Aggregates the records of `log` (JSON lines) within `range`, listing at
+/// most `top` dependencies.
+pub fn aggregate(log: &str, range: Range, top: usize) -> Stats {
+    let mut stats = Stats::default();
+    let mut by_dependency: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut total_ms, mut timed) = (0.0, 0);
+    for line in log.lines() {
+        let Ok(record) = serde_json::from_str::<Record>(line) else {
+            continue;
+        };
+        if !range.contains(record.timestamp) {
+            continue;
+        }
+        stats.merges += 1;
+        match record.outcome.as_str() {
+            "merged" => {
+                stats.merged += 1;
+                let resolution = record.resolution.unwrap_or_else(|| "unknown".to_string());
+                *stats.resolutions.entry(resolution).or_default() += 1;
+            }
+            "conflict" => stats.conflicts += 1,
+            _ => stats.errors += 1,
+        }
+        let mut dependencies: Vec<&str> = record
+            .conflict_paths
+            .iter()
+            .filter_map(|path| dependency_of(&path.split('.').collect::<Vec<_>>()))
+            .collect();
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        for dependency in dependencies {
+            *by_dependency.entry(dependency.to_string()).or_default() += 1;
+        }
+        if !record.durations_ms.is_empty() {
+            total_ms += record.durations_ms.values().sum::<f64>();
+            timed += 1;
+        }
+    }
+
+    let completed = stats.merged + stats.conflicts;
+    stats.auto_resolution_rate = (completed > 0).then(|| stats.merged as f64 / completed as f64);
+    stats.average_ms = (timed > 0).then(|| total_ms / f64::from(timed));
+    let mut prone: Vec<DependencyConflicts> = by_dependency
+        .into_iter()
+        .map(|(dependency, merges)| DependencyConflicts { dependency, merges })
+        .collect();
+    // Stable: ties stay in name order.
+    prone.sort_by_key(|entry| std::cmp::Reverse(entry.merges));
+    prone.truncate(top);
+    stats.conflict_prone = prone;
+    stats
+}
+
+/// Renders `stats` as a plain-text table.
+pub fn table(stats: &Stats) -> String {
+    let mut out = String::new();
+    let mut row = |label: &str, value: String| {
+        let _ = writeln!(out, "{label:<22} {value}");
+    };
+    row("merges", stats.merges.to_string());
+    row("  merged", stats.merged.to_string());
+    for (resolution, count) in &stats.resolutions {
+        row(&format!("    {resolution}"), count.to_string());
+    }
+    row("  conflicts", stats.conflicts.to_string());
+    row("  errors", stats.errors.to_string());
+    row(
+        "auto-resolution rate",
+        stats
+            .auto_resolution_rate
+            .map_or("-".to_string(), |rate| format!("{:.1}%", rate * 100.0)),
+    );
+    row(
+        "average merge time",
+        stats
+            .average_ms
+            .map_or("-".to_string(), |ms| format!("{ms:.1} ms")),
+    );
+    if let Some(merges) = stats.history_merges {
+        row("merge commits (git)", merges.to_string());
+    }
+    if !stats.conflict_prone.is_empty() {
+        out.push_str("\nmost conflict-prone dependencies\n");
+        for entry in &stats.conflict_prone {
+            let _ = writeln!(out, "  {:<20} {}", entry.dependency, entry.merges);
+        }
+    }
+    out
+}
+
+/// Counts the merge commits of `repo` in `range` that changed a Cargo
+/// manifest or lockfile relative to every parent: merges where both sides
+/// touched Cargo files.
+pub fn history_merges(repo: &Path, range: Range) -> Result<usize, Error> {
+    // One block per parent, headed `@<commit> <parents>`, listing the
+    // Cargo files that differ from that parent.
+    let mut args = vec![
+        "log".to_string(),
+        "--merges".to_string(),
+        "--full-history".to_string(),
+        "--diff-merges=separate".to_string(),
+        "--name-only".to_string(),
+        "--format=@%H %P".to_string(),
+    ];
+    args.extend(range.since.map(|since| format!("--since=@{since}")));
+    args.extend(range.until.map(|until| format!("--until=@{until}")));
+    args.extend(["--", ":(glob)**/Cargo.toml", ":(glob)**/Cargo.lock"].map(str::to_string));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(&args)
+        .output()
+        .map_err(|err| Error::io("running git in", repo, &err))?;
+    if !output.status.success() {
+        return Err(Error::Git {
+            args,
+            exit: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Commit to (parents, parents whose diff touched Cargo files).
+    let mut merges: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    let mut current = None;
+    for line in stdout.lines().filter(|line| !line.is_empty()) {
+        if let Some(header) = line.strip_prefix('@') {
+            let mut fields = header.split(' ');
+            let commit = fields.next().unwrap_or_default();
+            let parents = fields.count();
+            merges.entry(commit).or_insert((parents, 0));
+            current = Some((commit, false));
+        } else if let Some((commit, counted)) = &mut current
+            && !*counted
+        {
+            *counted = true;
+            if let Some((_, touched)) = merges.get_mut(commit) {
+                *touched += 1;
+            }
+        }
+    }
+    Ok(merges
+        .values()
+        .filter(|(parents, touched)| touched == parents)
+        .count())
+}
+
+/// Parses a range bound: seconds since the Unix epoch, a `YYYY-MM-DD` date
+/// (midnight UTC), or an age such as `30d`, `12h` or `2w` before `now`.
+pub fn parse_time(text: &str, now: u64) -> Result<u64, String> {
+    let invalid =
+        || format!("invalid time `{text}` (expected YYYY-MM-DD, seconds, or an age like 30d)");
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(seconds);
+    }
+    if let Some(unit) = text.chars().last().filter(char::is_ascii_alphabetic) {
+        let scale = match unit {
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 7 * 86_400,
+            _ => return Err(invalid()),
+        };
+        let count: u64 = text[..text.len() - 1].parse().map_err(|_| invalid())?;
+        return Ok(now.saturating_sub(count * scale));
+    }
+    let parts: Vec<&str> = text.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day): (i64, u32, u32) = (
+        year.parse().map_err(|_| invalid())?,
+        month.parse().map_err(|_| invalid())?,
+        day.parse().map_err(|_| invalid())?,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days * 86_400).map_err(|_| invalid())
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_records_in_range() {
+        let log = r#"{"timestamp":100,"command":"merge-manifest","manifest":"a","outcome":"merged","resolution":"semantic","conflicts":0,"durations_ms":{"parse":1.0,"merge":2.0}}
+{"timestamp":200,"command":"merge-manifest","manifest":"a","outcome":"conflict","conflicts":2,"conflict_paths":["dependencies.serde","dev-dependencies.serde.version"],"durations_ms":{"parse":4.0}}
+{"timestamp":250,"command":"merge-all","manifest":"b","outcome":"conflict","conflicts":1,"conflict_paths":["target.cfg(unix).dependencies.libc"],"durations_ms":{}}
+not a record
+{"timestamp":300,"command":"merge-batch","manifest":"c","outcome":"conflict","conflicts":1,"conflict_paths":["dependencies.serde"],"durations_ms":{}}
+{"timestamp":900,"command":"merge-manifest","manifest":"a","outcome":"error","conflicts":0,"error":"parse","durations_ms":{}}
+"#;
+        let range = Range {
+            since: Some(100),
+            until: Some(300),
+        };
+        let stats = aggregate(log, range, 10);
+        assert_eq!(
+            (stats.merges, stats.merged, stats.conflicts, stats.errors),
+            (4, 1, 3, 0)
+        );
+        assert_eq!(stats.auto_resolution_rate, Some(0.25));
+        assert_eq!(stats.average_ms, Some(3.5));
+        assert_eq!(stats.resolutions["semantic"], 1);
+        let prone: Vec<(&str, usize)> = stats
+            .conflict_prone
+            .iter()
+            .map(|entry| (entry.dependency.as_str(), entry.merges))
+            .collect();
+        assert_eq!(prone, [("serde", 2), ("libc", 1)]);
+
+        let text = table(&stats);
+        assert!(text.contains("auto-resolution rate   25.0%\n"), "{text}");
+        assert!(text.contains("  serde                2\n"), "{text}");
+        assert_eq!(aggregate(log, Range::default(), 1).conflict_prone.len(), 1);
+    }
+
+    #[test]
+    fn parses_dates_seconds_and_ages() {
+        assert_eq!(parse_time("2026-10-15", 0), Ok(1_792_022_400));
+        assert_eq!(parse_time("1970-01-01", 0), Ok(0));
+        assert_eq!(parse_time("12345", 0), Ok(12345));
+        assert_eq!(parse_time("2d", 1_000_000), Ok(1_000_000 - 172_800));
+        assert!(parse_time("2026-13-01", 0).is_err());
+        assert!(parse_time("yesterday", 0).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+//! Named merge strategies (`--strategy`).
+//!
+//! A strategy is a built-in [`Heuristic`] selected by name. The most common
+//! conflict is both branches bumping the same dependency
+//! (`serde = "1.0.199"` vs `"1.0.200"`); [`Strategy::SemverMax`] resolves it
+//! to the higher requirement when the lower one already admits it, and
+//! resolves competing `package.version` bumps to the higher version.
+//! [`Strategy::SemverIntersection`] resolves it to the requirement admitting
+//! exactly the versions both sides admit (`>=1.2, <2` and `^1.4` give
+//! `^1.4`), and leaves disjoint requirements in conflict.
+//!
+//! [`Strategy::Union`] merges two lists as sets: ours' items minus those
+//! theirs removed, then those theirs added.
+//!
+//! [`Strategy::Ours`] and [`Strategy::Theirs`] always take one side; they are
+//! meant for paths named in the policy's `[strategies]` table, where
+//! strategies apply only to the paths they are listed for.
+
+use std::fmt;
+
+use semver::{Op, Version, VersionReq};
+use toml::Value;
+
+use crate::hints::dependency_name;
+use crate::merge::{Heuristic, Proposal};
+use crate::metadata;
+
+/// A built-in heuristic selectable by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Take the higher of two compatible requirements or versions.
+    SemverMax,
+    /// Take the intersection of two overlapping requirements.
+    SemverIntersection,
+    /// Take the items of both lists.
+    Union,
+    /// Take ours.
+    Ours,
+    /// Take theirs.
+    Theirs,
+}
+
+impl Strategy {
+    /// The heuristic implementing the strategy.
+    pub fn heuristic(self) -> Heuristic {
+        match self {
+            Strategy::SemverMax => semver_max,
+            Strategy::SemverIntersection => semver_intersection,
+            Strategy::Union => union,
+            Strategy::Ours => prefer_ours,
+            Strategy::Theirs => prefer_theirs,
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Strategy::SemverMax => "semver-max",
+            Strategy::SemverIntersection => "semver-intersection",
+            Strategy::Union => "union",
+            Strategy::Ours => "ours",
+            Strategy::Theirs => "theirs",
+        })
+    }
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "semver-max" => Ok(Strategy::SemverMax),
+            "semver-intersection" => Ok(Strategy::SemverIntersection),
+            "union" => Ok(Strategy::Union),
+            "ours" => Ok(Strategy::Ours),
+            "theirs" => Ok(Strategy::Theirs),
+            other => Err(format!(
+                "unknown strategy `{other}` (expected `semver-max`, `semver-intersection`, \
+                 `union`, `ours` or `theirs`)"
+            )),
+        }
+    }
+}
+
+/// [`Heuristic`] taking the higher of two dependency requirements with the
+/// same operator when the lower one admits the higher one's minimum
+/// (`1.0.199` and `1.0.200`, not `0.8` and `0.9`), and the higher of two
+/// `package.version`s.
+pub fn semver_max(
+    path: &[&str],
+    _base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let (ours_text, theirs_text) = (ours?.as_str()?, theirs?.as_str()?);
+    let ours_wins = match path {
+        ["package", "version"] | ["workspace", "package", "version"] => {
+            Version::parse(ours_text).ok()? > Version::parse(theirs_text).ok()?
+        }
+        _ => {
+            dependency_name(path)?;
+            let (ours_req, theirs_req) = (single(ours_text)?, single(theirs_text)?);
+            if ours_req.0 != theirs_req.0 || !matches!(ours_req.0, Op::Caret | Op::Tilde) {
+                return None;
+            }
+            let (higher, lower) = if ours_req.1 > theirs_req.1 {
+                (&ours_req, &theirs_req)
+            } else {
+                (&theirs_req, &ours_req)
+            };
+            if !lower.2.matches(&higher.1) {
+                return None;
+            }
+            ours_req.1 > theirs_req.1
+        }
+    };
+    Some(Proposal {
+        value: if ours_wins { ours } else { theirs }.cloned(),
+        rule: "semver-max",
+        confidence: 1.0,
+    })
+}
+
+/// [`Heuristic`] replacing two dependency requirements with one admitting
+/// the versions both admit: the side whose requirement already is the
+/// intersection, else `>=low, <high`. Disjoint requirements, and
+/// requirements on pre-releases, are left in conflict.
+pub fn semver_intersection(
+    path: &[&str],
+    _base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    dependency_name(path)?;
+    let (ours, theirs) = (ours?, theirs?);
+    let ours_range = range(&VersionReq::parse(ours.as_str()?).ok()?)?;
+    let theirs_range = range(&VersionReq::parse(theirs.as_str()?).ok()?)?;
+    let low = ours_range.0.clone().max(theirs_range.0.clone());
+    let high = match (&ours_range.1, &theirs_range.1) {
+        (Some(ours), Some(theirs)) => Some(ours.min(theirs).clone()),
+        (high, None) | (None, high) => high.clone(),
+    };
+    if high.as_ref().is_some_and(|high| *high <= low) {
+        return None;
+    }
+    let both = (low, high);
+    let value = if both == ours_range {
+        ours.clone()
+    } else if both == theirs_range {
+        theirs.clone()
+    } else {
+        Value::String(match both.1 {
+            Some(high) => format!(">={}, <{high}", both.0),
+            None => format!(">={}", both.0),
+        })
+    };
+    Some(Proposal {
+        value: Some(value),
+        rule: "semver-intersection",
+        confidence: 1.0,
+    })
+}
+
+/// [`Heuristic`] merging two lists as sets against base's list.
+pub fn union(
+    _path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    let (ours, theirs) = (ours?.as_array()?, theirs?.as_array()?);
+    Some(Proposal {
+        value: Some(Value::Array(metadata::lists(base, ours, theirs))),
+        rule: "union",
+        confidence: 1.0,
+    })
+}
+
+/// [`Heuristic`] always taking ours.
+pub fn prefer_ours(
+    _path: &[&str],
+    _base: Option<&Value>,
+    ours: Option<&Value>,
+    _theirs: Option<&Value>,
+) -> Option<Proposal> {
+    Some(Proposal {
+        value: ours.cloned(),
+        rule: "prefer-ours",
+        confidence: 1.0,
+    })
+}
+
+/// [`Heuristic`] always taking theirs.
+pub fn prefer_theirs(
+    _path: &[&str],
+    _base: Option<&Value>,
+    _ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    Some(Proposal {
+        value: theirs.cloned(),
+        rule: "prefer-theirs",
+        confidence: 1.0,
+    })
+}
+
+/// [`Heuristic`] of `--on-conflict ours`: takes ours like [`prefer_ours`],
+/// as rule `on-conflict`, so the fallback is told apart from a strategy.
+pub fn on_conflict_ours(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    Some(Proposal {
+        rule: "on-conflict",
+        ..prefer_ours(path, base, ours, theirs)?
+    })
+}
+
+/// [`Heuristic`] of `--on-conflict theirs`; see [`on_conflict_ours`].
+pub fn on_conflict_theirs(
+    path: &[&str],
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Proposal> {
+    Some(Proposal {
+        rule: "on-conflict",
+        ..prefer_theirs(path, base, ours, theirs)?
+    })
+}
+
+/// The versions `req` admits as `[low, high)`, `high` unbounded when
+/// `None`; `None` for pre-release comparators and unknown operators.
+fn range(req: &VersionReq) -> Option<(Version, Option<Version>)> {
+    let mut low = Version::new(0, 0, 0);
+    let mut high: Option<Version> = None;
+    for comparator in &req.comparators {
+        if !comparator.pre.is_empty() {
+            return None;
+        }
+        let (major, minor, patch) = (comparator.major, comparator.minor, comparator.patch);
+        let exact = Version::new(major, minor.unwrap_or(0), patch.unwrap_or(0));
+        // The first version past those matching the given components.
+        let past = match (minor, patch) {
+            (Some(minor), Some(patch)) => Version::new(major, minor, patch + 1),
+            (Some(minor), None) => Version::new(major, minor + 1, 0),
+            _ => Version::new(major + 1, 0, 0),
+        };
+        let (from, to) = match comparator.op {
+            Op::Exact | Op::Wildcard => (Some(exact), Some(past)),
+            Op::Greater => (Some(past), None),
+            Op::GreaterEq => (Some(exact), None),
+            Op::Less => (None, Some(exact)),
+            Op::LessEq => (None, Some(past)),
+            Op::Tilde => {
+                let to = match minor {
+                    Some(minor) => Version::new(major, minor + 1, 0),
+                    None => Version::new(major + 1, 0, 0),
+                };
+                (Some(exact), Some(to))
+            }
+            Op::Caret => {
+                let to = match (major, minor, patch) {
+                    (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+                    (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+                    _ => Version::new(major + 1, 0, 0),
+                };
+                (Some(exact), Some(to))
+            }
+            _ => return None,
+        };
+        if let Some(from) = from {
+            low = low.max(from);
+        }
+        if let Some(to) = to {
+            high = Some(high.map_or(to.clone(), |high| high.min(to)));
+        }
+    }
+    Some((low, high))
+}
+
+/// The operator and minimum version of a single-comparator requirement.
+fn single(text: &str) -> Option<(Op, Version, VersionReq)> {
+    let req = VersionReq::parse(text).ok()?;
+    let [comparator] = req.comparators.as_slice() else {
+        return None;
+    };
+    let minimum = Version::new(
+        comparator.major,
+        comparator.minor.unwrap_or(0),
+        comparator.patch.unwrap_or(0),
+    );
+    Some((comparator.op, minimum, req))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, MergeOptions, merge_manifest_texts_with};
+
+    #[test]
+    fn takes_the_higher_compatible_requirement() {
+        let base = "[package]\nversion = \"0.1.0\"\n\n[dependencies]\n\
+                    serde = \"1.0.100\"\nrand = \"0.8\"\nlog = { version = \"0.4.1\" }\n";
+        let ours = base
+            .replace("1.0.100", "1.0.200")
+            .replace("0.1.0", "0.2.0")
+            .replace("0.4.1", "0.4.20");
+        let theirs = base
+            .replace("1.0.100", "1.0.199")
+            .replace("0.1.0", "0.1.1")
+            .replace("0.4.1", "0.4.21");
+        let options = MergeOptions {
+            heuristics: vec!["semver-max".parse::<Strategy>().unwrap().heuristic()],
+            ..MergeOptions::default()
+        };
+
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("serde = \"1.0.200\""), "{merged}");
+        assert!(merged.contains("version = \"0.2.0\""), "{merged}");
+        assert!(
+            merged.contains("log = { version = \"0.4.21\" }"),
+            "{merged}"
+        );
+
+        // A bump across a compatibility boundary stays a conflict.
+        let ours = ours.replace("rand = \"0.8\"", "rand = \"0.9\"");
+        let theirs = theirs.replace("rand = \"0.8\"", "rand = \"0.8.5\"");
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.rand");
+        assert!("semver-min".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn takes_the_intersection_of_overlapping_requirements() {
+        let base = "[dependencies]\nserde = \"1\"\nrand = \"0.8\"\nlog = \"0.4\"\n";
+        let ours = base
+            .replace("\"1\"", "\">=1.2, <2\"")
+            .replace("\"0.8\"", "\"0.8.5\"")
+            .replace("\"0.4\"", "\">=0.4.10\"");
+        let theirs = base
+            .replace("\"1\"", "\"^1.4\"")
+            .replace("\"0.8\"", "\"0.9\"")
+            .replace("\"0.4\"", "\"~0.4.3\"");
+        let options = MergeOptions {
+            heuristics: vec![Strategy::SemverIntersection.heuristic()],
+            ..MergeOptions::default()
+        };
+
+        // Disjoint requirements stay a conflict.
+        let err = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.path, "dependencies.rand");
+
+        let theirs = theirs.replace("\"0.9\"", "\"0.8\"");
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("serde = \"^1.4\""), "{merged}");
+        assert!(merged.contains("rand = \"0.8.5\""), "{merged}");
+        assert!(merged.contains("log = \">=0.4.10, <0.5.0\""), "{merged}");
+    }
+}
@@ -0,0 +1,224 @@
+//! `--strict` validation of merged manifests.
+//!
+//! Cargo ignores tables it does not know (with at most a warning), so a merge
+//! that carries `[dependecies]` or `verison = "1"` in from one side produces
+//! a manifest that builds without the dependency it was meant to add.
+//! [`check`] lists keys outside the Cargo schema at the top level, in
+//! `[target.*]` tables and in detailed dependency specifications, each with
+//! the closest known key when one is near enough to be a typo.
+
+use std::fmt;
+
+use toml::Value;
+
+use crate::passthrough::KNOWN_TOP_LEVEL_KEYS;
+use crate::unstable;
+
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// Keys of a detailed dependency specification (`serde = { ... }`).
+const DEPENDENCY_KEYS: &[&str] = &[
+    "artifact",
+    "base",
+    "branch",
+    "default-features",
+    "default_features",
+    "features",
+    "git",
+    "lib",
+    "optional",
+    "package",
+    "path",
+    "public",
+    "registry",
+    "registry-index",
+    "rev",
+    "tag",
+    "target",
+    "version",
+    "workspace",
+];
+
+/// A key outside the Cargo manifest schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// Dotted path of the key, e.g. `dependencies.serde.verison`.
+    pub path: String,
+    /// The known key it most likely misspells.
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown key `{}`", self.path)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Unknown keys in the manifest `doc`: top-level keys first, then those in
+/// `[target.*]` and `[workspace.dependencies]`.
+pub fn check(doc: &Value) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    let Some(root) = doc.as_table() else {
+        return unknown;
+    };
+    for (key, value) in root {
+        if !KNOWN_TOP_LEVEL_KEYS.contains(&key.as_str()) && !unstable::is_top_level(key) {
+            unknown.push(unknown_key(vec![key], KNOWN_TOP_LEVEL_KEYS));
+        } else if DEPENDENCY_TABLES.contains(&key.as_str()) {
+            check_dependencies(&[key], value, &mut unknown);
+        }
+    }
+
+    if let Some(targets) = root.get("target").and_then(Value::as_table) {
+        for (cfg, tables) in targets {
+            for (key, value) in tables.as_table().into_iter().flatten() {
+                let path = ["target", cfg, key];
+                if DEPENDENCY_TABLES.contains(&key.as_str()) {
+                    check_dependencies(&path, value, &mut unknown);
+                } else {
+                    unknown.push(unknown_key(path.to_vec(), DEPENDENCY_TABLES));
+                }
+            }
+        }
+    }
+    if let Some(dependencies) = root
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+    {
+        check_dependencies(&["workspace", "dependencies"], dependencies, &mut unknown);
+    }
+    unknown
+}
+
+fn check_dependencies(table: &[&str], value: &Value, unknown: &mut Vec<UnknownKey>) {
+    for (name, spec) in value.as_table().into_iter().flatten() {
+        for key in spec.as_table().into_iter().flat_map(|spec| spec.keys()) {
+            if !DEPENDENCY_KEYS.contains(&key.as_str()) {
+                let mut path = table.to_vec();
+                path.extend([name.as_str(), key.as_str()]);
+                unknown.push(unknown_key(path, DEPENDENCY_KEYS));
+            }
+        }
+    }
+}
+
+fn unknown_key(path: Vec<&str>, known: &[&'static str]) -> UnknownKey {
+    let key = path[path.len() - 1];
+    UnknownKey {
+        path: path.join("."),
+        suggestion: suggest(key, known),
+    }
+}
+
+/// The known key closest to `key`, if it is within a third of its length
+/// in edits.
+fn suggest(key: &str, known: &[&'static str]) -> Option<&'static str> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len().max(3) / 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and transpositions of adjacent characters each cost one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_misspelled_tables_and_dependency_keys() {
+        let doc: Value = r#"
+[package]
+name = "demo"
+
+[dependecies]
+log = "0.4"
+
+[dependencies]
+serde = { verison = "1", features = ["derive"] }
+
+[target.'cfg(unix)'.dev-dependenceis]
+libc = "0.2"
+
+[workspace.dependencies]
+anyhow = { version = "1", optinal = true }
+
+[zzz-tooling]
+x = 1
+"#
+        .parse()
+        .unwrap();
+
+        let messages: Vec<String> = check(&doc).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown key `dependecies` (did you mean `dependencies`?)",
+                "unknown key `dependencies.serde.verison` (did you mean `version`?)",
+                "unknown key `zzz-tooling`",
+                "unknown key `target.cfg(unix).dev-dependenceis` (did you mean `dev-dependencies`?)",
+                "unknown key `workspace.dependencies.anyhow.optinal` (did you mean `optional`?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_well_formed_manifests() {
+        let doc: Value = r#"
+cargo-features = ["edition2024"]
+
+[package]
+name = "demo"
+
+[dependencies]
+serde = { version = "1", default-features = false, optional = true }
+local = { path = "../local" }
+
+[build-dependencies]
+cc = { workspace = true }
+
+[target.'cfg(windows)'.dependencies]
+winapi = "0.3"
+"#
+        .parse()
+        .unwrap();
+        assert_eq!(check(&doc), []);
+    }
+}
@@ -0,0 +1,180 @@
+//! Output style enforcement (`[style]` in the merge policy).
+//!
+//! Merged values are written in the formatting of the side they came from,
+//! so a manifest edited on branches with different habits flip-flops between
+//! one-line and wrapped arrays from merge to merge. With an [`OutputStyle`]
+//! every array in the merged manifest is rewritten the way `taplo fmt` would
+//! with the same settings: on one line when `key = [...]` fits in
+//! `column_width`, otherwise one item per line, indented by `indent`, with a
+//! trailing comma unless `trailing_comma` is off. Arrays holding comments are
+//! left as written. The output's line endings can be fixed too.
+
+use std::fmt;
+
+use toml_edit::{Array, DocumentMut, Item, Table, Value};
+
+/// How merged manifests are formatted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputStyle {
+    /// Longest `key = [...]` line kept on one line (taplo's `column_width`).
+    pub column_width: usize,
+    /// Indentation of the items of a wrapped array (taplo's
+    /// `indent_string`).
+    pub indent: String,
+    /// Whether the last item of a wrapped array is followed by a comma
+    /// (taplo's `array_trailing_comma`).
+    pub trailing_comma: bool,
+    /// Line endings of the output; `None` keeps those of ours.
+    pub newline: Option<Newline>,
+}
+
+impl Default for OutputStyle {
+    /// taplo's defaults.
+    fn default() -> Self {
+        Self {
+            column_width: 80,
+            indent: "  ".to_string(),
+            trailing_comma: true,
+            newline: None,
+        }
+    }
+}
+
+/// Line endings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Newline {
+    Lf,
+    Crlf,
+}
+
+impl Newline {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::Crlf => "\r\n",
+        }
+    }
+}
+
+impl fmt::Display for Newline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Newline::Lf => "lf",
+            Newline::Crlf => "crlf",
+        })
+    }
+}
+
+impl std::str::FromStr for Newline {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "lf" => Ok(Newline::Lf),
+            "crlf" => Ok(Newline::Crlf),
+            other => Err(format!(
+                "unknown newline style `{other}` (expected `lf` or `crlf`)"
+            )),
+        }
+    }
+}
+
+/// `text` (LF line endings) with its arrays formatted per `style`; `text`
+/// itself when it does not parse.
+pub fn apply(text: &str, style: &OutputStyle) -> String {
+    let Ok(mut doc) = text.parse::<DocumentMut>() else {
+        return text.to_string();
+    };
+    table(doc.as_table_mut(), style);
+    doc.to_string()
+}
+
+fn table(table: &mut Table, style: &OutputStyle) {
+    for (key, item) in table.iter_mut() {
+        match item {
+            Item::Value(Value::Array(array)) => array_value(key.get().len(), array, style),
+            Item::Table(table) => self::table(table, style),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    self::table(table, style);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Formats the array under a key of `key_len` characters.
+fn array_value(key_len: usize, array: &mut Array, style: &OutputStyle) {
+    if commented(array) {
+        return;
+    }
+    compact(array);
+    let mut line = array.clone();
+    line.decor_mut().clear();
+    if array.is_empty() || key_len + " = ".len() + line.to_string().len() <= style.column_width {
+        return;
+    }
+    for item in array.iter_mut() {
+        item.decor_mut().set_prefix(format!("\n{}", style.indent));
+        item.decor_mut().set_suffix("");
+    }
+    array.set_trailing_comma(style.trailing_comma);
+    array.set_trailing("\n");
+}
+
+/// Formats `array` and the arrays in it on one line.
+fn compact(array: &mut Array) {
+    for item in array.iter_mut() {
+        if let Value::Array(inner) = item {
+            compact(inner);
+        }
+    }
+    array.fmt();
+}
+
+fn commented(array: &Array) -> bool {
+    let has_comment = |raw: Option<&toml_edit::RawString>| {
+        raw.and_then(|raw| raw.as_str())
+            .is_some_and(|raw| raw.contains('#'))
+    };
+    has_comment(Some(array.trailing()))
+        || array.iter().any(|item| {
+            has_comment(item.decor().prefix())
+                || has_comment(item.decor().suffix())
+                || matches!(item, Value::Array(inner) if commented(inner))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_arrays_past_the_column_width() {
+        let text = "[package]\nkeywords = [\n    \"a\",\n    \"b\"\n]\n\
+                    categories = [\"command-line-utilities\", \"development-tools::cargo-plugins\", \"parsing\"]\n\
+                    authors = [\n  \"x\", # the author\n]\n\n\
+                    [[bin]]\nname = \"x\"\nrequired-features = [ \"a\",\"b\" ]\n";
+        let style = OutputStyle::default();
+        assert_eq!(
+            apply(text, &style),
+            "[package]\nkeywords = [\"a\", \"b\"]\n\
+             categories = [\n  \"command-line-utilities\",\n  \"development-tools::cargo-plugins\",\n  \"parsing\",\n]\n\
+             authors = [\n  \"x\", # the author\n]\n\n\
+             [[bin]]\nname = \"x\"\nrequired-features = [\"a\", \"b\"]\n"
+        );
+
+        let style = OutputStyle {
+            column_width: 10,
+            indent: "    ".to_string(),
+            trailing_comma: false,
+            ..OutputStyle::default()
+        };
+        assert_eq!(
+            apply("a = [1, [2, 3]]\n", &style),
+            "a = [\n    1,\n    [2, 3]\n]\n"
+        );
+        assert_eq!("crlf".parse::<Newline>(), Ok(Newline::Crlf));
+    }
+}
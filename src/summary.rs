@@ -0,0 +1,229 @@
+//! Short Markdown reports (`--format summary-md`).
+//!
+//! Sized for a pull request comment or a GitHub Actions job summary
+//! (`>> "$GITHUB_STEP_SUMMARY"`): one heading with the totals and one table
+//! row per manifest, listing its conflict or the values resolved
+//! automatically. Long lists are cut short; the full details stay in the
+//! command's normal output.
+
+use std::fmt::Write;
+
+use crate::estimate::LockChange;
+use crate::merge::{MergeConflict, Resolution};
+
+/// Items listed per row before the rest are counted instead.
+const MAX_ITEMS: usize = 5;
+
+/// How the merge of one manifest ended.
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome<'a> {
+    Merged(&'a [Resolution]),
+    Conflict(&'a MergeConflict),
+    /// Every conflict of a merge run with `--all-conflicts`.
+    Conflicts(&'a [MergeConflict]),
+    /// The merge could not run; the message's first line is shown.
+    Failed(&'a str),
+}
+
+/// Renders a summary table of `(manifest, outcome)` rows.
+pub fn markdown(rows: &[(String, Outcome<'_>)]) -> String {
+    let count = |pick: fn(&Outcome<'_>) -> bool| rows.iter().filter(|(_, o)| pick(o)).count();
+    let merged = count(|outcome| matches!(outcome, Outcome::Merged(_)));
+    let conflicts =
+        count(|outcome| matches!(outcome, Outcome::Conflict(_) | Outcome::Conflicts(_)));
+    let failed = count(|outcome| matches!(outcome, Outcome::Failed(_)));
+
+    let mut totals = vec![format!("{merged} merged")];
+    if conflicts > 0 {
+        totals.push(format!("{conflicts} conflicting"));
+    }
+    if failed > 0 {
+        totals.push(format!("{failed} failed"));
+    }
+    let mut out = format!("### cargo-merge-assist: {}\n\n", totals.join(", "));
+    out.push_str("| Manifest | Result | Details |\n| --- | --- | --- |\n");
+
+    for (manifest, outcome) in rows {
+        let (result, details) = match outcome {
+            Outcome::Merged([]) => ("merged".to_string(), Vec::new()),
+            Outcome::Merged(resolutions) => (
+                format!("merged, {} resolved automatically", resolutions.len()),
+                resolutions.iter().map(resolution_item).collect(),
+            ),
+            Outcome::Conflict(conflict) => (
+                "**conflict**".to_string(),
+                std::iter::once(*conflict)
+                    .chain(&conflict.related)
+                    .map(conflict_item)
+                    .collect(),
+            ),
+            Outcome::Conflicts(conflicts) => (
+                format!("**{} conflicts**", conflicts.len()),
+                conflicts.iter().map(conflict_item).collect(),
+            ),
+            Outcome::Failed(message) => (
+                "**failed**".to_string(),
+                vec![message.lines().next().unwrap_or_default().to_string()],
+            ),
+        };
+        let _ = writeln!(
+            out,
+            "| `{}` | {result} | {} |",
+            escape(manifest),
+            cell(&details)
+        );
+    }
+    out
+}
+
+fn resolution_item(resolution: &Resolution) -> String {
+    let side = resolution
+        .side
+        .map_or("new value".to_string(), |side| format!("from {side}"));
+    format!("`{}` {side} ({})", resolution.path, resolution.rule)
+}
+
+fn conflict_item(conflict: &MergeConflict) -> String {
+    let value = |value: Option<&toml::Value>| {
+        value.map_or("deleted".to_string(), |value| format!("`{value}`"))
+    };
+    let mut item = format!(
+        "`{}` (`{}`): ours {}, theirs {}",
+        conflict.path,
+        conflict.fingerprint,
+        value(conflict.ours.as_ref()),
+        value(conflict.theirs.as_ref())
+    );
+    if !conflict.reviewers.is_empty() {
+        item.push_str(&format!("; owners: {}", conflict.reviewers.join(" ")));
+    }
+    item
+}
+
+/// A line on the `Cargo.lock` changes a merge is expected to force, for
+/// `merge-all --check`.
+pub fn lock_changes(changes: &[LockChange]) -> String {
+    if changes.is_empty() {
+        return "**Cargo.lock:** no changes expected\n".to_string();
+    }
+    let mut items: Vec<String> = changes.iter().take(MAX_ITEMS).map(lock_item).collect();
+    if changes.len() > MAX_ITEMS {
+        items.push(format!("and {} more", changes.len() - MAX_ITEMS));
+    }
+    format!("**Cargo.lock:** would {}\n", items.join(", "))
+}
+
+fn lock_item(change: &LockChange) -> String {
+    let version = |version: &Option<String>| version.as_deref().unwrap_or("(unknown)").to_string();
+    match change {
+        LockChange::Add { name, version: to } => format!("add `{name}` {}", version(to)),
+        LockChange::Update { name, from, to, .. } => {
+            format!("update `{name}` {from} → {}", version(to))
+        }
+        LockChange::Remove { name, version } => format!("remove `{name}` {version}"),
+        LockChange::Unpatch { name, version, .. } => format!("unpatch `{name}` {version}"),
+    }
+}
+
+/// `items` as one table cell, one per line, capped at [`MAX_ITEMS`].
+fn cell(items: &[String]) -> String {
+    let mut shown: Vec<String> = items
+        .iter()
+        .take(MAX_ITEMS)
+        .map(|item| escape(item))
+        .collect();
+    if items.len() > MAX_ITEMS {
+        shown.push(format!("and {} more", items.len() - MAX_ITEMS));
+    }
+    shown.join("<br>")
+}
+
+/// Keeps `text` inside one table cell.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use super::*;
+    use crate::merge::Side;
+
+    #[test]
+    fn renders_one_row_per_manifest() {
+        let resolutions = [Resolution {
+            path: "dependencies.serde".to_string(),
+            side: Some(Side::Theirs),
+            rule: "online-hints",
+        }];
+        let conflict = MergeConflict {
+            fingerprint: "0123456789abcdef".to_string(),
+            ..MergeConflict::new(
+                "dependencies.log".to_string(),
+                Some(Value::String("0.4".into())),
+                Some(Value::String("0.4.20".into())),
+                None,
+            )
+        };
+        let rows = [
+            ("a/Cargo.toml".to_string(), Outcome::Merged(&[])),
+            ("b/Cargo.toml".to_string(), Outcome::Merged(&resolutions)),
+            ("c/Cargo.toml".to_string(), Outcome::Conflict(&conflict)),
+            (
+                "d/Cargo.toml".to_string(),
+                Outcome::Failed("ours is not valid TOML: a|b\n  --> line 3"),
+            ),
+        ];
+
+        assert_eq!(
+            markdown(&rows),
+            "### cargo-merge-assist: 2 merged, 1 conflicting, 1 failed\n\n\
+             | Manifest | Result | Details |\n\
+             | --- | --- | --- |\n\
+             | `a/Cargo.toml` | merged |  |\n\
+             | `b/Cargo.toml` | merged, 1 resolved automatically | `dependencies.serde` from theirs (online-hints) |\n\
+             | `c/Cargo.toml` | **conflict** | `dependencies.log` (`0123456789abcdef`): ours `\"0.4.20\"`, theirs deleted |\n\
+             | `d/Cargo.toml` | **failed** | ours is not valid TOML: a\\|b |\n"
+        );
+    }
+
+    #[test]
+    fn lists_expected_lockfile_changes() {
+        let changes = [
+            LockChange::Update {
+                name: "tokio".to_string(),
+                from: "1.38.0".to_string(),
+                to: Some("1.39.2".to_string()),
+                req: "^1.39".to_string(),
+            },
+            LockChange::Add {
+                name: "rand".to_string(),
+                version: None,
+            },
+        ];
+        assert_eq!(
+            lock_changes(&changes),
+            "**Cargo.lock:** would update `tokio` 1.38.0 → 1.39.2, add `rand` (unknown)\n"
+        );
+        assert_eq!(lock_changes(&[]), "**Cargo.lock:** no changes expected\n");
+    }
+
+    #[test]
+    fn caps_long_detail_lists() {
+        let resolutions: Vec<Resolution> = (0..8)
+            .map(|idx| Resolution {
+                path: format!("dependencies.dep{idx}"),
+                side: None,
+                rule: "semver-max",
+            })
+            .collect();
+        let summary = markdown(&[("Cargo.toml".to_string(), Outcome::Merged(&resolutions))]);
+        assert!(
+            summary.contains("`dependencies.dep4` new value"),
+            "{summary}"
+        );
+        assert!(!summary.contains("dep5"), "{summary}");
+        assert!(summary.contains("<br>and 3 more |"), "{summary}");
+    }
+}
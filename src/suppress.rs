@@ -0,0 +1,110 @@
+//! Recorded decisions for recurring conflicts (`.cargo-merge-assist-ignore`).
+//!
+//! Some conflicts come back on every merge and are always resolved the same
+//! way, e.g. an internal version that deliberately differs between
+//! long-lived branches. The ignore file at the repository root lists their
+//! [fingerprints](crate::merge::fingerprint) together with the side to take:
+//!
+//! ```text
+//! # internal-api stays on the release branch's version
+//! 3f9a0c1d2b4e5f60 theirs
+//! ```
+//!
+//! A listed conflict is resolved as recorded (rule `recorded`) instead of
+//! being reported. Because the fingerprint covers the conflicting values, a
+//! recorded decision stops applying as soon as any of them changes.
+
+use std::fs;
+use std::path::Path;
+
+use crate::merge::Side;
+
+/// The ignore file, at the repository root.
+pub const IGNORE_FILE: &str = ".cargo-merge-assist-ignore";
+
+/// A recorded decision for the conflict with `fingerprint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppression {
+    pub fingerprint: String,
+    /// The input whose value the conflict resolves to.
+    pub side: Side,
+}
+
+/// Parses an ignore file: one `<fingerprint> <base|ours|theirs>` per line;
+/// `#` starts a comment.
+pub fn parse(text: &str) -> Result<Vec<Suppression>, String> {
+    let mut suppressions = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let invalid = |detail: &str| format!("{IGNORE_FILE}:{}: {detail}", idx + 1);
+        let (fingerprint, side) = match fields[..] {
+            [] => continue,
+            [fingerprint, side] => (fingerprint, side),
+            _ => return Err(invalid("expected `<fingerprint> <base|ours|theirs>`")),
+        };
+        if fingerprint.len() != 16 || !fingerprint.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid(&format!(
+                "`{fingerprint}` is not a conflict fingerprint"
+            )));
+        }
+        let side: Side = side.parse().map_err(|err: String| invalid(&err))?;
+        suppressions.push(Suppression {
+            fingerprint: fingerprint.to_ascii_lowercase(),
+            side,
+        });
+    }
+    Ok(suppressions)
+}
+
+/// Reads the ignore file of `repo`; none when it does not exist.
+pub fn load(repo: &Path) -> Result<Vec<Suppression>, String> {
+    match fs::read_to_string(repo.join(IGNORE_FILE)) {
+        Ok(text) => parse(&text),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("failed reading {IGNORE_FILE}: {err}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, MergeOptions, merge_manifest_texts_with};
+
+    #[test]
+    fn resolves_listed_conflicts_as_recorded() {
+        let base = "[dependencies]\ninternal = \"1.0\"\nlog = \"0.4\"\n";
+        let ours = base.replace("1.0", "1.1");
+        let theirs = base.replace("1.0", "2.0");
+        let err =
+            merge_manifest_texts_with(base, &ours, &theirs, &MergeOptions::default()).unwrap_err();
+        let MergeError::Conflict(conflict) = err else {
+            panic!("expected a conflict, got {err:?}");
+        };
+        assert_eq!(conflict.fingerprint.len(), 16);
+        assert!(conflict.to_string().contains(&conflict.fingerprint));
+
+        let file = format!(
+            "# internal stays on the mainline version\n{} theirs  # see #123\n",
+            conflict.fingerprint.to_uppercase()
+        );
+        let options = MergeOptions {
+            suppressions: parse(&file).unwrap(),
+            ..MergeOptions::default()
+        };
+        let merged = merge_manifest_texts_with(base, &ours, &theirs, &options).unwrap();
+        assert!(merged.contains("internal = \"2.0\""), "{merged}");
+
+        // A different divergence at the same path is not covered.
+        let theirs = base.replace("1.0", "3.0");
+        assert!(merge_manifest_texts_with(base, &ours, &theirs, &options).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse("0123456789abcdef mine\n").is_err());
+        assert!(parse("dependencies.log theirs\n").is_err());
+        let err = parse("\n0123456789abcdef\n").unwrap_err();
+        assert!(err.starts_with(".cargo-merge-assist-ignore:2:"), "{err}");
+    }
+}
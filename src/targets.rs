@@ -0,0 +1,60 @@
+//! Build targets (`[[bin]]`, `[[example]]`, `[[bench]]`, `[[test]]`).
+//!
+//! These are arrays of tables, and arrays are otherwise compared as whole
+//! values, so two branches adding or editing different binaries would
+//! conflict. Targets are identified by their `name` instead: the merge pairs
+//! the entries of the three sides by name and merges each pair like a table,
+//! reporting conflicts at `bin.<name>` and below. The merged array lists
+//! ours' targets in ours' order, then those only theirs added. Arrays where
+//! an entry has no `name`, or two share one, are still compared whole.
+
+use toml::Value;
+
+/// Top-level arrays of targets merged by name.
+pub(crate) const SECTIONS: [&str; 4] = ["bin", "example", "bench", "test"];
+
+/// The targets of `section` (absent: none) by name, in order; `None` when it
+/// is not an array of uniquely named tables.
+pub(crate) fn named(section: Option<&Value>) -> Option<Vec<(&str, &Value)>> {
+    let Some(section) = section else {
+        return Some(Vec::new());
+    };
+    let mut targets: Vec<(&str, &Value)> = Vec::new();
+    for target in section.as_array()? {
+        let name = target.as_table()?.get("name")?.as_str()?;
+        if targets.iter().any(|(other, _)| *other == name) {
+            return None;
+        }
+        targets.push((name, target));
+    }
+    Some(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeError, merge_manifest_texts};
+
+    #[test]
+    fn merges_targets_by_name() {
+        let base = "[[bin]]\nname = \"cli\"\npath = \"src/main.rs\"\n";
+        let ours = "[[bin]]\nname = \"cli\" # the CLI\npath = \"src/bin/cli.rs\"\n\n\
+                    [[bin]]\nname = \"server\"\npath = \"src/bin/server.rs\"\n";
+        let theirs = "[[bin]]\nname = \"cli\"\npath = \"src/main.rs\"\nrequired-features = [\"cli\"]\n\n\
+                      [[bin]]\nname = \"migrate\"\npath = \"src/bin/migrate.rs\"\n";
+
+        let merged = merge_manifest_texts(base, ours, theirs).unwrap();
+        assert_eq!(
+            merged,
+            "[[bin]]\nname = \"cli\" # the CLI\npath = \"src/bin/cli.rs\"\nrequired-features = [\"cli\"]\n\n\
+             [[bin]]\nname = \"server\"\npath = \"src/bin/server.rs\"\n\n\
+             [[bin]]\nname = \"migrate\"\npath = \"src/bin/migrate.rs\"\n"
+        );
+
+        let theirs = "[[bin]]\nname = \"cli\"\npath = \"src/cli.rs\"\n";
+        let err = merge_manifest_texts(base, ours, theirs).unwrap_err();
+        assert!(
+            matches!(&err, MergeError::Conflict(conflict) if conflict.path == "bin.cli.path"),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Property testing against the merge engine (`test-support` feature).
+//!
+//! Policies, strategies and heuristics change what the engine decides, and
+//! the inputs that break them are rarely the ones written by hand. A
+//! [`Triple`] generated from an [`Rng`] is a random base manifest and two
+//! sides that each applied a few edits to it; the same seed always yields the
+//! same triple, so a failure reproduces from its seed. The invariants check
+//! what a merge under any [`MergeOptions`] should keep, returning the
+//! counterexample as a [`Violation`]:
+//!
+//! - [`idempotence`]: merging theirs into the result again changes nothing;
+//! - [`symmetry`]: swapping ours and theirs merges to the same manifest (up
+//!   to the order of lists) or conflicts again. Options favouring one side
+//!   (`prefer`, the `ours`/`theirs` strategies) break it by design;
+//! - [`no_content_loss`]: a value both sides kept, or a change only one made,
+//!   is in the result, and a value both changed is not dropped. Options
+//!   rewriting the manifest beyond the merge (pins, `migrate_deprecated`)
+//!   break it by design.
+//!
+//! ```
+//! use cargo_merge_assist::merge::MergeOptions;
+//! use cargo_merge_assist::test_support::{Rng, Triple, check};
+//!
+//! let options = MergeOptions::default();
+//! for seed in 0..32 {
+//!     let triple = Triple::generate(&mut Rng::new(seed));
+//!     check(&triple, &options).unwrap();
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use toml::{Table, Value};
+
+use crate::merge::{MergeError, MergeOptions, merge_manifest_texts_with};
+
+/// Crates the generated manifests depend on.
+const CRATES: [&str; 10] = [
+    "anyhow",
+    "bytes",
+    "itertools",
+    "log",
+    "rand",
+    "regex",
+    "serde",
+    "thiserror",
+    "tokio",
+    "url",
+];
+
+/// Features enabled on the generated dependencies.
+const CRATE_FEATURES: [&str; 4] = ["alloc", "derive", "macros", "std"];
+
+/// Features the generated manifests declare.
+const FEATURES: [&str; 3] = ["extra", "std", "tracing"];
+
+const DESCRIPTIONS: [&str; 3] = ["A crate", "Utilities", "Bindings to the thing"];
+
+/// A small deterministic random number generator (SplitMix64), so that
+/// triples reproduce from their seed on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True `percent` times out of a hundred.
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+
+    /// One of `items`, which must not be empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Three versions of a manifest, as a merge driver receives them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Triple {
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+impl Triple {
+    /// A random base manifest and two sides applying one to four edits each:
+    /// requirement bumps, added and removed dependencies and features,
+    /// package and profile changes.
+    pub fn generate(rng: &mut Rng) -> Self {
+        let base = manifest(rng);
+        let [ours, theirs] = [(); 2].map(|()| {
+            let mut side = base.clone();
+            for _ in 0..=rng.below(4) {
+                edit(rng, &mut side);
+            }
+            side
+        });
+        Self {
+            base: render(&base),
+            ours: render(&ours),
+            theirs: render(&theirs),
+        }
+    }
+
+    /// The triple with ours and theirs swapped.
+    pub fn swapped(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            ours: self.theirs.clone(),
+            theirs: self.ours.clone(),
+        }
+    }
+
+    fn merge(&self, options: &MergeOptions) -> Result<String, MergeError> {
+        merge_manifest_texts_with(&self.base, &self.ours, &self.theirs, options)
+    }
+}
+
+/// An invariant a merge broke, with the inputs that show it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The invariant's function name, e.g. `symmetry`.
+    pub invariant: &'static str,
+    pub detail: String,
+    pub triple: Triple,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} violated: {}\n--- base\n{}--- ours\n{}--- theirs\n{}",
+            self.invariant, self.detail, self.triple.base, self.triple.ours, self.triple.theirs
+        )
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Checks every invariant.
+pub fn check(triple: &Triple, options: &MergeOptions) -> Result<(), Violation> {
+    idempotence(triple, options)?;
+    symmetry(triple, options)?;
+    no_content_loss(triple, options)
+}
+
+/// Merging theirs into the merged manifest again gives the same manifest.
+pub fn idempotence(triple: &Triple, options: &MergeOptions) -> Result<(), Violation> {
+    let violation = |detail: String| Violation {
+        invariant: "idempotence",
+        detail,
+        triple: triple.clone(),
+    };
+    let Ok(merged) = triple.merge(options) else {
+        return Ok(());
+    };
+    let again = Triple {
+        ours: merged.clone(),
+        ..triple.clone()
+    };
+    match again.merge(options) {
+        Ok(remerged) if parse(&remerged) == parse(&merged) => Ok(()),
+        Ok(remerged) => Err(violation(format!(
+            "merging theirs again changed the result to\n{remerged}"
+        ))),
+        Err(err) => Err(violation(format!("merging theirs again failed: {err}"))),
+    }
+}
+
+/// Swapping the sides gives the same manifest, up to the order of lists, or
+/// fails again.
+pub fn symmetry(triple: &Triple, options: &MergeOptions) -> Result<(), Violation> {
+    let violation = |detail: String| Violation {
+        invariant: "symmetry",
+        detail,
+        triple: triple.clone(),
+    };
+    match (triple.merge(options), triple.swapped().merge(options)) {
+        (Ok(merged), Ok(swapped)) => {
+            if unordered(parse(&merged)) == unordered(parse(&swapped)) {
+                Ok(())
+            } else {
+                Err(violation(format!(
+                    "merged to\n{merged}but with the sides swapped to\n{swapped}"
+                )))
+            }
+        }
+        (Err(_), Err(_)) => Ok(()),
+        (Ok(_), Err(err)) => Err(violation(format!("only the swapped merge failed: {err}"))),
+        (Err(err), Ok(_)) => Err(violation(format!(
+            "only the swapped merge succeeded: {err}"
+        ))),
+    }
+}
+
+/// Nothing either side meant to keep is lost. Compared value by value (lists
+/// are one value): where both sides agree, the result agrees; where one side
+/// changed a value, the result has its change; where both changed it and
+/// neither deleted it, the result has a value, and a list keeps every item
+/// either side added.
+pub fn no_content_loss(triple: &Triple, options: &MergeOptions) -> Result<(), Violation> {
+    let Ok(merged) = triple.merge(options) else {
+        return Ok(());
+    };
+    let docs = [&triple.base, &triple.ours, &triple.theirs, &merged].map(|text| parse(text));
+    let [base, ours, theirs, merged] = docs.each_ref().map(|doc| {
+        let mut values = BTreeMap::new();
+        leaves(doc, &mut Vec::new(), &mut values);
+        values
+    });
+    let paths: BTreeSet<&Vec<&str>> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    for path in paths {
+        let [base, ours, theirs, merged] = [&base, &ours, &theirs, &merged].map(|v| v.get(path));
+        let lost = if ours == theirs {
+            merged != ours
+        } else if ours == base {
+            merged != theirs
+        } else if theirs == base {
+            merged != ours
+        } else {
+            match (ours, theirs, merged) {
+                (Some(_), Some(_), None) => true,
+                (
+                    Some(Value::Array(ours)),
+                    Some(Value::Array(theirs)),
+                    Some(Value::Array(merged)),
+                ) => {
+                    let base = base.and_then(|base| base.as_array());
+                    ours.iter().chain(theirs).any(|item| {
+                        !base.is_some_and(|base| base.contains(item)) && !merged.contains(item)
+                    })
+                }
+                _ => false,
+            }
+        };
+        if lost {
+            let show =
+                |value: Option<&&Value>| value.map_or("<none>".to_string(), ToString::to_string);
+            return Err(Violation {
+                invariant: "no_content_loss",
+                detail: format!(
+                    "`{}` is {} in base, {} in ours, {} in theirs but {} merged",
+                    path.join("."),
+                    show(base),
+                    show(ours),
+                    show(theirs),
+                    show(merged)
+                ),
+                triple: triple.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn manifest(rng: &mut Rng) -> Table {
+    let mut package = Table::new();
+    package.insert("name".into(), format!("crate{}", rng.below(100)).into());
+    package.insert("version".into(), version(rng).into());
+    package.insert(
+        "edition".into(),
+        (*rng.pick(&["2018", "2021", "2024"])).into(),
+    );
+    let mut doc = Table::new();
+    doc.insert("package".into(), package.into());
+    let mut dependencies = Table::new();
+    for _ in 0..=rng.below(5) {
+        dependencies.insert((*rng.pick(&CRATES)).into(), dependency(rng));
+    }
+    doc.insert("dependencies".into(), dependencies.into());
+    if rng.chance(50) {
+        let mut features = Table::new();
+        features.insert("default".into(), Value::Array(vec!["std".into()]));
+        features.insert("std".into(), Value::Array(Vec::new()));
+        doc.insert("features".into(), features.into());
+    }
+    if rng.chance(30) {
+        set_opt_level(rng, &mut doc);
+    }
+    doc
+}
+
+/// Applies one random edit to `doc`.
+fn edit(rng: &mut Rng, doc: &mut Table) {
+    let table = |doc: &mut Table, key: &str| -> Table {
+        doc.get(key)
+            .and_then(Value::as_table)
+            .cloned()
+            .unwrap_or_default()
+    };
+    match rng.below(8) {
+        // Bump a requirement.
+        0 => {
+            let mut dependencies = table(doc, "dependencies");
+            let names: Vec<String> = dependencies.keys().cloned().collect();
+            if names.is_empty() {
+                return;
+            }
+            let requirement = requirement(rng);
+            match dependencies.get_mut(rng.pick(&names)) {
+                Some(Value::Table(entry)) => {
+                    entry.insert("version".into(), requirement.into());
+                }
+                Some(value) => *value = requirement.into(),
+                None => {}
+            }
+            doc.insert("dependencies".into(), dependencies.into());
+        }
+        // Add a dependency.
+        1 => {
+            let mut dependencies = table(doc, "dependencies");
+            let name = *rng.pick(&CRATES);
+            if !dependencies.contains_key(name) {
+                dependencies.insert(name.into(), dependency(rng));
+            }
+            doc.insert("dependencies".into(), dependencies.into());
+        }
+        // Remove a dependency.
+        2 => {
+            let mut dependencies = table(doc, "dependencies");
+            let names: Vec<String> = dependencies.keys().cloned().collect();
+            if !names.is_empty() {
+                dependencies.remove(rng.pick(&names));
+            }
+            doc.insert("dependencies".into(), dependencies.into());
+        }
+        // Enable a feature of a dependency.
+        3 => {
+            let mut dependencies = table(doc, "dependencies");
+            let names: Vec<String> = dependencies.keys().cloned().collect();
+            if names.is_empty() {
+                return;
+            }
+            let feature = Value::from(*rng.pick(&CRATE_FEATURES));
+            if let Some(Value::Table(entry)) = dependencies.get_mut(rng.pick(&names))
+                && let Value::Array(features) = entry
+                    .entry("features")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                && !features.contains(&feature)
+            {
+                features.push(feature);
+            }
+            doc.insert("dependencies".into(), dependencies.into());
+        }
+        4 => {
+            let mut package = table(doc, "package");
+            package.insert("version".into(), version(rng).into());
+            doc.insert("package".into(), package.into());
+        }
+        5 => {
+            let mut package = table(doc, "package");
+            package.insert("description".into(), (*rng.pick(&DESCRIPTIONS)).into());
+            doc.insert("package".into(), package.into());
+        }
+        // Declare a feature, maybe on by default.
+        6 => {
+            let mut features = table(doc, "features");
+            let name = *rng.pick(&FEATURES);
+            features
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if rng.chance(50)
+                && let Value::Array(default) = features
+                    .entry("default")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                && !default.contains(&name.into())
+            {
+                default.push(name.into());
+            }
+            doc.insert("features".into(), features.into());
+        }
+        _ => set_opt_level(rng, doc),
+    }
+}
+
+fn set_opt_level(rng: &mut Rng, doc: &mut Table) {
+    let mut release = Table::new();
+    release.insert("opt-level".into(), Value::Integer(rng.below(4) as i64));
+    let mut profile = Table::new();
+    profile.insert("release".into(), release.into());
+    doc.insert("profile".into(), profile.into());
+}
+
+/// A dependency entry: a bare requirement or a table with features and
+/// maybe `optional`.
+fn dependency(rng: &mut Rng) -> Value {
+    if rng.chance(50) {
+        return requirement(rng).into();
+    }
+    let mut entry = Table::new();
+    entry.insert("version".into(), requirement(rng).into());
+    if rng.chance(50) {
+        entry.insert(
+            "features".into(),
+            Value::Array(vec![(*rng.pick(&CRATE_FEATURES)).into()]),
+        );
+    }
+    if rng.chance(20) {
+        entry.insert("optional".into(), true.into());
+    }
+    entry.into()
+}
+
+fn requirement(rng: &mut Rng) -> String {
+    format!("{}.{}", rng.below(3), rng.below(10))
+}
+
+fn version(rng: &mut Rng) -> String {
+    format!("0.{}.{}", rng.below(5), rng.below(10))
+}
+
+fn render(doc: &Table) -> String {
+    toml::to_string(doc).expect("generated manifests serialize")
+}
+
+fn parse(text: &str) -> Value {
+    text.parse::<Table>()
+        .map_or(Value::Table(Table::new()), Value::Table)
+}
+
+/// `value` with every list sorted.
+fn unordered(value: Value) -> Value {
+    match value {
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items.into_iter().map(unordered).collect();
+            items.sort_by_cached_key(ToString::to_string);
+            Value::Array(items)
+        }
+        Value::Table(table) => {
+            Value::Table(table.into_iter().map(|(k, v)| (k, unordered(v))).collect())
+        }
+        value => value,
+    }
+}
+
+/// The values of `value` that are not tables, by path.
+fn leaves<'a>(
+    value: &'a Value,
+    path: &mut Vec<&'a str>,
+    out: &mut BTreeMap<Vec<&'a str>, &'a Value>,
+) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key);
+                leaves(value, path, out);
+                path.pop();
+            }
+        }
+        value => {
+            out.insert(path.clone(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_keeps_the_invariants() {
+        let options = MergeOptions::default();
+        let mut merged = 0;
+        for seed in 0..500 {
+            let triple = Triple::generate(&mut Rng::new(seed));
+            assert_eq!(triple, Triple::generate(&mut Rng::new(seed)));
+            if let Err(violation) = check(&triple, &options) {
+                panic!("seed {seed}: {violation}");
+            }
+            merged += usize::from(triple.merge(&options).is_ok());
+        }
+        // The triples exercise the merge, not just its conflict path.
+        assert!(merged > 250, "only {merged} of 500 triples merged");
+    }
+}
@@ -0,0 +1,73 @@
+//! Per-phase wall-clock timings for `--timings`.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Accumulated time per phase, in first-recorded order.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Runs `f`, adding its duration to `phase`.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    /// Adds `elapsed` to `phase`.
+    pub fn record(&mut self, phase: &'static str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Time recorded for `phase`, if any.
+    pub fn get(&self, phase: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(name, _)| *name == phase)
+            .map(|(_, elapsed)| *elapsed)
+    }
+
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = self.phases.iter().map(|(_, elapsed)| *elapsed).sum();
+        writeln!(f, "timings:")?;
+        for (phase, elapsed) in self.phases.iter().chain([&("total", total)]) {
+            writeln!(f, "  {phase:<24} {:>10.3} ms", elapsed.as_secs_f64() * 1e3)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_repeated_phases_in_order() {
+        let mut timings = Timings::default();
+        timings.record("parse", Duration::from_millis(2));
+        timings.record("merge", Duration::from_millis(1));
+        timings.record("parse", Duration::from_millis(3));
+        assert_eq!(timings.time("write", || 7), 7);
+
+        let names: Vec<_> = timings.phases().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["parse", "merge", "write"]);
+        assert_eq!(timings.get("parse"), Some(Duration::from_millis(5)));
+
+        let rendered = timings.to_string();
+        assert!(rendered.contains("parse"));
+        assert!(rendered.contains("total"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Bulk decisions for heavily diverged manifests (`triage`).
+//!
+//! After a restructure, one merge can report hundreds of conflicts, most of
+//! them alike: every dependency bumped on both branches, every feature list
+//! edited on both. Resolving them one at a time buries the few that need
+//! thought. Triage groups the conflicts into [`Partition`]s by table and
+//! [`Kind`], suggests a [`Strategy`] for partitions one fits, and turns the
+//! decisions taken per partition into path strategies, so that a single
+//! merge applies all of them.
+
+use std::fmt;
+
+use toml::Value;
+
+use crate::merge::{ConflictKind, MergeConflict, PathStrategy};
+use crate::strategy::Strategy;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// How the sides diverged on a conflicting value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// Both sides changed the value.
+    Changed,
+    /// One side deleted the value, the other changed it.
+    Deleted,
+    /// A side renamed the dependency.
+    Renamed,
+    /// The union of two lists exceeds a crates.io limit.
+    Limit,
+}
+
+impl Kind {
+    fn of(conflict: &MergeConflict) -> Self {
+        match conflict.kind {
+            ConflictKind::RenameDelete { .. } | ConflictKind::RenameRename { .. } => Kind::Renamed,
+            ConflictKind::Limit { .. } => Kind::Limit,
+            ConflictKind::Referenced { .. } => Kind::Deleted,
+            ConflictKind::Content if conflict.ours.is_none() || conflict.theirs.is_none() => {
+                Kind::Deleted
+            }
+            ConflictKind::Content => Kind::Changed,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Changed => "changed",
+            Kind::Deleted => "deleted",
+            Kind::Renamed => "renamed",
+            Kind::Limit => "limit",
+        })
+    }
+}
+
+/// Conflicts in one table that diverged the same way.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    /// The table holding the conflicting values, e.g. `dependencies` or
+    /// `target.cfg(unix).dependencies`.
+    pub table: String,
+    pub kind: Kind,
+    pub conflicts: Vec<MergeConflict>,
+    /// The strategy expected to settle the partition, if one fits.
+    pub suggested: Option<Strategy>,
+}
+
+impl Partition {
+    /// The partition's name on the command line, `<table>:<kind>`.
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.table, self.kind)
+    }
+
+    /// Strategies resolving every conflict of the partition, and the values
+    /// related to them, with `strategy`.
+    pub fn strategies(&self, strategy: Strategy) -> Vec<PathStrategy> {
+        let mut paths = Vec::new();
+        for conflict in &self.conflicts {
+            paths.push(conflict.path.clone());
+            paths.extend(conflict.related.iter().map(|related| related.path.clone()));
+        }
+        paths
+            .into_iter()
+            .map(|path| PathStrategy {
+                path,
+                heuristic: strategy.heuristic(),
+                name: strategy.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Groups `conflicts` by table and kind, the largest partitions first.
+pub fn partition(conflicts: Vec<MergeConflict>) -> Vec<Partition> {
+    let mut partitions: Vec<Partition> = Vec::new();
+    for conflict in conflicts {
+        let table = table(&conflict.path);
+        let kind = Kind::of(&conflict);
+        match partitions
+            .iter_mut()
+            .find(|partition| partition.table == table && partition.kind == kind)
+        {
+            Some(partition) => partition.conflicts.push(conflict),
+            None => partitions.push(Partition {
+                table,
+                kind,
+                conflicts: vec![conflict],
+                suggested: None,
+            }),
+        }
+    }
+    for partition in &mut partitions {
+        partition.suggested = suggest(partition);
+    }
+    partitions.sort_by(|a, b| {
+        b.conflicts
+            .len()
+            .cmp(&a.conflicts.len())
+            .then_with(|| (&a.table, a.kind).cmp(&(&b.table, b.kind)))
+    });
+    partitions
+}
+
+/// The report printed by `triage`: one line per partition.
+pub fn report(partitions: &[Partition]) -> String {
+    let count: usize = partitions
+        .iter()
+        .map(|partition| partition.conflicts.len())
+        .sum();
+    let plural = |n: usize, word: &str| format!("{n} {word}{}", if n == 1 { "" } else { "s" });
+    let mut report = format!(
+        "{} in {}\n",
+        plural(count, "conflict"),
+        plural(partitions.len(), "partition")
+    );
+    let width = partitions
+        .iter()
+        .map(|partition| partition.id().len())
+        .max()
+        .unwrap_or_default();
+    for partition in partitions {
+        let mut line = format!(
+            "  {:width$}  {:>4}",
+            partition.id(),
+            partition.conflicts.len()
+        );
+        if let Some(strategy) = partition.suggested {
+            line.push_str(&format!("  suggested: {strategy}"));
+        }
+        report.push_str(line.trim_end());
+        report.push('\n');
+    }
+    report
+}
+
+/// The table holding the value at the dotted `path`.
+fn table(path: &str) -> String {
+    let segments: Vec<&str> = path.split('.').collect();
+    let depth = match segments[..] {
+        ["target", _, _, ..] | ["profile", _, "package", _, ..] => 3,
+        ["workspace" | "profile" | "patch" | "lints", _, ..] => 2,
+        _ => 1,
+    };
+    segments[..depth.min(segments.len())].join(".")
+}
+
+/// [`Strategy::Union`] for lists, [`Strategy::SemverMax`] for dependency
+/// requirements both sides changed, nothing otherwise.
+fn suggest(partition: &Partition) -> Option<Strategy> {
+    if partition.kind != Kind::Changed {
+        return None;
+    }
+    let both = |conflict: &MergeConflict, test: fn(&Value) -> bool| {
+        [&conflict.ours, &conflict.theirs]
+            .into_iter()
+            .all(|value| value.as_ref().is_some_and(test))
+    };
+    if partition.conflicts.iter().all(|c| both(c, Value::is_array)) {
+        return Some(Strategy::Union);
+    }
+    let last = partition.table.rsplit('.').next().unwrap_or_default();
+    let dependencies =
+        DEPENDENCY_TABLES.contains(&last) || partition.table == "workspace.dependencies";
+    (dependencies && partition.conflicts.iter().all(|c| both(c, Value::is_str)))
+        .then_some(Strategy::SemverMax)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(path: &str, ours: Option<Value>, theirs: Option<Value>) -> MergeConflict {
+        MergeConflict::new(path.to_string(), None, ours, theirs)
+    }
+
+    #[test]
+    fn partitions_conflicts_by_table_and_kind() {
+        let text = |text: &str| Some(Value::from(text));
+        let list = |items: &[&str]| Some(Value::from(items.to_vec()));
+        let partitions = partition(vec![
+            conflict(
+                "dependencies.serde.version",
+                text("1.0.200"),
+                text("1.0.199"),
+            ),
+            conflict("features.std", list(&["a"]), list(&["b"])),
+            conflict("dependencies.rand", text("0.8"), text("0.9")),
+            conflict("dependencies.log", None, text("0.4.22")),
+            conflict(
+                "target.cfg(unix).dependencies.libc",
+                text("0.2.1"),
+                text("0.2.2"),
+            ),
+            conflict("features.full", list(&["std"]), list(&["std", "rc"])),
+        ]);
+
+        let summary: Vec<(String, usize, Option<Strategy>)> = partitions
+            .iter()
+            .map(|partition| {
+                (
+                    partition.id(),
+                    partition.conflicts.len(),
+                    partition.suggested,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "dependencies:changed".to_string(),
+                    2,
+                    Some(Strategy::SemverMax)
+                ),
+                ("features:changed".to_string(), 2, Some(Strategy::Union)),
+                ("dependencies:deleted".to_string(), 1, None),
+                (
+                    "target.cfg(unix).dependencies:changed".to_string(),
+                    1,
+                    Some(Strategy::SemverMax)
+                ),
+            ]
+        );
+        let strategies = partitions[0].strategies(Strategy::SemverMax);
+        assert_eq!(strategies[1].path, "dependencies.rand");
+        assert_eq!(strategies[1].name, "semver-max");
+        assert!(report(&partitions).starts_with("6 conflicts in 4 partitions\n"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Forward compatibility with unstable manifest surface.
+//!
+//! Nightly cargo keeps adding to the manifest format: artifact dependencies,
+//! `[hints]`, per-package targets. Merging such a key by the rules for its
+//! neighbours can combine edits that only make sense together (a list of
+//! `rustflags` united from two sides, one side's `artifact` kind with the
+//! other's `target`). [`MATRIX`] lists the unstable keys this tool recognizes
+//! and how each is merged until it learns their semantics: conservatively, as
+//! one value, so that a change only one side made wins and changes both sides
+//! made differently conflict. Where both sides changed one, the merge gets a
+//! [`Note`] suggesting a newer release, which may merge it properly.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use toml::Value;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Where an unstable key lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// The key at this path; `*` matches any one key. The value merges as
+    /// one.
+    Key(&'static [&'static str]),
+    /// This key of a dependency entry, in any dependency table. The entry
+    /// holding it merges as one.
+    Dependency(&'static str),
+}
+
+/// An unstable key the merge recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unstable {
+    pub location: Location,
+    /// The unstable cargo feature (`-Z` flag) adding it.
+    pub feature: &'static str,
+}
+
+/// The unstable keys merged conservatively.
+pub const MATRIX: &[Unstable] = &[
+    Unstable {
+        location: Location::Key(&["hints"]),
+        feature: "hints",
+    },
+    Unstable {
+        location: Location::Dependency("artifact"),
+        feature: "bindeps",
+    },
+    Unstable {
+        location: Location::Key(&["package", "forced-target"]),
+        feature: "per-package-target",
+    },
+    Unstable {
+        location: Location::Key(&["package", "default-target"]),
+        feature: "per-package-target",
+    },
+    Unstable {
+        location: Location::Key(&["profile", "*", "rustflags"]),
+        feature: "profile-rustflags",
+    },
+    Unstable {
+        location: Location::Key(&["profile", "*", "trim-paths"]),
+        feature: "trim-paths",
+    },
+];
+
+/// The entry of [`MATRIX`] making the value at `path` (base, ours, theirs)
+/// merge as one, if any.
+pub(crate) fn recognize(path: &[&str], sides: [Option<&Value>; 3]) -> Option<&'static Unstable> {
+    MATRIX.iter().find(|unstable| match unstable.location {
+        Location::Key(pattern) => matches(pattern, path),
+        Location::Dependency(key) => {
+            is_dependency(path)
+                && sides
+                    .into_iter()
+                    .flatten()
+                    .any(|entry| entry.get(key).is_some())
+        }
+    })
+}
+
+/// Whether the top-level `key` is one of [`MATRIX`].
+pub(crate) fn is_top_level(key: &str) -> bool {
+    MATRIX
+        .iter()
+        .any(|unstable| matches!(unstable.location, Location::Key([only]) if *only == key))
+}
+
+/// An unstable value both sides changed, which the merge took as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    /// Dotted path of the value, e.g. `hints` or `dependencies.tool` for an
+    /// artifact dependency.
+    pub path: String,
+    pub unstable: &'static Unstable,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` belongs to the unstable cargo feature `{}` and was merged as one value; \
+             a newer cargo-merge-assist may merge it key by key",
+            self.path, self.unstable.feature
+        )
+    }
+}
+
+/// The unstable values of the manifests `base`, `ours` and `theirs` that
+/// both sides changed, and differently.
+pub fn notes(base: &Value, ours: &Value, theirs: &Value) -> Vec<Note> {
+    let mut notes = Vec::new();
+    for unstable in MATRIX {
+        let mut paths = BTreeSet::new();
+        for doc in [base, ours, theirs] {
+            match unstable.location {
+                Location::Key(pattern) => collect(doc, pattern, &mut Vec::new(), &mut paths),
+                Location::Dependency(key) => paths.extend(
+                    dependencies(doc)
+                        .into_iter()
+                        .filter(|path| lookup(doc, path).is_some_and(|e| e.get(key).is_some())),
+                ),
+            }
+        }
+        for path in paths {
+            let [base, ours, theirs] = [base, ours, theirs].map(|doc| lookup(doc, &path));
+            if ours != base && theirs != base && ours != theirs {
+                notes.push(Note {
+                    path: path.join("."),
+                    unstable,
+                });
+            }
+        }
+    }
+    notes
+}
+
+fn matches(pattern: &[&str], path: &[&str]) -> bool {
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(path)
+            .all(|(pattern, key)| *pattern == "*" || pattern == key)
+}
+
+/// Whether `path` is a dependency entry, e.g. `dependencies.serde` or
+/// `target.cfg(unix).build-dependencies.cc`.
+fn is_dependency(path: &[&str]) -> bool {
+    match path {
+        ["target", _, table, _] | [table, _] => DEPENDENCY_TABLES.contains(table),
+        ["workspace", "dependencies", _] => true,
+        _ => false,
+    }
+}
+
+/// The paths in `doc` matching `pattern`.
+fn collect<'a>(
+    doc: &'a Value,
+    pattern: &[&str],
+    path: &mut Vec<&'a str>,
+    paths: &mut BTreeSet<Vec<&'a str>>,
+) {
+    let Some((first, rest)) = pattern.split_first() else {
+        paths.insert(path.clone());
+        return;
+    };
+    for (key, value) in doc.as_table().into_iter().flatten() {
+        if *first == "*" || first == key {
+            path.push(key);
+            collect(value, rest, path, paths);
+            path.pop();
+        }
+    }
+}
+
+/// The paths of the dependency entries in `doc`.
+fn dependencies(doc: &Value) -> Vec<Vec<&str>> {
+    let mut tables: Vec<Vec<&str>> = DEPENDENCY_TABLES.iter().map(|table| vec![*table]).collect();
+    tables.push(vec!["workspace", "dependencies"]);
+    for (cfg, _) in doc
+        .get("target")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flatten()
+    {
+        tables.extend(
+            DEPENDENCY_TABLES
+                .iter()
+                .map(|table| vec!["target", cfg, *table]),
+        );
+    }
+    let mut entries = Vec::new();
+    for table in tables {
+        let names = lookup(doc, &table).and_then(Value::as_table);
+        for name in names.into_iter().flat_map(|names| names.keys()) {
+            let mut entry = table.clone();
+            entry.push(name);
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+fn lookup<'a>(doc: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(doc, |value, key| value.get(*key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{MergeError, merge_manifest_texts};
+
+    #[test]
+    fn merges_unstable_keys_as_one_value() {
+        let base = "[dependencies]\ntool = { path = \"tool\", artifact = \"bin\" }\n\n\
+                    [profile.release]\nrustflags = [\"-Ctarget-cpu=native\"]\n";
+        // Artifact dependencies merge whole: these edits would combine into
+        // a `cdylib` artifact built for a target only one side meant.
+        let ours = base.replace("artifact = \"bin\"", "artifact = \"cdylib\"");
+        let theirs = base.replace(
+            "artifact = \"bin\"",
+            "artifact = \"bin\", target = \"wasm32-wasip1\"",
+        );
+        let Err(MergeError::Conflict(conflict)) = merge_manifest_texts(base, &ours, &theirs) else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflict.path, "dependencies.tool");
+
+        // Lists of an unstable key are not united.
+        let ours = base.replace("native\"]", "native\", \"-Zshare-generics\"]");
+        let theirs = base.replace("native\"]", "native\", \"-Cforce-frame-pointers\"]");
+        let Err(MergeError::Conflict(conflict)) = merge_manifest_texts(base, &ours, &theirs) else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflict.path, "profile.release.rustflags");
+
+        let [base, ours, theirs] = [base, &ours, &theirs].map(|text| text.parse().unwrap());
+        let notes: Vec<String> = notes(&base, &ours, &theirs)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                "`profile.release.rustflags` belongs to the unstable cargo feature \
+              `profile-rustflags` and was merged as one value; a newer cargo-merge-assist \
+              may merge it key by key"
+            ]
+        );
+        assert!(is_top_level("hints"));
+        assert!(!is_top_level("package"));
+    }
+}
@@ -0,0 +1,348 @@
+//! Chunked verification of large workspaces (`--jobs` on `resolve-lock
+//! --verify` and `merge-all`).
+//!
+//! One `cargo check` of a monorepo checks every member even when the merge
+//! touched a single crate. With `--jobs N` only the members [`affected`] by
+//! the merged manifests are checked, split into at most N groups that run as
+//! concurrent `cargo check -p ...` processes. cargo locks its build
+//! directory, so every group but the first builds into its own directory
+//! under `<target>/cargo-merge-assist/`, which stays warm between runs. The
+//! failures of all groups are reported together ([`Error::CargoSet`]).
+//!
+//! Successive merges often land on the same manifests and lockfile. Passing
+//! checks are recorded in a [`VerifyCache`] under
+//! `<git common dir>/cargo-merge-assist/verified/`, keyed by the contents of
+//! every manifest in the repository and of `Cargo.lock`, the toolchain
+//! (`rustc -vV`) and what was checked, so an identical state is not checked
+//! again. Source files are not part of the key.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::cache::git_common_dir;
+use crate::cargo;
+use crate::cleanup;
+use crate::discover::Excludes;
+use crate::error::Error;
+
+/// A workspace member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub manifest: PathBuf,
+    /// Members it depends on by path.
+    pub depends_on: Vec<String>,
+}
+
+/// Checks the members of the workspace at `repo` affected by the `changed`
+/// manifests (all of them when `changed` is empty) in up to `jobs`
+/// concurrent groups.
+pub fn check_affected(
+    repo: &Path,
+    changed: &[PathBuf],
+    jobs: usize,
+    offline: bool,
+) -> Result<(), Error> {
+    let (members, target_dir) = workspace(repo, offline)?;
+    let groups = groups(&affected(&members, changed), jobs);
+    check(repo, &groups, &target_dir, offline)
+}
+
+/// The members and target directory of the workspace at `repo`, from
+/// `cargo metadata --no-deps`.
+pub fn workspace(repo: &Path, offline: bool) -> Result<(Vec<Member>, PathBuf), Error> {
+    let args = ["metadata", "--no-deps", "--format-version", "1"];
+    let stdout = cargo::stdout(repo, &args, offline)?;
+    let invalid = |message: String| Error::Cargo {
+        dir: repo.to_path_buf(),
+        args: args.iter().map(ToString::to_string).collect(),
+        exit: Some(0),
+        stderr: message,
+        log: None,
+    };
+    let metadata: Value = serde_json::from_str(&stdout)
+        .map_err(|err| invalid(format!("unreadable metadata: {err}")))?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .map(PathBuf::from)
+        .ok_or_else(|| invalid("metadata has no target_directory".to_string()))?;
+
+    let packages = metadata["packages"].as_array().cloned().unwrap_or_default();
+    let names: BTreeSet<&str> = packages
+        .iter()
+        .filter_map(|package| package["name"].as_str())
+        .collect();
+    let members = packages
+        .iter()
+        .filter_map(|package| {
+            let depends_on = package["dependencies"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|dep| dep.get("path").is_some())
+                .filter_map(|dep| dep["name"].as_str())
+                .filter(|name| names.contains(name))
+                .map(str::to_string)
+                .collect();
+            Some(Member {
+                name: package["name"].as_str()?.to_string(),
+                manifest: PathBuf::from(package["manifest_path"].as_str()?),
+                depends_on,
+            })
+        })
+        .collect();
+    Ok((members, target_dir))
+}
+
+/// Names of the members whose manifest is one of `changed`, and of the
+/// members depending on them. Every member is affected when `changed` is
+/// empty or holds a manifest that is not a member's or that declares the
+/// `[workspace]` (its shared dependencies and lints reach every member).
+pub fn affected(members: &[Member], changed: &[PathBuf]) -> Vec<String> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let manifests: Vec<PathBuf> = members
+        .iter()
+        .map(|member| canonical(&member.manifest))
+        .collect();
+    let everything = changed.is_empty()
+        || changed.iter().any(|path| {
+            let workspace = fs::read_to_string(path)
+                .ok()
+                .and_then(|text| text.parse::<toml::Value>().ok())
+                .is_some_and(|doc| doc.get("workspace").is_some());
+            workspace || !manifests.contains(&canonical(path))
+        });
+    if everything {
+        return members.iter().map(|member| member.name.clone()).collect();
+    }
+
+    let changed: Vec<PathBuf> = changed.iter().map(|path| canonical(path)).collect();
+    let mut affected: BTreeSet<&str> = members
+        .iter()
+        .zip(&manifests)
+        .filter(|(_, manifest)| changed.contains(manifest))
+        .map(|(member, _)| member.name.as_str())
+        .collect();
+    loop {
+        let dependents: Vec<&str> = members
+            .iter()
+            .filter(|member| !affected.contains(member.name.as_str()))
+            .filter(|member| {
+                member
+                    .depends_on
+                    .iter()
+                    .any(|dep| affected.contains(dep.as_str()))
+            })
+            .map(|member| member.name.as_str())
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+    affected.into_iter().map(str::to_string).collect()
+}
+
+/// `names` split into at most `jobs` groups of nearly equal size.
+pub fn groups(names: &[String], jobs: usize) -> Vec<Vec<String>> {
+    let count = jobs.clamp(1, names.len().max(1));
+    (0..count)
+        .map(|idx| names[idx * names.len() / count..(idx + 1) * names.len() / count].to_vec())
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+/// Runs `cargo check -q -p ...` for every group concurrently. Groups after
+/// the first build into their own directories under `target_dir`.
+pub fn check(
+    repo: &Path,
+    groups: &[Vec<String>],
+    target_dir: &Path,
+    offline: bool,
+) -> Result<(), Error> {
+    let results: Vec<Result<(), Error>> = thread::scope(|scope| {
+        let handles: Vec<_> = groups
+            .iter()
+            .enumerate()
+            .map(|(idx, group)| {
+                let mut args = vec!["check".to_string(), "-q".to_string()];
+                for name in group {
+                    args.extend(["-p".to_string(), name.clone()]);
+                }
+                if idx > 0 {
+                    let dir = target_dir
+                        .join("cargo-merge-assist")
+                        .join(format!("check-{idx}"));
+                    args.extend(["--target-dir".to_string(), dir.display().to_string()]);
+                }
+                scope.spawn(move || {
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                    cargo::run(repo, &args, offline)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("cargo check thread panicked"))
+            .collect()
+    });
+    let mut failures: Vec<Error> = results.into_iter().filter_map(Result::err).collect();
+    match failures.len() {
+        0 => Ok(()),
+        1 => Err(failures.remove(0)),
+        _ => Err(Error::CargoSet { failures }),
+    }
+}
+
+/// Keys of states that passed verification.
+#[derive(Debug, Clone)]
+pub struct VerifyCache {
+    dir: PathBuf,
+}
+
+impl VerifyCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache of the git repository containing `start`, if any.
+    pub fn for_repo(start: &Path) -> Option<Self> {
+        git_common_dir(start).map(|dir| Self::new(dir.join("cargo-merge-assist").join("verified")))
+    }
+
+    /// Key for checking the workspace at `repo` as described by `scope` (the
+    /// check command and its selection); `None` when the toolchain cannot be
+    /// identified.
+    pub fn key(repo: &Path, scope: &str) -> Option<String> {
+        let output = Command::new("rustc")
+            .current_dir(repo)
+            .arg("-vV")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let mut hasher = Sha256::new();
+        let mut update = |part: &[u8]| {
+            // Length prefixes keep ("ab", "c") and ("a", "bc") distinct.
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        };
+        update(env!("CARGO_PKG_VERSION").as_bytes());
+        update(&output.stdout);
+        update(scope.as_bytes());
+        let mut files = Excludes::new(repo, &[], &[]).ok()?.manifests();
+        files.push(repo.join("Cargo.lock"));
+        for path in &files {
+            update(path.to_string_lossy().as_bytes());
+            // A missing file hashes apart from an empty one.
+            match fs::read(path) {
+                Ok(bytes) => update(&bytes),
+                Err(_) => update(b"\0missing"),
+            }
+        }
+        Some(
+            hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+        )
+    }
+
+    /// Whether the state with `key` passed before.
+    pub fn contains(&self, key: &str) -> bool {
+        self.dir.join(key).is_file()
+    }
+
+    /// Records that the state with `key` passed.
+    pub fn record(&self, key: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        cleanup::write_atomic(&self.dir.join(key), "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_changed_members_and_their_dependents_in_groups() {
+        let repo =
+            std::env::temp_dir().join(format!("cargo-merge-assist-verify-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(
+            repo.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\nresolver = \"2\"\n",
+        )
+        .unwrap();
+        for (name, deps) in [("a", ""), ("b", "a = { path = \"../a\" }\n"), ("c", "")] {
+            fs::create_dir_all(repo.join(name).join("src")).unwrap();
+            fs::write(
+                repo.join(name).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n{deps}"
+                ),
+            )
+            .unwrap();
+            fs::write(repo.join(name).join("src/lib.rs"), "").unwrap();
+        }
+
+        let (members, target_dir) = workspace(&repo, true).unwrap();
+        assert_eq!(members.len(), 3);
+        // Wherever the environment moves it, as on CI runners.
+        let moved = ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR"]
+            .iter()
+            .find_map(std::env::var_os);
+        let expected = fs::canonicalize(&repo)
+            .unwrap()
+            .join(moved.unwrap_or("target".into()));
+        assert_eq!(target_dir, expected);
+
+        let affected = affected(&members, &[repo.join("a/Cargo.toml")]);
+        assert_eq!(affected, ["a", "b"]);
+        assert_eq!(
+            self::affected(&members, &[repo.join("Cargo.toml")]).len(),
+            3
+        );
+        assert_eq!(groups(&affected, 4), [vec!["a"], vec!["b"]]);
+        assert_eq!(groups(&affected, 1), [vec!["a", "b"]]);
+
+        fs::write(repo.join("b/src/lib.rs"), "fn broken() -> u8 { \"\" }\n").unwrap();
+        fs::write(repo.join("c/src/lib.rs"), "fn broken() -> u8 { \"\" }\n").unwrap();
+        let all = self::affected(&members, &[]);
+        let err = check(&repo, &groups(&all, 3), &target_dir, true).unwrap_err();
+        let Error::CargoSet { failures } = &err else {
+            panic!("expected both failures, got {err:?}");
+        };
+        assert_eq!(failures.len(), 2);
+        assert!(err.to_string().starts_with("2 cargo commands failed"));
+        for failure in failures {
+            if let Error::Cargo { log: Some(log), .. } = failure {
+                fs::remove_file(log).unwrap();
+            }
+        }
+
+        let cache = VerifyCache::new(repo.join("verified"));
+        let key = VerifyCache::key(&repo, "check").unwrap();
+        assert!(!cache.contains(&key));
+        cache.record(&key).unwrap();
+        assert!(cache.contains(&key));
+        assert_eq!(VerifyCache::key(&repo, "check"), Some(key.clone()));
+        assert_ne!(VerifyCache::key(&repo, "check -p a"), Some(key.clone()));
+        // A member's manifest is part of the key.
+        fs::write(
+            repo.join("a/Cargo.toml"),
+            "[package]\nname = \"a\"\nversion = \"0.2.0\"\n",
+        )
+        .unwrap();
+        assert_ne!(VerifyCache::key(&repo, "check"), Some(key));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
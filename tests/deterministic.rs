@@ -0,0 +1,62 @@
+//! `--deterministic` output must not depend on locale, timezone or platform.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BASE: &str =
+    "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+const OURS: &str = "[package]\nname = \"demo\"\nversion = \"0.2.0\"\n\n[dependencies]\nserde = \"1\"\nZeta = \"1\"\n\"älpha\" = \"1\"\n";
+const THEIRS: &str = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\nalpha = \"1\"\nbeta = \"1\"\n";
+
+/// Ours' layout is kept; keys it lacks follow in byte order, never by
+/// locale collation.
+const EXPECTED: &str = "[package]\nname = \"demo\"\nversion = \"0.2.0\"\n\n[dependencies]\nserde = \"1\"\nZeta = \"1\"\n\"älpha\" = \"1\"\nalpha = \"1\"\nbeta = \"1\"\n";
+
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cargo-merge-assist-det-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+fn merge_with_env(dir: &Path, tag: &str, env: &[(&str, &str)]) -> Vec<u8> {
+    let out = dir.join(format!("out-{tag}.toml"));
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-merge-assist"))
+        .args(["--deterministic", "merge-manifest"])
+        .arg("--base")
+        .arg(dir.join("base.toml"))
+        .arg("--ours")
+        .arg(dir.join("ours.toml"))
+        .arg("--theirs")
+        .arg(dir.join("theirs.toml"))
+        .arg("--out")
+        .arg(&out)
+        .envs(env.iter().copied())
+        .status()
+        .expect("run cargo-merge-assist");
+    assert!(status.success(), "merge failed under {env:?}");
+    fs::read(out).expect("read merged output")
+}
+
+#[test]
+fn output_is_byte_identical_across_locales_and_timezones() {
+    let dir = scratch_dir();
+    fs::write(dir.join("base.toml"), BASE).unwrap();
+    fs::write(dir.join("ours.toml"), OURS).unwrap();
+    fs::write(dir.join("theirs.toml"), THEIRS).unwrap();
+
+    let environments: &[&[(&str, &str)]] = &[
+        &[("LC_ALL", "C"), ("TZ", "UTC")],
+        &[("LC_ALL", "tr_TR.UTF-8"), ("TZ", "Asia/Tokyo")],
+        &[("LANG", "de_DE.UTF-8"), ("LC_COLLATE", "sv_SE.UTF-8")],
+    ];
+
+    for (idx, env) in environments.iter().enumerate() {
+        let output = merge_with_env(&dir, &idx.to_string(), env);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            EXPECTED,
+            "under {env:?}"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! `merge-all` and `resolve-lock` where cargo is not installed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const BASE: &str =
+    "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\n";
+const OURS: &str = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1\"\nlog = \"0.4\"\n";
+const THEIRS: &str =
+    "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1.0.200\"\n";
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("cargo-merge-assist-{name}-{}", std::process::id()));
+    fs::create_dir_all(dir.join("bin")).expect("create scratch dir");
+    dir
+}
+
+/// Runs the binary with a `PATH` holding nothing but an empty directory.
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargo-merge-assist"))
+        .args(args)
+        .current_dir(dir)
+        .env("PATH", dir.join("bin"))
+        .output()
+        .expect("run cargo-merge-assist")
+}
+
+#[test]
+fn merges_manifests_without_cargo_when_allowed() {
+    let dir = scratch_dir("nocargo");
+    fs::write(dir.join("base.toml"), BASE).unwrap();
+    fs::write(dir.join("theirs.toml"), THEIRS).unwrap();
+    let merge_all = [
+        "merge-all",
+        "--base",
+        "base.toml",
+        "--ours",
+        "Cargo.toml",
+        "--theirs",
+        "theirs.toml",
+        "--out",
+        "Cargo.toml",
+    ];
+
+    fs::write(dir.join("Cargo.toml"), OURS).unwrap();
+    let output = run(&dir, &merge_all);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-no-cargo"));
+    assert_eq!(fs::read_to_string(dir.join("Cargo.toml")).unwrap(), OURS);
+
+    let output = run(&dir, &[&merge_all[..], &["--allow-no-cargo"]].concat());
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cargo is not installed"));
+    assert!(
+        fs::read_to_string(dir.join("Cargo.toml"))
+            .unwrap()
+            .contains("serde = \"1.0.200\"\nlog = \"0.4\"")
+    );
+
+    let output = run(&dir, &["resolve-lock", "--allow-no-cargo"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(run(&dir, &["resolve-lock"]).status.code(), Some(5));
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn check_writes_nothing() {
+    let dir = scratch_dir("check");
+    let root = "[workspace]\nmembers = [\"member\"]\n\n[workspace.dependencies]\nserde = \"1\"\n";
+    fs::write(dir.join("Cargo.toml"), root).unwrap();
+    fs::create_dir_all(dir.join("member")).unwrap();
+    fs::write(dir.join("base.toml"), BASE).unwrap();
+    let ours = BASE.replace("serde = \"1\"", "serde.workspace = true");
+    fs::write(dir.join("member/Cargo.toml"), &ours).unwrap();
+    fs::write(dir.join("theirs.toml"), THEIRS).unwrap();
+
+    let output = run(
+        &dir,
+        &[
+            "merge-all",
+            "--check",
+            "--base",
+            "base.toml",
+            "--ours",
+            "member/Cargo.toml",
+            "--theirs",
+            "theirs.toml",
+            "--out",
+            "member/Cargo.toml",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("carried"));
+    assert_eq!(
+        fs::read_to_string(dir.join("member/Cargo.toml")).unwrap(),
+        ours
+    );
+    assert_eq!(fs::read_to_string(dir.join("Cargo.toml")).unwrap(), root);
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        ["Cargo.toml", "base.toml", "bin", "member", "theirs.toml"]
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}